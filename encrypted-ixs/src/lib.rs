@@ -1,8 +1,23 @@
 //! Encrypted instructions for Dusk Exchange
 //!
 //! This module contains the Arcis MPC circuits for:
+//! - init_book: Create the empty encrypted state of a market's book
 //! - add_order: Insert an encrypted order, update best bid/ask
 //! - match_book: Find and match crossing orders
+//! - uncross_book: Clear the opening auction at a single price and reveal the
+//!   orders left in the book
+//! - clear_book: Empty the book when a market is delisted
+//! - add_hidden_order: Insert an order whose side is encrypted too
+//! - match_book_sealed, open_sealed_match, reveal_fill: Match without revealing
+//!   the fill terms or the parties; a party opens the match to settle it, and the
+//!   terms are handed to the two counterparties at settlement
+//! - disclose_orders, disclose_fill: Re-encrypt a position's orders or a sealed
+//!   fill under its registered auditor key
+//! - query_my_orders: Return a position's live orders to its owner
+//! - probe_cross: Reveal only whether match_book would find a match
+//! - init_balance, credit_balance, debit_balance, add_order_locked,
+//!   remove_order_unlocked, settle_fill, balance_is_empty: Confidential
+//!   balances for markets that keep them inside the MXE
 //!
 //! Built using Arcium's Arcis framework for confidential computation.
 
@@ -17,12 +32,31 @@ mod orderbook {
     pub struct Order {
         pub price: u64,
         pub amount: u64,
+        // Owner is the trader's sub-account position, split into two halves
         pub owner_lo: u128,
         pub owner_hi: u128,
         pub order_id: u64,
         pub side: bool, // true = buy, false = sell
     }
 
+    /// Encrypted part of an order, submitted by the trader under their shared key
+    /// Owner, order id and side are passed in plaintext so the program can bind
+    /// them to the signer and the locked balance
+    #[derive(Copy, Clone)]
+    pub struct OrderInput {
+        pub price: u64,
+        pub amount: u64,
+    }
+
+    /// Encrypted part of a side-hidden order
+    /// The side is encrypted with the price and amount, so it never appears in plaintext
+    #[derive(Copy, Clone)]
+    pub struct HiddenOrderInput {
+        pub price: u64,
+        pub amount: u64,
+        pub side: bool, // true = buy, false = sell
+    }
+
     /// The encrypted orderbook state - tracks best bid and ask
     #[derive(Copy, Clone)]
     pub struct OrderBookState {
@@ -53,18 +87,111 @@ mod orderbook {
         pub maker_hi: u128,
         pub taker_lo: u128,
         pub taker_hi: u128,
+        // Whether each order left the book with this fill
+        pub maker_filled: bool,
+        pub taker_filled: bool,
     }
 
-    /// Add a new order to the orderbook
-    /// Updates best bid/ask if the new order is better
+    /// Price and amount of a sealed fill, kept encrypted until settlement
+    /// The salt opens the fill's public commitment, see `fill_commitment`
+    #[derive(Copy, Clone)]
+    pub struct FillTerms {
+        pub price: u64,
+        pub amount: u64,
+        pub salt: u128,
+    }
+
+    /// Public part of a sealed match - revealed after computation
+    /// Neither the fill terms nor the orders and their owners are revealed
+    #[derive(Copy, Clone)]
+    pub struct SealedMatchResult {
+        pub matched: bool,
+        // Commitment to the fill terms, zero when nothing matched
+        pub fill_commitment: [u8; 32],
+    }
+
+    /// Orders and owners of a sealed match, kept encrypted until a party opens it
+    #[derive(Copy, Clone)]
+    pub struct MatchParties {
+        pub maker_order_id: u64,
+        pub taker_order_id: u64,
+        pub maker_lo: u128,
+        pub maker_hi: u128,
+        pub taker_lo: u128,
+        pub taker_hi: u128,
+        // Whether each order left the book with this fill
+        pub maker_filled: bool,
+        pub taker_filled: bool,
+    }
+
+    /// Amounts a sealed fill moves between the two positions - revealed at settlement
+    #[derive(Copy, Clone)]
+    pub struct FillDeltas {
+        pub base_amount: u64,
+        pub quote_amount: u64,
+    }
+
+    /// A position's orders resting in the book, with their unfilled amounts
+    /// Zero where the best bid or ask belongs to someone else
+    #[derive(Copy, Clone)]
+    pub struct OwnedOrders {
+        pub bid_order_id: u64,
+        pub bid_price: u64,
+        pub bid_amount: u64,
+        pub ask_order_id: u64,
+        pub ask_price: u64,
+        pub ask_amount: u64,
+    }
+
+    /// Orders the book holds when it is cleared or uncrossed - revealed after computation
+    /// An order id of 0 means that side was empty
+    #[derive(Copy, Clone)]
+    pub struct ClearedBook {
+        pub bid_order_id: u64,
+        pub bid_owner_lo: u128,
+        pub bid_owner_hi: u128,
+        pub ask_order_id: u64,
+        pub ask_owner_lo: u128,
+        pub ask_owner_hi: u128,
+    }
+
+    /// Confidential balances of one position, kept encrypted by the MXE
+    #[derive(Copy, Clone)]
+    pub struct Balances {
+        pub base_available: u64,
+        pub base_locked: u64,
+        pub quote_available: u64,
+        pub quote_locked: u64,
+    }
+
+    /// Create an empty orderbook state for a new market
+    /// The ask side starts at u64::MAX so any real ask improves on it
     #[instruction]
-    pub fn add_order(
-        order: Enc<Shared, Order>,
-        state_ctxt: Enc<Mxe, OrderBookState>,
-    ) -> Enc<Mxe, OrderBookState> {
-        let new_order = order.to_arcis();
-        let mut state = state_ctxt.to_arcis();
+    pub fn init_book(mxe: Mxe) -> Enc<Mxe, OrderBookState> {
+        let state = OrderBookState {
+            best_bid_price: 0,
+            best_bid_amount: 0,
+            best_bid_owner_lo: 0,
+            best_bid_owner_hi: 0,
+            best_bid_id: 0,
+
+            best_ask_price: 18446744073709551615u64, // u64::MAX
+            best_ask_amount: 0,
+            best_ask_owner_lo: 0,
+            best_ask_owner_hi: 0,
+            best_ask_id: 0,
+
+            order_count: 0,
+        };
+
+        mxe.from_arcis(state)
+    }
 
+    /// Insert an order into the book if it improves the best bid or ask
+    /// The book keeps one order a side: an order that doesn't improve it, or the
+    /// best it replaces, leaves the book while its OpenOrder stays resting. Its
+    /// owner can cancel it, and after an uncross `release_locks` frees it
+    fn insert_order(mut state: OrderBookState, new_order: Order) -> OrderBookState {
         if new_order.side {
             // Buy order - update if better (higher price)
             if new_order.price > state.best_bid_price {
@@ -76,7 +203,7 @@ mod orderbook {
             }
         } else {
             // Sell order - update if better (lower price)
-            // Note: For initial state, best_ask_price should be very high
+            // init_book starts best_ask_price at u64::MAX
             if new_order.price < state.best_ask_price {
                 state.best_ask_price = new_order.price;
                 state.best_ask_amount = new_order.amount;
@@ -86,24 +213,84 @@ mod orderbook {
             }
         }
         state.order_count = state.order_count + 1;
+        state
+    }
+
+    /// Add a new order to the orderbook
+    /// Updates best bid/ask if the new order is better
+    #[instruction]
+    pub fn add_order(
+        order: Enc<Shared, OrderInput>,
+        owner_lo: u128,
+        owner_hi: u128,
+        order_id: u64,
+        side: bool,
+        state_ctxt: Enc<Mxe, OrderBookState>,
+    ) -> Enc<Mxe, OrderBookState> {
+        let input = order.to_arcis();
+        let new_order = Order {
+            price: input.price,
+            amount: input.amount,
+            owner_lo,
+            owner_hi,
+            order_id,
+            side,
+        };
+        let state = insert_order(state_ctxt.to_arcis(), new_order);
 
         state_ctxt.owner.from_arcis(state)
     }
 
-    /// Remove/cancel an order from the orderbook
-    /// Only the order owner can cancel their order
+    /// Add an order whose side is encrypted
+    /// The trader locks both base and quote up to their own limits; the order is
+    /// accepted only if the lock of its side covers it, and only that is revealed
     #[instruction]
-    pub fn remove_order(
-        order_id: Enc<Shared, u64>,
-        owner_lo: Enc<Shared, u128>,
-        owner_hi: Enc<Shared, u128>,
+    pub fn add_hidden_order(
+        order: Enc<Shared, HiddenOrderInput>,
+        owner_lo: u128,
+        owner_hi: u128,
+        order_id: u64,
+        base_lock: u64,
+        quote_lock: u64,
         state_ctxt: Enc<Mxe, OrderBookState>,
     ) -> (Enc<Mxe, OrderBookState>, bool) {
-        let target_id = order_id.to_arcis();
-        let target_owner_lo = owner_lo.to_arcis();
-        let target_owner_hi = owner_hi.to_arcis();
+        let input = order.to_arcis();
         let mut state = state_ctxt.to_arcis();
+
+        let needed = order_lock(input.side, input.price, input.amount);
+        let mut available = base_lock;
+        if input.side {
+            available = quote_lock;
+        }
+        let accepted = needed > 0 && needed <= available;
+
+        if accepted {
+            state = insert_order(
+                state,
+                Order {
+                    price: input.price,
+                    amount: input.amount,
+                    owner_lo,
+                    owner_hi,
+                    order_id,
+                    side: input.side,
+                },
+            );
+        }
+
+        (state_ctxt.owner.from_arcis(state), accepted.reveal())
+    }
+
+    /// Take an order out of the book if it is the best bid or ask and belongs to the owner
+    /// Returns the updated book, whether it was found and its unfilled amount
+    fn take_order(
+        mut state: OrderBookState,
+        target_id: u64,
+        target_owner_lo: u128,
+        target_owner_hi: u128,
+    ) -> (OrderBookState, bool, u64) {
         let mut removed = false;
+        let mut remaining: u64 = 0;
 
         // Check if it's the best bid
         let is_best_bid = state.best_bid_id == target_id;
@@ -112,6 +299,7 @@ mod orderbook {
         let bid_owner_match = bid_owner_match_lo && bid_owner_match_hi;
 
         if is_best_bid && bid_owner_match {
+            remaining = state.best_bid_amount;
             // Clear the best bid
             state.best_bid_price = 0;
             state.best_bid_amount = 0;
@@ -128,6 +316,7 @@ mod orderbook {
         let ask_owner_match = ask_owner_match_lo && ask_owner_match_hi;
 
         if is_best_ask && ask_owner_match {
+            remaining = state.best_ask_amount;
             // Clear the best ask
             state.best_ask_price = 18446744073709551615u64; // u64::MAX
             state.best_ask_amount = 0;
@@ -141,19 +330,48 @@ mod orderbook {
             state.order_count = state.order_count - 1;
         }
 
-        (state_ctxt.owner.from_arcis(state), removed.reveal())
+        (state, removed, remaining)
     }
 
-    /// Match orders in the orderbook
-    /// If best_bid.price >= best_ask.price, a match is found
+    /// Remove/cancel an order from the orderbook
+    /// Only the order owner can cancel their order
+    /// The unfilled amount of the removed order is returned to the owner only
     #[instruction]
-    pub fn match_book(
+    pub fn remove_order(
+        client: Shared,
+        order_id: u64,
+        owner_lo: u128,
+        owner_hi: u128,
         state_ctxt: Enc<Mxe, OrderBookState>,
-    ) -> (Enc<Mxe, OrderBookState>, MatchResult) {
-        let mut state = state_ctxt.to_arcis();
+    ) -> (Enc<Mxe, OrderBookState>, bool, Enc<Shared, u64>) {
+        let (state, removed, remaining) =
+            take_order(state_ctxt.to_arcis(), order_id, owner_lo, owner_hi);
+
+        (
+            state_ctxt.owner.from_arcis(state),
+            removed.reveal(),
+            client.from_arcis(remaining),
+        )
+    }
 
-        // Initialize result with no match
-        let mut result = MatchResult {
+    /// Whether the best bid and ask cross and belong to different owners
+    fn is_crossed(state: OrderBookState) -> bool {
+        // Check for crossing orders
+        let has_match = state.best_bid_price >= state.best_ask_price;
+        let has_liquidity = state.best_bid_amount > 0;
+        let has_ask = state.best_ask_amount > 0;
+
+        // Self-trade prevention
+        let same_owner_lo = state.best_bid_owner_lo == state.best_ask_owner_lo;
+        let same_owner_hi = state.best_bid_owner_hi == state.best_ask_owner_hi;
+        let is_self_trade = same_owner_lo && same_owner_hi;
+
+        has_match && has_liquidity && has_ask && !is_self_trade
+    }
+
+    /// Result of a book job that found nothing to match
+    fn no_match() -> MatchResult {
+        MatchResult {
             matched: false,
             maker_order_id: 0,
             taker_order_id: 0,
@@ -163,64 +381,609 @@ mod orderbook {
             maker_hi: 0,
             taker_lo: 0,
             taker_hi: 0,
-        };
+            maker_filled: false,
+            taker_filled: false,
+        }
+    }
 
-        // Check for crossing orders
-        let has_match = state.best_bid_price >= state.best_ask_price;
-        let has_liquidity = state.best_bid_amount > 0;
-        let has_ask = state.best_ask_amount > 0;
+    /// Execute the best bid against the best ask at `execution_price`
+    /// Fills the smaller of the two amounts and takes filled orders out of the
+    /// book. Shared by continuous matching and the auction uncross, which only
+    /// differ in the price; callers check `is_crossed` first
+    fn fill_best(mut state: OrderBookState, execution_price: u64) -> (OrderBookState, MatchResult) {
+        // Calculate execution amount (minimum of both)
+        let mut execution_amount = state.best_bid_amount;
+        if state.best_ask_amount < execution_amount {
+            execution_amount = state.best_ask_amount;
+        }
 
-        // Self-trade prevention
-        let same_owner_lo = state.best_bid_owner_lo == state.best_ask_owner_lo;
-        let same_owner_hi = state.best_bid_owner_hi == state.best_ask_owner_hi;
-        let is_self_trade = same_owner_lo && same_owner_hi;
+        // Set result
+        let mut result = no_match();
+        result.matched = true;
+        result.maker_order_id = state.best_ask_id;
+        result.taker_order_id = state.best_bid_id;
+        result.execution_price = execution_price;
+        result.execution_amount = execution_amount;
+        result.maker_lo = state.best_ask_owner_lo;
+        result.maker_hi = state.best_ask_owner_hi;
+        result.taker_lo = state.best_bid_owner_lo;
+        result.taker_hi = state.best_bid_owner_hi;
+
+        // Update state - clear filled orders
+        let bid_remaining = state.best_bid_amount - execution_amount;
+        let ask_remaining = state.best_ask_amount - execution_amount;
+        result.maker_filled = ask_remaining == 0;
+        result.taker_filled = bid_remaining == 0;
+
+        if bid_remaining == 0 {
+            state.best_bid_price = 0;
+            state.best_bid_amount = 0;
+            state.best_bid_owner_lo = 0;
+            state.best_bid_owner_hi = 0;
+            state.best_bid_id = 0;
+        } else {
+            state.best_bid_amount = bid_remaining;
+        }
+
+        if ask_remaining == 0 {
+            state.best_ask_price = 18446744073709551615u64; // u64::MAX
+            state.best_ask_amount = 0;
+            state.best_ask_owner_lo = 0;
+            state.best_ask_owner_hi = 0;
+            state.best_ask_id = 0;
+        } else {
+            state.best_ask_amount = ask_remaining;
+        }
+
+        (state, result)
+    }
 
-        if has_match && has_liquidity && has_ask && !is_self_trade {
+    /// Match the best bid against the best ask
+    /// If best_bid.price >= best_ask.price, a match is found at the midpoint
+    fn match_best(state: OrderBookState) -> (OrderBookState, MatchResult) {
+        let mut next = state;
+        let mut result = no_match();
+
+        if is_crossed(state) {
             // Calculate execution price (midpoint)
             let execution_price = (state.best_bid_price + state.best_ask_price) / 2;
+            let (filled, fill) = fill_best(state, execution_price);
+            next = filled;
+            result = fill;
+        }
+
+        (next, result)
+    }
+
+    /// Match orders in the orderbook
+    /// If best_bid.price >= best_ask.price, a match is found
+    #[instruction]
+    pub fn match_book(
+        state_ctxt: Enc<Mxe, OrderBookState>,
+    ) -> (Enc<Mxe, OrderBookState>, MatchResult) {
+        let (state, result) = match_best(state_ctxt.to_arcis());
+
+        (state_ctxt.owner.from_arcis(state), result.reveal())
+    }
 
-            // Calculate execution amount (minimum of both)
-            let mut execution_amount = state.best_bid_amount;
-            if state.best_ask_amount < execution_amount {
-                execution_amount = state.best_ask_amount;
+    /// Reveal whether match_book would find a match, without touching the book
+    /// The book only keeps the best bid and ask, so at most one level can cross
+    /// and the bool is all there is to tell
+    #[instruction]
+    pub fn probe_cross(state_ctxt: Enc<Mxe, OrderBookState>) -> bool {
+        is_crossed(state_ctxt.to_arcis()).reveal()
+    }
+
+    /// Commitment to the terms of a sealed fill: SHA3-256 of price, amount and
+    /// salt, each little-endian
+    /// The salt is random and only handed to the counterparties with their copy
+    /// of the terms, so only they can open it
+    fn fill_commitment(terms: FillTerms) -> [u8; 32] {
+        let price = terms.price.to_le_bytes();
+        let amount = terms.amount.to_le_bytes();
+        let salt = terms.salt.to_le_bytes();
+
+        let mut preimage = [0u8; 32];
+        for i in 0..8 {
+            preimage[i] = price[i];
+            preimage[8 + i] = amount[i];
+        }
+        for i in 0..16 {
+            preimage[16 + i] = salt[i];
+        }
+
+        SHA3_256::new().digest(&preimage)
+    }
+
+    /// Match orders without revealing the fill terms or the parties
+    /// Only whether a match was found and a commitment to the terms are
+    /// revealed; price and amount stay encrypted under the MXE key until
+    /// settlement, the orders and their owners until a party opens the match
+    #[instruction]
+    pub fn match_book_sealed(
+        state_ctxt: Enc<Mxe, OrderBookState>,
+    ) -> (
+        Enc<Mxe, OrderBookState>,
+        SealedMatchResult,
+        Enc<Mxe, FillTerms>,
+        Enc<Mxe, MatchParties>,
+    ) {
+        let (state, result) = match_best(state_ctxt.to_arcis());
+
+        let terms = FillTerms {
+            price: result.execution_price,
+            amount: result.execution_amount,
+            salt: ArcisRNG::gen_integer_from_width(128),
+        };
+        let mut commitment = [0u8; 32];
+        if result.matched {
+            commitment = fill_commitment(terms);
+        }
+
+        let public = SealedMatchResult {
+            matched: result.matched,
+            fill_commitment: commitment,
+        };
+        let parties = MatchParties {
+            maker_order_id: result.maker_order_id,
+            taker_order_id: result.taker_order_id,
+            maker_lo: result.maker_lo,
+            maker_hi: result.maker_hi,
+            taker_lo: result.taker_lo,
+            taker_hi: result.taker_hi,
+            maker_filled: result.maker_filled,
+            taker_filled: result.taker_filled,
+        };
+
+        (
+            state_ctxt.owner.from_arcis(state),
+            public.reveal(),
+            state_ctxt.owner.from_arcis(terms),
+            state_ctxt.owner.from_arcis(parties),
+        )
+    }
+
+    /// Open the parties of a sealed match so it can be settled
+    /// They are revealed only when the claimer owns the maker or taker order,
+    /// or when `anyone` may open it; otherwise all that is revealed is the refusal
+    #[instruction]
+    pub fn open_sealed_match(
+        claimer_lo: u128,
+        claimer_hi: u128,
+        anyone: bool,
+        parties_ctxt: Enc<Mxe, MatchParties>,
+    ) -> (bool, MatchParties) {
+        let parties = parties_ctxt.to_arcis();
+
+        let is_maker = parties.maker_lo == claimer_lo && parties.maker_hi == claimer_hi;
+        let is_taker = parties.taker_lo == claimer_lo && parties.taker_hi == claimer_hi;
+        let allowed = anyone || is_maker || is_taker;
+
+        let mut opened = MatchParties {
+            maker_order_id: 0,
+            taker_order_id: 0,
+            maker_lo: 0,
+            maker_hi: 0,
+            taker_lo: 0,
+            taker_hi: 0,
+            maker_filled: false,
+            taker_filled: false,
+        };
+        if allowed {
+            opened = parties;
+        }
+
+        (allowed.reveal(), opened.reveal())
+    }
+
+    /// Hand the terms of a sealed fill to both counterparties
+    /// Each party gets price, amount and the commitment salt under its own key,
+    /// so it can check the terms against the public commitment; only the base and
+    /// quote amounts the settlement moves are revealed, and their ratio is the
+    /// price, so the terms stay sealed only until settlement
+    #[instruction]
+    pub fn reveal_fill(
+        maker: Shared,
+        taker: Shared,
+        fill_ctxt: Enc<Mxe, FillTerms>,
+    ) -> (Enc<Shared, FillTerms>, Enc<Shared, FillTerms>, FillDeltas) {
+        let terms = fill_ctxt.to_arcis();
+
+        let deltas = FillDeltas {
+            base_amount: terms.amount,
+            quote_amount: order_lock(true, terms.price, terms.amount),
+        };
+
+        (
+            maker.from_arcis(terms),
+            taker.from_arcis(terms),
+            deltas.reveal(),
+        )
+    }
+
+    /// Collect the orders of one position from the book
+    fn owned_orders(state: OrderBookState, owner_lo: u128, owner_hi: u128) -> OwnedOrders {
+        let mut owned = OwnedOrders {
+            bid_order_id: 0,
+            bid_price: 0,
+            bid_amount: 0,
+            ask_order_id: 0,
+            ask_price: 0,
+            ask_amount: 0,
+        };
+
+        let owns_bid = state.best_bid_amount > 0
+            && state.best_bid_owner_lo == owner_lo
+            && state.best_bid_owner_hi == owner_hi;
+        if owns_bid {
+            owned.bid_order_id = state.best_bid_id;
+            owned.bid_price = state.best_bid_price;
+            owned.bid_amount = state.best_bid_amount;
+        }
+
+        let owns_ask = state.best_ask_amount > 0
+            && state.best_ask_owner_lo == owner_lo
+            && state.best_ask_owner_hi == owner_hi;
+        if owns_ask {
+            owned.ask_order_id = state.best_ask_id;
+            owned.ask_price = state.best_ask_price;
+            owned.ask_amount = state.best_ask_amount;
+        }
+
+        owned
+    }
+
+    /// Re-encrypt a position's resting orders under its auditor's key
+    /// Nothing is revealed; only the auditor can read the result
+    #[instruction]
+    pub fn disclose_orders(
+        auditor: Shared,
+        owner_lo: u128,
+        owner_hi: u128,
+        state_ctxt: Enc<Mxe, OrderBookState>,
+    ) -> Enc<Shared, OwnedOrders> {
+        auditor.from_arcis(owned_orders(state_ctxt.to_arcis(), owner_lo, owner_hi))
+    }
+
+    /// Return a position's live orders, with what is left of them after fills,
+    /// encrypted to the caller's key
+    #[instruction]
+    pub fn query_my_orders(
+        client: Shared,
+        owner_lo: u128,
+        owner_hi: u128,
+        state_ctxt: Enc<Mxe, OrderBookState>,
+    ) -> Enc<Shared, OwnedOrders> {
+        client.from_arcis(owned_orders(state_ctxt.to_arcis(), owner_lo, owner_hi))
+    }
+
+    /// Re-encrypt the terms of a sealed fill under an auditor's key
+    #[instruction]
+    pub fn disclose_fill(
+        auditor: Shared,
+        fill_ctxt: Enc<Mxe, FillTerms>,
+    ) -> Enc<Shared, FillTerms> {
+        auditor.from_arcis(fill_ctxt.to_arcis())
+    }
+
+    /// The orders the book still holds: its best bid and best ask
+    fn book_orders(state: OrderBookState) -> ClearedBook {
+        ClearedBook {
+            bid_order_id: state.best_bid_id,
+            bid_owner_lo: state.best_bid_owner_lo,
+            bid_owner_hi: state.best_bid_owner_hi,
+            ask_order_id: state.best_ask_id,
+            ask_owner_lo: state.best_ask_owner_lo,
+            ask_owner_hi: state.best_ask_owner_hi,
+        }
+    }
+
+    /// Uncross the opening auction
+    /// Executes the crossing volume once at a single clearing price. The price
+    /// leans toward the side with excess volume: a buy imbalance clears at the
+    /// bid, a sell imbalance at the ask, and a balanced book at the midpoint.
+    /// Also reveals the orders left in the book: every other order placed so far
+    /// was displaced or never improved on the best, and can release its lock
+    #[instruction]
+    pub fn uncross_book(
+        state_ctxt: Enc<Mxe, OrderBookState>,
+    ) -> (Enc<Mxe, OrderBookState>, MatchResult, ClearedBook) {
+        let state = state_ctxt.to_arcis();
+        let mut next = state;
+        let mut result = no_match();
+
+        if is_crossed(state) {
+            // Clearing price from the volume imbalance
+            let mut clearing_price = (state.best_bid_price + state.best_ask_price) / 2;
+            if state.best_bid_amount > state.best_ask_amount {
+                clearing_price = state.best_bid_price;
+            }
+            if state.best_ask_amount > state.best_bid_amount {
+                clearing_price = state.best_ask_price;
             }
 
-            // Set result
-            result.matched = true;
-            result.maker_order_id = state.best_ask_id;
-            result.taker_order_id = state.best_bid_id;
-            result.execution_price = execution_price;
-            result.execution_amount = execution_amount;
-            result.maker_lo = state.best_ask_owner_lo;
-            result.maker_hi = state.best_ask_owner_hi;
-            result.taker_lo = state.best_bid_owner_lo;
-            result.taker_hi = state.best_bid_owner_hi;
-
-            // Update state - clear filled orders
-            let bid_remaining = state.best_bid_amount - execution_amount;
-            let ask_remaining = state.best_ask_amount - execution_amount;
-
-            if bid_remaining == 0 {
-                state.best_bid_price = 0;
-                state.best_bid_amount = 0;
-                state.best_bid_owner_lo = 0;
-                state.best_bid_owner_hi = 0;
-                state.best_bid_id = 0;
-            } else {
-                state.best_bid_amount = bid_remaining;
+            let (filled, fill) = fill_best(state, clearing_price);
+            next = filled;
+            result = fill;
+        }
+
+        let kept = book_orders(next);
+
+        (state_ctxt.owner.from_arcis(next), result.reveal(), kept.reveal())
+    }
+
+    /// Clear the orderbook for a delisted market
+    /// Reveals the remaining order ids and owners so their locks can be
+    /// released, and resets the state to an empty book
+    #[instruction]
+    pub fn clear_book(
+        state_ctxt: Enc<Mxe, OrderBookState>,
+    ) -> (Enc<Mxe, OrderBookState>, ClearedBook) {
+        let mut state = state_ctxt.to_arcis();
+
+        let cleared = book_orders(state);
+
+        state.best_bid_price = 0;
+        state.best_bid_amount = 0;
+        state.best_bid_owner_lo = 0;
+        state.best_bid_owner_hi = 0;
+        state.best_bid_id = 0;
+
+        state.best_ask_price = 18446744073709551615u64; // u64::MAX
+        state.best_ask_amount = 0;
+        state.best_ask_owner_lo = 0;
+        state.best_ask_owner_hi = 0;
+        state.best_ask_id = 0;
+
+        state.order_count = 0;
+
+        (state_ctxt.owner.from_arcis(state), cleared.reveal())
+    }
+
+    /// Create an empty confidential balance for a position
+    #[instruction]
+    pub fn init_balance(mxe: Mxe) -> Enc<Mxe, Balances> {
+        let balance = Balances {
+            base_available: 0,
+            base_locked: 0,
+            quote_available: 0,
+            quote_locked: 0,
+        };
+
+        mxe.from_arcis(balance)
+    }
+
+    /// Credit deposits that arrived in the vaults
+    /// Deposit amounts are public at the vault boundary
+    #[instruction]
+    pub fn credit_balance(
+        base_amount: u64,
+        quote_amount: u64,
+        balance_ctxt: Enc<Mxe, Balances>,
+    ) -> Enc<Mxe, Balances> {
+        let mut balance = balance_ctxt.to_arcis();
+        balance.base_available = balance.base_available + base_amount;
+        balance.quote_available = balance.quote_available + quote_amount;
+
+        balance_ctxt.owner.from_arcis(balance)
+    }
+
+    /// Debit a withdrawal from the available balance
+    /// Reveals only whether the balance was sufficient
+    #[instruction]
+    pub fn debit_balance(
+        amount: u64,
+        is_base: bool,
+        balance_ctxt: Enc<Mxe, Balances>,
+    ) -> (Enc<Mxe, Balances>, bool) {
+        let mut balance = balance_ctxt.to_arcis();
+        let mut ok = false;
+
+        if is_base {
+            if balance.base_available >= amount {
+                balance.base_available = balance.base_available - amount;
+                ok = true;
+            }
+        } else {
+            if balance.quote_available >= amount {
+                balance.quote_available = balance.quote_available - amount;
+                ok = true;
             }
+        }
 
-            if ask_remaining == 0 {
-                state.best_ask_price = 18446744073709551615u64; // u64::MAX
-                state.best_ask_amount = 0;
-                state.best_ask_owner_lo = 0;
-                state.best_ask_owner_hi = 0;
-                state.best_ask_id = 0;
+        (balance_ctxt.owner.from_arcis(balance), ok.reveal())
+    }
+
+    /// Amount an order locks: quote (amount * price / 10^6) for buys, base for sells
+    fn order_lock(side: bool, price: u64, amount: u64) -> u64 {
+        let quote = ((amount as u128) * (price as u128) / 1_000_000u128) as u64;
+        let mut lock = amount;
+        if side {
+            lock = quote;
+        }
+        lock
+    }
+
+    /// Lock an order's funds in the confidential balance and add it to the book
+    /// The order is rejected, without touching the book, if the balance can't cover it
+    #[instruction]
+    pub fn add_order_locked(
+        order: Enc<Shared, OrderInput>,
+        owner_lo: u128,
+        owner_hi: u128,
+        order_id: u64,
+        side: bool,
+        balance_ctxt: Enc<Mxe, Balances>,
+        state_ctxt: Enc<Mxe, OrderBookState>,
+    ) -> (Enc<Mxe, Balances>, Enc<Mxe, u64>, Enc<Mxe, OrderBookState>, bool) {
+        let input = order.to_arcis();
+        let mut balance = balance_ctxt.to_arcis();
+        let mut state = state_ctxt.to_arcis();
+
+        let lock = order_lock(side, input.price, input.amount);
+        let mut accepted = false;
+        let mut locked: u64 = 0;
+
+        if lock > 0 {
+            if side {
+                if balance.quote_available >= lock {
+                    balance.quote_available = balance.quote_available - lock;
+                    balance.quote_locked = balance.quote_locked + lock;
+                    accepted = true;
+                }
             } else {
-                state.best_ask_amount = ask_remaining;
+                if balance.base_available >= lock {
+                    balance.base_available = balance.base_available - lock;
+                    balance.base_locked = balance.base_locked + lock;
+                    accepted = true;
+                }
             }
         }
 
-        (state_ctxt.owner.from_arcis(state), result.reveal())
+        if accepted {
+            locked = lock;
+            state = insert_order(
+                state,
+                Order {
+                    price: input.price,
+                    amount: input.amount,
+                    owner_lo,
+                    owner_hi,
+                    order_id,
+                    side,
+                },
+            );
+        }
+
+        (
+            balance_ctxt.owner.from_arcis(balance),
+            balance_ctxt.owner.from_arcis(locked),
+            state_ctxt.owner.from_arcis(state),
+            accepted.reveal(),
+        )
+    }
+
+    /// Remove an order from the book and release its remaining lock
+    /// The lock is released even if the order is no longer in the book
+    #[instruction]
+    pub fn remove_order_unlocked(
+        client: Shared,
+        order_id: u64,
+        owner_lo: u128,
+        owner_hi: u128,
+        side: bool,
+        balance_ctxt: Enc<Mxe, Balances>,
+        lock_ctxt: Enc<Mxe, u64>,
+        state_ctxt: Enc<Mxe, OrderBookState>,
+    ) -> (Enc<Mxe, OrderBookState>, Enc<Mxe, Balances>, bool, Enc<Shared, u64>) {
+        let mut balance = balance_ctxt.to_arcis();
+        let mut lock = lock_ctxt.to_arcis();
+        let (state, removed, remaining) =
+            take_order(state_ctxt.to_arcis(), order_id, owner_lo, owner_hi);
+
+        // Never release more than the side still has locked, so the balance
+        // can't wrap around
+        if side {
+            if balance.quote_locked < lock {
+                lock = balance.quote_locked;
+            }
+            balance.quote_locked = balance.quote_locked - lock;
+            balance.quote_available = balance.quote_available + lock;
+        } else {
+            if balance.base_locked < lock {
+                lock = balance.base_locked;
+            }
+            balance.base_locked = balance.base_locked - lock;
+            balance.base_available = balance.base_available + lock;
+        }
+
+        (
+            state_ctxt.owner.from_arcis(state),
+            balance_ctxt.owner.from_arcis(balance),
+            removed.reveal(),
+            client.from_arcis(remaining),
+        )
+    }
+
+    /// Apply a revealed fill to the confidential balances of both parties
+    /// Each side pays from its order lock first, then from its available balance;
+    /// nothing changes unless both sides can pay.
+    /// An order whose last fill this is releases what is left of its lock either way
+    #[instruction]
+    pub fn settle_fill(
+        buyer_ctxt: Enc<Mxe, Balances>,
+        buyer_lock_ctxt: Enc<Mxe, u64>,
+        seller_ctxt: Enc<Mxe, Balances>,
+        seller_lock_ctxt: Enc<Mxe, u64>,
+        base_amount: u64,
+        quote_amount: u64,
+        fee: u64,
+        release_buyer_lock: bool,
+        release_seller_lock: bool,
+    ) -> (Enc<Mxe, Balances>, Enc<Mxe, u64>, Enc<Mxe, Balances>, Enc<Mxe, u64>, bool) {
+        let mut buyer = buyer_ctxt.to_arcis();
+        let mut buyer_lock = buyer_lock_ctxt.to_arcis();
+        let mut seller = seller_ctxt.to_arcis();
+        let mut seller_lock = seller_lock_ctxt.to_arcis();
+
+        // Buyer pays quote
+        let mut buyer_from_lock = quote_amount;
+        if buyer_lock < buyer_from_lock {
+            buyer_from_lock = buyer_lock;
+        }
+        let buyer_from_available = quote_amount - buyer_from_lock;
+
+        // Seller pays base
+        let mut seller_from_lock = base_amount;
+        if seller_lock < seller_from_lock {
+            seller_from_lock = seller_lock;
+        }
+        let seller_from_available = base_amount - seller_from_lock;
+
+        let settled = buyer.quote_available >= buyer_from_available
+            && seller.base_available >= seller_from_available;
+
+        if settled {
+            buyer_lock = buyer_lock - buyer_from_lock;
+            buyer.quote_locked = buyer.quote_locked - buyer_from_lock;
+            buyer.quote_available = buyer.quote_available - buyer_from_available;
+            buyer.base_available = buyer.base_available + base_amount;
+
+            seller_lock = seller_lock - seller_from_lock;
+            seller.base_locked = seller.base_locked - seller_from_lock;
+            seller.base_available = seller.base_available - seller_from_available;
+            seller.quote_available = seller.quote_available + (quote_amount - fee);
+        }
+
+        if release_buyer_lock {
+            buyer.quote_locked = buyer.quote_locked - buyer_lock;
+            buyer.quote_available = buyer.quote_available + buyer_lock;
+            buyer_lock = 0;
+        }
+        if release_seller_lock {
+            seller.base_locked = seller.base_locked - seller_lock;
+            seller.base_available = seller.base_available + seller_lock;
+            seller_lock = 0;
+        }
+
+        (
+            buyer_ctxt.owner.from_arcis(buyer),
+            buyer_lock_ctxt.owner.from_arcis(buyer_lock),
+            seller_ctxt.owner.from_arcis(seller),
+            seller_lock_ctxt.owner.from_arcis(seller_lock),
+            settled.reveal(),
+        )
+    }
+
+    /// Reveal whether a confidential balance holds nothing, so its position can close
+    #[instruction]
+    pub fn balance_is_empty(balance_ctxt: Enc<Mxe, Balances>) -> bool {
+        let balance = balance_ctxt.to_arcis();
+        let empty = balance.base_available == 0
+            && balance.base_locked == 0
+            && balance.quote_available == 0
+            && balance.quote_locked == 0;
+
+        empty.reveal()
     }
 }
@@ -55,4 +55,148 @@ pub enum DuskError {
 
     #[msg("Arcium computation failed")]
     ArciumComputationFailed,
+
+    #[msg("Market is in its opening auction")]
+    MarketInAuction,
+
+    #[msg("Market is not in an opening auction")]
+    MarketNotInAuction,
+
+    #[msg("Market is not paused")]
+    MarketNotPaused,
+
+    #[msg("Keeper position must be a separate position on this market")]
+    InvalidKeeperPosition,
+
+    #[msg("Trade has not been settled")]
+    TradeNotSettled,
+
+    #[msg("Settlement retention period has not elapsed")]
+    RetentionPeriodActive,
+
+    #[msg("Position still holds funds or open orders")]
+    PositionNotEmpty,
+
+    #[msg("Mint has a token extension that is not supported")]
+    UnsupportedMintExtension,
+
+    #[msg("Market has no native SOL side")]
+    NotNativeMarket,
+
+    #[msg("Withdrawal recipient is not on the position allowlist")]
+    RecipientNotAllowed,
+
+    #[msg("Withdrawal allowlist is full")]
+    AllowlistFull,
+
+    #[msg("Protocol fee share cannot exceed 100% of the trading fee")]
+    InvalidFeeShare,
+
+    #[msg("Market creation is restricted to the protocol admin")]
+    MarketCreationRestricted,
+
+    #[msg("Market is delisted")]
+    MarketDelisted,
+
+    #[msg("Market is not in the expected delisting phase")]
+    InvalidDelistingPhase,
+
+    #[msg("Market still has open positions, settlements or uncollected protocol fees")]
+    MarketNotEmpty,
+
+    #[msg("Order book state has not been initialized")]
+    OrderBookNotInitialized,
+
+    #[msg("Order book state is already initialized")]
+    OrderBookAlreadyInitialized,
+
+    #[msg("Computation offset does not match the next offset for this market")]
+    InvalidComputationOffset,
+
+    #[msg("The MPC computation was aborted or its output failed verification")]
+    AbortedComputation,
+
+    #[msg("Arcium cluster not set on the MXE account")]
+    ClusterNotSet,
+
+    #[msg("Callback is for a different computation than the one queued")]
+    ComputationMismatch,
+
+    #[msg("Order has no pending computation past its deadline")]
+    ComputationNotStuck,
+
+    #[msg("Market keeps balances confidential; use the confidential instructions")]
+    ConfidentialMarket,
+
+    #[msg("Market does not keep balances confidential")]
+    NotConfidentialMarket,
+
+    #[msg("Confidential balance has not been initialized")]
+    ConfidentialBalanceNotInitialized,
+
+    #[msg("Confidential balance is already initialized")]
+    ConfidentialBalanceAlreadyInitialized,
+
+    #[msg("A computation on this confidential balance is still pending")]
+    ConfidentialBalanceBusy,
+
+    #[msg("Deposits are waiting to be credited to this confidential balance")]
+    ConfidentialCreditPending,
+
+    #[msg("Market seals fill terms; use the sealed-fill instructions")]
+    SealedFillMarket,
+
+    #[msg("Market does not seal fill terms")]
+    NotSealedFillMarket,
+
+    #[msg("A computation on this settlement is still pending")]
+    SettlementBusy,
+
+    #[msg("Position has no auditor key registered")]
+    NoAuditorKey,
+
+    #[msg("Remaining accounts must be (settlement, rent_receiver) pairs")]
+    InvalidSettlementAccountList,
+
+    #[msg("Settlement account must be writable to be closed")]
+    SettlementNotWritable,
+
+    #[msg("A computation on the order book is still in flight")]
+    OrderBookBusy,
+
+    #[msg("Delegate must be a key other than the owner")]
+    InvalidDelegate,
+
+    #[msg("Delegate expiry must be in the future, or 0 for no expiry")]
+    InvalidDelegateExpiry,
+
+    #[msg("Cannot transfer a balance to the same position")]
+    SameAccountTransfer,
+
+    #[msg("Allowlist recipient must be a key other than the owner")]
+    InvalidAllowlistRecipient,
+
+    #[msg("Recipient is already on the withdrawal allowlist")]
+    RecipientAlreadyAllowlisted,
+
+    #[msg("Withdrawal allowlist is not enabled")]
+    AllowlistNotEnabled,
+
+    #[msg("The result of this computation has already been applied")]
+    ComputationAlreadyApplied,
+
+    #[msg("The parties of the pending sealed match have not been opened yet")]
+    SealedMatchNotOpened,
+
+    #[msg("No sealed match is waiting to be opened")]
+    NoSealedMatch,
+
+    #[msg("An open_sealed_match computation is still in flight")]
+    SealedMatchOpening,
+
+    #[msg("Keeper reward exceeds the share of a fill's fee available to one crank")]
+    KeeperRewardTooHigh,
+
+    #[msg("The order may still be in the book; cancel it instead")]
+    OrderStillInBook,
 }
@@ -1,5 +1,7 @@
 use anchor_lang::prelude::*;
 
+use crate::state::{DisclosureKind, KeeperCrank, MarketStatus, FILL_TERMS_FIELDS};
+
 /// Emitted when a new market is created
 #[event]
 pub struct MarketCreated {
@@ -25,6 +27,8 @@ pub struct Deposited {
 pub struct Withdrawn {
     pub market: Pubkey,
     pub user: Pubkey,
+    /// Wallet owning the destination token account
+    pub recipient: Pubkey,
     pub amount: u64,
     pub is_base: bool,
 }
@@ -37,6 +41,8 @@ pub struct OrderPlaced {
     pub user: Pubkey,
     pub order_id: u64,
     pub is_buy: bool,
+    /// Offset of the add_order computation
+    pub computation_offset: u64,
     /// Timestamp when order was submitted
     pub timestamp: i64,
 }
@@ -47,6 +53,9 @@ pub struct OrderCancelled {
     pub market: Pubkey,
     pub user: Pubkey,
     pub order_id: u64,
+    /// Unfilled amount of the order, encrypted to the key passed to `cancel_order`
+    pub remaining_nonce: u128,
+    pub encrypted_remaining: [u8; 32],
 }
 
 /// Emitted when orders are matched
@@ -54,7 +63,9 @@ pub struct OrderCancelled {
 #[event]
 pub struct OrdersMatched {
     pub market: Pubkey,
+    /// Maker position (sub-account)
     pub maker: Pubkey,
+    /// Taker position (sub-account)
     pub taker: Pubkey,
     pub maker_order_id: u64,
     pub taker_order_id: u64,
@@ -75,3 +86,368 @@ pub struct TradeSettled {
     pub base_transferred: u64,
     pub quote_transferred: u64,
 }
+
+/// Emitted when a settlement is dropped because one of its orders was reclaimed
+#[event]
+pub struct SettlementVoided {
+    pub market: Pubkey,
+    pub settlement: Pubkey,
+}
+
+/// Emitted when a market is paused or resumed
+#[event]
+pub struct MarketStatusChanged {
+    pub market: Pubkey,
+    pub status: MarketStatus,
+}
+
+/// Emitted when the opening auction is uncrossed and continuous trading begins
+#[event]
+pub struct AuctionUncrossed {
+    pub market: Pubkey,
+    pub matched: bool,
+    /// Revealed clearing price (scaled by 10^6), 0 if nothing crossed
+    pub clearing_price: u64,
+    /// Revealed clearing amount (base tokens), 0 if nothing crossed
+    pub clearing_amount: u64,
+    /// Orders left in the book, 0 where that side is empty; any other order
+    /// placed before the uncross can release its lock
+    pub bid_order_id: u64,
+    pub bid_position: Pubkey,
+    pub ask_order_id: u64,
+    pub ask_position: Pubkey,
+    pub timestamp: i64,
+}
+
+/// Emitted when a keeper is paid for a successful crank
+#[event]
+pub struct KeeperRewarded {
+    pub market: Pubkey,
+    pub keeper: Pubkey,
+    pub crank: KeeperCrank,
+    /// Quote tokens credited to the keeper's position
+    pub amount: u64,
+}
+
+/// Emitted when a crank that would earn a keeper reward passes no keeper position
+/// The reward stays in the market's accrued fees
+#[event]
+pub struct KeeperRewardSkipped {
+    pub market: Pubkey,
+    pub crank: KeeperCrank,
+}
+
+/// Emitted when a settled trade account is closed and its rent reclaimed
+#[event]
+pub struct SettlementClosed {
+    pub market: Pubkey,
+    pub settlement: Pubkey,
+    pub rent_receiver: Pubkey,
+}
+
+/// Emitted when an empty user position is closed
+#[event]
+pub struct PositionClosed {
+    pub market: Pubkey,
+    pub user: Pubkey,
+}
+
+/// Emitted when an owner authorizes a trading delegate
+#[event]
+pub struct DelegateSet {
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub delegate: Pubkey,
+    /// Unix timestamp when the delegate expires (0 = no expiry)
+    pub expires_at: i64,
+}
+
+/// Emitted when an owner revokes their trading delegate
+#[event]
+pub struct DelegateRevoked {
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub delegate: Pubkey,
+}
+
+/// Emitted when funds move between two sub-accounts of the same owner
+#[event]
+pub struct SubAccountTransfer {
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub from_sub_account: u16,
+    pub to_sub_account: u16,
+    pub amount: u64,
+    pub is_base: bool,
+}
+
+/// Emitted when available funds move between two positions on a market
+#[event]
+pub struct BalanceTransferred {
+    pub market: Pubkey,
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub from_owner: Pubkey,
+    pub to_owner: Pubkey,
+    pub amount: u64,
+    pub is_base: bool,
+}
+
+/// Emitted when a withdrawal recipient is added to a position's allowlist
+#[event]
+pub struct WithdrawRecipientAdded {
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub recipient: Pubkey,
+    pub active_at: i64,
+}
+
+/// Emitted when a withdrawal recipient is removed from a position's allowlist
+#[event]
+pub struct WithdrawRecipientRemoved {
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub recipient: Pubkey,
+}
+
+/// Emitted when a position's withdrawal allowlist is enabled or a disable is requested
+#[event]
+pub struct WithdrawAllowlistUpdated {
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub enabled: bool,
+    pub effective_at: i64,
+}
+
+/// Emitted when the global exchange config is created or updated
+#[event]
+pub struct ExchangeConfigUpdated {
+    pub admin: Pubkey,
+    pub permissionless_markets: bool,
+    pub protocol_fee_share_bps: u16,
+}
+
+/// Emitted when the protocol share of a market's fees is collected
+#[event]
+pub struct ProtocolFeesCollected {
+    pub market: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+}
+
+/// Emitted when a delisted market's book is cleared
+/// Order ids of 0 mean that side of the book was empty
+#[event]
+pub struct BookCleared {
+    pub market: Pubkey,
+    pub bid_order_id: u64,
+    pub bid_position: Pubkey,
+    pub ask_order_id: u64,
+    pub ask_position: Pubkey,
+}
+
+/// Emitted when the lock of an order that left the book is released
+#[event]
+pub struct LocksReleased {
+    pub market: Pubkey,
+    pub position: Pubkey,
+    pub order_id: u64,
+    pub base_released: u64,
+    pub quote_released: u64,
+}
+
+/// Emitted when a delisted market and its vaults are closed
+#[event]
+pub struct MarketClosed {
+    pub market: Pubkey,
+    pub market_id: u64,
+    pub base_swept: u64,
+    pub quote_swept: u64,
+}
+
+/// Emitted by `audit_market` when a vault holds less than it owes, or lock
+/// totals don't add up
+#[event]
+pub struct MarketAuditDiscrepancy {
+    pub market: Pubkey,
+    pub base_vault_balance: u64,
+    /// Total base deposits
+    pub base_expected: u64,
+    pub quote_vault_balance: u64,
+    /// Total quote deposits plus accrued keeper and protocol fees
+    pub quote_expected: u64,
+    pub base_locked: u64,
+    pub quote_locked: u64,
+}
+
+/// Emitted by `audit_market` when a vault holds more than it owes
+/// Tokens sent straight to a vault are not owed to anyone, so this is no discrepancy
+#[event]
+pub struct MarketAuditSurplus {
+    pub market: Pubkey,
+    /// Base vault balance above total base deposits
+    pub base_surplus: u64,
+    /// Quote vault balance above total quote deposits and accrued fees
+    pub quote_surplus: u64,
+}
+
+/// Emitted when an owner reclaims the lock of an order whose add never called back
+#[event]
+pub struct StuckComputationReclaimed {
+    pub market: Pubkey,
+    pub user: Pubkey,
+    pub order_id: u64,
+    pub released: u64,
+}
+
+/// Emitted when a market switches confidential balances on or off
+#[event]
+pub struct ConfidentialBalancesSet {
+    pub market: Pubkey,
+    pub enabled: bool,
+}
+
+/// Emitted when a confidential withdrawal exceeds the encrypted available balance
+#[event]
+pub struct ConfidentialWithdrawalRejected {
+    pub market: Pubkey,
+    pub user: Pubkey,
+    pub amount: u64,
+    pub is_base: bool,
+}
+
+/// Emitted when a confidential order can't be covered by the encrypted available balance
+#[event]
+pub struct ConfidentialOrderRejected {
+    pub market: Pubkey,
+    pub user: Pubkey,
+    pub order_id: u64,
+}
+
+/// Emitted when a side-hidden order is placed
+/// Unlike `OrderPlaced`, the side is not included
+#[event]
+pub struct HiddenOrderPlaced {
+    pub market: Pubkey,
+    pub user: Pubkey,
+    pub order_id: u64,
+    /// Offset of the add_hidden_order computation
+    pub computation_offset: u64,
+    pub timestamp: i64,
+}
+
+/// Emitted when a side-hidden order is larger than the lock of its side
+#[event]
+pub struct HiddenOrderRejected {
+    pub market: Pubkey,
+    pub user: Pubkey,
+    pub order_id: u64,
+    pub base_released: u64,
+    pub quote_released: u64,
+}
+
+/// Emitted when a market switches sealed fills on or off
+#[event]
+pub struct SealedFillsSet {
+    pub market: Pubkey,
+    pub enabled: bool,
+}
+
+/// Emitted when a match is found on a sealed-fill market
+/// Price and amount stay encrypted; only a commitment to them is published
+/// The orders and their owners stay encrypted too, see `SealedMatchOpened`
+#[event]
+pub struct SealedOrdersMatched {
+    pub market: Pubkey,
+    /// SHA3-256(price || amount || salt), opened by the salt each counterparty gets at settlement
+    pub fill_commitment: [u8; 32],
+    pub timestamp: i64,
+}
+
+/// Emitted when a party opens a sealed match, or anyone after the open delay
+/// From here on the match is public, as the settlement that moves the two
+/// positions' balances would make it anyway
+#[event]
+pub struct SealedMatchOpened {
+    pub market: Pubkey,
+    /// Maker position (sub-account)
+    pub maker: Pubkey,
+    /// Taker position (sub-account)
+    pub taker: Pubkey,
+    pub maker_order_id: u64,
+    pub taker_order_id: u64,
+}
+
+/// Emitted when a sealed fill is settled
+/// Each counterparty gets price and amount encrypted to the key of its order
+#[event]
+pub struct SealedTradeSettled {
+    pub market: Pubkey,
+    pub settlement: Pubkey,
+    pub maker: Pubkey,
+    pub taker: Pubkey,
+    pub fill_commitment: [u8; 32],
+    /// Net base moved from seller to buyer
+    pub base_transferred: u64,
+    /// Net quote moved from buyer to seller, before the fee
+    pub quote_transferred: u64,
+    pub maker_fill_nonce: u128,
+    /// FillTerms (price, amount, salt) encrypted to the maker order's client key
+    pub maker_fill: [[u8; 32]; FILL_TERMS_FIELDS],
+    pub taker_fill_nonce: u128,
+    /// FillTerms (price, amount, salt) encrypted to the taker order's client key
+    pub taker_fill: [[u8; 32]; FILL_TERMS_FIELDS],
+}
+
+/// Emitted when a position owner registers or removes its auditor key
+#[event]
+pub struct AuditorKeySet {
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    /// All zeros when the auditor was removed
+    pub auditor_pubkey: [u8; 32],
+}
+
+/// Emitted when a position owner requests a disclosure to its auditor
+#[event]
+pub struct DisclosureRequested {
+    pub market: Pubkey,
+    pub position: Pubkey,
+    pub record: Pubkey,
+    pub kind: DisclosureKind,
+    /// Settlement whose fill is disclosed (Pubkey::default() for orders)
+    pub settlement: Pubkey,
+    pub auditor_pubkey: [u8; 32],
+    pub computation_offset: u64,
+}
+
+/// Emitted when the MXE has written the disclosed data to its record
+#[event]
+pub struct DisclosureCompleted {
+    pub market: Pubkey,
+    pub position: Pubkey,
+    pub record: Pubkey,
+}
+
+/// Emitted with the result of `query_my_orders`
+/// Only the holder of the query's client key can read the orders
+#[event]
+pub struct MyOrdersQueried {
+    pub market: Pubkey,
+    pub position: Pubkey,
+    pub nonce: u128,
+    /// OwnedOrders fields: bid order id, price and remaining amount, then the same for the ask
+    pub orders: [[u8; 32]; 6],
+}
+
+/// Emitted when a probe_cross computation completes
+/// Keepers call match_orders when `crossed` is set and the book state nonce is unchanged
+#[event]
+pub struct BookProbed {
+    pub market: Pubkey,
+    pub crossed: bool,
+    /// Book state nonce the probe read
+    pub state_nonce: u128,
+    pub timestamp: i64,
+}
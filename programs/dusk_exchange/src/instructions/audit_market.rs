@@ -0,0 +1,85 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::TokenAccount;
+
+use crate::state::Market;
+use crate::events::{MarketAuditDiscrepancy, MarketAuditSurplus};
+use crate::errors::DuskError;
+
+/// Check that a market's vaults hold what its positions and fees are owed
+#[derive(Accounts)]
+pub struct AuditMarket<'info> {
+    /// Anyone can audit a market
+    pub caller: Signer<'info>,
+
+    pub market: Account<'info, Market>,
+
+    #[account(address = market.base_vault @ DuskError::InvalidMarketConfig)]
+    pub base_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = market.quote_vault @ DuskError::InvalidMarketConfig)]
+    pub quote_vault: InterfaceAccount<'info, TokenAccount>,
+}
+
+pub fn handler(ctx: Context<AuditMarket>) -> Result<()> {
+    let market = &ctx.accounts.market;
+
+    // Base is only ever owed to positions; quote also backs fees that have
+    // not been paid out yet
+    let base_expected = market.base_deposited;
+    let quote_expected = market
+        .quote_deposited
+        .checked_add(market.fees_accrued)
+        .and_then(|total| total.checked_add(market.protocol_fees_accrued))
+        .ok_or(DuskError::MathOverflow)?;
+
+    let base_balance = ctx.accounts.base_vault.amount;
+    let quote_balance = ctx.accounts.quote_vault.amount;
+
+    // Locks can never exceed what is deposited
+    let locks_consistent = market.base_locked <= market.base_deposited
+        && market.quote_locked <= market.quote_deposited;
+
+    // Anyone can send tokens straight to a vault, so only a shortfall is a
+    // discrepancy; a surplus is reported on its own
+    let base_surplus = base_balance.saturating_sub(base_expected);
+    let quote_surplus = quote_balance.saturating_sub(quote_expected);
+    if base_surplus > 0 || quote_surplus > 0 {
+        emit!(MarketAuditSurplus {
+            market: market.key(),
+            base_surplus,
+            quote_surplus,
+        });
+
+        msg!(
+            "Audit surplus on market {}: base {}, quote {}",
+            market.market_id,
+            base_surplus,
+            quote_surplus
+        );
+    }
+
+    if base_balance < base_expected || quote_balance < quote_expected || !locks_consistent {
+        emit!(MarketAuditDiscrepancy {
+            market: market.key(),
+            base_vault_balance: base_balance,
+            base_expected,
+            quote_vault_balance: quote_balance,
+            quote_expected,
+            base_locked: market.base_locked,
+            quote_locked: market.quote_locked,
+        });
+
+        msg!(
+            "Audit discrepancy on market {}: base {} (expected {}), quote {} (expected {})",
+            market.market_id,
+            base_balance,
+            base_expected,
+            quote_balance,
+            quote_expected
+        );
+    } else {
+        msg!("Audit passed on market {}", market.market_id);
+    }
+
+    Ok(())
+}
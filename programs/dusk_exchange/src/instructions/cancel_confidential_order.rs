@@ -0,0 +1,380 @@
+use anchor_lang::prelude::*;
+use arcium_anchor::prelude::*;
+use arcium_client::idl::arcium::{types::CallbackAccount, ID_CONST};
+
+use crate::ID;
+use crate::state::{ConfidentialBalance, Market, OpenOrder, OrderBook, OrderStatus, UserPosition};
+use crate::events::OrderCancelled;
+use crate::errors::DuskError;
+use crate::instructions::place_order::SignerAccount;
+use crate::{RemoveOrderUnlockedOutput, RemoveOrderUnlockedOutputStruct0};
+
+/// Computation definition offset for remove_order_unlocked
+pub const COMP_DEF_OFFSET_REMOVE_ORDER_UNLOCKED: u8 = 10;
+
+/// Cancel a confidential order and release its encrypted lock
+/// Also accepted once the book is cleared, since only the circuit can release the lock
+#[queue_computation_accounts("remove_order_unlocked", user)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64, order_id: u64)]
+pub struct CancelConfidentialOrder<'info> {
+    /// Position owner or its trading delegate
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// Encrypted order book of this market
+    #[account(
+        mut,
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig,
+        constraint = order_book.initialized @ DuskError::OrderBookNotInitialized
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    #[account(
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            user_position.owner.as_ref(),
+            user_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = user_position.bump
+    )]
+    pub user_position: Account<'info, UserPosition>,
+
+    #[account(
+        mut,
+        seeds = [ConfidentialBalance::SEED_PREFIX, user_position.key().as_ref()],
+        bump = confidential_balance.bump,
+        constraint = confidential_balance.initialized @ DuskError::ConfidentialBalanceNotInitialized
+    )]
+    pub confidential_balance: Account<'info, ConfidentialBalance>,
+
+    #[account(
+        mut,
+        seeds = [
+            OpenOrder::SEED_PREFIX,
+            user_position.key().as_ref(),
+            order_id.to_le_bytes().as_ref()
+        ],
+        bump = open_order.bump
+    )]
+    pub open_order: Account<'info, OpenOrder>,
+
+    /// Signer PDA for CPI to Arcium
+    #[account(
+        init_if_needed,
+        space = 9,
+        payer = user,
+        seeds = [&SIGN_PDA_SEED],
+        bump
+    )]
+    pub sign_pda_account: Account<'info, SignerAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Mempool account
+    #[account(mut)]
+    pub mempool_account: UncheckedAccount<'info>,
+
+    /// CHECK: Executing pool account
+    #[account(mut)]
+    pub executing_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Computation account at the PDA of `computation_offset` (will be initialized)
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset, mxe_account, DuskError::ClusterNotSet)
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    /// Computation definition account for remove_order_unlocked
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_REMOVE_ORDER_UNLOCKED))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    /// Cluster account
+    #[account(mut, address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// Pool account (Arcium fee pool)
+    #[account(mut)]
+    pub pool_account: Account<'info, FeePool>,
+
+    /// Clock account
+    pub clock_account: Account<'info, ClockAccount>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(
+    ctx: Context<CancelConfidentialOrder>,
+    computation_offset: u64,
+    order_id: u64,
+    client_pubkey: [u8; 32],
+    nonce: u128,
+) -> Result<()> {
+    // Set the sign_pda_account bump for CPI signing
+    ctx.accounts.sign_pda_account.bump = ctx.bumps.sign_pda_account;
+
+    let market = &ctx.accounts.market;
+    require!(market.confidential_balances, DuskError::NotConfidentialMarket);
+
+    // Only the owner or an unexpired delegate can cancel orders of this position
+    let now = Clock::get()?.unix_timestamp;
+    require!(
+        ctx.accounts.user_position.can_trade(&ctx.accounts.user.key(), now),
+        DuskError::Unauthorized
+    );
+
+    // A fill of this order waiting for settlement still draws on its lock,
+    // and the circuit releases the whole lock
+    require!(
+        ctx.accounts.open_order.open_fills == 0
+            && !market.pending_match_fills(&ctx.accounts.user_position.key(), order_id),
+        DuskError::ComputationNotReady
+    );
+
+    match ctx.accounts.open_order.status {
+        OrderStatus::Resting => {}
+        OrderStatus::Void => return Err(DuskError::OrderNotFound.into()),
+        _ => return Err(DuskError::ComputationNotReady.into()),
+    }
+
+    require!(
+        !ctx.accounts.confidential_balance.is_busy(now),
+        DuskError::ConfidentialBalanceBusy
+    );
+
+    // Every book job reads the state the previous one wrote
+    require!(!ctx.accounts.order_book.is_busy(now), DuskError::OrderBookBusy);
+
+    // Reserve this market's next computation offset for the remove_order_unlocked job
+    let market_key = market.key();
+    ctx.accounts.market.claim_computation_offset(&market_key, computation_offset)?;
+    ctx.accounts.order_book.begin_computation(computation_offset, now);
+    ctx.accounts.confidential_balance.begin_computation(computation_offset, now);
+    let open_order = &mut ctx.accounts.open_order;
+    open_order.computation_offset = computation_offset;
+    open_order.begin_computation(OrderStatus::PendingCancel, now);
+
+    // Orders in the book are identified by the sub-account position
+    let owner = ctx.accounts.user_position.owner;
+    let (owner_lo, owner_hi) = UserPosition::split_key(&ctx.accounts.user_position.key());
+    let confidential_balance = &ctx.accounts.confidential_balance;
+    let open_order = &ctx.accounts.open_order;
+
+    // The client key and nonce receive the removed order's unfilled amount
+    let computation_args = ArgBuilder::new()
+        .x25519_pubkey(client_pubkey)
+        .plaintext_u128(nonce)
+        .plaintext_u64(order_id)
+        .plaintext_u128(owner_lo)
+        .plaintext_u128(owner_hi)
+        .plaintext_bool(open_order.is_buy)
+        .plaintext_u128(confidential_balance.state_nonce)
+        .account(
+            confidential_balance.key(),
+            ConfidentialBalance::STATE_OFFSET,
+            ConfidentialBalance::STATE_LEN,
+        )
+        // The order's remaining encrypted lock
+        .plaintext_u128(open_order.lock_nonce)
+        .account(open_order.key(), OpenOrder::ENCRYPTED_LOCK_OFFSET, 32)
+        .plaintext_u128(ctx.accounts.order_book.state_nonce)
+        .account(
+            ctx.accounts.order_book.key(),
+            OrderBook::STATE_OFFSET,
+            OrderBook::STATE_LEN,
+        )
+        .build();
+
+    // Define callback accounts
+    let callback_accounts = vec![
+        CallbackAccount {
+            pubkey: market_key,
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.order_book.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: owner,
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.user_position.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: confidential_balance.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: open_order.key(),
+            is_writable: true,
+        },
+    ];
+
+    // Queue the encrypted computation
+    queue_computation(
+        ctx.accounts,
+        computation_offset,
+        computation_args,
+        None,
+        vec![RemoveOrderUnlockedCallback::callback_ix(
+            computation_offset,
+            &ctx.accounts.mxe_account,
+            &callback_accounts,
+        )?],
+        1, // returns the book, the balance, whether the order was removed and its encrypted remaining amount
+        0, // tip
+    )?;
+
+    msg!(
+        "Cancel of confidential order {} requested on market {}",
+        order_id,
+        ctx.accounts.market.market_id
+    );
+
+    Ok(())
+}
+
+/// Callback for remove_order_unlocked computation
+#[callback_accounts("remove_order_unlocked")]
+#[derive(Accounts)]
+pub struct RemoveOrderUnlockedCallback<'info> {
+    pub arcium_program: Program<'info, Arcium>,
+
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_REMOVE_ORDER_UNLOCKED))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Computation account, must be the one queued by `cancel_confidential_order` for this order
+    #[account(
+        address = derive_comp_pda!(open_order.computation_offset, mxe_account, DuskError::ClusterNotSet)
+            @ DuskError::ComputationMismatch,
+        constraint = confidential_balance.computation_offset == open_order.computation_offset
+            @ DuskError::ComputationMismatch
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// CHECK: Instructions sysvar, checked by the address constraint
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// Only the latest job queued on the book may write it
+    #[account(
+        mut,
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig,
+        constraint = order_book.computation_offset == open_order.computation_offset
+            @ DuskError::ComputationMismatch
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    /// CHECK: Position owner, receives the order account's rent
+    #[account(mut)]
+    pub user: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            user.key().as_ref(),
+            user_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = user_position.bump
+    )]
+    pub user_position: Account<'info, UserPosition>,
+
+    #[account(
+        mut,
+        constraint = confidential_balance.position == user_position.key() @ DuskError::Unauthorized
+    )]
+    pub confidential_balance: Account<'info, ConfidentialBalance>,
+
+    #[account(
+        mut,
+        constraint = open_order.position == user_position.key() @ DuskError::OrderNotFound
+    )]
+    pub open_order: Account<'info, OpenOrder>,
+}
+
+pub fn callback_handler(
+    ctx: Context<RemoveOrderUnlockedCallback>,
+    output: SignedComputationOutputs<RemoveOrderUnlockedOutput>,
+) -> Result<()> {
+    // Only an output signed by the cluster for this computation is accepted
+    let (book, balance, removed, remaining) = match output.verify_output(
+        &ctx.accounts.cluster_account,
+        &ctx.accounts.computation_account,
+    ) {
+        Ok(RemoveOrderUnlockedOutput {
+            field_0: RemoveOrderUnlockedOutputStruct0 { field_0, field_1, field_2, field_3 },
+        }) => (field_0, field_1, field_2, field_3),
+        Err(_) => return Err(DuskError::AbortedComputation.into()),
+    };
+
+    // A cancel reopened after its deadline still takes the result: the book
+    // constraint above guarantees no other job has written the book since
+    if !matches!(
+        ctx.accounts.open_order.status,
+        OrderStatus::PendingCancel | OrderStatus::Resting
+    ) {
+        // The book is still freed: this was its latest job
+        ctx.accounts.order_book.end_computation();
+        ctx.accounts.confidential_balance.end_computation();
+        msg!(
+            "Ignoring late remove_order_unlocked result for order {}",
+            ctx.accounts.open_order.order_id
+        );
+        return Ok(());
+    }
+
+    ctx.accounts.order_book.store_state(book.nonce, book.ciphertexts);
+    ctx.accounts.confidential_balance.store_state(balance.nonce, balance.ciphertexts);
+
+    let market = &mut ctx.accounts.market;
+    let user_position = &mut ctx.accounts.user_position;
+    let open_order = &ctx.accounts.open_order;
+    let order_id = open_order.order_id;
+
+    // The order was resting when the cancel was queued, so it still counts as
+    // active even when the circuit did not find it (displaced from the book)
+    user_position.active_order_count = user_position.active_order_count.saturating_sub(1);
+    market.remove_active_order(open_order.is_buy, false);
+
+    emit!(OrderCancelled {
+        market: market.key(),
+        user: ctx.accounts.user.key(),
+        order_id,
+        remaining_nonce: remaining.nonce,
+        encrypted_remaining: remaining.ciphertexts[0],
+    });
+
+    // The lock is released, so the order's bookkeeping goes too
+    ctx.accounts.open_order.close(ctx.accounts.user.to_account_info())?;
+
+    msg!(
+        "Confidential order {} cancelled for user {} on market {} (in book: {})",
+        order_id,
+        ctx.accounts.user.key(),
+        ctx.accounts.market.market_id,
+        removed
+    );
+
+    Ok(())
+}
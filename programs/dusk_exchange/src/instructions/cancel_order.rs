@@ -3,10 +3,11 @@ use arcium_anchor::prelude::*;
 use arcium_client::idl::arcium::{types::CallbackAccount, ID_CONST};
 
 use crate::ID;
-use crate::state::{Market, UserPosition};
+use crate::state::{Market, MarketStatus, OpenOrder, OrderBook, OrderStatus, UserPosition};
 use crate::events::OrderCancelled;
 use crate::errors::DuskError;
 use crate::instructions::place_order::SignerAccount;
+use crate::{RemoveOrderOutput, RemoveOrderOutputStruct0};
 
 /// Computation definition offset for remove_order
 pub const COMP_DEF_OFFSET_REMOVE_ORDER: u8 = 1;
@@ -14,26 +15,46 @@ pub const COMP_DEF_OFFSET_REMOVE_ORDER: u8 = 1;
 /// Cancel an existing order
 #[queue_computation_accounts("remove_order", user)]
 #[derive(Accounts)]
-#[instruction(order_id: u64)]
+#[instruction(computation_offset: u64, order_id: u64)]
 pub struct CancelOrder<'info> {
+    /// Position owner or its trading delegate
     #[account(mut)]
     pub user: Signer<'info>,
 
     #[account(mut)]
     pub market: Account<'info, Market>,
 
+    /// Encrypted order book of this market
+    #[account(
+        mut,
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig,
+        constraint = order_book.initialized @ DuskError::OrderBookNotInitialized
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
     #[account(
         mut,
         seeds = [
             UserPosition::SEED_PREFIX,
             market.key().as_ref(),
-            user.key().as_ref()
+            user_position.owner.as_ref(),
+            user_position.sub_account_id.to_le_bytes().as_ref()
         ],
-        bump = user_position.bump,
-        constraint = user_position.owner == user.key() @ DuskError::Unauthorized
+        bump = user_position.bump
     )]
     pub user_position: Account<'info, UserPosition>,
 
+    #[account(
+        mut,
+        seeds = [
+            OpenOrder::SEED_PREFIX,
+            user_position.key().as_ref(),
+            order_id.to_le_bytes().as_ref()
+        ],
+        bump = open_order.bump
+    )]
+    pub open_order: Account<'info, OpenOrder>,
+
     /// Signer PDA for CPI to Arcium
     #[account(
         init_if_needed,
@@ -55,8 +76,11 @@ pub struct CancelOrder<'info> {
     #[account(mut)]
     pub executing_pool: UncheckedAccount<'info>,
 
-    /// CHECK: Computation account (will be initialized)
-    #[account(mut)]
+    /// CHECK: Computation account at the PDA of `computation_offset` (will be initialized)
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset, mxe_account, DuskError::ClusterNotSet)
+    )]
     pub computation_account: UncheckedAccount<'info>,
 
     /// Computation definition account for remove_order
@@ -64,7 +88,7 @@ pub struct CancelOrder<'info> {
     pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
 
     /// Cluster account
-    #[account(mut)]
+    #[account(mut, address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
     pub cluster_account: Account<'info, Cluster>,
 
     /// Pool account (Arcium fee pool)
@@ -78,57 +102,117 @@ pub struct CancelOrder<'info> {
     pub system_program: Program<'info, System>,
 }
 
-pub fn handler(ctx: Context<CancelOrder>, order_id: u64) -> Result<()> {
+pub fn handler(
+    ctx: Context<CancelOrder>,
+    computation_offset: u64,
+    order_id: u64,
+    client_pubkey: [u8; 32],
+    nonce: u128,
+) -> Result<()> {
     // Set the sign_pda_account bump for CPI signing
     ctx.accounts.sign_pda_account.bump = ctx.bumps.sign_pda_account;
 
     let market = &ctx.accounts.market;
 
+    // Confidential orders release an encrypted lock via cancel_confidential_order
+    require!(!market.confidential_balances, DuskError::ConfidentialMarket);
+
+    // Once the book is cleared there is nothing left to cancel
+    require!(
+        market.status != MarketStatus::WithdrawOnly,
+        DuskError::MarketDelisted
+    );
+
+    // Only the owner or an unexpired delegate can cancel orders of this position
+    let now = Clock::get()?.unix_timestamp;
     require!(
-        ctx.accounts.user_position.active_order_count > 0,
-        DuskError::OrderNotFound
+        ctx.accounts.user_position.can_trade(&ctx.accounts.user.key(), now),
+        DuskError::Unauthorized
     );
 
-    // Split the user's pubkey into two u128 values
-    let user_bytes = ctx.accounts.user.key().to_bytes();
-    let owner_lo = u128::from_le_bytes(user_bytes[..16].try_into().unwrap());
-    let owner_hi = u128::from_le_bytes(user_bytes[16..].try_into().unwrap());
+    // A fill of this order waiting for settlement still draws on its lock
+    require!(
+        ctx.accounts.open_order.open_fills == 0
+            && !market.pending_match_fills(&ctx.accounts.user_position.key(), order_id),
+        DuskError::ComputationNotReady
+    );
+
+    // Only an order confirmed in the book can be cancelled; a pending add or
+    // cancel must call back (or be reclaimed) first
+    match ctx.accounts.open_order.status {
+        OrderStatus::Resting => {}
+        OrderStatus::Void => return Err(DuskError::OrderNotFound.into()),
+        _ => return Err(DuskError::ComputationNotReady.into()),
+    }
+
+    // Every book job reads the state the previous one wrote
+    require!(!ctx.accounts.order_book.is_busy(now), DuskError::OrderBookBusy);
+
+    // Reserve this market's next computation offset for the remove_order job
+    let market_key = market.key();
+    ctx.accounts.market.claim_computation_offset(&market_key, computation_offset)?;
+    ctx.accounts.order_book.begin_computation(computation_offset, now);
+    let open_order = &mut ctx.accounts.open_order;
+    open_order.computation_offset = computation_offset;
+    open_order.begin_computation(OrderStatus::PendingCancel, now);
+
+    // Orders in the book are identified by the sub-account position
+    let owner = ctx.accounts.user_position.owner;
+    let (owner_lo, owner_hi) = UserPosition::split_key(&ctx.accounts.user_position.key());
 
     // Build computation arguments for remove_order circuit using ArgBuilder
+    // The client key and nonce receive the removed order's unfilled amount
     let computation_args = ArgBuilder::new()
+        .x25519_pubkey(client_pubkey)
+        .plaintext_u128(nonce)
         .plaintext_u64(order_id)
         .plaintext_u128(owner_lo)
         .plaintext_u128(owner_hi)
+        // Current encrypted book of this market
+        .plaintext_u128(ctx.accounts.order_book.state_nonce)
+        .account(
+            ctx.accounts.order_book.key(),
+            OrderBook::STATE_OFFSET,
+            OrderBook::STATE_LEN,
+        )
         .build();
 
     // Define callback accounts
     let callback_accounts = vec![
         CallbackAccount {
-            pubkey: market.key(),
+            pubkey: market_key,
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.order_book.key(),
             is_writable: true,
         },
         CallbackAccount {
-            pubkey: ctx.accounts.user.key(),
-            is_writable: false,
+            pubkey: owner,
+            is_writable: true,
         },
         CallbackAccount {
             pubkey: ctx.accounts.user_position.key(),
             is_writable: true,
         },
+        CallbackAccount {
+            pubkey: ctx.accounts.open_order.key(),
+            is_writable: true,
+        },
     ];
 
     // Queue the encrypted computation
     queue_computation(
         ctx.accounts,
-        0,
+        computation_offset,
         computation_args,
         None,
         vec![RemoveOrderCallback::callback_ix(
-            COMP_DEF_OFFSET_REMOVE_ORDER as u64,
+            computation_offset,
             &ctx.accounts.mxe_account,
             &callback_accounts,
         )?],
-        1, // returns bool indicating if order was removed
+        1, // returns the book, whether the order was removed and its encrypted remaining amount
         0, // tip
     )?;
 
@@ -142,15 +226,45 @@ pub fn handler(ctx: Context<CancelOrder>, order_id: u64) -> Result<()> {
 }
 
 /// Callback for remove_order computation
+#[callback_accounts("remove_order")]
 #[derive(Accounts)]
 pub struct RemoveOrderCallback<'info> {
-    /// CHECK: Arcium callback authority
-    pub callback_authority: Signer<'info>,
+    pub arcium_program: Program<'info, Arcium>,
+
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_REMOVE_ORDER))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Computation account, must be the one queued by `cancel_order` for this order
+    #[account(
+        address = derive_comp_pda!(open_order.computation_offset, mxe_account, DuskError::ClusterNotSet)
+            @ DuskError::ComputationMismatch
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// CHECK: Instructions sysvar, checked by the address constraint
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
 
     #[account(mut)]
     pub market: Account<'info, Market>,
 
-    /// CHECK: User who cancelled the order
+    /// Only the latest job queued on the book may write it
+    #[account(
+        mut,
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig,
+        constraint = order_book.computation_offset == open_order.computation_offset
+            @ DuskError::ComputationMismatch
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    /// CHECK: Position owner, receives the order account's rent
+    #[account(mut)]
     pub user: UncheckedAccount<'info>,
 
     #[account(
@@ -158,50 +272,105 @@ pub struct RemoveOrderCallback<'info> {
         seeds = [
             UserPosition::SEED_PREFIX,
             market.key().as_ref(),
-            user.key().as_ref()
+            user.key().as_ref(),
+            user_position.sub_account_id.to_le_bytes().as_ref()
         ],
         bump = user_position.bump
     )]
     pub user_position: Account<'info, UserPosition>,
+
+    /// Closed by the callback once nothing draws on its lock any more
+    #[account(
+        mut,
+        constraint = open_order.position == user_position.key() @ DuskError::OrderNotFound
+    )]
+    pub open_order: Account<'info, OpenOrder>,
 }
 
-impl RemoveOrderCallback<'_> {
-    pub fn callback_ix(
-        _computation_offset: u64,
-        _mxe_account: &MXEAccount,
-        extra_accs: &[CallbackAccount],
-    ) -> Result<arcium_client::idl::arcium::types::CallbackInstruction> {
-        Ok(arcium_client::idl::arcium::types::CallbackInstruction {
-            program_id: crate::ID,
-            discriminator: vec![0u8; 8], // Will be set correctly by Arcium runtime
-            accounts: extra_accs.to_vec(),
-        })
+pub fn callback_handler(
+    ctx: Context<RemoveOrderCallback>,
+    output: SignedComputationOutputs<RemoveOrderOutput>,
+) -> Result<()> {
+    // Only an output signed by the cluster for this computation is accepted
+    let (book, removed, remaining) = match output.verify_output(
+        &ctx.accounts.cluster_account,
+        &ctx.accounts.computation_account,
+    ) {
+        Ok(RemoveOrderOutput {
+            field_0: RemoveOrderOutputStruct0 { field_0, field_1, field_2 },
+        }) => (field_0, field_1, field_2),
+        Err(_) => return Err(DuskError::AbortedComputation.into()),
+    };
+
+    // A cancel reopened after its deadline still takes the result: the book
+    // constraint above guarantees no other job has written the book since
+    if !matches!(
+        ctx.accounts.open_order.status,
+        OrderStatus::PendingCancel | OrderStatus::Resting
+    ) {
+        // The book is still freed: this was its latest job
+        ctx.accounts.order_book.end_computation();
+        msg!(
+            "Ignoring late remove_order result for order {}",
+            ctx.accounts.open_order.order_id
+        );
+        return Ok(());
     }
-}
 
-pub fn callback_handler(ctx: Context<RemoveOrderCallback>) -> Result<()> {
+    ctx.accounts.order_book.store_state(book.nonce, book.ciphertexts);
+
     let market = &mut ctx.accounts.market;
     let user_position = &mut ctx.accounts.user_position;
-
-    // Decrement active order count
-    user_position.active_order_count = user_position
-        .active_order_count
-        .saturating_sub(1);
-
-    // Note: The callback would receive the 'removed' bool from MPC
-    // For now, we assume success and emit the event
+    let open_order = &mut ctx.accounts.open_order;
+
+    // The order was resting when the cancel was queued, so it still counts as
+    // active even when the circuit did not find it (displaced from the book)
+    market.remove_active_order(open_order.is_buy, open_order.side_hidden);
+
+    // A fill still waiting for settlement draws on the lock, so the order
+    // stays open; its last settlement releases the rest and closes it
+    let has_open_fills = open_order.open_fills > 0;
+    let released = if has_open_fills {
+        open_order.status = OrderStatus::Filled;
+        open_order.deadline = 0;
+        0
+    } else {
+        // An order the circuit did not find can no longer be filled (it was
+        // filled, or displaced from the book), so its remaining lock is
+        // released either way
+        let released = open_order.locked_amount;
+        if open_order.side_hidden {
+            // Never filled, so both locks are still held
+            user_position.unlock_hidden_for_cancel(open_order.locked_base, released);
+            market.unlock(open_order.locked_base, false);
+            market.unlock(released, true);
+        } else {
+            user_position.unlock_for_cancel(released, open_order.is_buy);
+            market.unlock(released, open_order.is_buy);
+        }
+        released
+    };
 
     emit!(OrderCancelled {
         market: market.key(),
         user: ctx.accounts.user.key(),
-        order_id: 0, // Would come from callback data
+        order_id: open_order.order_id,
+        remaining_nonce: remaining.nonce,
+        encrypted_remaining: remaining.ciphertexts[0],
     });
 
     msg!(
-        "Order cancelled for user {} on market {}",
+        "Order {} cancelled for user {} on market {} (in book: {}, released: {})",
+        open_order.order_id,
         ctx.accounts.user.key(),
-        market.market_id
+        market.market_id,
+        removed,
+        released
     );
 
+    if !has_open_fills {
+        ctx.accounts.open_order.close(ctx.accounts.user.to_account_info())?;
+    }
+
     Ok(())
 }
@@ -0,0 +1,252 @@
+use anchor_lang::prelude::*;
+use arcium_anchor::prelude::*;
+use arcium_client::idl::arcium::{types::CallbackAccount, ID_CONST};
+
+use crate::ID;
+use crate::state::{Market, MarketStatus, OrderBook};
+use crate::events::{BookCleared, MarketStatusChanged};
+use crate::errors::DuskError;
+use crate::instructions::place_order::SignerAccount;
+use crate::instructions::match_orders::reconstruct_pubkey;
+use crate::{ClearBookOutput, ClearBookOutputStruct0, ClearBookOutputStruct01};
+
+/// Computation definition offset for clear_book
+pub const COMP_DEF_OFFSET_CLEAR_BOOK: u8 = 4;
+
+/// Clear the encrypted book of a cancel-only market
+/// On callback the market becomes withdraw-only
+#[queue_computation_accounts("clear_book", authority)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct ClearBook<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority @ DuskError::Unauthorized
+    )]
+    pub market: Account<'info, Market>,
+
+    /// Encrypted order book of this market
+    #[account(
+        mut,
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig,
+        constraint = order_book.initialized @ DuskError::OrderBookNotInitialized
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    /// Signer PDA for CPI to Arcium
+    #[account(
+        init_if_needed,
+        space = 9,
+        payer = authority,
+        seeds = [&SIGN_PDA_SEED],
+        bump
+    )]
+    pub sign_pda_account: Account<'info, SignerAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Mempool account
+    #[account(mut)]
+    pub mempool_account: UncheckedAccount<'info>,
+
+    /// CHECK: Executing pool account
+    #[account(mut)]
+    pub executing_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Computation account at the PDA of `computation_offset` (will be initialized)
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset, mxe_account, DuskError::ClusterNotSet)
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    /// Computation definition account for clear_book
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_CLEAR_BOOK))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    /// Cluster account
+    #[account(mut, address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// Pool account (Arcium fee pool)
+    #[account(mut)]
+    pub pool_account: Account<'info, FeePool>,
+
+    /// Clock account
+    pub clock_account: Account<'info, ClockAccount>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(ctx: Context<ClearBook>, computation_offset: u64) -> Result<()> {
+    // Set the sign_pda_account bump for CPI signing
+    ctx.accounts.sign_pda_account.bump = ctx.bumps.sign_pda_account;
+
+    let market = &ctx.accounts.market;
+
+    require!(
+        market.status == MarketStatus::CancelOnly,
+        DuskError::InvalidDelistingPhase
+    );
+
+    // Every match must be turned into a settlement and settled first, since
+    // clearing the book releases the locks that back them
+    require!(
+        !market.has_pending_match && market.open_settlements == 0,
+        DuskError::ComputationNotReady
+    );
+
+    // Every book job reads the state the previous one wrote
+    let now = Clock::get()?.unix_timestamp;
+    require!(!ctx.accounts.order_book.is_busy(now), DuskError::OrderBookBusy);
+
+    // Reserve this market's next computation offset for the clear_book job
+    let market_key = market.key();
+    ctx.accounts.market.claim_computation_offset(&market_key, computation_offset)?;
+    ctx.accounts.order_book.begin_computation(computation_offset, now);
+
+    // clear_book operates on this market's encrypted book
+    let computation_args = ArgBuilder::new()
+        .plaintext_u128(ctx.accounts.order_book.state_nonce)
+        .account(
+            ctx.accounts.order_book.key(),
+            OrderBook::STATE_OFFSET,
+            OrderBook::STATE_LEN,
+        )
+        .build();
+
+    // Define callback accounts
+    let callback_accounts = vec![
+        CallbackAccount {
+            pubkey: market_key,
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.order_book.key(),
+            is_writable: true,
+        },
+    ];
+
+    // Queue the encrypted computation
+    // clear_book returns the orders that were left in the book
+    queue_computation(
+        ctx.accounts,
+        computation_offset,
+        computation_args,
+        None,
+        vec![ClearBookCallback::callback_ix(
+            computation_offset,
+            &ctx.accounts.mxe_account,
+            &callback_accounts,
+        )?],
+        1, // returns ClearedBook
+        0, // tip
+    )?;
+
+    msg!("Clear book requested on market {}", market.market_id);
+
+    Ok(())
+}
+
+/// Callback for clear_book computation
+/// Publishes the remaining orders and switches the market to withdraw-only
+#[callback_accounts("clear_book")]
+#[derive(Accounts)]
+pub struct ClearBookCallback<'info> {
+    pub arcium_program: Program<'info, Arcium>,
+
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_CLEAR_BOOK))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Computation account, must be the latest job queued on this order book
+    #[account(
+        address = derive_comp_pda!(order_book.computation_offset, mxe_account, DuskError::ClusterNotSet)
+            @ DuskError::ComputationMismatch
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// CHECK: Instructions sysvar, checked by the address constraint
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig
+    )]
+    pub order_book: Account<'info, OrderBook>,
+}
+
+pub fn callback_handler(
+    ctx: Context<ClearBookCallback>,
+    output: SignedComputationOutputs<ClearBookOutput>,
+) -> Result<()> {
+    // Only an output signed by the cluster for this computation is accepted
+    let (book, cleared) = match output.verify_output(
+        &ctx.accounts.cluster_account,
+        &ctx.accounts.computation_account,
+    ) {
+        Ok(ClearBookOutput {
+            field_0: ClearBookOutputStruct0 { field_0, field_1 },
+        }) => (field_0, field_1),
+        Err(_) => return Err(DuskError::AbortedComputation.into()),
+    };
+    // Fields follow the ClearedBook circuit struct
+    let ClearBookOutputStruct01 {
+        field_0: bid_order_id,
+        field_1: bid_owner_lo,
+        field_2: bid_owner_hi,
+        field_3: ask_order_id,
+        field_4: ask_owner_lo,
+        field_5: ask_owner_hi,
+    } = cleared;
+
+    let market = &mut ctx.accounts.market;
+
+    if market.status != MarketStatus::CancelOnly {
+        msg!("Book of market {} already cleared", market.market_id);
+        return Ok(());
+    }
+
+    ctx.accounts.order_book.store_state(book.nonce, book.ciphertexts);
+
+    market.active_bids = 0;
+    market.active_asks = 0;
+    market.active_hidden = 0;
+    market.status = MarketStatus::WithdrawOnly;
+
+    emit!(BookCleared {
+        market: market.key(),
+        bid_order_id,
+        bid_position: reconstruct_pubkey(bid_owner_lo, bid_owner_hi),
+        ask_order_id,
+        ask_position: reconstruct_pubkey(ask_owner_lo, ask_owner_hi),
+    });
+
+    emit!(MarketStatusChanged {
+        market: market.key(),
+        status: market.status,
+    });
+
+    msg!(
+        "Book cleared on market {} (bid order: {}, ask order: {}). Market is now withdraw-only.",
+        market.market_id,
+        bid_order_id,
+        ask_order_id
+    );
+
+    Ok(())
+}
@@ -0,0 +1,269 @@
+use anchor_lang::prelude::*;
+use arcium_anchor::prelude::*;
+use arcium_client::idl::arcium::{types::CallbackAccount, ID_CONST};
+
+use crate::ID;
+use crate::state::{ConfidentialBalance, Market, UserPosition};
+use crate::events::PositionClosed;
+use crate::errors::DuskError;
+use crate::instructions::place_order::SignerAccount;
+use crate::BalanceIsEmptyOutput;
+
+/// Computation definition offset for balance_is_empty
+pub const COMP_DEF_OFFSET_BALANCE_IS_EMPTY: u8 = 12;
+
+/// Close a confidential position once the MXE confirms its balance is empty
+/// Rent of the position and its balance is refunded to the owner on callback
+#[queue_computation_accounts("balance_is_empty", user)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct CloseConfidentialPosition<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// Public balances must be empty too; keeper rewards are credited there
+    #[account(
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            user.key().as_ref(),
+            user_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = user_position.bump,
+        constraint = user_position.owner == user.key() @ DuskError::Unauthorized,
+        constraint = user_position.is_empty() @ DuskError::PositionNotEmpty
+    )]
+    pub user_position: Account<'info, UserPosition>,
+
+    #[account(
+        mut,
+        seeds = [ConfidentialBalance::SEED_PREFIX, user_position.key().as_ref()],
+        bump = confidential_balance.bump,
+        constraint = confidential_balance.initialized @ DuskError::ConfidentialBalanceNotInitialized
+    )]
+    pub confidential_balance: Account<'info, ConfidentialBalance>,
+
+    /// Signer PDA for CPI to Arcium
+    #[account(
+        init_if_needed,
+        space = 9,
+        payer = user,
+        seeds = [&SIGN_PDA_SEED],
+        bump
+    )]
+    pub sign_pda_account: Account<'info, SignerAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Mempool account
+    #[account(mut)]
+    pub mempool_account: UncheckedAccount<'info>,
+
+    /// CHECK: Executing pool account
+    #[account(mut)]
+    pub executing_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Computation account at the PDA of `computation_offset` (will be initialized)
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset, mxe_account, DuskError::ClusterNotSet)
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    /// Computation definition account for balance_is_empty
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_BALANCE_IS_EMPTY))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    /// Cluster account
+    #[account(mut, address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// Pool account (Arcium fee pool)
+    #[account(mut)]
+    pub pool_account: Account<'info, FeePool>,
+
+    /// Clock account
+    pub clock_account: Account<'info, ClockAccount>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(ctx: Context<CloseConfidentialPosition>, computation_offset: u64) -> Result<()> {
+    // Set the sign_pda_account bump for CPI signing
+    ctx.accounts.sign_pda_account.bump = ctx.bumps.sign_pda_account;
+
+    require!(
+        ctx.accounts.market.confidential_balances,
+        DuskError::NotConfidentialMarket
+    );
+
+    let now = Clock::get()?.unix_timestamp;
+    let confidential_balance = &ctx.accounts.confidential_balance;
+    require!(!confidential_balance.is_busy(now), DuskError::ConfidentialBalanceBusy);
+
+    // Deposits not yet credited are in the vault but not in the encrypted balance
+    require!(
+        !confidential_balance.has_pending_credit(),
+        DuskError::ConfidentialCreditPending
+    );
+
+    // Reserve this market's next computation offset for the balance_is_empty job
+    let market_key = ctx.accounts.market.key();
+    ctx.accounts.market.claim_computation_offset(&market_key, computation_offset)?;
+    ctx.accounts.confidential_balance.begin_computation(computation_offset, now);
+
+    let confidential_balance = &ctx.accounts.confidential_balance;
+    let computation_args = ArgBuilder::new()
+        .plaintext_u128(confidential_balance.state_nonce)
+        .account(
+            confidential_balance.key(),
+            ConfidentialBalance::STATE_OFFSET,
+            ConfidentialBalance::STATE_LEN,
+        )
+        .build();
+
+    // Define callback accounts
+    let callback_accounts = vec![
+        CallbackAccount {
+            pubkey: market_key,
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.user.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.user_position.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: confidential_balance.key(),
+            is_writable: true,
+        },
+    ];
+
+    // Queue the encrypted computation
+    queue_computation(
+        ctx.accounts,
+        computation_offset,
+        computation_args,
+        None,
+        vec![BalanceIsEmptyCallback::callback_ix(
+            computation_offset,
+            &ctx.accounts.mxe_account,
+            &callback_accounts,
+        )?],
+        1, // returns whether the balance is empty
+        0, // tip
+    )?;
+
+    msg!(
+        "Close of confidential position requested for user {} on market {}",
+        ctx.accounts.user.key(),
+        ctx.accounts.market.market_id
+    );
+
+    Ok(())
+}
+
+/// Callback for balance_is_empty computation
+#[callback_accounts("balance_is_empty")]
+#[derive(Accounts)]
+pub struct BalanceIsEmptyCallback<'info> {
+    pub arcium_program: Program<'info, Arcium>,
+
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_BALANCE_IS_EMPTY))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Computation account, must be the latest one queued on this balance
+    #[account(
+        address = derive_comp_pda!(confidential_balance.computation_offset, mxe_account, DuskError::ClusterNotSet)
+            @ DuskError::ComputationMismatch
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// CHECK: Instructions sysvar, checked by the address constraint
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// CHECK: Position owner, receives the rent of both accounts
+    #[account(mut)]
+    pub user: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            user.key().as_ref(),
+            user_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = user_position.bump
+    )]
+    pub user_position: Account<'info, UserPosition>,
+
+    #[account(
+        mut,
+        constraint = confidential_balance.position == user_position.key() @ DuskError::Unauthorized
+    )]
+    pub confidential_balance: Account<'info, ConfidentialBalance>,
+}
+
+pub fn callback_handler(
+    ctx: Context<BalanceIsEmptyCallback>,
+    output: SignedComputationOutputs<BalanceIsEmptyOutput>,
+) -> Result<()> {
+    // Only an output signed by the cluster for this computation is accepted
+    let empty = match output.verify_output(
+        &ctx.accounts.cluster_account,
+        &ctx.accounts.computation_account,
+    ) {
+        Ok(BalanceIsEmptyOutput { field_0 }) => field_0,
+        Err(_) => return Err(DuskError::AbortedComputation.into()),
+    };
+
+    // The balance was only read, so it is idle again either way
+    ctx.accounts.confidential_balance.pending_until = 0;
+
+    if !empty || !ctx.accounts.user_position.is_empty() {
+        msg!(
+            "Confidential position of user {} is not empty and stays open",
+            ctx.accounts.user.key()
+        );
+        return Ok(());
+    }
+
+    let market = &mut ctx.accounts.market;
+    market.open_position_count = market.open_position_count.saturating_sub(1);
+
+    emit!(PositionClosed {
+        market: market.key(),
+        user: ctx.accounts.user.key(),
+    });
+
+    msg!(
+        "Confidential position closed for user {} on market {}",
+        ctx.accounts.user.key(),
+        market.market_id
+    );
+
+    let user = ctx.accounts.user.to_account_info();
+    ctx.accounts.confidential_balance.close(user.clone())?;
+    ctx.accounts.user_position.close(user)?;
+
+    Ok(())
+}
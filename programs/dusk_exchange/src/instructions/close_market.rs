@@ -0,0 +1,236 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::invoke;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{
+        transfer_fee::{instruction::harvest_withheld_tokens_to_mint, TransferFeeAmount},
+        BaseStateWithExtensions, StateWithExtensions,
+    },
+};
+use anchor_spl::token_interface::{
+    self, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked,
+};
+
+use crate::state::{ExchangeConfig, Market, MarketRegistryEntry, MarketStatus, OrderBook};
+use crate::events::MarketClosed;
+use crate::errors::DuskError;
+
+/// Close a delisted market once every position is closed
+/// Leftover vault balances (unpaid keeper fees, rounding dust) go to the
+/// authority's token accounts; all rent, including the order book's, goes to
+/// the authority
+#[derive(Accounts)]
+pub struct CloseMarket<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        close = authority,
+        has_one = authority @ DuskError::Unauthorized
+    )]
+    pub market: Account<'info, Market>,
+
+    /// Encrypted book of this market
+    #[account(
+        mut,
+        close = authority,
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    /// Global config, whose market count drops with the closed market
+    #[account(
+        mut,
+        seeds = [ExchangeConfig::SEED_PREFIX],
+        bump = exchange_config.bump
+    )]
+    pub exchange_config: Account<'info, ExchangeConfig>,
+
+    /// Registry entry for the pair; closing it lets the pair be listed again
+    #[account(
+        mut,
+        close = authority,
+        seeds = [
+            MarketRegistryEntry::SEED_PREFIX,
+            market.base_mint.as_ref(),
+            market.quote_mint.as_ref()
+        ],
+        bump = registry_entry.bump,
+        constraint = registry_entry.market == market.key() @ DuskError::InvalidMarketConfig
+    )]
+    pub registry_entry: Account<'info, MarketRegistryEntry>,
+
+    /// Writable to take in transfer fees withheld on the base vault
+    #[account(mut, address = market.base_mint @ DuskError::InvalidMarketConfig)]
+    pub base_mint: InterfaceAccount<'info, Mint>,
+
+    /// Writable to take in transfer fees withheld on the quote vault
+    #[account(mut, address = market.quote_mint @ DuskError::InvalidMarketConfig)]
+    pub quote_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        address = market.base_vault @ DuskError::InvalidMarketConfig
+    )]
+    pub base_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        address = market.quote_vault @ DuskError::InvalidMarketConfig
+    )]
+    pub quote_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Receives any base left in the vault
+    #[account(
+        mut,
+        constraint = base_destination.mint == market.base_mint @ DuskError::InvalidMarketConfig
+    )]
+    pub base_destination: InterfaceAccount<'info, TokenAccount>,
+
+    /// Receives any quote left in the vault
+    #[account(
+        mut,
+        constraint = quote_destination.mint == market.quote_mint @ DuskError::InvalidMarketConfig
+    )]
+    pub quote_destination: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = market.base_token_program @ DuskError::InvalidMarketConfig)]
+    pub base_token_program: Interface<'info, TokenInterface>,
+
+    #[account(address = market.quote_token_program @ DuskError::InvalidMarketConfig)]
+    pub quote_token_program: Interface<'info, TokenInterface>,
+}
+
+/// Transfer fees a Token-2022 mint withheld on the vault from deposits
+/// Legacy SPL Token vaults and mints without transfer fees withhold nothing
+fn withheld_transfer_fees(vault: &InterfaceAccount<TokenAccount>) -> Result<u64> {
+    let vault_info = vault.to_account_info();
+    if *vault_info.owner != spl_token_2022::ID {
+        return Ok(0);
+    }
+
+    let vault_data = vault_info.try_borrow_data()?;
+    let vault_state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&vault_data)?;
+    Ok(vault_state
+        .get_extension::<TransferFeeAmount>()
+        .map(|fees| u64::from(fees.withheld_amount))
+        .unwrap_or(0))
+}
+
+/// Move a vault's remaining balance out, then close the vault
+/// Withheld transfer fees would keep the vault from closing, so they are first
+/// harvested to the mint, where its withdraw authority collects them as usual
+fn sweep_and_close_vault<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    destination: &InterfaceAccount<'info, TokenAccount>,
+    market: &AccountInfo<'info>,
+    rent_receiver: &AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<u64> {
+    let remaining = vault.amount;
+
+    // Harvesting is permissionless, so the market does not sign it
+    if withheld_transfer_fees(vault)? > 0 {
+        invoke(
+            &harvest_withheld_tokens_to_mint(token_program.key, &mint.key(), &[&vault.key()])?,
+            &[mint.to_account_info(), vault.to_account_info()],
+        )?;
+    }
+
+    if remaining > 0 {
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                TransferChecked {
+                    from: vault.to_account_info(),
+                    mint: mint.to_account_info(),
+                    to: destination.to_account_info(),
+                    authority: market.clone(),
+                },
+                signer_seeds,
+            ),
+            remaining,
+            mint.decimals,
+        )?;
+    }
+
+    token_interface::close_account(CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        CloseAccount {
+            account: vault.to_account_info(),
+            destination: rent_receiver.clone(),
+            authority: market.clone(),
+        },
+        signer_seeds,
+    ))?;
+
+    Ok(remaining)
+}
+
+pub fn handler(ctx: Context<CloseMarket>) -> Result<()> {
+    let market = &ctx.accounts.market;
+
+    require!(
+        market.status == MarketStatus::WithdrawOnly,
+        DuskError::InvalidDelistingPhase
+    );
+    require!(
+        market.open_position_count == 0
+            && market.open_settlements == 0
+            && market.protocol_fees_accrued == 0,
+        DuskError::MarketNotEmpty
+    );
+
+    let market_id_bytes = market.market_id.to_le_bytes();
+    let market_seeds = &[
+        Market::SEED_PREFIX,
+        market_id_bytes.as_ref(),
+        &[market.bump],
+    ];
+    let signer_seeds = &[&market_seeds[..]];
+
+    let market_info = market.to_account_info();
+    let authority_info = ctx.accounts.authority.to_account_info();
+
+    let base_swept = sweep_and_close_vault(
+        &ctx.accounts.base_token_program,
+        &ctx.accounts.base_vault,
+        &ctx.accounts.base_mint,
+        &ctx.accounts.base_destination,
+        &market_info,
+        &authority_info,
+        signer_seeds,
+    )?;
+
+    let quote_swept = sweep_and_close_vault(
+        &ctx.accounts.quote_token_program,
+        &ctx.accounts.quote_vault,
+        &ctx.accounts.quote_mint,
+        &ctx.accounts.quote_destination,
+        &market_info,
+        &authority_info,
+        signer_seeds,
+    )?;
+
+    let exchange_config = &mut ctx.accounts.exchange_config;
+    exchange_config.market_count = exchange_config.market_count.saturating_sub(1);
+
+    emit!(MarketClosed {
+        market: market.key(),
+        market_id: market.market_id,
+        base_swept,
+        quote_swept,
+    });
+
+    msg!(
+        "Market {} closed (swept {} base, {} quote)",
+        market.market_id,
+        base_swept,
+        quote_swept
+    );
+
+    Ok(())
+}
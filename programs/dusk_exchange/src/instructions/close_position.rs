@@ -0,0 +1,53 @@
+use anchor_lang::prelude::*;
+
+use crate::state::{Market, UserPosition};
+use crate::events::PositionClosed;
+use crate::errors::DuskError;
+
+/// Close an empty user position and refund its rent to the owner
+#[derive(Accounts)]
+pub struct ClosePosition<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        close = user,
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            user.key().as_ref(),
+            user_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = user_position.bump,
+        constraint = user_position.owner == user.key() @ DuskError::Unauthorized,
+        constraint = user_position.is_empty() @ DuskError::PositionNotEmpty
+    )]
+    pub user_position: Account<'info, UserPosition>,
+}
+
+pub fn handler(ctx: Context<ClosePosition>) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+
+    // The public balances of a confidential position are always zero;
+    // close_confidential_position checks the encrypted ones instead
+    require!(!market.confidential_balances, DuskError::ConfidentialMarket);
+
+    market.open_position_count = market.open_position_count.saturating_sub(1);
+
+    emit!(PositionClosed {
+        market: market.key(),
+        user: ctx.accounts.user.key(),
+    });
+
+    msg!(
+        "Position closed for user {} on market {}",
+        ctx.accounts.user.key(),
+        market.market_id
+    );
+
+    Ok(())
+}
@@ -0,0 +1,202 @@
+use anchor_lang::prelude::*;
+
+use crate::state::{KeeperCrank, Market, TradeSettlement, UserPosition};
+use crate::events::{KeeperRewardSkipped, KeeperRewarded, SettlementClosed};
+use crate::errors::DuskError;
+
+/// Close a settled trade account and return its rent to the original payer
+#[derive(Accounts)]
+pub struct CloseSettlement<'info> {
+    /// Anyone can close an expired settlement; rent always goes to the payer
+    pub caller: Signer<'info>,
+
+    /// CHECK: Market of the settlement. Settlements outlive a delisted market
+    /// by up to `TradeSettlement::RETENTION_PERIOD`, so it may already be
+    /// closed; it is only loaded to pay a keeper reward
+    #[account(mut)]
+    pub market: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        close = rent_receiver,
+        constraint = settlement.market == market.key() @ DuskError::InvalidMarketConfig,
+        constraint = settlement.settled @ DuskError::TradeNotSettled
+    )]
+    pub settlement: Account<'info, TradeSettlement>,
+
+    /// CHECK: Must match the payer recorded on the settlement
+    #[account(
+        mut,
+        address = settlement.payer @ DuskError::Unauthorized
+    )]
+    pub rent_receiver: UncheckedAccount<'info>,
+
+    /// Caller's position on this market, credited with the keeper reward
+    #[account(
+        mut,
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            caller.key().as_ref(),
+            keeper_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = keeper_position.bump
+    )]
+    pub keeper_position: Option<Account<'info, UserPosition>>,
+}
+
+pub fn handler(ctx: Context<CloseSettlement>) -> Result<()> {
+    let settlement = &ctx.accounts.settlement;
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(
+        settlement.retention_elapsed(now),
+        DuskError::RetentionPeriodActive
+    );
+
+    emit!(SettlementClosed {
+        market: settlement.market,
+        settlement: settlement.key(),
+        rent_receiver: settlement.payer,
+    });
+
+    msg!(
+        "Settlement {} closed, rent returned to {}",
+        settlement.key(),
+        settlement.payer
+    );
+
+    if let Some(keeper_position) = ctx.accounts.keeper_position.as_mut() {
+        pay_close_rewards(&ctx.accounts.market, keeper_position, 1)?;
+     else {
+        note_skipped_close_reward(&ctx.accounts.market)?;
+    }
+
+    Ok(())
+}
+
+/// Pay the keeper reward for `closed` settlements into the caller's position
+/// A keeper position only exists while its market is open, since
+/// `close_market` requires every position to be closed first
+fn pay_close_rewards(
+    market_info: &AccountInfo,
+    keeper_position: &mut UserPosition,
+    closed: usize,
+) -> Result<()> {
+    require_keys_eq!(*market_info.owner, crate::ID, DuskError::InvalidMarketConfig);
+    let mut market = Market::try_deserialize(&mut &market_info.try_borrow_data()?[..])?;
+
+    // One reward per settlement closed, as if each had been its own crank
+    let mut reward = 0u64;
+    for _ in 0..closed {
+        reward = reward
+            .checked_add(market.pay_keeper_reward(keeper_position)?)
+            .ok_or(DuskError::MathOverflow)?;
+    }
+    market.try_serialize(&mut &mut market_info.try_borrow_mut_data()?[..])?;
+
+    if reward > 0 {
+        emit!(KeeperRewarded {
+            market: market_info.key(),
+            keeper: keeper_position.owner,
+            crank: KeeperCrank::CloseSettlement,
+            amount: reward,
+        });
+    }
+
+    Ok(())
+}
+
+/// Report a close reward left in the fees because no keeper position was passed
+/// A market that is already closed has no reward to skip
+fn note_skipped_close_reward(market_info: &AccountInfo) -> Result<()> {
+    if *market_info.owner != crate::ID {
+        return Ok(());
+    }
+    let market = Market::try_deserialize(&mut &market_info.try_borrow_data()?[..])?;
+    if market.keeper_reward > 0 {
+        emit!(KeeperRewardSkipped {
+            market: market_info.key(),
+            crank: KeeperCrank::CloseSettlement,
+        });
+    }
+
+    Ok(())
+}
+
+/// Close many settled trade accounts in one transaction
+/// Remaining accounts are passed as (settlement, rent_receiver) pairs
+#[derive(Accounts)]
+pub struct CloseSettlements<'info> {
+    /// Anyone can close expired settlements; rent always goes to the payers
+    pub caller: Signer<'info>,
+
+    /// CHECK: Market of the settlements; may already be closed, see `CloseSettlement`
+    #[account(mut)]
+    pub market: UncheckedAccount<'info>,
+
+    /// Caller's position on this market, credited with the keeper reward
+    #[account(
+        mut,
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            caller.key().as_ref(),
+            keeper_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = keeper_position.bump
+    )]
+    pub keeper_position: Option<Account<'info, UserPosition>>,
+}
+
+
+pub fn batch_handler<'info>(
+    ctx: Context<'_, '_, 'info, 'info, CloseSettlements<'info>>,
+) -> Result<()> {
+    let market_key = ctx.accounts.market.key();
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(
+        !ctx.remaining_accounts.is_empty() && ctx.remaining_accounts.len() % 2 == 0,
+        DuskError::InvalidSettlementAccountList
+    );
+
+    for pair in ctx.remaining_accounts.chunks_exact(2) {
+        let settlement_info = &pair[0];
+        let rent_receiver = &pair[1];
+
+        require!(settlement_info.is_writable, DuskError::SettlementNotWritable);
+
+        let settlement = Account::<TradeSettlement>::try_from(settlement_info)?;
+
+        require!(settlement.market == market_key, DuskError::InvalidMarketConfig);
+        require!(settlement.settled, DuskError::TradeNotSettled);
+        require!(
+            settlement.retention_elapsed(now),
+            DuskError::RetentionPeriodActive
+        );
+        require_keys_eq!(rent_receiver.key(), settlement.payer, DuskError::Unauthorized);
+
+        settlement.close(rent_receiver.clone())?;
+
+        emit!(SettlementClosed {
+            market: market_key,
+            settlement: settlement_info.key(),
+            rent_receiver: rent_receiver.key(),
+        });
+    }
+
+    msg!(
+        "Closed {} settlements on market {}",
+        ctx.remaining_accounts.len() / 2,
+        market_key
+    );
+
+    if let Some(keeper_position) = ctx.accounts.keeper_position.as_mut() {
+        pay_close_rewards(&ctx.accounts.market, keeper_position, ctx.remaining_accounts.len() / 2)?;
+     else {
+        note_skipped_close_reward(&ctx.accounts.market)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+
+use crate::state::{ExchangeConfig, Market};
+use crate::events::ProtocolFeesCollected;
+use crate::errors::DuskError;
+
+/// Collect the protocol share of a market's trading fees
+#[derive(Accounts)]
+pub struct CollectProtocolFees<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [ExchangeConfig::SEED_PREFIX],
+        bump = exchange_config.bump,
+        has_one = admin @ DuskError::Unauthorized
+    )]
+    pub exchange_config: Account<'info, ExchangeConfig>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    #[account(address = market.quote_mint @ DuskError::InvalidMarketConfig)]
+    pub quote_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        address = market.quote_vault @ DuskError::InvalidMarketConfig
+    )]
+    pub quote_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Token account receiving the fees
+    #[account(
+        mut,
+        constraint = destination.mint == market.quote_mint @ DuskError::InvalidMarketConfig
+    )]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = market.quote_token_program @ DuskError::InvalidMarketConfig)]
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+pub fn handler(ctx: Context<CollectProtocolFees>) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    let amount = market.protocol_fees_accrued;
+
+    require!(amount > 0, DuskError::AmountTooSmall);
+
+    let market_id_bytes = market.market_id.to_le_bytes();
+    let market_seeds = &[
+        Market::SEED_PREFIX,
+        market_id_bytes.as_ref(),
+        &[market.bump],
+    ];
+    let signer_seeds = &[&market_seeds[..]];
+
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.quote_vault.to_account_info(),
+                mint: ctx.accounts.quote_mint.to_account_info(),
+                to: ctx.accounts.destination.to_account_info(),
+                authority: market.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        amount,
+        ctx.accounts.quote_mint.decimals,
+    )?;
+
+    market.protocol_fees_accrued = 0;
+
+    emit!(ProtocolFeesCollected {
+        market: market.key(),
+        destination: ctx.accounts.destination.key(),
+        amount,
+    });
+
+    msg!(
+        "Collected {} quote tokens of protocol fees from market {}",
+        amount,
+        market.market_id
+    );
+
+    Ok(())
+}
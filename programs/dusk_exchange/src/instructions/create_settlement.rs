@@ -1,6 +1,7 @@
 use anchor_lang::prelude::*;
 
-use crate::state::{Market, TradeSettlement};
+use crate::state::{KeeperCrank, Market, OpenOrder, TradeSettlement, UserPosition, FILL_TERMS_FIELDS};
+use crate::events::{KeeperRewardSkipped, KeeperRewarded};
 use crate::errors::DuskError;
 
 /// Create a settlement account from pending match data stored in market
@@ -12,7 +13,8 @@ pub struct CreateSettlement<'info> {
 
     #[account(
         mut,
-        constraint = market.has_pending_match @ DuskError::NoMatchingOrders
+        constraint = market.has_pending_match @ DuskError::NoMatchingOrders,
+        constraint = !market.pending_match_sealed @ DuskError::SealedMatchNotOpened
     )]
     pub market: Account<'info, Market>,
 
@@ -31,6 +33,43 @@ pub struct CreateSettlement<'info> {
     )]
     pub settlement: Account<'info, TradeSettlement>,
 
+    /// Maker order of the pending match, which counts the fill until it is settled
+    #[account(
+        mut,
+        seeds = [
+            OpenOrder::SEED_PREFIX,
+            market.pending_maker.as_ref(),
+            market.pending_maker_order_id.to_le_bytes().as_ref()
+        ],
+        bump = maker_order.bump
+    )]
+    pub maker_order: Account<'info, OpenOrder>,
+
+    /// Taker order of the pending match, which counts the fill until it is settled
+    #[account(
+        mut,
+        seeds = [
+            OpenOrder::SEED_PREFIX,
+            market.pending_taker.as_ref(),
+            market.pending_taker_order_id.to_le_bytes().as_ref()
+        ],
+        bump = taker_order.bump
+    )]
+    pub taker_order: Account<'info, OpenOrder>,
+
+    /// Payer's position on this market, credited with the keeper reward
+    #[account(
+        mut,
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            payer.key().as_ref(),
+            keeper_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = keeper_position.bump
+    )]
+    pub keeper_position: Option<Account<'info, UserPosition>>,
+
     pub system_program: Program<'info, System>,
 }
 
@@ -41,6 +80,7 @@ pub fn handler(ctx: Context<CreateSettlement>) -> Result<()> {
     // Increment settlement count
     market.settlement_count = market.settlement_count.saturating_add(1);
     let settlement_id = market.settlement_count;
+    market.open_settlements = market.open_settlements.saturating_add(1);
 
     // Initialize the settlement account from pending match data
     settlement.market = market.key();
@@ -55,8 +95,31 @@ pub fn handler(ctx: Context<CreateSettlement>) -> Result<()> {
     settlement.settled = false;
     settlement.matched_at = market.pending_matched_at;
     settlement.settled_at = 0;
+    settlement.payer = ctx.accounts.payer.key();
+    // A sealed match carries its encrypted terms over; price and amount stay 0
+    settlement.sealed_fill = market.sealed_fills;
+    if market.sealed_fills {
+        settlement.fill_nonce = market.pending_fill_nonce;
+        settlement.encrypted_fill = market.pending_fill;
+        settlement.fill_commitment = market.pending_fill_commitment;
+    }
     settlement.bump = ctx.bumps.settlement;
 
+    // An order that left the book with this fill closes once its fills are settled
+    ctx.accounts.maker_order.record_fill(market.pending_maker_filled);
+    ctx.accounts.taker_order.record_fill(market.pending_taker_filled);
+
+    // Only an order the fill took out of the book stops counting as active;
+    // a partly filled one keeps resting
+    if market.pending_maker_filled {
+        let maker_order = &ctx.accounts.maker_order;
+        market.remove_active_order(maker_order.is_buy, maker_order.side_hidden);
+    }
+    if market.pending_taker_filled {
+        let taker_order = &ctx.accounts.taker_order;
+        market.remove_active_order(taker_order.is_buy, taker_order.side_hidden);
+    }
+
     // Clear pending match data
     market.has_pending_match = false;
     market.pending_maker = Pubkey::default();
@@ -65,7 +128,29 @@ pub fn handler(ctx: Context<CreateSettlement>) -> Result<()> {
     market.pending_taker_order_id = 0;
     market.pending_execution_price = 0;
     market.pending_execution_amount = 0;
+    market.pending_maker_filled = false;
+    market.pending_taker_filled = false;
     market.pending_matched_at = 0;
+    market.pending_fill_nonce = 0;
+    market.pending_fill = [[0u8; 32]; FILL_TERMS_FIELDS];
+    market.pending_fill_commitment = [0u8; 32];
+
+    if let Some(keeper_position) = ctx.accounts.keeper_position.as_mut() {
+        let reward = market.pay_keeper_reward(keeper_position)?;
+        if reward > 0 {
+            emit!(KeeperRewarded {
+                market: market.key(),
+                keeper: keeper_position.owner,
+                crank: KeeperCrank::CreateSettlement,
+                amount: reward,
+            });
+        }
+     else if market.keeper_reward > 0 {
+        emit!(KeeperRewardSkipped {
+            market: market.key(),
+            crank: KeeperCrank::CreateSettlement,
+        });
+    }
 
     msg!(
         "Settlement {} created: {} base @ {} price. Maker: {}, Taker: {}",
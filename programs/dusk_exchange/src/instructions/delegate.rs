@@ -0,0 +1,83 @@
+use anchor_lang::prelude::*;
+
+use crate::state::{Market, UserPosition};
+use crate::events::{DelegateRevoked, DelegateSet};
+use crate::errors::DuskError;
+
+/// Set or revoke the trading delegate of a position
+/// Only the owner can manage delegates
+#[derive(Accounts)]
+pub struct ManageDelegate<'info> {
+    pub user: Signer<'info>,
+
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            user.key().as_ref(),
+            user_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = user_position.bump,
+        constraint = user_position.owner == user.key() @ DuskError::Unauthorized
+    )]
+    pub user_position: Account<'info, UserPosition>,
+}
+
+pub fn set_handler(ctx: Context<ManageDelegate>, delegate: Pubkey, expires_at: i64) -> Result<()> {
+    let user_position = &mut ctx.accounts.user_position;
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(
+        delegate != Pubkey::default() && delegate != user_position.owner,
+        DuskError::InvalidDelegate
+    );
+    require!(
+        expires_at == 0 || expires_at > now,
+        DuskError::InvalidDelegateExpiry
+    );
+
+    user_position.delegate = delegate;
+    user_position.delegate_expires_at = expires_at;
+
+    emit!(DelegateSet {
+        market: ctx.accounts.market.key(),
+        owner: user_position.owner,
+        delegate,
+        expires_at,
+    });
+
+    msg!(
+        "Delegate {} set for user {} on market {}",
+        delegate,
+        user_position.owner,
+        ctx.accounts.market.market_id
+    );
+
+    Ok(())
+}
+
+pub fn revoke_handler(ctx: Context<ManageDelegate>) -> Result<()> {
+    let user_position = &mut ctx.accounts.user_position;
+    let delegate = user_position.delegate;
+
+    user_position.delegate = Pubkey::default();
+    user_position.delegate_expires_at = 0;
+
+    emit!(DelegateRevoked {
+        market: ctx.accounts.market.key(),
+        owner: user_position.owner,
+        delegate,
+    });
+
+    msg!(
+        "Delegate {} revoked for user {} on market {}",
+        delegate,
+        user_position.owner,
+        ctx.accounts.market.market_id
+    );
+
+    Ok(())
+}
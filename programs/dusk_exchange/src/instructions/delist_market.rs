@@ -0,0 +1,34 @@
+use anchor_lang::prelude::*;
+
+use crate::state::{Market, MarketStatus};
+use crate::events::MarketStatusChanged;
+use crate::errors::DuskError;
+
+/// Start delisting a market: only cancellations are accepted from now on
+#[derive(Accounts)]
+pub struct DelistMarket<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority @ DuskError::Unauthorized
+    )]
+    pub market: Account<'info, Market>,
+}
+
+pub fn handler(ctx: Context<DelistMarket>) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+
+    require!(!market.is_delisted(), DuskError::MarketDelisted);
+
+    market.status = MarketStatus::CancelOnly;
+
+    emit!(MarketStatusChanged {
+        market: market.key(),
+        status: market.status,
+    });
+
+    msg!("Market {} is now cancel-only", market.market_id);
+
+    Ok(())
+}
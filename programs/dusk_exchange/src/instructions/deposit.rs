@@ -1,11 +1,12 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
 
 use crate::state::{Market, UserPosition};
 use crate::events::Deposited;
 use crate::errors::DuskError;
 
 #[derive(Accounts)]
+#[instruction(amount: u64, is_base: bool, sub_account_id: u16)]
 pub struct Deposit<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
@@ -20,92 +21,123 @@ pub struct Deposit<'info> {
         seeds = [
             UserPosition::SEED_PREFIX,
             market.key().as_ref(),
-            user.key().as_ref()
+            user.key().as_ref(),
+            sub_account_id.to_le_bytes().as_ref()
         ],
         bump
     )]
     pub user_position: Account<'info, UserPosition>,
 
+    /// Mint of the token being deposited (base or quote)
+    #[account(
+        constraint = mint.key() == market.base_mint || mint.key() == market.quote_mint
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+
     /// User's token account to deposit from
     #[account(
         mut,
         constraint = user_token_account.owner == user.key()
     )]
-    pub user_token_account: Account<'info, TokenAccount>,
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
 
     /// Market vault to deposit into (base or quote)
     #[account(
         mut,
         constraint = vault.key() == market.base_vault || vault.key() == market.quote_vault
     )]
-    pub vault: Account<'info, TokenAccount>,
+    pub vault: InterfaceAccount<'info, TokenAccount>,
 
-    pub token_program: Program<'info, Token>,
+    /// Token program of the mint (SPL Token or Token-2022)
+    pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
 }
 
-pub fn handler(ctx: Context<Deposit>, amount: u64, is_base: bool) -> Result<()> {
+pub fn handler(ctx: Context<Deposit>, amount: u64, is_base: bool, sub_account_id: u16) -> Result<()> {
     require!(amount > 0, DuskError::AmountTooSmall);
 
     let user_position = &mut ctx.accounts.user_position;
-    let market = &ctx.accounts.market;
+    let market = &mut ctx.accounts.market;
+
+    require!(!market.is_delisted(), DuskError::MarketDelisted);
+    require!(!market.confidential_balances, DuskError::ConfidentialMarket);
 
     // Initialize user position if new
     if user_position.owner == Pubkey::default() {
         user_position.owner = ctx.accounts.user.key();
         user_position.market = market.key();
+        user_position.sub_account_id = sub_account_id;
         user_position.bump = ctx.bumps.user_position;
+        market.open_position_count = market
+            .open_position_count
+            .checked_add(1)
+            .ok_or(DuskError::MathOverflow)?;
     }
 
-    // Verify correct vault
-    let expected_vault = if is_base {
-        market.base_vault
+    // Verify correct vault, mint and token program
+    let (expected_vault, expected_mint, expected_token_program) = if is_base {
+        (market.base_vault, market.base_mint, market.base_token_program)
     } else {
-        market.quote_vault
+        (market.quote_vault, market.quote_mint, market.quote_token_program)
     };
     require!(
-        ctx.accounts.vault.key() == expected_vault,
+        ctx.accounts.vault.key() == expected_vault
+            && ctx.accounts.mint.key() == expected_mint
+            && ctx.accounts.token_program.key() == expected_token_program,
         DuskError::InvalidMarketConfig
     );
 
     // Transfer tokens from user to vault
-    let transfer_accounts = Transfer {
+    let transfer_accounts = TransferChecked {
         from: ctx.accounts.user_token_account.to_account_info(),
+        mint: ctx.accounts.mint.to_account_info(),
         to: ctx.accounts.vault.to_account_info(),
         authority: ctx.accounts.user.to_account_info(),
     };
 
-    token::transfer(
+    let vault_balance_before = ctx.accounts.vault.amount;
+
+    token_interface::transfer_checked(
         CpiContext::new(
             ctx.accounts.token_program.to_account_info(),
             transfer_accounts,
         ),
         amount,
+        ctx.accounts.mint.decimals,
     )?;
 
+    // Credit what actually arrived: Token-2022 transfer fees are withheld
+    // from the destination
+    ctx.accounts.vault.reload()?;
+    let received = ctx.accounts.vault.amount
+        .checked_sub(vault_balance_before)
+        .ok_or(DuskError::MathOverflow)?;
+    require!(received > 0, DuskError::AmountTooSmall);
+
     // Update user position
     if is_base {
         user_position.base_deposited = user_position
             .base_deposited
-            .checked_add(amount)
+            .checked_add(received)
             .ok_or(DuskError::MathOverflow)?;
     } else {
         user_position.quote_deposited = user_position
             .quote_deposited
-            .checked_add(amount)
+            .checked_add(received)
             .ok_or(DuskError::MathOverflow)?;
     }
+    market.credit_deposit(received, is_base)?;
 
     emit!(Deposited {
         market: market.key(),
         user: ctx.accounts.user.key(),
-        amount,
+        amount: received,
         is_base,
     });
 
     msg!(
         "Deposited {} {} tokens to market {}",
-        amount,
+        received,
         if is_base { "base" } else { "quote" },
         market.market_id
     );
@@ -0,0 +1,315 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use arcium_anchor::prelude::*;
+use arcium_client::idl::arcium::{types::CallbackAccount, ID_CONST};
+
+use crate::ID;
+use crate::state::{ConfidentialBalance, Market, UserPosition};
+use crate::events::Deposited;
+use crate::errors::DuskError;
+use crate::instructions::place_order::SignerAccount;
+use crate::CreditBalanceOutput;
+
+/// Computation definition offset for credit_balance
+pub const COMP_DEF_OFFSET_CREDIT_BALANCE: u8 = 7;
+
+/// Deposit tokens into a confidential balance
+/// The transfer into the vault is public; the credit happens inside the MXE
+#[queue_computation_accounts("credit_balance", user)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct DepositConfidential<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            user.key().as_ref(),
+            user_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = user_position.bump
+    )]
+    pub user_position: Account<'info, UserPosition>,
+
+    #[account(
+        mut,
+        seeds = [ConfidentialBalance::SEED_PREFIX, user_position.key().as_ref()],
+        bump = confidential_balance.bump,
+        constraint = confidential_balance.initialized @ DuskError::ConfidentialBalanceNotInitialized
+    )]
+    pub confidential_balance: Account<'info, ConfidentialBalance>,
+
+    /// Mint of the token being deposited (base or quote)
+    #[account(
+        constraint = mint.key() == market.base_mint || mint.key() == market.quote_mint
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// User's token account to deposit from
+    #[account(
+        mut,
+        constraint = user_token_account.owner == user.key()
+    )]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Market vault to deposit into (base or quote)
+    #[account(
+        mut,
+        constraint = vault.key() == market.base_vault || vault.key() == market.quote_vault
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Token program of the mint (SPL Token or Token-2022)
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// Signer PDA for CPI to Arcium
+    #[account(
+        init_if_needed,
+        space = 9,
+        payer = user,
+        seeds = [&SIGN_PDA_SEED],
+        bump
+    )]
+    pub sign_pda_account: Account<'info, SignerAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Mempool account
+    #[account(mut)]
+    pub mempool_account: UncheckedAccount<'info>,
+
+    /// CHECK: Executing pool account
+    #[account(mut)]
+    pub executing_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Computation account at the PDA of `computation_offset` (will be initialized)
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset, mxe_account, DuskError::ClusterNotSet)
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    /// Computation definition account for credit_balance
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_CREDIT_BALANCE))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    /// Cluster account
+    #[account(mut, address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// Pool account (Arcium fee pool)
+    #[account(mut)]
+    pub pool_account: Account<'info, FeePool>,
+
+    /// Clock account
+    pub clock_account: Account<'info, ClockAccount>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(
+    ctx: Context<DepositConfidential>,
+    computation_offset: u64,
+    amount: u64,
+    is_base: bool,
+) -> Result<()> {
+    // Set the sign_pda_account bump for CPI signing
+    ctx.accounts.sign_pda_account.bump = ctx.bumps.sign_pda_account;
+
+    let market = &mut ctx.accounts.market;
+    require!(market.confidential_balances, DuskError::NotConfidentialMarket);
+    require!(!market.is_delisted(), DuskError::MarketDelisted);
+
+    let now = Clock::get()?.unix_timestamp;
+    require!(
+        !ctx.accounts.confidential_balance.is_busy(now),
+        DuskError::ConfidentialBalanceBusy
+    );
+
+    // A zero deposit only retries the credit of earlier deposits whose
+    // computation never called back
+    require!(
+        amount > 0 || ctx.accounts.confidential_balance.has_pending_credit(),
+        DuskError::AmountTooSmall
+    );
+
+    if amount > 0 {
+        // Verify correct vault, mint and token program
+        let (expected_vault, expected_mint, expected_token_program) = if is_base {
+            (market.base_vault, market.base_mint, market.base_token_program)
+        } else {
+            (market.quote_vault, market.quote_mint, market.quote_token_program)
+        };
+        require!(
+            ctx.accounts.vault.key() == expected_vault
+                && ctx.accounts.mint.key() == expected_mint
+                && ctx.accounts.token_program.key() == expected_token_program,
+            DuskError::InvalidMarketConfig
+        );
+
+        // Transfer tokens from user to vault
+        let transfer_accounts = TransferChecked {
+            from: ctx.accounts.user_token_account.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.vault.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+
+        let vault_balance_before = ctx.accounts.vault.amount;
+
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                transfer_accounts,
+            ),
+            amount,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        // Credit what actually arrived: Token-2022 transfer fees are withheld
+        // from the destination
+        ctx.accounts.vault.reload()?;
+        let received = ctx.accounts.vault.amount
+            .checked_sub(vault_balance_before)
+            .ok_or(DuskError::MathOverflow)?;
+        require!(received > 0, DuskError::AmountTooSmall);
+
+        // Counted until the credit_balance callback adds it to the encrypted balance
+        let confidential_balance = &mut ctx.accounts.confidential_balance;
+        if is_base {
+            confidential_balance.pending_base_credit = confidential_balance
+                .pending_base_credit
+                .checked_add(received)
+                .ok_or(DuskError::MathOverflow)?;
+        } else {
+            confidential_balance.pending_quote_credit = confidential_balance
+                .pending_quote_credit
+                .checked_add(received)
+                .ok_or(DuskError::MathOverflow)?;
+        }
+        market.credit_deposit(received, is_base)?;
+
+        emit!(Deposited {
+            market: market.key(),
+            user: ctx.accounts.user.key(),
+            amount: received,
+            is_base,
+        });
+    }
+
+    // Reserve this market's next computation offset for the credit_balance job
+    let market_key = market.key();
+    market.claim_computation_offset(&market_key, computation_offset)?;
+    ctx.accounts.confidential_balance.begin_computation(computation_offset, now);
+
+    // Everything not yet credited goes in one computation, so a stuck credit
+    // is folded into the next deposit
+    let confidential_balance = &ctx.accounts.confidential_balance;
+    let computation_args = ArgBuilder::new()
+        .plaintext_u64(confidential_balance.pending_base_credit)
+        .plaintext_u64(confidential_balance.pending_quote_credit)
+        .plaintext_u128(confidential_balance.state_nonce)
+        .account(
+            confidential_balance.key(),
+            ConfidentialBalance::STATE_OFFSET,
+            ConfidentialBalance::STATE_LEN,
+        )
+        .build();
+
+    // Define callback accounts
+    let callback_accounts = vec![
+        CallbackAccount {
+            pubkey: confidential_balance.key(),
+            is_writable: true,
+        },
+    ];
+
+    // Queue the encrypted computation
+    queue_computation(
+        ctx.accounts,
+        computation_offset,
+        computation_args,
+        None,
+        vec![CreditBalanceCallback::callback_ix(
+            computation_offset,
+            &ctx.accounts.mxe_account,
+            &callback_accounts,
+        )?],
+        1, // returns the credited balance
+        0, // tip
+    )?;
+
+    msg!(
+        "Confidential deposit of {} {} tokens queued on market {}",
+        amount,
+        if is_base { "base" } else { "quote" },
+        ctx.accounts.market.market_id
+    );
+
+    Ok(())
+}
+
+/// Callback for credit_balance computation
+#[callback_accounts("credit_balance")]
+#[derive(Accounts)]
+pub struct CreditBalanceCallback<'info> {
+    pub arcium_program: Program<'info, Arcium>,
+
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_CREDIT_BALANCE))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Computation account, must be the latest one queued on this balance
+    #[account(
+        address = derive_comp_pda!(confidential_balance.computation_offset, mxe_account, DuskError::ClusterNotSet)
+            @ DuskError::ComputationMismatch
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// CHECK: Instructions sysvar, checked by the address constraint
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub confidential_balance: Account<'info, ConfidentialBalance>,
+}
+
+pub fn callback_handler(
+    ctx: Context<CreditBalanceCallback>,
+    output: SignedComputationOutputs<CreditBalanceOutput>,
+) -> Result<()> {
+    // Only an output signed by the cluster for this computation is accepted
+    let balance = match output.verify_output(
+        &ctx.accounts.cluster_account,
+        &ctx.accounts.computation_account,
+    ) {
+        Ok(CreditBalanceOutput { field_0 }) => field_0,
+        Err(_) => return Err(DuskError::AbortedComputation.into()),
+    };
+
+    // The computation credited every pending deposit at the time it was queued,
+    // and no deposit can be added while it runs
+    let confidential_balance = &mut ctx.accounts.confidential_balance;
+    confidential_balance.store_state(balance.nonce, balance.ciphertexts);
+    confidential_balance.pending_base_credit = 0;
+    confidential_balance.pending_quote_credit = 0;
+
+    msg!(
+        "Deposits credited to confidential balance of position {}",
+        confidential_balance.position
+    );
+
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{self, Transfer};
+use anchor_spl::token_interface::{self, SyncNative, TokenAccount, TokenInterface};
+
+use crate::state::{Market, UserPosition};
+use crate::events::Deposited;
+use crate::errors::DuskError;
+
+/// Deposit native SOL into a market whose base or quote mint is wSOL
+/// Lamports are wrapped directly into the market vault
+#[derive(Accounts)]
+#[instruction(amount: u64, sub_account_id: u16)]
+pub struct DepositNative<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = UserPosition::LEN,
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            user.key().as_ref(),
+            sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub user_position: Account<'info, UserPosition>,
+
+    /// Market wSOL vault (base or quote)
+    #[account(
+        mut,
+        constraint = vault.key() == market.base_vault || vault.key() == market.quote_vault
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Token program of the wSOL side
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(ctx: Context<DepositNative>, amount: u64, sub_account_id: u16) -> Result<()> {
+    require!(amount > 0, DuskError::AmountTooSmall);
+
+    let user_position = &mut ctx.accounts.user_position;
+    let market = &mut ctx.accounts.market;
+
+    require!(!market.is_delisted(), DuskError::MarketDelisted);
+    require!(!market.confidential_balances, DuskError::ConfidentialMarket);
+
+    let is_base = market.native_side().ok_or(DuskError::NotNativeMarket)?;
+
+    // Initialize user position if new
+    if user_position.owner == Pubkey::default() {
+        user_position.owner = ctx.accounts.user.key();
+        user_position.market = market.key();
+        user_position.sub_account_id = sub_account_id;
+        user_position.bump = ctx.bumps.user_position;
+        market.open_position_count = market
+            .open_position_count
+            .checked_add(1)
+            .ok_or(DuskError::MathOverflow)?;
+    }
+
+    // Verify correct vault and token program
+    let (expected_vault, expected_token_program) = if is_base {
+        (market.base_vault, market.base_token_program)
+    } else {
+        (market.quote_vault, market.quote_token_program)
+    };
+    require!(
+        ctx.accounts.vault.key() == expected_vault
+            && ctx.accounts.token_program.key() == expected_token_program,
+        DuskError::InvalidMarketConfig
+    );
+
+    // Move lamports into the vault, then sync its token balance
+    system_program::transfer(
+        CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.user.to_account_info(),
+                to: ctx.accounts.vault.to_account_info(),
+            },
+        ),
+        amount,
+    )?;
+
+    token_interface::sync_native(CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        SyncNative {
+            account: ctx.accounts.vault.to_account_info(),
+        },
+    ))?;
+
+    // Update user position
+    if is_base {
+        user_position.base_deposited = user_position
+            .base_deposited
+            .checked_add(amount)
+            .ok_or(DuskError::MathOverflow)?;
+    } else {
+        user_position.quote_deposited = user_position
+            .quote_deposited
+            .checked_add(amount)
+            .ok_or(DuskError::MathOverflow)?;
+    }
+    market.credit_deposit(amount, is_base)?;
+
+    emit!(Deposited {
+        market: market.key(),
+        user: ctx.accounts.user.key(),
+        amount,
+        is_base,
+    });
+
+    msg!(
+        "Deposited {} lamports as {} wSOL to market {}",
+        amount,
+        if is_base { "base" } else { "quote" },
+        market.market_id
+    );
+
+    Ok(())
+}
@@ -0,0 +1,246 @@
+use anchor_lang::prelude::*;
+use arcium_anchor::prelude::*;
+use arcium_client::idl::arcium::{types::CallbackAccount, ID_CONST};
+
+use crate::ID;
+use crate::state::{DisclosureKind, DisclosureRecord, Market, OrderBook, UserPosition};
+use crate::events::{DisclosureCompleted, DisclosureRequested};
+use crate::errors::DuskError;
+use crate::instructions::place_order::SignerAccount;
+use crate::DiscloseOrdersOutput;
+
+/// Computation definition offset for disclose_orders
+pub const COMP_DEF_OFFSET_DISCLOSE_ORDERS: u8 = 16;
+
+/// Disclose a position's resting orders to its registered auditor
+/// The orders are re-encrypted under the auditor key and kept in a new disclosure record
+#[queue_computation_accounts("disclose_orders", user)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct DiscloseOrders<'info> {
+    /// Position owner
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// Encrypted order book of this market
+    #[account(
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig,
+        constraint = order_book.initialized @ DuskError::OrderBookNotInitialized
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    #[account(
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            user.key().as_ref(),
+            user_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = user_position.bump,
+        constraint = user_position.owner == user.key() @ DuskError::Unauthorized,
+        constraint = user_position.has_auditor() @ DuskError::NoAuditorKey
+    )]
+    pub user_position: Account<'info, UserPosition>,
+
+    /// Record of this disclosure, filled in by the callback
+    #[account(
+        init,
+        payer = user,
+        space = DisclosureRecord::LEN,
+        seeds = [
+            DisclosureRecord::SEED_PREFIX,
+            user_position.key().as_ref(),
+            computation_offset.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub disclosure_record: Account<'info, DisclosureRecord>,
+
+    /// Signer PDA for CPI to Arcium
+    #[account(
+        init_if_needed,
+        space = 9,
+        payer = user,
+        seeds = [&SIGN_PDA_SEED],
+        bump
+    )]
+    pub sign_pda_account: Account<'info, SignerAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Mempool account
+    #[account(mut)]
+    pub mempool_account: UncheckedAccount<'info>,
+
+    /// CHECK: Executing pool account
+    #[account(mut)]
+    pub executing_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Computation account at the PDA of `computation_offset` (will be initialized)
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset, mxe_account, DuskError::ClusterNotSet)
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    /// Computation definition account for disclose_orders
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_DISCLOSE_ORDERS))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    /// Cluster account
+    #[account(mut, address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// Pool account (Arcium fee pool)
+    #[account(mut)]
+    pub pool_account: Account<'info, FeePool>,
+
+    /// Clock account
+    pub clock_account: Account<'info, ClockAccount>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+/// `nonce` is the nonce of the auditor copy; it must not be reused with the same auditor key
+pub fn handler(ctx: Context<DiscloseOrders>, computation_offset: u64, nonce: u128) -> Result<()> {
+    // Set the sign_pda_account bump for CPI signing
+    ctx.accounts.sign_pda_account.bump = ctx.bumps.sign_pda_account;
+
+    // Reserve this market's next computation offset for the disclose_orders job
+    let market_key = ctx.accounts.market.key();
+    ctx.accounts.market.claim_computation_offset(&market_key, computation_offset)?;
+
+    let auditor_pubkey = ctx.accounts.user_position.auditor_pubkey;
+    let user_position_key = ctx.accounts.user_position.key();
+
+    let record = &mut ctx.accounts.disclosure_record;
+    record.market = market_key;
+    record.position = user_position_key;
+    record.auditor_pubkey = auditor_pubkey;
+    record.kind = DisclosureKind::Orders;
+    record.settlement = Pubkey::default();
+    record.computation_offset = computation_offset;
+    record.requested_at = Clock::get()?.unix_timestamp;
+    record.completed = false;
+    record.bump = ctx.bumps.disclosure_record;
+
+    // Orders in the book are identified by the sub-account position
+    let (owner_lo, owner_hi) = UserPosition::split_key(&user_position_key);
+
+    let computation_args = ArgBuilder::new()
+        .x25519_pubkey(auditor_pubkey)
+        .plaintext_u128(nonce)
+        .plaintext_u128(owner_lo)
+        .plaintext_u128(owner_hi)
+        // Current encrypted book of this market
+        .plaintext_u128(ctx.accounts.order_book.state_nonce)
+        .account(
+            ctx.accounts.order_book.key(),
+            OrderBook::STATE_OFFSET,
+            OrderBook::STATE_LEN,
+        )
+        .build();
+
+    // Define callback accounts
+    let callback_accounts = vec![CallbackAccount {
+        pubkey: ctx.accounts.disclosure_record.key(),
+        is_writable: true,
+    }];
+
+    // Queue the encrypted computation
+    queue_computation(
+        ctx.accounts,
+        computation_offset,
+        computation_args,
+        None,
+        vec![DiscloseOrdersCallback::callback_ix(
+            computation_offset,
+            &ctx.accounts.mxe_account,
+            &callback_accounts,
+        )?],
+        1, // returns the orders encrypted to the auditor
+        0, // tip
+    )?;
+
+    emit!(DisclosureRequested {
+        market: market_key,
+        position: user_position_key,
+        record: ctx.accounts.disclosure_record.key(),
+        kind: DisclosureKind::Orders,
+        settlement: Pubkey::default(),
+        auditor_pubkey,
+        computation_offset,
+    });
+
+    msg!(
+        "Order disclosure requested for user {} on market {}",
+        ctx.accounts.user.key(),
+        ctx.accounts.market.market_id
+    );
+
+    Ok(())
+}
+
+/// Callback for disclose_orders computation
+#[callback_accounts("disclose_orders")]
+#[derive(Accounts)]
+pub struct DiscloseOrdersCallback<'info> {
+    pub arcium_program: Program<'info, Arcium>,
+
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_DISCLOSE_ORDERS))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Computation account, must be the one queued for this record
+    #[account(
+        address = derive_comp_pda!(disclosure_record.computation_offset, mxe_account, DuskError::ClusterNotSet)
+            @ DuskError::ComputationMismatch
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// CHECK: Instructions sysvar, checked by the address constraint
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub disclosure_record: Account<'info, DisclosureRecord>,
+}
+
+pub fn callback_handler(
+    ctx: Context<DiscloseOrdersCallback>,
+    output: SignedComputationOutputs<DiscloseOrdersOutput>,
+) -> Result<()> {
+    // Only an output signed by the cluster for this computation is accepted
+    let disclosed = match output.verify_output(
+        &ctx.accounts.cluster_account,
+        &ctx.accounts.computation_account,
+    ) {
+        Ok(DiscloseOrdersOutput { field_0 }) => field_0,
+        Err(_) => return Err(DuskError::AbortedComputation.into()),
+    };
+
+    let record = &mut ctx.accounts.disclosure_record;
+    record.result_nonce = disclosed.nonce;
+    record.result = disclosed.ciphertexts;
+    record.completed = true;
+
+    emit!(DisclosureCompleted {
+        market: record.market,
+        position: record.position,
+        record: record.key(),
+    });
+
+    msg!("Orders of position {} disclosed to its auditor", record.position);
+
+    Ok(())
+}
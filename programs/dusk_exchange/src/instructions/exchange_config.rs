@@ -0,0 +1,122 @@
+use anchor_lang::prelude::*;
+
+use crate::state::ExchangeConfig;
+use crate::events::ExchangeConfigUpdated;
+use crate::errors::DuskError;
+
+/// Create the global exchange config; the payer becomes the protocol admin
+/// Only the program's upgrade authority may do this, so the deployment
+/// cannot be front-run by someone claiming the admin role
+#[derive(Accounts)]
+pub struct InitializeExchange<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    /// Upgrade authority of this program
+    pub upgrade_authority: Signer<'info>,
+
+    #[account(
+        constraint = program.programdata_address()? == Some(program_data.key())
+            @ DuskError::Unauthorized
+    )]
+    pub program: Program<'info, crate::program::DuskExchange>,
+
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(upgrade_authority.key())
+            @ DuskError::Unauthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = ExchangeConfig::LEN,
+        seeds = [ExchangeConfig::SEED_PREFIX],
+        bump
+    )]
+    pub exchange_config: Account<'info, ExchangeConfig>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn initialize_handler(
+    ctx: Context<InitializeExchange>,
+    permissionless_markets: bool,
+    protocol_fee_share_bps: u16,
+) -> Result<()> {
+    require!(
+        protocol_fee_share_bps <= ExchangeConfig::MAX_PROTOCOL_FEE_SHARE_BPS,
+        DuskError::InvalidFeeShare
+    );
+
+    let exchange_config = &mut ctx.accounts.exchange_config;
+
+    exchange_config.admin = ctx.accounts.admin.key();
+    exchange_config.permissionless_markets = permissionless_markets;
+    exchange_config.protocol_fee_share_bps = protocol_fee_share_bps;
+    exchange_config.market_count = 0;
+    exchange_config.bump = ctx.bumps.exchange_config;
+
+    emit!(ExchangeConfigUpdated {
+        admin: exchange_config.admin,
+        permissionless_markets,
+        protocol_fee_share_bps,
+    });
+
+    msg!(
+        "Exchange initialized (admin: {}, permissionless markets: {}, protocol fee share: {} bps)",
+        exchange_config.admin,
+        permissionless_markets,
+        protocol_fee_share_bps
+    );
+
+    Ok(())
+}
+
+/// Update the global exchange config
+#[derive(Accounts)]
+pub struct UpdateExchangeConfig<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [ExchangeConfig::SEED_PREFIX],
+        bump = exchange_config.bump,
+        has_one = admin @ DuskError::Unauthorized
+    )]
+    pub exchange_config: Account<'info, ExchangeConfig>,
+}
+
+pub fn update_handler(
+    ctx: Context<UpdateExchangeConfig>,
+    new_admin: Pubkey,
+    permissionless_markets: bool,
+    protocol_fee_share_bps: u16,
+) -> Result<()> {
+    require!(new_admin != Pubkey::default(), DuskError::Unauthorized);
+    require!(
+        protocol_fee_share_bps <= ExchangeConfig::MAX_PROTOCOL_FEE_SHARE_BPS,
+        DuskError::InvalidFeeShare
+    );
+
+    let exchange_config = &mut ctx.accounts.exchange_config;
+
+    exchange_config.admin = new_admin;
+    exchange_config.permissionless_markets = permissionless_markets;
+    exchange_config.protocol_fee_share_bps = protocol_fee_share_bps;
+
+    emit!(ExchangeConfigUpdated {
+        admin: new_admin,
+        permissionless_markets,
+        protocol_fee_share_bps,
+    });
+
+    msg!(
+        "Exchange config updated (admin: {}, permissionless markets: {}, protocol fee share: {} bps)",
+        new_admin,
+        permissionless_markets,
+        protocol_fee_share_bps
+    );
+
+    Ok(())
+}
@@ -73,3 +73,435 @@ pub fn init_match_book_handler(ctx: Context<InitMatchBookCompDef>) -> Result<()>
     init_comp_def(ctx.accounts, None, None)?;
     Ok(())
 }
+
+/// Initialize computation definition for uncross_book
+#[init_computation_definition_accounts("uncross_book", payer)]
+#[derive(Accounts)]
+pub struct InitUncrossBookCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Arcium computation definition account (PDA derived by macro)
+    #[account(mut)]
+    pub comp_def_account: UncheckedAccount<'info>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn init_uncross_book_handler(ctx: Context<InitUncrossBookCompDef>) -> Result<()> {
+    msg!("Initializing uncross_book computation definition");
+    init_comp_def(ctx.accounts, None, None)?;
+    Ok(())
+}
+
+/// Initialize computation definition for clear_book
+#[init_computation_definition_accounts("clear_book", payer)]
+#[derive(Accounts)]
+pub struct InitClearBookCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Arcium computation definition account (PDA derived by macro)
+    #[account(mut)]
+    pub comp_def_account: UncheckedAccount<'info>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn init_clear_book_handler(ctx: Context<InitClearBookCompDef>) -> Result<()> {
+    msg!("Initializing clear_book computation definition");
+    init_comp_def(ctx.accounts, None, None)?;
+    Ok(())
+}
+
+/// Initialize computation definition for init_book
+#[init_computation_definition_accounts("init_book", payer)]
+#[derive(Accounts)]
+pub struct InitInitBookCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Arcium computation definition account (PDA derived by macro)
+    #[account(mut)]
+    pub comp_def_account: UncheckedAccount<'info>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn init_init_book_handler(ctx: Context<InitInitBookCompDef>) -> Result<()> {
+    msg!("Initializing init_book computation definition");
+    init_comp_def(ctx.accounts, None, None)?;
+    Ok(())
+}
+
+/// Initialize computation definition for init_balance
+#[init_computation_definition_accounts("init_balance", payer)]
+#[derive(Accounts)]
+pub struct InitInitBalanceCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Arcium computation definition account (PDA derived by macro)
+    #[account(mut)]
+    pub comp_def_account: UncheckedAccount<'info>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn init_init_balance_handler(ctx: Context<InitInitBalanceCompDef>) -> Result<()> {
+    msg!("Initializing init_balance computation definition");
+    init_comp_def(ctx.accounts, None, None)?;
+    Ok(())
+}
+
+/// Initialize computation definition for credit_balance
+#[init_computation_definition_accounts("credit_balance", payer)]
+#[derive(Accounts)]
+pub struct InitCreditBalanceCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Arcium computation definition account (PDA derived by macro)
+    #[account(mut)]
+    pub comp_def_account: UncheckedAccount<'info>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn init_credit_balance_handler(ctx: Context<InitCreditBalanceCompDef>) -> Result<()> {
+    msg!("Initializing credit_balance computation definition");
+    init_comp_def(ctx.accounts, None, None)?;
+    Ok(())
+}
+
+/// Initialize computation definition for debit_balance
+#[init_computation_definition_accounts("debit_balance", payer)]
+#[derive(Accounts)]
+pub struct InitDebitBalanceCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Arcium computation definition account (PDA derived by macro)
+    #[account(mut)]
+    pub comp_def_account: UncheckedAccount<'info>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn init_debit_balance_handler(ctx: Context<InitDebitBalanceCompDef>) -> Result<()> {
+    msg!("Initializing debit_balance computation definition");
+    init_comp_def(ctx.accounts, None, None)?;
+    Ok(())
+}
+
+/// Initialize computation definition for add_order_locked
+#[init_computation_definition_accounts("add_order_locked", payer)]
+#[derive(Accounts)]
+pub struct InitAddOrderLockedCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Arcium computation definition account (PDA derived by macro)
+    #[account(mut)]
+    pub comp_def_account: UncheckedAccount<'info>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn init_add_order_locked_handler(ctx: Context<InitAddOrderLockedCompDef>) -> Result<()> {
+    msg!("Initializing add_order_locked computation definition");
+    init_comp_def(ctx.accounts, None, None)?;
+    Ok(())
+}
+
+/// Initialize computation definition for remove_order_unlocked
+#[init_computation_definition_accounts("remove_order_unlocked", payer)]
+#[derive(Accounts)]
+pub struct InitRemoveOrderUnlockedCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Arcium computation definition account (PDA derived by macro)
+    #[account(mut)]
+    pub comp_def_account: UncheckedAccount<'info>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn init_remove_order_unlocked_handler(ctx: Context<InitRemoveOrderUnlockedCompDef>) -> Result<()> {
+    msg!("Initializing remove_order_unlocked computation definition");
+    init_comp_def(ctx.accounts, None, None)?;
+    Ok(())
+}
+
+/// Initialize computation definition for settle_fill
+#[init_computation_definition_accounts("settle_fill", payer)]
+#[derive(Accounts)]
+pub struct InitSettleFillCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Arcium computation definition account (PDA derived by macro)
+    #[account(mut)]
+    pub comp_def_account: UncheckedAccount<'info>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn init_settle_fill_handler(ctx: Context<InitSettleFillCompDef>) -> Result<()> {
+    msg!("Initializing settle_fill computation definition");
+    init_comp_def(ctx.accounts, None, None)?;
+    Ok(())
+}
+
+/// Initialize computation definition for balance_is_empty
+#[init_computation_definition_accounts("balance_is_empty", payer)]
+#[derive(Accounts)]
+pub struct InitBalanceIsEmptyCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Arcium computation definition account (PDA derived by macro)
+    #[account(mut)]
+    pub comp_def_account: UncheckedAccount<'info>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn init_balance_is_empty_handler(ctx: Context<InitBalanceIsEmptyCompDef>) -> Result<()> {
+    msg!("Initializing balance_is_empty computation definition");
+    init_comp_def(ctx.accounts, None, None)?;
+    Ok(())
+}
+
+/// Initialize computation definition for add_hidden_order
+#[init_computation_definition_accounts("add_hidden_order", payer)]
+#[derive(Accounts)]
+pub struct InitAddHiddenOrderCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Arcium computation definition account (PDA derived by macro)
+    #[account(mut)]
+    pub comp_def_account: UncheckedAccount<'info>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn init_add_hidden_order_handler(ctx: Context<InitAddHiddenOrderCompDef>) -> Result<()> {
+    msg!("Initializing add_hidden_order computation definition");
+    init_comp_def(ctx.accounts, None, None)?;
+    Ok(())
+}
+
+/// Initialize computation definition for match_book_sealed
+#[init_computation_definition_accounts("match_book_sealed", payer)]
+#[derive(Accounts)]
+pub struct InitMatchBookSealedCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Arcium computation definition account (PDA derived by macro)
+    #[account(mut)]
+    pub comp_def_account: UncheckedAccount<'info>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn init_match_book_sealed_handler(ctx: Context<InitMatchBookSealedCompDef>) -> Result<()> {
+    msg!("Initializing match_book_sealed computation definition");
+    init_comp_def(ctx.accounts, None, None)?;
+    Ok(())
+}
+
+/// Initialize computation definition for reveal_fill
+#[init_computation_definition_accounts("reveal_fill", payer)]
+#[derive(Accounts)]
+pub struct InitRevealFillCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Arcium computation definition account (PDA derived by macro)
+    #[account(mut)]
+    pub comp_def_account: UncheckedAccount<'info>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn init_reveal_fill_handler(ctx: Context<InitRevealFillCompDef>) -> Result<()> {
+    msg!("Initializing reveal_fill computation definition");
+    init_comp_def(ctx.accounts, None, None)?;
+    Ok(())
+}
+
+/// Initialize computation definition for disclose_orders
+#[init_computation_definition_accounts("disclose_orders", payer)]
+#[derive(Accounts)]
+pub struct InitDiscloseOrdersCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Arcium computation definition account (PDA derived by macro)
+    #[account(mut)]
+    pub comp_def_account: UncheckedAccount<'info>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn init_disclose_orders_handler(ctx: Context<InitDiscloseOrdersCompDef>) -> Result<()> {
+    msg!("Initializing disclose_orders computation definition");
+    init_comp_def(ctx.accounts, None, None)?;
+    Ok(())
+}
+
+/// Initialize computation definition for disclose_fill
+#[init_computation_definition_accounts("disclose_fill", payer)]
+#[derive(Accounts)]
+pub struct InitDiscloseFillCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Arcium computation definition account (PDA derived by macro)
+    #[account(mut)]
+    pub comp_def_account: UncheckedAccount<'info>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn init_disclose_fill_handler(ctx: Context<InitDiscloseFillCompDef>) -> Result<()> {
+    msg!("Initializing disclose_fill computation definition");
+    init_comp_def(ctx.accounts, None, None)?;
+    Ok(())
+}
+
+/// Initialize computation definition for query_my_orders
+#[init_computation_definition_accounts("query_my_orders", payer)]
+#[derive(Accounts)]
+pub struct InitQueryMyOrdersCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Arcium computation definition account (PDA derived by macro)
+    #[account(mut)]
+    pub comp_def_account: UncheckedAccount<'info>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn init_query_my_orders_handler(ctx: Context<InitQueryMyOrdersCompDef>) -> Result<()> {
+    msg!("Initializing query_my_orders computation definition");
+    init_comp_def(ctx.accounts, None, None)?;
+    Ok(())
+}
+
+/// Initialize computation definition for probe_cross
+#[init_computation_definition_accounts("probe_cross", payer)]
+#[derive(Accounts)]
+pub struct InitProbeCrossCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Arcium computation definition account (PDA derived by macro)
+    #[account(mut)]
+    pub comp_def_account: UncheckedAccount<'info>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn init_probe_cross_handler(ctx: Context<InitProbeCrossCompDef>) -> Result<()> {
+    msg!("Initializing probe_cross computation definition");
+    init_comp_def(ctx.accounts, None, None)?;
+    Ok(())
+}
+
+/// Initialize computation definition for open_sealed_match
+#[init_computation_definition_accounts("open_sealed_match", payer)]
+#[derive(Accounts)]
+pub struct InitOpenSealedMatchCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut, address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Arcium computation definition account (PDA derived by macro)
+    #[account(mut)]
+    pub comp_def_account: UncheckedAccount<'info>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn init_open_sealed_match_handler(ctx: Context<InitOpenSealedMatchCompDef>) -> Result<()> {
+    msg!("Initializing open_sealed_match computation definition");
+    init_comp_def(ctx.accounts, None, None)?;
+    Ok(())
+}
@@ -0,0 +1,240 @@
+use anchor_lang::prelude::*;
+use arcium_anchor::prelude::*;
+use arcium_client::idl::arcium::{types::CallbackAccount, ID_CONST};
+
+use crate::ID;
+use crate::state::{ConfidentialBalance, Market, UserPosition};
+use crate::errors::DuskError;
+use crate::instructions::place_order::SignerAccount;
+use crate::InitBalanceOutput;
+
+/// Computation definition offset for init_balance
+pub const COMP_DEF_OFFSET_INIT_BALANCE: u8 = 6;
+
+/// Open a position on a confidential market with an empty encrypted balance
+/// Calling it again for the same position retries a stuck initialization
+#[queue_computation_accounts("init_balance", user)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64, sub_account_id: u16)]
+pub struct InitConfidentialBalance<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = UserPosition::LEN,
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            user.key().as_ref(),
+            sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub user_position: Account<'info, UserPosition>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = ConfidentialBalance::LEN,
+        seeds = [ConfidentialBalance::SEED_PREFIX, user_position.key().as_ref()],
+        bump
+    )]
+    pub confidential_balance: Account<'info, ConfidentialBalance>,
+
+    /// Signer PDA for CPI to Arcium
+    #[account(
+        init_if_needed,
+        space = 9,
+        payer = user,
+        seeds = [&SIGN_PDA_SEED],
+        bump
+    )]
+    pub sign_pda_account: Account<'info, SignerAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Mempool account
+    #[account(mut)]
+    pub mempool_account: UncheckedAccount<'info>,
+
+    /// CHECK: Executing pool account
+    #[account(mut)]
+    pub executing_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Computation account at the PDA of `computation_offset` (will be initialized)
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset, mxe_account, DuskError::ClusterNotSet)
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    /// Computation definition account for init_balance
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_INIT_BALANCE))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    /// Cluster account
+    #[account(mut, address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// Pool account (Arcium fee pool)
+    #[account(mut)]
+    pub pool_account: Account<'info, FeePool>,
+
+    /// Clock account
+    pub clock_account: Account<'info, ClockAccount>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(
+    ctx: Context<InitConfidentialBalance>,
+    computation_offset: u64,
+    sub_account_id: u16,
+) -> Result<()> {
+    // Set the sign_pda_account bump for CPI signing
+    ctx.accounts.sign_pda_account.bump = ctx.bumps.sign_pda_account;
+
+    let market = &mut ctx.accounts.market;
+    require!(market.confidential_balances, DuskError::NotConfidentialMarket);
+    require!(!market.is_delisted(), DuskError::MarketDelisted);
+
+    let market_key = market.key();
+    let now = Clock::get()?.unix_timestamp;
+    let user_position = &mut ctx.accounts.user_position;
+    let confidential_balance = &mut ctx.accounts.confidential_balance;
+
+    if user_position.owner == Pubkey::default() {
+        // The public balances of the position stay zero; funds live in the
+        // encrypted balance instead
+        user_position.owner = ctx.accounts.user.key();
+        user_position.market = market_key;
+        user_position.sub_account_id = sub_account_id;
+        user_position.bump = ctx.bumps.user_position;
+        market.open_position_count = market
+            .open_position_count
+            .checked_add(1)
+            .ok_or(DuskError::MathOverflow)?;
+    }
+
+    if confidential_balance.position == Pubkey::default() {
+        confidential_balance.position = user_position.key();
+        confidential_balance.bump = ctx.bumps.confidential_balance;
+    } else {
+        // Only a request that never called back is retried
+        require!(
+            !confidential_balance.initialized,
+            DuskError::ConfidentialBalanceAlreadyInitialized
+        );
+        require!(
+            !confidential_balance.is_busy(now),
+            DuskError::ConfidentialBalanceBusy
+        );
+    }
+
+    // Reserve this market's next computation offset for the init_balance job
+    market.claim_computation_offset(&market_key, computation_offset)?;
+    confidential_balance.begin_computation(computation_offset, now);
+
+    // init_balance takes no arguments - it creates a fresh MXE-encrypted balance
+    let computation_args = ArgBuilder::new().build();
+
+    // Define callback accounts
+    let callback_accounts = vec![
+        CallbackAccount {
+            pubkey: ctx.accounts.confidential_balance.key(),
+            is_writable: true,
+        },
+    ];
+
+    // Queue the encrypted computation
+    queue_computation(
+        ctx.accounts,
+        computation_offset,
+        computation_args,
+        None,
+        vec![InitBalanceCallback::callback_ix(
+            computation_offset,
+            &ctx.accounts.mxe_account,
+            &callback_accounts,
+        )?],
+        1, // returns the encrypted empty balance
+        0, // tip
+    )?;
+
+    msg!(
+        "Confidential balance requested for user {} on market {}",
+        ctx.accounts.user.key(),
+        ctx.accounts.market.market_id
+    );
+
+    Ok(())
+}
+
+/// Callback for init_balance computation
+#[callback_accounts("init_balance")]
+#[derive(Accounts)]
+pub struct InitBalanceCallback<'info> {
+    pub arcium_program: Program<'info, Arcium>,
+
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_INIT_BALANCE))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Computation account, must be the latest one queued on this balance
+    #[account(
+        address = derive_comp_pda!(confidential_balance.computation_offset, mxe_account, DuskError::ClusterNotSet)
+            @ DuskError::ComputationMismatch
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// CHECK: Instructions sysvar, checked by the address constraint
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub confidential_balance: Account<'info, ConfidentialBalance>,
+}
+
+pub fn callback_handler(
+    ctx: Context<InitBalanceCallback>,
+    output: SignedComputationOutputs<InitBalanceOutput>,
+) -> Result<()> {
+    // Only an output signed by the cluster for this computation is accepted
+    let balance = match output.verify_output(
+        &ctx.accounts.cluster_account,
+        &ctx.accounts.computation_account,
+    ) {
+        Ok(InitBalanceOutput { field_0 }) => field_0,
+        Err(_) => return Err(DuskError::AbortedComputation.into()),
+    };
+
+    let confidential_balance = &mut ctx.accounts.confidential_balance;
+
+    // A retried request must not wipe a balance that is already in use
+    if confidential_balance.initialized {
+        msg!("Confidential balance {} already initialized", confidential_balance.key());
+        return Ok(());
+    }
+
+    confidential_balance.store_state(balance.nonce, balance.ciphertexts);
+    confidential_balance.initialized = true;
+
+    msg!(
+        "Confidential balance initialized for position {}",
+        confidential_balance.position
+    );
+
+    Ok(())
+}
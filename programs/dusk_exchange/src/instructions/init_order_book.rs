@@ -0,0 +1,196 @@
+use anchor_lang::prelude::*;
+use arcium_anchor::prelude::*;
+use arcium_client::idl::arcium::{types::CallbackAccount, ID_CONST};
+
+use crate::ID;
+use crate::state::{Market, OrderBook};
+use crate::errors::DuskError;
+use crate::instructions::place_order::SignerAccount;
+use crate::InitBookOutput;
+
+/// Computation definition offset for init_book
+pub const COMP_DEF_OFFSET_INIT_BOOK: u8 = 5;
+
+/// Queue `init_book` again for a market whose order book is still empty
+/// `initialize_market` queues it already; this retries once that computation
+/// has timed out without calling back
+#[queue_computation_accounts("init_book", payer)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct InitOrderBook<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    /// Signer PDA for CPI to Arcium
+    #[account(
+        init_if_needed,
+        space = 9,
+        payer = payer,
+        seeds = [&SIGN_PDA_SEED],
+        bump
+    )]
+    pub sign_pda_account: Account<'info, SignerAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Mempool account
+    #[account(mut)]
+    pub mempool_account: UncheckedAccount<'info>,
+
+    /// CHECK: Executing pool account
+    #[account(mut)]
+    pub executing_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Computation account at the PDA of `computation_offset` (will be initialized)
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset, mxe_account, DuskError::ClusterNotSet)
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    /// Computation definition account for init_book
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_INIT_BOOK))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    /// Cluster account
+    #[account(mut, address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// Pool account (Arcium fee pool)
+    #[account(mut)]
+    pub pool_account: Account<'info, FeePool>,
+
+    /// Clock account
+    pub clock_account: Account<'info, ClockAccount>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(ctx: Context<InitOrderBook>, computation_offset: u64) -> Result<()> {
+    // Set the sign_pda_account bump for CPI signing
+    ctx.accounts.sign_pda_account.bump = ctx.bumps.sign_pda_account;
+
+    require!(
+        !ctx.accounts.order_book.initialized,
+        DuskError::OrderBookAlreadyInitialized
+    );
+    let now = Clock::get()?.unix_timestamp;
+    require!(!ctx.accounts.order_book.is_busy(now), DuskError::OrderBookBusy);
+
+    // Reserve this market's next computation offset for the init_book job
+    let market_key = ctx.accounts.market.key();
+    ctx.accounts.market.claim_computation_offset(&market_key, computation_offset)?;
+    ctx.accounts.order_book.begin_computation(computation_offset, now);
+
+    // init_book takes no arguments - it creates a fresh MXE-encrypted state
+    let computation_args = ArgBuilder::new().build();
+
+    // Define callback accounts
+    let callback_accounts = vec![
+        CallbackAccount {
+            pubkey: market_key,
+            is_writable: false,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.order_book.key(),
+            is_writable: true,
+        },
+    ];
+
+    // Queue the encrypted computation
+    queue_computation(
+        ctx.accounts,
+        computation_offset,
+        computation_args,
+        None,
+        vec![InitBookCallback::callback_ix(
+            computation_offset,
+            &ctx.accounts.mxe_account,
+            &callback_accounts,
+        )?],
+        1, // returns the encrypted empty state
+        0, // tip
+    )?;
+
+    msg!(
+        "Order book initialization requested for market {}",
+        ctx.accounts.market.market_id
+    );
+
+    Ok(())
+}
+
+/// Callback for init_book computation
+#[callback_accounts("init_book")]
+#[derive(Accounts)]
+pub struct InitBookCallback<'info> {
+    pub arcium_program: Program<'info, Arcium>,
+
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_INIT_BOOK))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Computation account, must be the latest job queued on this order book
+    #[account(
+        address = derive_comp_pda!(order_book.computation_offset, mxe_account, DuskError::ClusterNotSet)
+            @ DuskError::ComputationMismatch
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// CHECK: Instructions sysvar, checked by the address constraint
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig
+    )]
+    pub order_book: Account<'info, OrderBook>,
+}
+
+pub fn callback_handler(
+    ctx: Context<InitBookCallback>,
+    output: SignedComputationOutputs<InitBookOutput>,
+) -> Result<()> {
+    // Only an output signed by the cluster for this computation is accepted
+    let book = match output.verify_output(
+        &ctx.accounts.cluster_account,
+        &ctx.accounts.computation_account,
+    ) {
+        Ok(InitBookOutput { field_0 }) => field_0,
+        Err(_) => return Err(DuskError::AbortedComputation.into()),
+    };
+
+    let order_book = &mut ctx.accounts.order_book;
+
+    // A duplicate request must not wipe a book that is already in use
+    if order_book.initialized {
+        msg!("Order book {} already initialized", order_book.key());
+        return Ok(());
+    }
+
+    order_book.store_state(book.nonce, book.ciphertexts);
+    order_book.initialized = true;
+
+    msg!("Order book initialized for market {}", order_book.market);
+
+    Ok(())
+}
@@ -1,11 +1,32 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{Mint, Token, TokenAccount};
+use arcium_anchor::prelude::*;
+use arcium_client::idl::arcium::{types::CallbackAccount, ID_CONST};
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{BaseStateWithExtensions, ExtensionType, StateWithExtensions},
+};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
 
-use crate::state::Market;
+use crate::ID;
+use crate::state::{ExchangeConfig, Market, MarketRegistryEntry, MarketStatus, OrderBook};
 use crate::events::MarketCreated;
+use crate::errors::DuskError;
+use crate::instructions::init_order_book::{InitBookCallback, COMP_DEF_OFFSET_INIT_BOOK};
+use crate::instructions::place_order::SignerAccount;
 
+/// Token-2022 extensions that let someone other than the market move or
+/// freeze vault funds, or that make vault transfers fail
+const UNSUPPORTED_MINT_EXTENSIONS: [ExtensionType; 4] = [
+    ExtensionType::PermanentDelegate,
+    ExtensionType::NonTransferable,
+    ExtensionType::TransferHook,
+    ExtensionType::PausableConfig,
+];
+
+/// Create a market with its vaults and queue the `init_book` job for its order book
+#[queue_computation_accounts("init_book", authority)]
 #[derive(Accounts)]
-#[instruction(market_id: u64)]
+#[instruction(computation_offset: u64, market_id: u64)]
 pub struct InitializeMarket<'info> {
     #[account(mut)]
     pub authority: Signer<'info>,
@@ -17,13 +38,48 @@ pub struct InitializeMarket<'info> {
         seeds = [Market::SEED_PREFIX, market_id.to_le_bytes().as_ref()],
         bump
     )]
-    pub market: Account<'info, Market>,
+    pub market: Box<Account<'info, Market>>,
+
+    /// Encrypted book of this market; its empty state is written by the `init_book` callback
+    #[account(
+        init,
+        payer = authority,
+        space = OrderBook::LEN,
+        seeds = [OrderBook::SEED_PREFIX, market.key().as_ref()],
+        bump
+    )]
+    pub order_book: Box<Account<'info, OrderBook>>,
+
+    /// Global config: decides who may create markets and counts them
+    #[account(
+        mut,
+        seeds = [ExchangeConfig::SEED_PREFIX],
+        bump = exchange_config.bump,
+        constraint = exchange_config.can_create_market(&authority.key()) @ DuskError::MarketCreationRestricted
+    )]
+    pub exchange_config: Account<'info, ExchangeConfig>,
+
+    /// Registry entry for this mint pair; creation fails if the pair already has a market
+    #[account(
+        init,
+        payer = authority,
+        space = MarketRegistryEntry::LEN,
+        seeds = [
+            MarketRegistryEntry::SEED_PREFIX,
+            base_mint.key().as_ref(),
+            quote_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub registry_entry: Account<'info, MarketRegistryEntry>,
 
     /// Base token mint (e.g., wSOL)
-    pub base_mint: Account<'info, Mint>,
+    #[account(mint::token_program = base_token_program)]
+    pub base_mint: InterfaceAccount<'info, Mint>,
 
     /// Quote token mint (e.g., USDC)
-    pub quote_mint: Account<'info, Mint>,
+    #[account(mint::token_program = quote_token_program)]
+    pub quote_mint: InterfaceAccount<'info, Mint>,
 
     /// Vault for base tokens
     #[account(
@@ -31,10 +87,11 @@ pub struct InitializeMarket<'info> {
         payer = authority,
         token::mint = base_mint,
         token::authority = market,
+        token::token_program = base_token_program,
         seeds = [b"base_vault", market.key().as_ref()],
         bump
     )]
-    pub base_vault: Account<'info, TokenAccount>,
+    pub base_vault: InterfaceAccount<'info, TokenAccount>,
 
     /// Vault for quote tokens
     #[account(
@@ -42,21 +99,104 @@ pub struct InitializeMarket<'info> {
         payer = authority,
         token::mint = quote_mint,
         token::authority = market,
+        token::token_program = quote_token_program,
         seeds = [b"quote_vault", market.key().as_ref()],
         bump
     )]
-    pub quote_vault: Account<'info, TokenAccount>,
+    pub quote_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Token program of the base mint (SPL Token or Token-2022)
+    pub base_token_program: Interface<'info, TokenInterface>,
+
+    /// Token program of the quote mint (SPL Token or Token-2022)
+    pub quote_token_program: Interface<'info, TokenInterface>,
+
+    /// Signer PDA for CPI to Arcium
+    #[account(
+        init_if_needed,
+        space = 9,
+        payer = authority,
+        seeds = [&SIGN_PDA_SEED],
+        bump
+    )]
+    pub sign_pda_account: Box<Account<'info, SignerAccount>>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Mempool account
+    #[account(mut)]
+    pub mempool_account: UncheckedAccount<'info>,
+
+    /// CHECK: Executing pool account
+    #[account(mut)]
+    pub executing_pool: UncheckedAccount<'info>,
 
-    pub token_program: Program<'info, Token>,
+    /// CHECK: Computation account at the PDA of `computation_offset` (will be initialized)
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset, mxe_account, DuskError::ClusterNotSet)
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    /// Computation definition account for init_book
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_INIT_BOOK))]
+    pub comp_def_account: Box<Account<'info, ComputationDefinitionAccount>>,
+
+    /// Cluster account
+    #[account(mut, address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Box<Account<'info, Cluster>>,
+
+    /// Pool account (Arcium fee pool)
+    #[account(mut)]
+    pub pool_account: Box<Account<'info, FeePool>>,
+
+    /// Clock account
+    pub clock_account: Box<Account<'info, ClockAccount>>,
+
+    pub arcium_program: Program<'info, Arcium>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
 
+/// Reject Token-2022 mints whose extensions break vault custody
+/// Legacy SPL Token mints have no extensions and always pass
+pub fn validate_mint_extensions(mint: &AccountInfo) -> Result<()> {
+    if *mint.owner != spl_token_2022::ID {
+        return Ok(());
+    }
+
+    let mint_data = mint.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+    let extensions = mint_state.get_extension_types()?;
+
+    require!(
+        !extensions
+            .iter()
+            .any(|extension| UNSUPPORTED_MINT_EXTENSIONS.contains(extension)),
+        DuskError::UnsupportedMintExtension
+    );
+
+    Ok(())
+}
+
 pub fn handler(
     ctx: Context<InitializeMarket>,
+    computation_offset: u64,
     market_id: u64,
     fee_rate_bps: u16,
 ) -> Result<()> {
+    // Set the sign_pda_account bump for CPI signing
+    ctx.accounts.sign_pda_account.bump = ctx.bumps.sign_pda_account;
+
+    validate_mint_extensions(&ctx.accounts.base_mint.to_account_info())?;
+    validate_mint_extensions(&ctx.accounts.quote_mint.to_account_info())?;
+
+    require!(
+        ctx.accounts.base_mint.key() != ctx.accounts.quote_mint.key(),
+        DuskError::InvalidMarketConfig
+    );
+
     let market = &mut ctx.accounts.market;
 
     market.authority = ctx.accounts.authority.key();
@@ -64,16 +204,76 @@ pub fn handler(
     market.quote_mint = ctx.accounts.quote_mint.key();
     market.base_vault = ctx.accounts.base_vault.key();
     market.quote_vault = ctx.accounts.quote_vault.key();
+    market.base_token_program = ctx.accounts.base_token_program.key();
+    market.quote_token_program = ctx.accounts.quote_token_program.key();
     market.market_id = market_id;
     market.fee_rate_bps = fee_rate_bps;
     market.order_count = 0;
-    market.orderbook_ref = Pubkey::default(); // Set after MXE initialization
+    market.orderbook_ref = ctx.accounts.order_book.key();
     market.base_locked = 0;
     market.quote_locked = 0;
     market.active_bids = 0;
     market.active_asks = 0;
+    market.active_hidden = 0;
+    // New markets open with an auction so the first crossing pair doesn't set the price
+    market.status = MarketStatus::Auction;
     market.bump = ctx.bumps.market;
 
+    // The market's first computation writes the empty book
+    let market_key = market.key();
+    market.claim_computation_offset(&market_key, computation_offset)?;
+
+    let order_book = &mut ctx.accounts.order_book;
+    order_book.market = market_key;
+    order_book.initialized = false;
+    order_book.begin_computation(computation_offset, Clock::get()?.unix_timestamp);
+    order_book.bump = ctx.bumps.order_book;
+
+    let registry_entry = &mut ctx.accounts.registry_entry;
+    registry_entry.base_mint = market.base_mint;
+    registry_entry.quote_mint = market.quote_mint;
+    registry_entry.market = market.key();
+    registry_entry.market_id = market_id;
+    registry_entry.bump = ctx.bumps.registry_entry;
+
+    let exchange_config = &mut ctx.accounts.exchange_config;
+    exchange_config.market_count = exchange_config
+        .market_count
+        .checked_add(1)
+        .ok_or(DuskError::MathOverflow)?;
+
+    // init_book takes no arguments - it creates a fresh MXE-encrypted state
+    let computation_args = ArgBuilder::new().build();
+
+    // Define callback accounts
+    let callback_accounts = vec![
+        CallbackAccount {
+            pubkey: market_key,
+            is_writable: false,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.order_book.key(),
+            is_writable: true,
+        },
+    ];
+
+    // Queue the encrypted computation
+    queue_computation(
+        ctx.accounts,
+        computation_offset,
+        computation_args,
+        None,
+        vec![InitBookCallback::callback_ix(
+            computation_offset,
+            &ctx.accounts.mxe_account,
+            &callback_accounts,
+        )?],
+        1, // returns the encrypted empty state
+        0, // tip
+    )?;
+
+    let market = &ctx.accounts.market;
+
     emit!(MarketCreated {
         market: market.key(),
         market_id,
@@ -3,10 +3,11 @@ use arcium_anchor::prelude::*;
 use arcium_client::idl::arcium::{types::CallbackAccount, ID_CONST};
 
 use crate::ID;
-use crate::state::Market;
-use crate::events::OrdersMatched;
+use crate::state::{KeeperCrank, Market, MarketStatus, OrderBook, UserPosition};
+use crate::events::{KeeperRewardSkipped, KeeperRewarded, OrdersMatched};
 use crate::errors::DuskError;
 use crate::instructions::place_order::SignerAccount;
+use crate::{MatchBookOutput, MatchBookOutputStruct0, MatchBookOutputStruct01};
 
 /// Computation definition offset for match_book
 pub const COMP_DEF_OFFSET_MATCH_BOOK: u8 = 2;
@@ -14,6 +15,7 @@ pub const COMP_DEF_OFFSET_MATCH_BOOK: u8 = 2;
 /// Trigger order matching via MPC
 #[queue_computation_accounts("match_book", caller)]
 #[derive(Accounts)]
+#[instruction(computation_offset: u64)]
 pub struct MatchOrders<'info> {
     /// Anyone can trigger matching (keeper, user, etc.)
     #[account(mut)]
@@ -22,6 +24,26 @@ pub struct MatchOrders<'info> {
     #[account(mut)]
     pub market: Account<'info, Market>,
 
+    /// Encrypted order book of this market
+    #[account(
+        mut,
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig,
+        constraint = order_book.initialized @ DuskError::OrderBookNotInitialized
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    /// Caller's position on this market, credited with the keeper reward if the match succeeds
+    #[account(
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            caller.key().as_ref(),
+            keeper_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = keeper_position.bump
+    )]
+    pub keeper_position: Option<Account<'info, UserPosition>>,
+
     /// Signer PDA for CPI to Arcium
     #[account(
         init_if_needed,
@@ -43,8 +65,11 @@ pub struct MatchOrders<'info> {
     #[account(mut)]
     pub executing_pool: UncheckedAccount<'info>,
 
-    /// CHECK: Computation account (will be initialized)
-    #[account(mut)]
+    /// CHECK: Computation account at the PDA of `computation_offset` (will be initialized)
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset, mxe_account, DuskError::ClusterNotSet)
+    )]
     pub computation_account: UncheckedAccount<'info>,
 
     /// Computation definition account for match_book
@@ -52,7 +77,7 @@ pub struct MatchOrders<'info> {
     pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
 
     /// Cluster account
-    #[account(mut)]
+    #[account(mut, address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
     pub cluster_account: Account<'info, Cluster>,
 
     /// Pool account (Arcium fee pool)
@@ -66,38 +91,78 @@ pub struct MatchOrders<'info> {
     pub system_program: Program<'info, System>,
 }
 
-pub fn handler(ctx: Context<MatchOrders>) -> Result<()> {
+pub fn handler(ctx: Context<MatchOrders>, computation_offset: u64) -> Result<()> {
     // Set the sign_pda_account bump for CPI signing
     ctx.accounts.sign_pda_account.bump = ctx.bumps.sign_pda_account;
 
     let market = &ctx.accounts.market;
 
-    // Require at least one bid and one ask to attempt matching
+    // Matching only runs during continuous trading; the opening auction is
+    // resolved by `uncross`
     require!(
-        market.active_bids > 0 && market.active_asks > 0,
-        DuskError::NoMatchingOrders
+        market.status != MarketStatus::Auction,
+        DuskError::MarketInAuction
     );
+    require!(
+        market.status == MarketStatus::Continuous,
+        DuskError::MarketPaused
+    );
+
+    // Sealed-fill markets match with match_sealed_orders
+    require!(!market.sealed_fills, DuskError::SealedFillMarket);
 
-    // match_book takes no additional arguments - it operates on MXE state
-    let computation_args = ArgBuilder::new().build();
+    // The settlement slot must be free, or a new match would overwrite the pending one
+    require!(!market.has_pending_match, DuskError::ComputationNotReady);
+
+    // Require at least one bid and one ask to attempt matching
+    require!(market.may_cross(), DuskError::NoMatchingOrders);
+
+    // Every book job reads the state the previous one wrote
+    let now = Clock::get()?.unix_timestamp;
+    require!(!ctx.accounts.order_book.is_busy(now), DuskError::OrderBookBusy);
+
+    // Reserve this market's next computation offset for the match_book job
+    let market_key = market.key();
+    ctx.accounts.market.claim_computation_offset(&market_key, computation_offset)?;
+    ctx.accounts.order_book.begin_computation(computation_offset, now);
+
+    // match_book operates on this market's encrypted book
+    let computation_args = ArgBuilder::new()
+        .plaintext_u128(ctx.accounts.order_book.state_nonce)
+        .account(
+            ctx.accounts.order_book.key(),
+            OrderBook::STATE_OFFSET,
+            OrderBook::STATE_LEN,
+        )
+        .build();
 
     // Define callback accounts
+    // Without a keeper position the program ID stands in for the empty optional account
+    let keeper_position = ctx.accounts.keeper_position.as_ref().map(|p| p.key());
     let callback_accounts = vec![
         CallbackAccount {
-            pubkey: market.key(),
+            pubkey: market_key,
             is_writable: true,
         },
+        CallbackAccount {
+            pubkey: ctx.accounts.order_book.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: keeper_position.unwrap_or(crate::ID),
+            is_writable: keeper_position.is_some(),
+        },
     ];
 
     // Queue the encrypted computation
     // match_book returns MatchResult with revealed execution details
     queue_computation(
         ctx.accounts,
-        0,
+        computation_offset,
         computation_args,
         None,
         vec![MatchBookCallback::callback_ix(
-            COMP_DEF_OFFSET_MATCH_BOOK as u64,
+            computation_offset,
             &ctx.accounts.mxe_account,
             &callback_accounts,
         )?],
@@ -117,27 +182,46 @@ pub fn handler(ctx: Context<MatchOrders>) -> Result<()> {
 
 /// Callback for match_book computation
 /// Receives revealed execution details from MatchResult and stores in market
+#[callback_accounts("match_book")]
 #[derive(Accounts)]
 pub struct MatchBookCallback<'info> {
-    /// CHECK: Arcium callback authority
-    pub callback_authority: Signer<'info>,
+    pub arcium_program: Program<'info, Arcium>,
+
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_MATCH_BOOK))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Computation account, must be the latest job queued on this order book
+    #[account(
+        address = derive_comp_pda!(order_book.computation_offset, mxe_account, DuskError::ClusterNotSet)
+            @ DuskError::ComputationMismatch
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// CHECK: Instructions sysvar, checked by the address constraint
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
 
     #[account(mut)]
     pub market: Account<'info, Market>,
-}
 
-impl MatchBookCallback<'_> {
-    pub fn callback_ix(
-        _computation_offset: u64,
-        _mxe_account: &MXEAccount,
-        extra_accs: &[CallbackAccount],
-    ) -> Result<arcium_client::idl::arcium::types::CallbackInstruction> {
-        Ok(arcium_client::idl::arcium::types::CallbackInstruction {
-            program_id: crate::ID,
-            discriminator: vec![0u8; 8], // Will be set correctly by Arcium runtime
-            accounts: extra_accs.to_vec(),
-        })
-    }
+    #[account(
+        mut,
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    /// Position of the keeper who requested the match
+    #[account(
+        mut,
+        constraint = keeper_position.market == market.key() @ DuskError::InvalidKeeperPosition
+    )]
+    pub keeper_position: Option<Account<'info, UserPosition>>,
 }
 
 /// Reconstruct a Pubkey from two u128 values (low and high bits)
@@ -150,16 +234,39 @@ pub fn reconstruct_pubkey(lo: u128, hi: u128) -> Pubkey {
 
 pub fn callback_handler(
     ctx: Context<MatchBookCallback>,
-    matched: bool,
-    execution_price: u64,
-    execution_amount: u64,
-    maker_order_id: u64,
-    taker_order_id: u64,
-    maker_lo: u128,
-    maker_hi: u128,
-    taker_lo: u128,
-    taker_hi: u128,
+    output: SignedComputationOutputs<MatchBookOutput>,
 ) -> Result<()> {
+    // Only an output signed by the cluster for this computation is accepted,
+    // so the revealed match can no longer be forged by the caller
+    let (book, result) = match output.verify_output(
+        &ctx.accounts.cluster_account,
+        &ctx.accounts.computation_account,
+    ) {
+        Ok(MatchBookOutput {
+            field_0: MatchBookOutputStruct0 { field_0, field_1 },
+        }) => (field_0, field_1),
+        Err(_) => return Err(DuskError::AbortedComputation.into()),
+    };
+    // Fields follow the MatchResult circuit struct
+    let MatchBookOutputStruct01 {
+        field_0: matched,
+        field_1: maker_order_id,
+        field_2: taker_order_id,
+        field_3: execution_price,
+        field_4: execution_amount,
+        field_5: maker_lo,
+        field_6: maker_hi,
+        field_7: taker_lo,
+        field_8: taker_hi,
+        field_9: maker_filled,
+        field_10: taker_filled,
+    } = result;
+
+    // The match is recorded and rewarded once: storing the state marks the
+    // book idle, so a re-delivered output of the same job is rejected here
+    require!(ctx.accounts.order_book.is_pending(), DuskError::ComputationAlreadyApplied);
+    ctx.accounts.order_book.store_state(book.nonce, book.ciphertexts);
+
     let market = &mut ctx.accounts.market;
     let clock = Clock::get()?;
 
@@ -172,19 +279,35 @@ pub fn callback_handler(
     let maker = reconstruct_pubkey(maker_lo, maker_hi);
     let taker = reconstruct_pubkey(taker_lo, taker_hi);
 
-    // Update market stats
-    market.active_bids = market.active_bids.saturating_sub(1);
-    market.active_asks = market.active_asks.saturating_sub(1);
-
     // Store pending match info in market for later settlement creation
-    market.pending_maker = maker;
-    market.pending_taker = taker;
-    market.pending_maker_order_id = maker_order_id;
-    market.pending_taker_order_id = taker_order_id;
-    market.pending_execution_price = execution_price;
-    market.pending_execution_amount = execution_amount;
-    market.pending_matched_at = clock.unix_timestamp;
-    market.has_pending_match = true;
+    market.record_pending_match(
+        maker,
+        taker,
+        maker_order_id,
+        taker_order_id,
+        execution_price,
+        execution_amount,
+        maker_filled,
+        taker_filled,
+        clock.unix_timestamp,
+    );
+
+    if let Some(keeper_position) = ctx.accounts.keeper_position.as_mut() {
+        let reward = market.pay_keeper_reward(keeper_position)?;
+        if reward > 0 {
+            emit!(KeeperRewarded {
+                market: market.key(),
+                keeper: keeper_position.owner,
+                crank: KeeperCrank::MatchOrders,
+                amount: reward,
+            });
+        }
+     else if market.keeper_reward > 0 {
+        emit!(KeeperRewardSkipped {
+            market: market.key(),
+            crank: KeeperCrank::MatchOrders,
+        });
+    }
 
     emit!(OrdersMatched {
         market: market.key(),
@@ -0,0 +1,296 @@
+use anchor_lang::prelude::*;
+use arcium_anchor::prelude::*;
+use arcium_client::idl::arcium::{types::CallbackAccount, ID_CONST};
+
+use crate::ID;
+use crate::state::{KeeperCrank, Market, MarketStatus, OrderBook, UserPosition};
+use crate::events::{KeeperRewardSkipped, KeeperRewarded, SealedOrdersMatched};
+use crate::errors::DuskError;
+use crate::instructions::place_order::SignerAccount;
+use crate::{
+    MatchBookSealedOutput, MatchBookSealedOutputStruct0, MatchBookSealedOutputStruct01,
+};
+
+/// Computation definition offset for match_book_sealed
+pub const COMP_DEF_OFFSET_MATCH_BOOK_SEALED: u8 = 14;
+
+/// Trigger order matching on a sealed-fill market
+/// Same as `match_orders`, but price and amount of a match stay encrypted, and
+/// so do its orders and owners until a party opens it with `open_sealed_match`
+#[queue_computation_accounts("match_book_sealed", caller)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct MatchSealedOrders<'info> {
+    /// Anyone can trigger matching (keeper, user, etc.)
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// Encrypted order book of this market
+    #[account(
+        mut,
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig,
+        constraint = order_book.initialized @ DuskError::OrderBookNotInitialized
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    /// Caller's position on this market, credited with the keeper reward if the match succeeds
+    #[account(
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            caller.key().as_ref(),
+            keeper_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = keeper_position.bump
+    )]
+    pub keeper_position: Option<Account<'info, UserPosition>>,
+
+    /// Signer PDA for CPI to Arcium
+    #[account(
+        init_if_needed,
+        space = 9,
+        payer = caller,
+        seeds = [&SIGN_PDA_SEED],
+        bump
+    )]
+    pub sign_pda_account: Account<'info, SignerAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Mempool account
+    #[account(mut)]
+    pub mempool_account: UncheckedAccount<'info>,
+
+    /// CHECK: Executing pool account
+    #[account(mut)]
+    pub executing_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Computation account at the PDA of `computation_offset` (will be initialized)
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset, mxe_account, DuskError::ClusterNotSet)
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    /// Computation definition account for match_book_sealed
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_MATCH_BOOK_SEALED))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    /// Cluster account
+    #[account(mut, address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// Pool account (Arcium fee pool)
+    #[account(mut)]
+    pub pool_account: Account<'info, FeePool>,
+
+    /// Clock account
+    pub clock_account: Account<'info, ClockAccount>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(ctx: Context<MatchSealedOrders>, computation_offset: u64) -> Result<()> {
+    // Set the sign_pda_account bump for CPI signing
+    ctx.accounts.sign_pda_account.bump = ctx.bumps.sign_pda_account;
+
+    let market = &ctx.accounts.market;
+
+    require!(market.sealed_fills, DuskError::NotSealedFillMarket);
+
+    // Matching only runs during continuous trading; the opening auction is
+    // resolved by `uncross`
+    require!(
+        market.status != MarketStatus::Auction,
+        DuskError::MarketInAuction
+    );
+    require!(
+        market.status == MarketStatus::Continuous,
+        DuskError::MarketPaused
+    );
+
+    // The settlement slot must be free, or a new match would overwrite the pending one
+    require!(!market.has_pending_match, DuskError::ComputationNotReady);
+
+    // Require at least one bid and one ask to attempt matching
+    require!(market.may_cross(), DuskError::NoMatchingOrders);
+
+    // Every book job reads the state the previous one wrote
+    let now = Clock::get()?.unix_timestamp;
+    require!(!ctx.accounts.order_book.is_busy(now), DuskError::OrderBookBusy);
+
+    // Reserve this market's next computation offset for the match_book_sealed job
+    let market_key = market.key();
+    ctx.accounts.market.claim_computation_offset(&market_key, computation_offset)?;
+    ctx.accounts.order_book.begin_computation(computation_offset, now);
+
+    // match_book_sealed operates on this market's encrypted book
+    let computation_args = ArgBuilder::new()
+        .plaintext_u128(ctx.accounts.order_book.state_nonce)
+        .account(
+            ctx.accounts.order_book.key(),
+            OrderBook::STATE_OFFSET,
+            OrderBook::STATE_LEN,
+        )
+        .build();
+
+    // Define callback accounts
+    // Without a keeper position the program ID stands in for the empty optional account
+    let keeper_position = ctx.accounts.keeper_position.as_ref().map(|p| p.key());
+    let callback_accounts = vec![
+        CallbackAccount {
+            pubkey: market_key,
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.order_book.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: keeper_position.unwrap_or(crate::ID),
+            is_writable: keeper_position.is_some(),
+        },
+    ];
+
+    // Queue the encrypted computation
+    queue_computation(
+        ctx.accounts,
+        computation_offset,
+        computation_args,
+        None,
+        vec![MatchBookSealedCallback::callback_ix(
+            computation_offset,
+            &ctx.accounts.mxe_account,
+            &callback_accounts,
+        )?],
+        1, // returns the book, the public match result, the fill terms and the parties
+        0, // tip
+    )?;
+
+    let market = &ctx.accounts.market;
+    msg!(
+        "Sealed match requested on market {} ({} bids, {} asks)",
+        market.market_id,
+        market.active_bids,
+        market.active_asks
+    );
+
+    Ok(())
+}
+
+/// Callback for match_book_sealed computation
+/// Records the match with its fill terms and parties still encrypted
+#[callback_accounts("match_book_sealed")]
+#[derive(Accounts)]
+pub struct MatchBookSealedCallback<'info> {
+    pub arcium_program: Program<'info, Arcium>,
+
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_MATCH_BOOK_SEALED))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Computation account, must be the latest job queued on this order book
+    #[account(
+        address = derive_comp_pda!(order_book.computation_offset, mxe_account, DuskError::ClusterNotSet)
+            @ DuskError::ComputationMismatch
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// CHECK: Instructions sysvar, checked by the address constraint
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    /// Position of the keeper who requested the match
+    #[account(
+        mut,
+        constraint = keeper_position.market == market.key() @ DuskError::InvalidKeeperPosition
+    )]
+    pub keeper_position: Option<Account<'info, UserPosition>>,
+}
+
+pub fn callback_handler(
+    ctx: Context<MatchBookSealedCallback>,
+    output: SignedComputationOutputs<MatchBookSealedOutput>,
+) -> Result<()> {
+    // Only an output signed by the cluster for this computation is accepted
+    let (book, result, fill, parties) = match output.verify_output(
+        &ctx.accounts.cluster_account,
+        &ctx.accounts.computation_account,
+    ) {
+        Ok(MatchBookSealedOutput {
+            field_0: MatchBookSealedOutputStruct0 { field_0, field_1, field_2, field_3 },
+        }) => (field_0, field_1, field_2, field_3),
+        Err(_) => return Err(DuskError::AbortedComputation.into()),
+    };
+    // Fields follow the SealedMatchResult circuit struct
+    let MatchBookSealedOutputStruct01 {
+        field_0: matched,
+        field_1: fill_commitment,
+    } = result;
+
+    // The match is recorded and rewarded once: storing the state marks the
+    // book idle, so a re-delivered output of the same job is rejected here
+    require!(ctx.accounts.order_book.is_pending(), DuskError::ComputationAlreadyApplied);
+    ctx.accounts.order_book.store_state(book.nonce, book.ciphertexts);
+
+    let market = &mut ctx.accounts.market;
+    let clock = Clock::get()?;
+
+    if !matched {
+        msg!("No matching orders found");
+        return Ok(());
+    }
+
+    // Neither the parties nor the terms are known yet: open_sealed_match
+    // reveals the parties, and create_settlement carries the encrypted terms over
+    market.record_sealed_match(parties.nonce, parties.ciphertexts, clock.unix_timestamp);
+    market.pending_fill_nonce = fill.nonce;
+    market.pending_fill = fill.ciphertexts;
+    market.pending_fill_commitment = fill_commitment;
+
+    if let Some(keeper_position) = ctx.accounts.keeper_position.as_mut() {
+        let reward = market.pay_keeper_reward(keeper_position)?;
+        if reward > 0 {
+            emit!(KeeperRewarded {
+                market: market.key(),
+                keeper: keeper_position.owner,
+                crank: KeeperCrank::MatchOrders,
+                amount: reward,
+            });
+        }
+     else if market.keeper_reward > 0 {
+        emit!(KeeperRewardSkipped {
+            market: market.key(),
+            crank: KeeperCrank::MatchOrders,
+        });
+    }
+
+    emit!(SealedOrdersMatched {
+        market: market.key(),
+        fill_commitment,
+        timestamp: clock.unix_timestamp,
+    });
+
+    msg!("Orders matched with sealed terms and parties. Call open_sealed_match to settle.");
+
+    Ok(())
+}
@@ -7,6 +7,43 @@ pub mod cancel_order;
 pub mod match_orders;
 pub mod create_settlement;
 pub mod settle_trade;
+pub mod set_market_paused;
+pub mod uncross;
+pub mod set_keeper_reward;
+pub mod close_settlement;
+pub mod close_position;
+pub mod deposit_native;
+pub mod withdraw_native;
+pub mod delegate;
+pub mod transfer_balance;
+pub mod withdraw_allowlist;
+pub mod exchange_config;
+pub mod collect_protocol_fees;
+pub mod delist_market;
+pub mod clear_book;
+pub mod release_locks;
+pub mod close_market;
+pub mod init_order_book;
+pub mod audit_market;
+pub mod reclaim_stuck_computation;
+pub mod set_confidential_balances;
+pub mod init_confidential_balance;
+pub mod deposit_confidential;
+pub mod withdraw_confidential;
+pub mod place_confidential_order;
+pub mod cancel_confidential_order;
+pub mod settle_confidential_trade;
+pub mod close_confidential_position;
+pub mod place_hidden_order;
+pub mod set_sealed_fills;
+pub mod match_sealed_orders;
+pub mod settle_sealed_trade;
+pub mod set_auditor_key;
+pub mod disclose_orders;
+pub mod disclose_fill;
+pub mod query_my_orders;
+pub mod probe_cross;
+pub mod open_sealed_match;
 
 pub use initialize_market::*;
 pub use deposit::*;
@@ -17,3 +54,40 @@ pub use cancel_order::*;
 pub use match_orders::*;
 pub use create_settlement::*;
 pub use settle_trade::*;
+pub use set_market_paused::*;
+pub use uncross::*;
+pub use set_keeper_reward::*;
+pub use close_settlement::*;
+pub use close_position::*;
+pub use deposit_native::*;
+pub use withdraw_native::*;
+pub use delegate::*;
+pub use transfer_balance::*;
+pub use withdraw_allowlist::*;
+pub use exchange_config::*;
+pub use collect_protocol_fees::*;
+pub use delist_market::*;
+pub use clear_book::*;
+pub use release_locks::*;
+pub use close_market::*;
+pub use init_order_book::*;
+pub use audit_market::*;
+pub use reclaim_stuck_computation::*;
+pub use set_confidential_balances::*;
+pub use init_confidential_balance::*;
+pub use deposit_confidential::*;
+pub use withdraw_confidential::*;
+pub use place_confidential_order::*;
+pub use cancel_confidential_order::*;
+pub use settle_confidential_trade::*;
+pub use close_confidential_position::*;
+pub use place_hidden_order::*;
+pub use set_sealed_fills::*;
+pub use match_sealed_orders::*;
+pub use settle_sealed_trade::*;
+pub use set_auditor_key::*;
+pub use disclose_orders::*;
+pub use disclose_fill::*;
+pub use query_my_orders::*;
+pub use probe_cross::*;
+pub use open_sealed_match::*;
@@ -0,0 +1,272 @@
+use anchor_lang::prelude::*;
+use arcium_anchor::prelude::*;
+use arcium_client::idl::arcium::{types::CallbackAccount, ID_CONST};
+
+use crate::ID;
+use crate::state::{Market, UserPosition, MATCH_PARTIES_FIELDS};
+use crate::events::SealedMatchOpened;
+use crate::errors::DuskError;
+use crate::instructions::match_orders::reconstruct_pubkey;
+use crate::instructions::place_order::SignerAccount;
+use crate::{OpenSealedMatchOutput, OpenSealedMatchOutputStruct0, OpenSealedMatchOutputStruct01};
+
+/// Computation definition offset for open_sealed_match
+pub const COMP_DEF_OFFSET_OPEN_SEALED_MATCH: u8 = 20;
+
+/// Reveal the orders and owners of the pending sealed match so it can be settled
+/// Only a party to the match can open it, until `SEALED_MATCH_OPEN_DELAY` has
+/// passed since the match; after that anyone can
+#[queue_computation_accounts("open_sealed_match", caller)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct OpenSealedMatch<'info> {
+    /// Owner of the claiming position, or anyone once the open delay has passed
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// Caller's position on this market, checked against the sealed parties
+    #[account(
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            caller.key().as_ref(),
+            claimer_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = claimer_position.bump
+    )]
+    pub claimer_position: Option<Account<'info, UserPosition>>,
+
+    /// Signer PDA for CPI to Arcium
+    #[account(
+        init_if_needed,
+        space = 9,
+        payer = caller,
+        seeds = [&SIGN_PDA_SEED],
+        bump
+    )]
+    pub sign_pda_account: Account<'info, SignerAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Mempool account
+    #[account(mut)]
+    pub mempool_account: UncheckedAccount<'info>,
+
+    /// CHECK: Executing pool account
+    #[account(mut)]
+    pub executing_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Computation account at the PDA of `computation_offset` (will be initialized)
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset, mxe_account, DuskError::ClusterNotSet)
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    /// Computation definition account for open_sealed_match
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_OPEN_SEALED_MATCH))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    /// Cluster account
+    #[account(mut, address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// Pool account (Arcium fee pool)
+    #[account(mut)]
+    pub pool_account: Account<'info, FeePool>,
+
+    /// Clock account
+    pub clock_account: Account<'info, ClockAccount>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(ctx: Context<OpenSealedMatch>, computation_offset: u64) -> Result<()> {
+    // Set the sign_pda_account bump for CPI signing
+    ctx.accounts.sign_pda_account.bump = ctx.bumps.sign_pda_account;
+
+    let now = Clock::get()?.unix_timestamp;
+    let market = &ctx.accounts.market;
+
+    require!(
+        market.has_pending_match && market.pending_match_sealed,
+        DuskError::NoSealedMatch
+    );
+
+    // One open at a time; a stuck one can be retried after the timeout
+    require!(!market.is_opening(now), DuskError::SealedMatchOpening);
+
+    // The parties get a head start; after it anyone may open the match, so a
+    // match no party opens can't keep the market from matching again
+    let anyone = now >= market.pending_matched_at.saturating_add(Market::SEALED_MATCH_OPEN_DELAY);
+    let claimer = match ctx.accounts.claimer_position.as_ref() {
+        Some(position) => position.key(),
+        None => {
+            require!(anyone, DuskError::Unauthorized);
+            Pubkey::default()
+        }
+    };
+    let (claimer_lo, claimer_hi) = UserPosition::split_key(&claimer);
+
+    // Reserve this market's next computation offset for the open_sealed_match job
+    let market_key = market.key();
+    let parties_nonce = market.pending_parties_nonce;
+    let market = &mut ctx.accounts.market;
+    market.claim_computation_offset(&market_key, computation_offset)?;
+    market.open_computation_offset = computation_offset;
+    market.open_pending_until = now.saturating_add(Market::OPEN_COMPUTATION_TIMEOUT);
+
+    // Layout: the plaintext claimer and whether anyone may open, then the
+    // encrypted parties kept on the market
+    let computation_args = ArgBuilder::new()
+        .plaintext_u128(claimer_lo)
+        .plaintext_u128(claimer_hi)
+        .plaintext_bool(anyone)
+        .plaintext_u128(parties_nonce)
+        .account(market_key, Market::PENDING_PARTIES_OFFSET, Market::PENDING_PARTIES_LEN)
+        .build();
+
+    // Define callback accounts
+    let callback_accounts = vec![CallbackAccount {
+        pubkey: market_key,
+        is_writable: true,
+    }];
+
+    // Queue the encrypted computation
+    queue_computation(
+        ctx.accounts,
+        computation_offset,
+        computation_args,
+        None,
+        vec![OpenSealedMatchCallback::callback_ix(
+            computation_offset,
+            &ctx.accounts.mxe_account,
+            &callback_accounts,
+        )?],
+        1, // returns whether the claimer may open the match, and the parties if so
+        0, // tip
+    )?;
+
+    msg!(
+        "Opening of the sealed match on market {} requested",
+        ctx.accounts.market.market_id
+    );
+
+    Ok(())
+}
+
+/// Callback for open_sealed_match computation
+/// Records the revealed parties so that `create_settlement` can pick the match up
+#[callback_accounts("open_sealed_match")]
+#[derive(Accounts)]
+pub struct OpenSealedMatchCallback<'info> {
+    pub arcium_program: Program<'info, Arcium>,
+
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_OPEN_SEALED_MATCH))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Computation account, must be the latest open queued for this market
+    #[account(
+        address = derive_comp_pda!(market.open_computation_offset, mxe_account, DuskError::ClusterNotSet)
+            @ DuskError::ComputationMismatch
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// CHECK: Instructions sysvar, checked by the address constraint
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+}
+
+pub fn callback_handler(
+    ctx: Context<OpenSealedMatchCallback>,
+    output: SignedComputationOutputs<OpenSealedMatchOutput>,
+) -> Result<()> {
+    // Only an output signed by the cluster for this computation is accepted
+    let (allowed, parties) = match output.verify_output(
+        &ctx.accounts.cluster_account,
+        &ctx.accounts.computation_account,
+    ) {
+        Ok(OpenSealedMatchOutput {
+            field_0: OpenSealedMatchOutputStruct0 { field_0, field_1 },
+        }) => (field_0, field_1),
+        Err(_) => return Err(DuskError::AbortedComputation.into()),
+    };
+    // Fields follow the MatchParties circuit struct
+    let OpenSealedMatchOutputStruct01 {
+        field_0: maker_order_id,
+        field_1: taker_order_id,
+        field_2: maker_lo,
+        field_3: maker_hi,
+        field_4: taker_lo,
+        field_5: taker_hi,
+        field_6: maker_filled,
+        field_7: taker_filled,
+    } = parties;
+
+    let market = &mut ctx.accounts.market;
+    market.open_pending_until = 0;
+
+    // A re-delivered result finds the match already opened
+    if !market.pending_match_sealed {
+        msg!("Ignoring late open_sealed_match result for market {}", market.market_id);
+        return Ok(());
+    }
+
+    if !allowed {
+        msg!(
+            "Sealed match on market {} not opened: the claimer is not a party",
+            market.market_id
+        );
+        return Ok(());
+    }
+
+    let maker = reconstruct_pubkey(maker_lo, maker_hi);
+    let taker = reconstruct_pubkey(taker_lo, taker_hi);
+
+    // Price and amount stay 0; create_settlement carries the encrypted terms over
+    let matched_at = market.pending_matched_at;
+    market.record_pending_match(
+        maker,
+        taker,
+        maker_order_id,
+        taker_order_id,
+        0,
+        0,
+        maker_filled,
+        taker_filled,
+        matched_at,
+    );
+    market.pending_match_sealed = false;
+    market.pending_parties_nonce = 0;
+    market.pending_parties = [[0u8; 32]; MATCH_PARTIES_FIELDS];
+
+    emit!(SealedMatchOpened {
+        market: market.key(),
+        maker,
+        taker,
+        maker_order_id,
+        taker_order_id,
+    });
+
+    msg!(
+        "Sealed match opened. Maker: {}, Taker: {}. Call create_settlement to finalize.",
+        maker,
+        taker
+    );
+
+    Ok(())
+}
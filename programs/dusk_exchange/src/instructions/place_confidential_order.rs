@@ -0,0 +1,394 @@
+use anchor_lang::prelude::*;
+use arcium_anchor::prelude::*;
+use arcium_client::idl::arcium::{types::CallbackAccount, ID_CONST};
+
+use crate::ID;
+use crate::state::{
+    ConfidentialBalance, Market, MarketStatus, OpenOrder, OrderBook, OrderStatus, UserPosition,
+};
+use crate::events::{ConfidentialOrderRejected, OrderPlaced};
+use crate::errors::DuskError;
+use crate::instructions::place_order::SignerAccount;
+use crate::{AddOrderLockedOutput, AddOrderLockedOutputStruct0};
+
+/// Computation definition offset for add_order_locked
+pub const COMP_DEF_OFFSET_ADD_ORDER_LOCKED: u8 = 9;
+
+/// Place an encrypted limit order backed by a confidential balance
+/// The lock is computed and taken inside the MXE, so no amount is public
+#[queue_computation_accounts("add_order_locked", user)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64, order_id: u64)]
+pub struct PlaceConfidentialOrder<'info> {
+    /// Position owner or its trading delegate
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// Encrypted order book of this market
+    #[account(
+        mut,
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig,
+        constraint = order_book.initialized @ DuskError::OrderBookNotInitialized
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    #[account(
+        mut,
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            user_position.owner.as_ref(),
+            user_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = user_position.bump
+    )]
+    pub user_position: Account<'info, UserPosition>,
+
+    #[account(
+        mut,
+        seeds = [ConfidentialBalance::SEED_PREFIX, user_position.key().as_ref()],
+        bump = confidential_balance.bump,
+        constraint = confidential_balance.initialized @ DuskError::ConfidentialBalanceNotInitialized
+    )]
+    pub confidential_balance: Account<'info, ConfidentialBalance>,
+
+    /// Bookkeeping for this order; its encrypted lock is written on callback
+    #[account(
+        init,
+        payer = user,
+        space = OpenOrder::LEN,
+        seeds = [
+            OpenOrder::SEED_PREFIX,
+            user_position.key().as_ref(),
+            order_id.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub open_order: Account<'info, OpenOrder>,
+
+    /// Signer PDA for CPI to Arcium
+    #[account(
+        init_if_needed,
+        space = 9,
+        payer = user,
+        seeds = [&SIGN_PDA_SEED],
+        bump
+    )]
+    pub sign_pda_account: Account<'info, SignerAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Mempool account
+    #[account(mut)]
+    pub mempool_account: UncheckedAccount<'info>,
+
+    /// CHECK: Executing pool account
+    #[account(mut)]
+    pub executing_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Computation account at the PDA of `computation_offset` (will be initialized)
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset, mxe_account, DuskError::ClusterNotSet)
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    /// Computation definition account for add_order_locked
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_ADD_ORDER_LOCKED))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    /// Cluster account
+    #[account(mut, address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// Pool account (Arcium fee pool)
+    #[account(mut)]
+    pub pool_account: Account<'info, FeePool>,
+
+    /// Clock account
+    pub clock_account: Account<'info, ClockAccount>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(
+    ctx: Context<PlaceConfidentialOrder>,
+    computation_offset: u64,
+    order_id: u64,
+    is_buy: bool,
+    encrypted_price: Vec<u8>,
+    encrypted_amount: Vec<u8>,
+    client_pubkey: [u8; 32],
+    nonce: u128,
+) -> Result<()> {
+    // Set the sign_pda_account bump for CPI signing
+    ctx.accounts.sign_pda_account.bump = ctx.bumps.sign_pda_account;
+
+    let market = &ctx.accounts.market;
+    require!(market.confidential_balances, DuskError::NotConfidentialMarket);
+    require!(!market.is_delisted(), DuskError::MarketDelisted);
+    require!(market.status != MarketStatus::Paused, DuskError::MarketPaused);
+
+    // Only the owner or an unexpired delegate can trade this position
+    let now = Clock::get()?.unix_timestamp;
+    require!(
+        ctx.accounts.user_position.can_trade(&ctx.accounts.user.key(), now),
+        DuskError::Unauthorized
+    );
+
+    require!(
+        !ctx.accounts.confidential_balance.is_busy(now),
+        DuskError::ConfidentialBalanceBusy
+    );
+
+    // Validate encrypted data lengths (32 bytes for encrypted values)
+    require!(
+        encrypted_price.len() == 32 && encrypted_amount.len() == 32,
+        DuskError::InvalidEncryptedData
+    );
+    let price_arr: [u8; 32] = encrypted_price.try_into()
+        .map_err(|_| DuskError::InvalidEncryptedData)?;
+    let amount_arr: [u8; 32] = encrypted_amount.try_into()
+        .map_err(|_| DuskError::InvalidEncryptedData)?;
+
+    // Every book job reads the state the previous one wrote
+    require!(!ctx.accounts.order_book.is_busy(now), DuskError::OrderBookBusy);
+
+    // Reserve this market's next computation offset for the add_order_locked job
+    let market_key = market.key();
+    ctx.accounts.market.claim_computation_offset(&market_key, computation_offset)?;
+    ctx.accounts.order_book.begin_computation(computation_offset, now);
+    ctx.accounts.confidential_balance.begin_computation(computation_offset, now);
+
+    // The order counts as active until the callback accepts or rejects it
+    let user_position = &mut ctx.accounts.user_position;
+    user_position.active_order_count = user_position.active_order_count
+        .checked_add(1)
+        .ok_or(DuskError::TooManyOrders)?;
+
+    let open_order = &mut ctx.accounts.open_order;
+    open_order.market = market_key;
+    open_order.position = user_position.key();
+    open_order.order_id = order_id;
+    open_order.is_buy = is_buy;
+    open_order.client_pubkey = client_pubkey;
+    open_order.computation_offset = computation_offset;
+    open_order.begin_computation(OrderStatus::PendingAdd, now);
+    open_order.bump = ctx.bumps.open_order;
+
+    // Orders always belong to the position owner, even when a delegate signs
+    let user_key = user_position.owner;
+    let user_position_key = user_position.key();
+    let (owner_lo, owner_hi) = UserPosition::split_key(&user_position_key);
+    let order_book_key = ctx.accounts.order_book.key();
+    let confidential_balance = &ctx.accounts.confidential_balance;
+
+    // Layout: Enc<Shared, OrderInput>, the plaintext owner, order id and side,
+    // then the position's encrypted balance and the book
+    let computation_args = ArgBuilder::new()
+        .x25519_pubkey(client_pubkey)
+        .plaintext_u128(nonce)
+        .encrypted_u64(price_arr)
+        .encrypted_u64(amount_arr)
+        .plaintext_u128(owner_lo)
+        .plaintext_u128(owner_hi)
+        .plaintext_u64(order_id)
+        .plaintext_bool(is_buy)
+        .plaintext_u128(confidential_balance.state_nonce)
+        .account(
+            confidential_balance.key(),
+            ConfidentialBalance::STATE_OFFSET,
+            ConfidentialBalance::STATE_LEN,
+        )
+        .plaintext_u128(ctx.accounts.order_book.state_nonce)
+        .account(order_book_key, OrderBook::STATE_OFFSET, OrderBook::STATE_LEN)
+        .build();
+
+    // Define callback accounts
+    let callback_accounts = vec![
+        CallbackAccount { pubkey: market_key, is_writable: true },
+        CallbackAccount { pubkey: order_book_key, is_writable: true },
+        CallbackAccount { pubkey: user_key, is_writable: true },
+        CallbackAccount { pubkey: user_position_key, is_writable: true },
+        CallbackAccount { pubkey: confidential_balance.key(), is_writable: true },
+        CallbackAccount { pubkey: ctx.accounts.open_order.key(), is_writable: true },
+    ];
+
+    // Queue the encrypted computation
+    queue_computation(
+        ctx.accounts,
+        computation_offset,
+        computation_args,
+        None,
+        vec![AddOrderLockedCallback::callback_ix(
+            computation_offset,
+            &ctx.accounts.mxe_account,
+            &callback_accounts,
+        )?],
+        1, // returns the balance, the order's lock, the book and whether it was accepted
+        0, // tip
+    )?;
+
+    let market = &mut ctx.accounts.market;
+    market.order_count = market.order_count.saturating_add(1);
+    ctx.accounts.open_order.book_seq = market.order_count;
+    if is_buy {
+        market.active_bids = market.active_bids.saturating_add(1);
+    } else {
+        market.active_asks = market.active_asks.saturating_add(1);
+    }
+
+    emit!(OrderPlaced {
+        market: market_key,
+        user: user_key,
+        order_id,
+        is_buy,
+        computation_offset,
+        timestamp: now,
+    });
+
+    msg!(
+        "Confidential order {} placed: {} order on market {}",
+        order_id,
+        if is_buy { "BUY" } else { "SELL" },
+        market.market_id
+    );
+
+    Ok(())
+}
+
+/// Callback for add_order_locked computation
+#[callback_accounts("add_order_locked")]
+#[derive(Accounts)]
+pub struct AddOrderLockedCallback<'info> {
+    pub arcium_program: Program<'info, Arcium>,
+
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_ADD_ORDER_LOCKED))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Computation account, must be the one queued by `place_confidential_order` for this order
+    #[account(
+        address = derive_comp_pda!(open_order.computation_offset, mxe_account, DuskError::ClusterNotSet)
+            @ DuskError::ComputationMismatch,
+        constraint = confidential_balance.computation_offset == open_order.computation_offset
+            @ DuskError::ComputationMismatch
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// CHECK: Instructions sysvar, checked by the address constraint
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// Only the latest job queued on the book may write it
+    #[account(
+        mut,
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig,
+        constraint = order_book.computation_offset == open_order.computation_offset
+            @ DuskError::ComputationMismatch
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    /// CHECK: Position owner, receives the rent of a rejected order
+    #[account(mut, address = user_position.owner @ DuskError::Unauthorized)]
+    pub user: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = user_position.market == market.key() @ DuskError::InvalidMarketConfig
+    )]
+    pub user_position: Account<'info, UserPosition>,
+
+    #[account(
+        mut,
+        constraint = confidential_balance.position == user_position.key() @ DuskError::Unauthorized
+    )]
+    pub confidential_balance: Account<'info, ConfidentialBalance>,
+
+    /// Order whose add_order_locked computation is being delivered
+    #[account(
+        mut,
+        constraint = open_order.position == user_position.key() @ DuskError::OrderNotFound
+    )]
+    pub open_order: Account<'info, OpenOrder>,
+}
+
+pub fn callback_handler(
+    ctx: Context<AddOrderLockedCallback>,
+    output: SignedComputationOutputs<AddOrderLockedOutput>,
+) -> Result<()> {
+    // Only an output signed by the cluster for this computation is accepted
+    let (balance, lock, book, accepted) = match output.verify_output(
+        &ctx.accounts.cluster_account,
+        &ctx.accounts.computation_account,
+    ) {
+        Ok(AddOrderLockedOutput {
+            field_0: AddOrderLockedOutputStruct0 { field_0, field_1, field_2, field_3 },
+        }) => (field_0, field_1, field_2, field_3),
+        Err(_) => return Err(DuskError::AbortedComputation.into()),
+    };
+
+    // The order was reclaimed after the deadline, so nothing here may be
+    // applied; the book and balance are still freed, as this was their latest job
+    if ctx.accounts.open_order.status != OrderStatus::PendingAdd {
+        ctx.accounts.order_book.end_computation();
+        ctx.accounts.confidential_balance.end_computation();
+        msg!(
+            "Ignoring late add_order_locked result for order {}",
+            ctx.accounts.open_order.order_id
+        );
+        return Ok(());
+    }
+    let open_order = &mut ctx.accounts.open_order;
+
+    // The balance and book are written back either way: a rejected order leaves
+    // them unchanged, and storing the book frees it for the next job
+    ctx.accounts.confidential_balance.store_state(balance.nonce, balance.ciphertexts);
+    ctx.accounts.order_book.store_state(book.nonce, book.ciphertexts);
+    open_order.lock_nonce = lock.nonce;
+    open_order.encrypted_lock = lock.ciphertexts[0];
+    open_order.deadline = 0;
+
+    let market = &mut ctx.accounts.market;
+    if accepted {
+        open_order.status = OrderStatus::Resting;
+
+        msg!(
+            "Confidential order {} added to the book of market {}",
+            open_order.order_id,
+            market.market_id
+        );
+        return Ok(());
+    }
+
+    // Nothing was locked and the book is unchanged, so the order is closed
+    let order_id = open_order.order_id;
+    let user_position = &mut ctx.accounts.user_position;
+    user_position.active_order_count = user_position.active_order_count.saturating_sub(1);
+    market.remove_active_order(open_order.is_buy, false);
+
+    emit!(ConfidentialOrderRejected {
+        market: market.key(),
+        user: user_position.owner,
+        order_id,
+    });
+
+    ctx.accounts.open_order.close(ctx.accounts.user.to_account_info())?;
+
+    msg!("Confidential order {} rejected: balance can't cover it", order_id);
+
+    Ok(())
+}
@@ -0,0 +1,380 @@
+use anchor_lang::prelude::*;
+use arcium_anchor::prelude::*;
+use arcium_client::idl::arcium::{types::CallbackAccount, ID_CONST};
+
+use crate::ID;
+use crate::state::{Market, MarketStatus, OpenOrder, OrderBook, OrderStatus, UserPosition};
+use crate::events::{HiddenOrderPlaced, HiddenOrderRejected};
+use crate::errors::DuskError;
+use crate::instructions::place_order::SignerAccount;
+use crate::{AddHiddenOrderOutput, AddHiddenOrderOutputStruct0};
+
+/// Computation definition offset for add_hidden_order
+pub const COMP_DEF_OFFSET_ADD_HIDDEN_ORDER: u8 = 13;
+
+/// Place an encrypted limit order whose side is encrypted too
+/// Both base and quote are locked, so the lock pattern doesn't give the side away
+#[queue_computation_accounts("add_hidden_order", user)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64, order_id: u64)]
+pub struct PlaceHiddenOrder<'info> {
+    /// Position owner or its trading delegate
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// Encrypted order book of this market
+    #[account(
+        mut,
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig,
+        constraint = order_book.initialized @ DuskError::OrderBookNotInitialized
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    #[account(
+        mut,
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            user_position.owner.as_ref(),
+            user_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = user_position.bump
+    )]
+    pub user_position: Account<'info, UserPosition>,
+
+    /// Lock bookkeeping for this order; order IDs are unique per position
+    #[account(
+        init,
+        payer = user,
+        space = OpenOrder::LEN,
+        seeds = [
+            OpenOrder::SEED_PREFIX,
+            user_position.key().as_ref(),
+            order_id.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub open_order: Account<'info, OpenOrder>,
+
+    /// Signer PDA for CPI to Arcium
+    #[account(
+        init_if_needed,
+        space = 9,
+        payer = user,
+        seeds = [&SIGN_PDA_SEED],
+        bump
+    )]
+    pub sign_pda_account: Account<'info, SignerAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Mempool account
+    #[account(mut)]
+    pub mempool_account: UncheckedAccount<'info>,
+
+    /// CHECK: Executing pool account
+    #[account(mut)]
+    pub executing_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Computation account at the PDA of `computation_offset` (will be initialized)
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset, mxe_account, DuskError::ClusterNotSet)
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    /// Computation definition account for add_hidden_order
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_ADD_HIDDEN_ORDER))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    /// Cluster account
+    #[account(mut, address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// Pool account (Arcium fee pool)
+    #[account(mut)]
+    pub pool_account: Account<'info, FeePool>,
+
+    /// Clock account
+    pub clock_account: Account<'info, ClockAccount>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(
+    ctx: Context<PlaceHiddenOrder>,
+    computation_offset: u64,
+    order_id: u64,
+    encrypted_price: Vec<u8>,
+    encrypted_amount: Vec<u8>,
+    encrypted_side: Vec<u8>,
+    client_pubkey: [u8; 32],
+    nonce: u128,
+    base_lock: u64,
+    quote_lock: u64,
+) -> Result<()> {
+    // Set the sign_pda_account bump for CPI signing
+    ctx.accounts.sign_pda_account.bump = ctx.bumps.sign_pda_account;
+
+    // Orders are accepted during the opening auction and continuous trading
+    require!(!ctx.accounts.market.is_delisted(), DuskError::MarketDelisted);
+    require!(
+        !ctx.accounts.market.confidential_balances,
+        DuskError::ConfidentialMarket
+    );
+    require!(
+        ctx.accounts.market.status != MarketStatus::Paused,
+        DuskError::MarketPaused
+    );
+
+    // Only the owner or an unexpired delegate can trade this position
+    let now = Clock::get()?.unix_timestamp;
+    require!(
+        ctx.accounts.user_position.can_trade(&ctx.accounts.user.key(), now),
+        DuskError::Unauthorized
+    );
+
+    // Validate encrypted data lengths (32 bytes for encrypted values)
+    require!(
+        encrypted_price.len() == 32 && encrypted_amount.len() == 32 && encrypted_side.len() == 32,
+        DuskError::InvalidEncryptedData
+    );
+
+    // Both locks are the trader's own caps; the circuit checks the order
+    // against the one of its side
+    require!(base_lock > 0 && quote_lock > 0, DuskError::AmountTooSmall);
+
+    let user_position = &mut ctx.accounts.user_position;
+    user_position.lock_for_hidden_order(base_lock, quote_lock)?;
+    ctx.accounts.market.lock(base_lock, false)?;
+    ctx.accounts.market.lock(quote_lock, true)?;
+
+    // Every book job reads the state the previous one wrote
+    require!(!ctx.accounts.order_book.is_busy(now), DuskError::OrderBookBusy);
+
+    // Reserve this market's next computation offset for the add_hidden_order job
+    let market_key = ctx.accounts.market.key();
+    ctx.accounts.market.claim_computation_offset(&market_key, computation_offset)?;
+    ctx.accounts.order_book.begin_computation(computation_offset, now);
+
+    let open_order = &mut ctx.accounts.open_order;
+    open_order.market = market_key;
+    open_order.position = user_position.key();
+    open_order.order_id = order_id;
+    open_order.side_hidden = true;
+    open_order.locked_amount = quote_lock;
+    open_order.locked_base = base_lock;
+    open_order.client_pubkey = client_pubkey;
+    open_order.computation_offset = computation_offset;
+    open_order.begin_computation(OrderStatus::PendingAdd, now);
+    open_order.bump = ctx.bumps.open_order;
+
+    // Convert Vec<u8> to [u8; 32] for encrypted values
+    let price_arr: [u8; 32] = encrypted_price.try_into()
+        .map_err(|_| DuskError::InvalidEncryptedData)?;
+    let amount_arr: [u8; 32] = encrypted_amount.try_into()
+        .map_err(|_| DuskError::InvalidEncryptedData)?;
+    let side_arr: [u8; 32] = encrypted_side.try_into()
+        .map_err(|_| DuskError::InvalidEncryptedData)?;
+
+    // Orders always belong to the position owner, even when a delegate signs
+    let user_key = ctx.accounts.user_position.owner;
+    let user_position_key = ctx.accounts.user_position.key();
+    let order_book_key = ctx.accounts.order_book.key();
+    let (owner_lo, owner_hi) = UserPosition::split_key(&user_position_key);
+
+    // Layout: Enc<Shared, HiddenOrderInput> (client key, nonce, price, amount,
+    // side), then the plaintext owner, order id and both locks, then the book
+    let computation_args = ArgBuilder::new()
+        .x25519_pubkey(client_pubkey)
+        .plaintext_u128(nonce)
+        .encrypted_u64(price_arr)
+        .encrypted_u64(amount_arr)
+        .encrypted_bool(side_arr)
+        .plaintext_u128(owner_lo)
+        .plaintext_u128(owner_hi)
+        .plaintext_u64(order_id)
+        .plaintext_u64(base_lock)
+        .plaintext_u64(quote_lock)
+        // Current encrypted book of this market
+        .plaintext_u128(ctx.accounts.order_book.state_nonce)
+        .account(order_book_key, OrderBook::STATE_OFFSET, OrderBook::STATE_LEN)
+        .build();
+
+    // Define callback accounts
+    let callback_accounts = vec![
+        CallbackAccount { pubkey: market_key, is_writable: true },
+        CallbackAccount { pubkey: order_book_key, is_writable: true },
+        CallbackAccount { pubkey: user_key, is_writable: true },
+        CallbackAccount { pubkey: user_position_key, is_writable: true },
+        CallbackAccount { pubkey: ctx.accounts.open_order.key(), is_writable: true },
+    ];
+
+    // Queue the encrypted computation
+    queue_computation(
+        ctx.accounts,
+        computation_offset,
+        computation_args,
+        None,
+        vec![AddHiddenOrderCallback::callback_ix(
+            computation_offset,
+            &ctx.accounts.mxe_account,
+            &callback_accounts,
+        )?],
+        1, // returns the book and whether the order was accepted
+        0, // tip
+    )?;
+
+    // Counted apart from bids and asks, so the counters don't reveal the side
+    let market = &mut ctx.accounts.market;
+    market.order_count = market.order_count.saturating_add(1);
+    ctx.accounts.open_order.book_seq = market.order_count;
+    market.active_hidden = market.active_hidden.saturating_add(1);
+
+    emit!(HiddenOrderPlaced {
+        market: market_key,
+        user: user_key,
+        order_id,
+        computation_offset,
+        timestamp: now,
+    });
+
+    msg!("Side-hidden order {} placed on market {}", order_id, market.market_id);
+
+    Ok(())
+}
+
+/// Callback for add_hidden_order computation
+#[callback_accounts("add_hidden_order")]
+#[derive(Accounts)]
+pub struct AddHiddenOrderCallback<'info> {
+    pub arcium_program: Program<'info, Arcium>,
+
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_ADD_HIDDEN_ORDER))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Computation account, must be the one queued by `place_hidden_order` for this order
+    #[account(
+        address = derive_comp_pda!(open_order.computation_offset, mxe_account, DuskError::ClusterNotSet)
+            @ DuskError::ComputationMismatch
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// CHECK: Instructions sysvar, checked by the address constraint
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// Only the latest job queued on the book may write it
+    #[account(
+        mut,
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig,
+        constraint = order_book.computation_offset == open_order.computation_offset
+            @ DuskError::ComputationMismatch
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    /// CHECK: Position owner, receives the rent of a rejected order
+    #[account(mut)]
+    pub user: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            user.key().as_ref(),
+            user_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = user_position.bump
+    )]
+    pub user_position: Account<'info, UserPosition>,
+
+    /// Order whose add_hidden_order computation is being delivered
+    #[account(
+        mut,
+        constraint = open_order.position == user_position.key() @ DuskError::OrderNotFound
+    )]
+    pub open_order: Account<'info, OpenOrder>,
+}
+
+pub fn callback_handler(
+    ctx: Context<AddHiddenOrderCallback>,
+    output: SignedComputationOutputs<AddHiddenOrderOutput>,
+) -> Result<()> {
+    // Only an output signed by the cluster for this computation is accepted
+    let (book, accepted) = match output.verify_output(
+        &ctx.accounts.cluster_account,
+        &ctx.accounts.computation_account,
+    ) {
+        Ok(AddHiddenOrderOutput {
+            field_0: AddHiddenOrderOutputStruct0 { field_0, field_1 },
+        }) => (field_0, field_1),
+        Err(_) => return Err(DuskError::AbortedComputation.into()),
+    };
+
+    // The order was reclaimed by its owner after the deadline, so this late
+    // result must not put it back in the book
+    // The book is still freed: this was its latest job, so it must not stay busy
+    if ctx.accounts.open_order.status != OrderStatus::PendingAdd {
+        ctx.accounts.order_book.end_computation();
+        msg!("Ignoring late add_hidden_order result for order {}", ctx.accounts.open_order.order_id);
+        return Ok(());
+    }
+    let open_order = &mut ctx.accounts.open_order;
+    open_order.deadline = 0;
+
+    // A rejected order leaves the book unchanged; storing it frees the book for the next job
+    ctx.accounts.order_book.store_state(book.nonce, book.ciphertexts);
+
+    if accepted {
+        open_order.status = OrderStatus::Resting;
+
+        msg!(
+            "Side-hidden order {} added to the book of market {}",
+            open_order.order_id,
+            ctx.accounts.market.market_id
+        );
+        return Ok(());
+    }
+
+    // The lock of its side didn't cover the order; the book is unchanged and
+    // both locks go back with the closed order, which says nothing about the
+    // side either
+    let order_id = open_order.order_id;
+    let base_released = open_order.locked_base;
+    let quote_released = open_order.locked_amount;
+
+    let market = &mut ctx.accounts.market;
+    ctx.accounts.user_position.unlock_hidden_for_cancel(base_released, quote_released);
+    market.unlock(base_released, false);
+    market.unlock(quote_released, true);
+    market.active_hidden = market.active_hidden.saturating_sub(1);
+
+    emit!(HiddenOrderRejected {
+        market: market.key(),
+        user: ctx.accounts.user.key(),
+        order_id,
+        base_released,
+        quote_released,
+    });
+
+    ctx.accounts.open_order.close(ctx.accounts.user.to_account_info())?;
+
+    msg!("Side-hidden order {} rejected: its lock does not cover it", order_id);
+
+    Ok(())
+}
@@ -3,9 +3,10 @@ use arcium_anchor::prelude::*;
 use arcium_client::idl::arcium::{types::CallbackAccount, ID_CONST};
 
 use crate::ID;
-use crate::state::{Market, UserPosition};
+use crate::state::{Market, MarketStatus, OpenOrder, OrderBook, OrderStatus, UserPosition};
 use crate::events::OrderPlaced;
 use crate::errors::DuskError;
+use crate::AddOrderOutput;
 
 /// SignerAccount for the Arcium CPI signing PDA
 /// 9 bytes: 8 for discriminator + 1 for bump
@@ -20,26 +21,49 @@ pub const COMP_DEF_OFFSET_ADD_ORDER: u8 = 0;
 /// Place an encrypted limit order
 #[queue_computation_accounts("add_order", user)]
 #[derive(Accounts)]
-#[instruction(order_id: u64)]
+#[instruction(computation_offset: u64, order_id: u64)]
 pub struct PlaceOrder<'info> {
+    /// Position owner or its trading delegate
     #[account(mut)]
     pub user: Signer<'info>,
 
     #[account(mut)]
     pub market: Account<'info, Market>,
 
+    /// Encrypted order book of this market
+    #[account(
+        mut,
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig,
+        constraint = order_book.initialized @ DuskError::OrderBookNotInitialized
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
     #[account(
         mut,
         seeds = [
             UserPosition::SEED_PREFIX,
             market.key().as_ref(),
-            user.key().as_ref()
+            user_position.owner.as_ref(),
+            user_position.sub_account_id.to_le_bytes().as_ref()
         ],
-        bump = user_position.bump,
-        constraint = user_position.owner == user.key() @ DuskError::Unauthorized
+        bump = user_position.bump
     )]
     pub user_position: Account<'info, UserPosition>,
 
+    /// Lock bookkeeping for this order; order IDs are unique per position
+    #[account(
+        init,
+        payer = user,
+        space = OpenOrder::LEN,
+        seeds = [
+            OpenOrder::SEED_PREFIX,
+            user_position.key().as_ref(),
+            order_id.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub open_order: Account<'info, OpenOrder>,
+
     /// Signer PDA for CPI to Arcium
     #[account(
         init_if_needed,
@@ -61,8 +85,11 @@ pub struct PlaceOrder<'info> {
     #[account(mut)]
     pub executing_pool: UncheckedAccount<'info>,
 
-    /// CHECK: Computation account (will be initialized)
-    #[account(mut)]
+    /// CHECK: Computation account at the PDA of `computation_offset` (will be initialized)
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset, mxe_account, DuskError::ClusterNotSet)
+    )]
     pub computation_account: UncheckedAccount<'info>,
 
     /// Computation definition account for add_order
@@ -70,7 +97,7 @@ pub struct PlaceOrder<'info> {
     pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
 
     /// Cluster account
-    #[account(mut)]
+    #[account(mut, address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
     pub cluster_account: Account<'info, Cluster>,
 
     /// Pool account (Arcium fee pool)
@@ -86,16 +113,36 @@ pub struct PlaceOrder<'info> {
 
 pub fn handler(
     ctx: Context<PlaceOrder>,
+    computation_offset: u64,
     order_id: u64,
     is_buy: bool,
     encrypted_price: Vec<u8>,
     encrypted_amount: Vec<u8>,
-    _nonce: [u8; 12],
+    client_pubkey: [u8; 32],
+    nonce: u128,
     lock_amount: u64,
 ) -> Result<()> {
     // Set the sign_pda_account bump for CPI signing
     ctx.accounts.sign_pda_account.bump = ctx.bumps.sign_pda_account;
 
+    // Orders are accepted during the opening auction and continuous trading
+    require!(!ctx.accounts.market.is_delisted(), DuskError::MarketDelisted);
+    require!(
+        !ctx.accounts.market.confidential_balances,
+        DuskError::ConfidentialMarket
+    );
+    require!(
+        ctx.accounts.market.status != MarketStatus::Paused,
+        DuskError::MarketPaused
+    );
+
+    // Only the owner or an unexpired delegate can trade this position
+    let now = Clock::get()?.unix_timestamp;
+    require!(
+        ctx.accounts.user_position.can_trade(&ctx.accounts.user.key(), now),
+        DuskError::Unauthorized
+    );
+
     // Validate encrypted data lengths (32 bytes for encrypted values)
     require!(
         encrypted_price.len() == 32 && encrypted_amount.len() == 32,
@@ -109,6 +156,26 @@ pub fn handler(
     // Buy orders lock quote tokens, sell orders lock base tokens
     let user_position = &mut ctx.accounts.user_position;
     user_position.lock_for_order(lock_amount, is_buy)?;
+    ctx.accounts.market.lock(lock_amount, is_buy)?;
+
+    // Every book job reads the state the previous one wrote
+    require!(!ctx.accounts.order_book.is_busy(now), DuskError::OrderBookBusy);
+
+    // Reserve this market's next computation offset for the add_order job
+    let market_key = ctx.accounts.market.key();
+    ctx.accounts.market.claim_computation_offset(&market_key, computation_offset)?;
+    ctx.accounts.order_book.begin_computation(computation_offset, now);
+
+    let open_order = &mut ctx.accounts.open_order;
+    open_order.market = ctx.accounts.market.key();
+    open_order.position = user_position.key();
+    open_order.order_id = order_id;
+    open_order.is_buy = is_buy;
+    open_order.locked_amount = lock_amount;
+    open_order.client_pubkey = client_pubkey;
+    open_order.computation_offset = computation_offset;
+    open_order.begin_computation(OrderStatus::PendingAdd, now);
+    open_order.bump = ctx.bumps.open_order;
 
     // Convert Vec<u8> to [u8; 32] for encrypted values
     let price_arr: [u8; 32] = encrypted_price.try_into()
@@ -117,41 +184,50 @@ pub fn handler(
         .map_err(|_| DuskError::InvalidEncryptedData)?;
 
     // Capture keys before mutable borrows
-    let market_key = ctx.accounts.market.key();
-    let user_key = ctx.accounts.user.key();
+    // Orders always belong to the position owner, even when a delegate signs
+    let user_key = ctx.accounts.user_position.owner;
     let user_position_key = ctx.accounts.user_position.key();
+    let order_book_key = ctx.accounts.order_book.key();
     let market_id = ctx.accounts.market.market_id;
 
-    // Split the user's pubkey into two u128 values for the Order struct
-    let user_bytes = user_key.to_bytes();
-    let owner_lo = u128::from_le_bytes(user_bytes[..16].try_into().unwrap());
-    let owner_hi = u128::from_le_bytes(user_bytes[16..].try_into().unwrap());
+    // Orders are owned by the sub-account position, so matches settle
+    // against that position rather than the owner's wallet
+    let (owner_lo, owner_hi) = UserPosition::split_key(&user_position_key);
 
     // Build computation arguments for add_order circuit using ArgBuilder
+    // Layout: Enc<Shared, OrderInput> (client key, nonce, price, amount),
+    // then the plaintext owner, order id and side, then the book
     let computation_args = ArgBuilder::new()
+        .x25519_pubkey(client_pubkey)
+        .plaintext_u128(nonce)
         .encrypted_u64(price_arr)
         .encrypted_u64(amount_arr)
         .plaintext_u128(owner_lo)
         .plaintext_u128(owner_hi)
         .plaintext_u64(order_id)
         .plaintext_bool(is_buy)
+        // Current encrypted book of this market
+        .plaintext_u128(ctx.accounts.order_book.state_nonce)
+        .account(order_book_key, OrderBook::STATE_OFFSET, OrderBook::STATE_LEN)
         .build();
 
     // Define callback accounts
     let callback_accounts = vec![
         CallbackAccount { pubkey: market_key, is_writable: true },
+        CallbackAccount { pubkey: order_book_key, is_writable: true },
         CallbackAccount { pubkey: user_key, is_writable: false },
         CallbackAccount { pubkey: user_position_key, is_writable: true },
+        CallbackAccount { pubkey: ctx.accounts.open_order.key(), is_writable: true },
     ];
 
     // Queue the encrypted computation
     queue_computation(
         ctx.accounts,
-        0,
+        computation_offset,
         computation_args,
         None,
         vec![AddOrderCallback::callback_ix(
-            COMP_DEF_OFFSET_ADD_ORDER as u64,
+            computation_offset,
             &ctx.accounts.mxe_account,
             &callback_accounts,
         )?],
@@ -164,20 +240,20 @@ pub fn handler(
     let market = &mut ctx.accounts.market;
 
     market.order_count = market.order_count.saturating_add(1);
+    ctx.accounts.open_order.book_seq = market.order_count;
     if is_buy {
         market.active_bids = market.active_bids.saturating_add(1);
     } else {
         market.active_asks = market.active_asks.saturating_add(1);
     }
 
-    let clock = Clock::get()?;
-
     emit!(OrderPlaced {
         market: market_key,
         user: user_key,
         order_id,
         is_buy,
-        timestamp: clock.unix_timestamp,
+        computation_offset,
+        timestamp: now,
     });
 
     msg!(
@@ -191,15 +267,43 @@ pub fn handler(
 }
 
 /// Callback for add_order computation
-/// Note: Using manual implementation instead of callback_accounts macro due to SDK version issues
+#[callback_accounts("add_order")]
 #[derive(Accounts)]
 pub struct AddOrderCallback<'info> {
-    /// CHECK: Arcium callback authority
-    pub callback_authority: Signer<'info>,
+    pub arcium_program: Program<'info, Arcium>,
+
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_ADD_ORDER))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Computation account, must be the one queued by `place_order` for this order
+    #[account(
+        address = derive_comp_pda!(open_order.computation_offset, mxe_account, DuskError::ClusterNotSet)
+            @ DuskError::ComputationMismatch
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// CHECK: Instructions sysvar, checked by the address constraint
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
 
     #[account(mut)]
     pub market: Account<'info, Market>,
 
+    /// Only the latest job queued on the book may write it
+    #[account(
+        mut,
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig,
+        constraint = order_book.computation_offset == open_order.computation_offset
+            @ DuskError::ComputationMismatch
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
     /// CHECK: User who placed the order
     pub user: UncheckedAccount<'info>,
 
@@ -208,31 +312,49 @@ pub struct AddOrderCallback<'info> {
         seeds = [
             UserPosition::SEED_PREFIX,
             market.key().as_ref(),
-            user.key().as_ref()
+            user.key().as_ref(),
+            user_position.sub_account_id.to_le_bytes().as_ref()
         ],
         bump = user_position.bump
     )]
     pub user_position: Account<'info, UserPosition>,
+
+    /// Order whose add_order computation is being delivered
+    #[account(
+        mut,
+        constraint = open_order.position == user_position.key() @ DuskError::OrderNotFound
+    )]
+    pub open_order: Account<'info, OpenOrder>,
 }
 
-impl AddOrderCallback<'_> {
-    /// Generate callback instruction for queue_computation
-    pub fn callback_ix(
-        _computation_offset: u64,
-        _mxe_account: &MXEAccount,
-        extra_accs: &[CallbackAccount],
-    ) -> Result<arcium_client::idl::arcium::types::CallbackInstruction> {
-        Ok(arcium_client::idl::arcium::types::CallbackInstruction {
-            program_id: crate::ID,
-            discriminator: vec![0u8; 8], // Will be set correctly by Arcium runtime
-            accounts: extra_accs.to_vec(),
-        })
+pub fn callback_handler(
+    ctx: Context<AddOrderCallback>,
+    output: SignedComputationOutputs<AddOrderOutput>,
+) -> Result<()> {
+    // Only an output signed by the cluster for this computation is accepted
+    let book = match output.verify_output(
+        &ctx.accounts.cluster_account,
+        &ctx.accounts.computation_account,
+    ) {
+        Ok(AddOrderOutput { field_0 }) => field_0,
+        Err(_) => return Err(DuskError::AbortedComputation.into()),
+    };
+
+    // The order was reclaimed by its owner after the deadline, so this late
+    // result must not put it back in the book
+    // The book is still freed: this was its latest job, so it must not stay busy
+    if ctx.accounts.open_order.status != OrderStatus::PendingAdd {
+        ctx.accounts.order_book.end_computation();
+        msg!("Ignoring late add_order result for order {}", ctx.accounts.open_order.order_id);
+        return Ok(());
     }
-}
+    let open_order = &mut ctx.accounts.open_order;
+    open_order.status = OrderStatus::Resting;
+    open_order.deadline = 0;
 
-pub fn callback_handler(ctx: Context<AddOrderCallback>) -> Result<()> {
     // Callback from Arcium after order is added to encrypted orderbook
-    // The actual order data is stored encrypted in the MXE
+    // The updated book is stored encrypted in the market's OrderBook account
+    ctx.accounts.order_book.store_state(book.nonce, book.ciphertexts);
 
     msg!(
         "Order placed callback received for user {} on market {}",
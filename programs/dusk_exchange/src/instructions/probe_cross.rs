@@ -0,0 +1,206 @@
+use anchor_lang::prelude::*;
+use arcium_anchor::prelude::*;
+use arcium_client::idl::arcium::{types::CallbackAccount, ID_CONST};
+
+use crate::ID;
+use crate::state::{Market, MarketStatus, OrderBook};
+use crate::events::BookProbed;
+use crate::errors::DuskError;
+use crate::instructions::place_order::SignerAccount;
+use crate::ProbeCrossOutput;
+
+/// Computation definition offset for probe_cross
+pub const COMP_DEF_OFFSET_PROBE_CROSS: u8 = 19;
+
+/// Check whether the book is crossed without matching it
+/// Much cheaper than match_book; the result is kept on the market for keepers
+#[queue_computation_accounts("probe_cross", caller)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct ProbeCross<'info> {
+    /// Anyone can probe the book (keeper, user, etc.)
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// Encrypted order book of this market
+    #[account(
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig,
+        constraint = order_book.initialized @ DuskError::OrderBookNotInitialized
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    /// Signer PDA for CPI to Arcium
+    #[account(
+        init_if_needed,
+        space = 9,
+        payer = caller,
+        seeds = [&SIGN_PDA_SEED],
+        bump
+    )]
+    pub sign_pda_account: Account<'info, SignerAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Mempool account
+    #[account(mut)]
+    pub mempool_account: UncheckedAccount<'info>,
+
+    /// CHECK: Executing pool account
+    #[account(mut)]
+    pub executing_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Computation account at the PDA of `computation_offset` (will be initialized)
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset, mxe_account, DuskError::ClusterNotSet)
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    /// Computation definition account for probe_cross
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_PROBE_CROSS))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    /// Cluster account
+    #[account(mut, address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// Pool account (Arcium fee pool)
+    #[account(mut)]
+    pub pool_account: Account<'info, FeePool>,
+
+    /// Clock account
+    pub clock_account: Account<'info, ClockAccount>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(ctx: Context<ProbeCross>, computation_offset: u64) -> Result<()> {
+    // Set the sign_pda_account bump for CPI signing
+    ctx.accounts.sign_pda_account.bump = ctx.bumps.sign_pda_account;
+
+    let market = &ctx.accounts.market;
+
+    // The answer is only useful to decide on a match, which needs continuous trading
+    require!(
+        market.status != MarketStatus::Auction,
+        DuskError::MarketInAuction
+    );
+    require!(
+        market.status == MarketStatus::Continuous,
+        DuskError::MarketPaused
+    );
+
+    // Without a bid and an ask the book cannot be crossed
+    require!(market.may_cross(), DuskError::NoMatchingOrders);
+
+    // Reserve this market's next computation offset for the probe_cross job
+    // The probe does not write the book, so it does not take book_computation_offset
+    let market_key = market.key();
+    let state_nonce = ctx.accounts.order_book.state_nonce;
+    let market = &mut ctx.accounts.market;
+    market.claim_computation_offset(&market_key, computation_offset)?;
+    market.probe_computation_offset = computation_offset;
+    market.probe_state_nonce = state_nonce;
+    market.book_crossed = false;
+
+    let computation_args = ArgBuilder::new()
+        .plaintext_u128(state_nonce)
+        .account(
+            ctx.accounts.order_book.key(),
+            OrderBook::STATE_OFFSET,
+            OrderBook::STATE_LEN,
+        )
+        .build();
+
+    // Define callback accounts
+    let callback_accounts = vec![CallbackAccount {
+        pubkey: market_key,
+        is_writable: true,
+    }];
+
+    // Queue the encrypted computation
+    queue_computation(
+        ctx.accounts,
+        computation_offset,
+        computation_args,
+        None,
+        vec![ProbeCrossCallback::callback_ix(
+            computation_offset,
+            &ctx.accounts.mxe_account,
+            &callback_accounts,
+        )?],
+        1, // returns whether the book is crossed
+        0, // tip
+    )?;
+
+    msg!("Cross probe requested on market {}", ctx.accounts.market.market_id);
+
+    Ok(())
+}
+
+/// Callback for probe_cross computation
+#[callback_accounts("probe_cross")]
+#[derive(Accounts)]
+pub struct ProbeCrossCallback<'info> {
+    pub arcium_program: Program<'info, Arcium>,
+
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_PROBE_CROSS))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Computation account, must be the latest probe queued for this market
+    #[account(
+        address = derive_comp_pda!(market.probe_computation_offset, mxe_account, DuskError::ClusterNotSet)
+            @ DuskError::ComputationMismatch
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// CHECK: Instructions sysvar, checked by the address constraint
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+}
+
+pub fn callback_handler(
+    ctx: Context<ProbeCrossCallback>,
+    output: SignedComputationOutputs<ProbeCrossOutput>,
+) -> Result<()> {
+    // Only an output signed by the cluster for this computation is accepted
+    let crossed = match output.verify_output(
+        &ctx.accounts.cluster_account,
+        &ctx.accounts.computation_account,
+    ) {
+        Ok(ProbeCrossOutput { field_0 }) => field_0,
+        Err(_) => return Err(DuskError::AbortedComputation.into()),
+    };
+
+    let market = &mut ctx.accounts.market;
+    market.book_crossed = crossed;
+
+    emit!(BookProbed {
+        market: market.key(),
+        crossed,
+        state_nonce: market.probe_state_nonce,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!(
+        "Book of market {} is {}",
+        market.market_id,
+        if crossed { "crossed" } else { "not crossed" }
+    );
+
+    Ok(())
+}
@@ -0,0 +1,219 @@
+use anchor_lang::prelude::*;
+use arcium_anchor::prelude::*;
+use arcium_client::idl::arcium::{types::CallbackAccount, ID_CONST};
+
+use crate::ID;
+use crate::state::{Market, OrderBook, UserPosition};
+use crate::events::MyOrdersQueried;
+use crate::errors::DuskError;
+use crate::instructions::place_order::SignerAccount;
+use crate::QueryMyOrdersOutput;
+
+/// Computation definition offset for query_my_orders
+pub const COMP_DEF_OFFSET_QUERY_MY_ORDERS: u8 = 18;
+
+/// Read a position's live orders from the encrypted book
+/// Id, price and remaining amount come back encrypted to `client_pubkey` in an event
+#[queue_computation_accounts("query_my_orders", user)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct QueryMyOrders<'info> {
+    /// Position owner or its trading delegate
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// Encrypted order book of this market
+    #[account(
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig,
+        constraint = order_book.initialized @ DuskError::OrderBookNotInitialized
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    #[account(
+        mut,
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            user_position.owner.as_ref(),
+            user_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = user_position.bump
+    )]
+    pub user_position: Account<'info, UserPosition>,
+
+    /// Signer PDA for CPI to Arcium
+    #[account(
+        init_if_needed,
+        space = 9,
+        payer = user,
+        seeds = [&SIGN_PDA_SEED],
+        bump
+    )]
+    pub sign_pda_account: Account<'info, SignerAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Mempool account
+    #[account(mut)]
+    pub mempool_account: UncheckedAccount<'info>,
+
+    /// CHECK: Executing pool account
+    #[account(mut)]
+    pub executing_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Computation account at the PDA of `computation_offset` (will be initialized)
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset, mxe_account, DuskError::ClusterNotSet)
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    /// Computation definition account for query_my_orders
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_QUERY_MY_ORDERS))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    /// Cluster account
+    #[account(mut, address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// Pool account (Arcium fee pool)
+    #[account(mut)]
+    pub pool_account: Account<'info, FeePool>,
+
+    /// Clock account
+    pub clock_account: Account<'info, ClockAccount>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(
+    ctx: Context<QueryMyOrders>,
+    computation_offset: u64,
+    client_pubkey: [u8; 32],
+    nonce: u128,
+) -> Result<()> {
+    // Set the sign_pda_account bump for CPI signing
+    ctx.accounts.sign_pda_account.bump = ctx.bumps.sign_pda_account;
+
+    // The orders are only readable by the query key, but who may ask follows
+    // who may trade the position
+    let now = Clock::get()?.unix_timestamp;
+    require!(
+        ctx.accounts.user_position.can_trade(&ctx.accounts.user.key(), now),
+        DuskError::Unauthorized
+    );
+
+    // Reserve this market's next computation offset for the query_my_orders job
+    // A newer query replaces an older one still in flight
+    let market_key = ctx.accounts.market.key();
+    ctx.accounts.market.claim_computation_offset(&market_key, computation_offset)?;
+    ctx.accounts.user_position.query_computation_offset = computation_offset;
+
+    // Orders in the book are identified by the sub-account position
+    let user_position_key = ctx.accounts.user_position.key();
+    let (owner_lo, owner_hi) = UserPosition::split_key(&user_position_key);
+
+    let computation_args = ArgBuilder::new()
+        .x25519_pubkey(client_pubkey)
+        .plaintext_u128(nonce)
+        .plaintext_u128(owner_lo)
+        .plaintext_u128(owner_hi)
+        // Current encrypted book of this market
+        .plaintext_u128(ctx.accounts.order_book.state_nonce)
+        .account(
+            ctx.accounts.order_book.key(),
+            OrderBook::STATE_OFFSET,
+            OrderBook::STATE_LEN,
+        )
+        .build();
+
+    // Define callback accounts
+    let callback_accounts = vec![CallbackAccount {
+        pubkey: user_position_key,
+        is_writable: false,
+    }];
+
+    // Queue the encrypted computation
+    queue_computation(
+        ctx.accounts,
+        computation_offset,
+        computation_args,
+        None,
+        vec![QueryMyOrdersCallback::callback_ix(
+            computation_offset,
+            &ctx.accounts.mxe_account,
+            &callback_accounts,
+        )?],
+        1, // returns the orders encrypted to the client
+        0, // tip
+    )?;
+
+    msg!(
+        "Order query requested for position {} on market {}",
+        user_position_key,
+        ctx.accounts.market.market_id
+    );
+
+    Ok(())
+}
+
+/// Callback for query_my_orders computation
+#[callback_accounts("query_my_orders")]
+#[derive(Accounts)]
+pub struct QueryMyOrdersCallback<'info> {
+    pub arcium_program: Program<'info, Arcium>,
+
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_QUERY_MY_ORDERS))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Computation account, must be the latest query queued for this position
+    #[account(
+        address = derive_comp_pda!(user_position.query_computation_offset, mxe_account, DuskError::ClusterNotSet)
+            @ DuskError::ComputationMismatch
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// CHECK: Instructions sysvar, checked by the address constraint
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+
+    pub user_position: Account<'info, UserPosition>,
+}
+
+pub fn callback_handler(
+    ctx: Context<QueryMyOrdersCallback>,
+    output: SignedComputationOutputs<QueryMyOrdersOutput>,
+) -> Result<()> {
+    // Only an output signed by the cluster for this computation is accepted
+    let orders = match output.verify_output(
+        &ctx.accounts.cluster_account,
+        &ctx.accounts.computation_account,
+    ) {
+        Ok(QueryMyOrdersOutput { field_0 }) => field_0,
+        Err(_) => return Err(DuskError::AbortedComputation.into()),
+    };
+
+    let user_position = &ctx.accounts.user_position;
+
+    emit!(MyOrdersQueried {
+        market: user_position.market,
+        position: user_position.key(),
+        nonce: orders.nonce,
+        orders: orders.ciphertexts,
+    });
+
+    msg!("Order query answered for position {}", user_position.key());
+
+    Ok(())
+}
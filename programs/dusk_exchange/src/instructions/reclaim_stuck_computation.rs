@@ -0,0 +1,101 @@
+use anchor_lang::prelude::*;
+
+use crate::state::{Market, OpenOrder, OrderStatus, UserPosition};
+use crate::events::StuckComputationReclaimed;
+use crate::errors::DuskError;
+
+/// Release the lock of an order whose add never called back and close it, or
+/// reopen an order whose cancel never called back
+#[derive(Accounts)]
+#[instruction(order_id: u64)]
+pub struct ReclaimStuckComputation<'info> {
+    /// Position owner, receives the rent of a closed order
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            user.key().as_ref(),
+            user_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = user_position.bump
+    )]
+    pub user_position: Account<'info, UserPosition>,
+
+    #[account(
+        mut,
+        seeds = [
+            OpenOrder::SEED_PREFIX,
+            user_position.key().as_ref(),
+            order_id.to_le_bytes().as_ref()
+        ],
+        bump = open_order.bump
+    )]
+    pub open_order: Account<'info, OpenOrder>,
+}
+
+pub fn handler(ctx: Context<ReclaimStuckComputation>, order_id: u64) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    let user_position = &mut ctx.accounts.user_position;
+    let open_order = &mut ctx.accounts.open_order;
+
+    let now = Clock::get()?.unix_timestamp;
+    require!(open_order.is_stuck(now), DuskError::ComputationNotStuck);
+
+    // A stuck cancel leaves the order in the book, where it can still be
+    // matched, so its lock stays and it is reopened for another cancel
+    // A late result of the stuck cancel still applies while no other job has
+    // written the book since
+    if open_order.status == OrderStatus::PendingCancel {
+        open_order.status = OrderStatus::Resting;
+        open_order.deadline = 0;
+
+        msg!("Reopened stuck cancel of order {} for another attempt", order_id);
+        return Ok(());
+    }
+
+    // A stuck add never wrote the book, so the order is released and closed;
+    // a late add result for it no longer finds the order and cannot land
+    let released = open_order.locked_amount;
+    if open_order.side_hidden {
+        // Both locks of a side-hidden order go back
+        let released_base = open_order.locked_base;
+        user_position.unlock_hidden_for_cancel(released_base, released);
+        market.unlock(released_base, false);
+        market.unlock(released, true);
+        market.active_hidden = market.active_hidden.saturating_sub(1);
+    } else if open_order.is_buy {
+        user_position.unlock_for_cancel(released, true);
+        market.unlock(released, true);
+        market.active_bids = market.active_bids.saturating_sub(1);
+    } else {
+        user_position.unlock_for_cancel(released, false);
+        market.unlock(released, false);
+        market.active_asks = market.active_asks.saturating_sub(1);
+    }
+
+    ctx.accounts.open_order.close(ctx.accounts.user.to_account_info())?;
+
+    emit!(StuckComputationReclaimed {
+        market: market.key(),
+        user: user_position.owner,
+        order_id,
+        released,
+    });
+
+    msg!(
+        "Reclaimed stuck order {} for user {} on market {} (released: {})",
+        order_id,
+        user_position.owner,
+        market.market_id,
+        released
+    );
+
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use anchor_lang::prelude::*;
+
+use crate::state::{Market, MarketStatus, OpenOrder, OrderStatus, UserPosition};
+use crate::events::LocksReleased;
+use crate::errors::DuskError;
+
+/// Release the lock of an order left over when its market's book was cleared,
+/// or that the latest uncross shows is out of the book, and close the order,
+/// returning its rent to the position owner
+/// `BookCleared` and `AuctionUncrossed` name the orders that were still in the
+/// book; orders displaced from it earlier are released the same way
+#[derive(Accounts)]
+pub struct ReleaseLocks<'info> {
+    /// Anyone can release locks; funds stay in the position
+    pub caller: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        constraint = user_position.market == market.key() @ DuskError::InvalidMarketConfig
+    )]
+    pub user_position: Account<'info, UserPosition>,
+
+    #[account(
+        mut,
+        close = owner,
+        seeds = [
+            OpenOrder::SEED_PREFIX,
+            user_position.key().as_ref(),
+            open_order.order_id.to_le_bytes().as_ref()
+        ],
+        bump = open_order.bump
+    )]
+    pub open_order: Account<'info, OpenOrder>,
+
+    /// CHECK: Position owner, receives the order's rent
+    #[account(
+        mut,
+        address = user_position.owner @ DuskError::Unauthorized
+    )]
+    pub owner: UncheckedAccount<'info>,
+}
+
+pub fn handler(ctx: Context<ReleaseLocks>) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    let user_position = &mut ctx.accounts.user_position;
+    let open_order = &ctx.accounts.open_order;
+
+    // Confidential orders hold an encrypted lock that only a circuit can release
+    require!(!market.confidential_balances, DuskError::ConfidentialMarket);
+
+    if market.status != MarketStatus::WithdrawOnly {
+        // Before the book is cleared, only an order the latest uncross shows
+        // out of the book is stale: one that never improved on the best or
+        // was displaced from it
+        require!(!market.is_delisted(), DuskError::InvalidDelistingPhase);
+        require!(
+            open_order.status == OrderStatus::Resting
+                && market.left_book_at_uncross(
+                    &user_position.key(),
+                    open_order.order_id,
+                    open_order.book_seq
+                ),
+            DuskError::OrderStillInBook
+        );
+        market.remove_active_order(open_order.is_buy, open_order.side_hidden);
+    }
+
+    // clear_book waits for every fill to settle, so no fill can still draw on
+    // the lock; an order out of the book waits for its own
+    require!(open_order.open_fills == 0, DuskError::ComputationNotReady);
+
+    // The order is out of the book, so whatever it still locks is stale
+    // A void order already gave its lock back and left the position's count
+    let (base_released, quote_released) = if open_order.status == OrderStatus::Void {
+        (0, 0)
+    } else if open_order.side_hidden {
+        user_position.unlock_hidden_for_cancel(open_order.locked_base, open_order.locked_amount);
+        (open_order.locked_base, open_order.locked_amount)
+    } else if open_order.is_buy {
+        user_position.unlock_for_cancel(open_order.locked_amount, true);
+        (0, open_order.locked_amount)
+    } else {
+        user_position.unlock_for_cancel(open_order.locked_amount, false);
+        (open_order.locked_amount, 0)
+    };
+    market.unlock(base_released, false);
+    market.unlock(quote_released, true);
+
+    emit!(LocksReleased {
+        market: market.key(),
+        position: user_position.key(),
+        order_id: open_order.order_id,
+        base_released,
+        quote_released,
+    });
+
+    msg!(
+        "Released {} base and {} quote locked by order {} of user {} on market {}",
+        base_released,
+        quote_released,
+        open_order.order_id,
+        user_position.owner,
+        market.market_id
+    );
+
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use anchor_lang::prelude::*;
+
+use crate::state::{Market, UserPosition};
+use crate::events::AuditorKeySet;
+use crate::errors::DuskError;
+
+/// Register or remove the auditor key of a position
+/// Only the owner can choose who its orders and fills are disclosed to
+#[derive(Accounts)]
+pub struct SetAuditorKey<'info> {
+    pub user: Signer<'info>,
+
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            user.key().as_ref(),
+            user_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = user_position.bump,
+        constraint = user_position.owner == user.key() @ DuskError::Unauthorized
+    )]
+    pub user_position: Account<'info, UserPosition>,
+}
+
+/// An all-zero `auditor_pubkey` removes the auditor
+pub fn handler(ctx: Context<SetAuditorKey>, auditor_pubkey: [u8; 32]) -> Result<()> {
+    let user_position = &mut ctx.accounts.user_position;
+    user_position.auditor_pubkey = auditor_pubkey;
+
+    emit!(AuditorKeySet {
+        market: ctx.accounts.market.key(),
+        owner: user_position.owner,
+        position: user_position.key(),
+        auditor_pubkey,
+    });
+
+    msg!(
+        "Auditor {} for user {} on market {}",
+        if user_position.has_auditor() { "registered" } else { "removed" },
+        user_position.owner,
+        ctx.accounts.market.market_id
+    );
+
+    Ok(())
+}
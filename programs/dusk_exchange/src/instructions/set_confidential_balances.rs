@@ -0,0 +1,48 @@
+use anchor_lang::prelude::*;
+
+use crate::state::Market;
+use crate::events::ConfidentialBalancesSet;
+use crate::errors::DuskError;
+
+/// Switch a market between public and confidential balances
+#[derive(Accounts)]
+pub struct SetConfidentialBalances<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority @ DuskError::Unauthorized
+    )]
+    pub market: Account<'info, Market>,
+}
+
+pub fn handler(ctx: Context<SetConfidentialBalances>, enabled: bool) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+
+    require!(!market.is_delisted(), DuskError::MarketDelisted);
+
+    // Balances can't be carried between the two modes, so the switch is only
+    // allowed before any position is opened
+    require!(market.open_position_count == 0, DuskError::MarketNotEmpty);
+
+    // Confidential settlement needs the fill terms in plaintext
+    require!(
+        !enabled || !market.sealed_fills,
+        DuskError::SealedFillMarket
+    );
+
+    market.confidential_balances = enabled;
+
+    emit!(ConfidentialBalancesSet {
+        market: market.key(),
+        enabled,
+    });
+
+    msg!(
+        "Market {} confidential balances {}",
+        market.market_id,
+        if enabled { "enabled" } else { "disabled" }
+    );
+
+    Ok(())
+}
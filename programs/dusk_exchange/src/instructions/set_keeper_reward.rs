@@ -0,0 +1,37 @@
+use anchor_lang::prelude::*;
+
+use crate::state::Market;
+use crate::errors::DuskError;
+
+/// Configure the per-crank keeper reward
+/// Capped by `Market::max_keeper_reward`, a share of the fee on a reference fill
+#[derive(Accounts)]
+pub struct SetKeeperReward<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority @ DuskError::Unauthorized
+    )]
+    pub market: Account<'info, Market>,
+}
+
+pub fn handler(ctx: Context<SetKeeperReward>, keeper_reward: u64) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+
+    // A fill pays one reward per crank; together they must not outgrow its fee
+    require!(
+        keeper_reward <= market.max_keeper_reward(),
+        DuskError::KeeperRewardTooHigh
+    );
+
+    market.keeper_reward = keeper_reward;
+
+    msg!(
+        "Keeper reward on market {} set to {} quote tokens per crank",
+        market.market_id,
+        keeper_reward
+    );
+
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use anchor_lang::prelude::*;
+
+use crate::state::{Market, MarketStatus};
+use crate::events::MarketStatusChanged;
+use crate::errors::DuskError;
+
+/// Pause or resume a market
+#[derive(Accounts)]
+pub struct SetMarketPaused<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority @ DuskError::Unauthorized
+    )]
+    pub market: Account<'info, Market>,
+}
+
+pub fn handler(ctx: Context<SetMarketPaused>, paused: bool) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+
+    require!(!market.is_delisted(), DuskError::MarketDelisted);
+
+    if paused {
+        market.status = MarketStatus::Paused;
+    } else {
+        require!(
+            market.status == MarketStatus::Paused,
+            DuskError::MarketNotPaused
+        );
+        // Resumed markets go through a fresh opening auction
+        market.status = MarketStatus::Auction;
+    }
+
+    emit!(MarketStatusChanged {
+        market: market.key(),
+        status: market.status,
+    });
+
+    msg!(
+        "Market {} {}",
+        market.market_id,
+        if paused { "paused" } else { "resumed into opening auction" }
+    );
+
+    Ok(())
+}
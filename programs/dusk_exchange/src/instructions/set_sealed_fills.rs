@@ -0,0 +1,50 @@
+use anchor_lang::prelude::*;
+
+use crate::state::Market;
+use crate::events::SealedFillsSet;
+use crate::errors::DuskError;
+
+/// Switch a market between revealed and sealed fills
+#[derive(Accounts)]
+pub struct SetSealedFills<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority @ DuskError::Unauthorized
+    )]
+    pub market: Account<'info, Market>,
+}
+
+pub fn handler(ctx: Context<SetSealedFills>, enabled: bool) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+
+    require!(!market.is_delisted(), DuskError::MarketDelisted);
+
+    // Confidential settlement needs the fill terms in plaintext
+    require!(
+        !enabled || !market.confidential_balances,
+        DuskError::ConfidentialMarket
+    );
+
+    // Matches and settlements already recorded keep the mode they were made in
+    require!(
+        !market.has_pending_match && market.open_settlements == 0,
+        DuskError::ComputationNotReady
+    );
+
+    market.sealed_fills = enabled;
+
+    emit!(SealedFillsSet {
+        market: market.key(),
+        enabled,
+    });
+
+    msg!(
+        "Market {} sealed fills {}",
+        market.market_id,
+        if enabled { "enabled" } else { "disabled" }
+    );
+
+    Ok(())
+}
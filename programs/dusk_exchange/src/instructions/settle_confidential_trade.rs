@@ -0,0 +1,500 @@
+use anchor_lang::prelude::*;
+use arcium_anchor::prelude::*;
+use arcium_client::idl::arcium::{types::CallbackAccount, ID_CONST};
+
+use crate::ID;
+use crate::state::{
+    ConfidentialBalance, ExchangeConfig, Market, OpenOrder, OrderStatus, TradeSettlement,
+    UserPosition,
+};
+use crate::events::{SettlementVoided, TradeSettled};
+use crate::errors::DuskError;
+use crate::instructions::place_order::SignerAccount;
+use crate::{SettleFillOutput, SettleFillOutputStruct0};
+
+/// Computation definition offset for settle_fill
+pub const COMP_DEF_OFFSET_SETTLE_FILL: u8 = 11;
+
+/// Settle a matched trade between two confidential balances
+/// Execution price and amount are already public; the balances they move stay encrypted
+#[queue_computation_accounts("settle_fill", caller)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct SettleConfidentialTrade<'info> {
+    /// Anyone can settle (usually maker, taker, or keeper)
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// Global config holding the protocol fee share, applied on callback
+    #[account(
+        seeds = [ExchangeConfig::SEED_PREFIX],
+        bump = exchange_config.bump
+    )]
+    pub exchange_config: Account<'info, ExchangeConfig>,
+
+    #[account(
+        mut,
+        constraint = settlement.market == market.key() @ DuskError::InvalidMarketConfig,
+        constraint = !settlement.settled @ DuskError::TradeAlreadySettled
+    )]
+    pub settlement: Account<'info, TradeSettlement>,
+
+    /// Maker sub-account position recorded by the match
+    #[account(
+        address = settlement.maker @ DuskError::Unauthorized,
+        constraint = maker_position.market == market.key() @ DuskError::InvalidMarketConfig
+    )]
+    pub maker_position: Account<'info, UserPosition>,
+
+    /// Taker sub-account position recorded by the match
+    #[account(
+        address = settlement.taker @ DuskError::Unauthorized,
+        constraint = taker_position.market == market.key() @ DuskError::InvalidMarketConfig
+    )]
+    pub taker_position: Account<'info, UserPosition>,
+
+    #[account(
+        mut,
+        seeds = [ConfidentialBalance::SEED_PREFIX, maker_position.key().as_ref()],
+        bump = maker_balance.bump
+    )]
+    pub maker_balance: Account<'info, ConfidentialBalance>,
+
+    #[account(
+        mut,
+        seeds = [ConfidentialBalance::SEED_PREFIX, taker_position.key().as_ref()],
+        bump = taker_balance.bump
+    )]
+    pub taker_balance: Account<'info, ConfidentialBalance>,
+
+    /// Maker order, whose encrypted lock backs the maker side of the fill
+    #[account(
+        mut,
+        seeds = [
+            OpenOrder::SEED_PREFIX,
+            maker_position.key().as_ref(),
+            settlement.maker_order_id.to_le_bytes().as_ref()
+        ],
+        bump = maker_order.bump
+    )]
+    pub maker_order: Account<'info, OpenOrder>,
+
+    /// Taker order, whose encrypted lock backs the taker side of the fill
+    #[account(
+        mut,
+        seeds = [
+            OpenOrder::SEED_PREFIX,
+            taker_position.key().as_ref(),
+            settlement.taker_order_id.to_le_bytes().as_ref()
+        ],
+        bump = taker_order.bump
+    )]
+    pub taker_order: Account<'info, OpenOrder>,
+
+    /// Signer PDA for CPI to Arcium
+    #[account(
+        init_if_needed,
+        space = 9,
+        payer = caller,
+        seeds = [&SIGN_PDA_SEED],
+        bump
+    )]
+    pub sign_pda_account: Account<'info, SignerAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Mempool account
+    #[account(mut)]
+    pub mempool_account: UncheckedAccount<'info>,
+
+    /// CHECK: Executing pool account
+    #[account(mut)]
+    pub executing_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Computation account at the PDA of `computation_offset` (will be initialized)
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset, mxe_account, DuskError::ClusterNotSet)
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    /// Computation definition account for settle_fill
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_SETTLE_FILL))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    /// Cluster account
+    #[account(mut, address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// Pool account (Arcium fee pool)
+    #[account(mut)]
+    pub pool_account: Account<'info, FeePool>,
+
+    /// Clock account
+    pub clock_account: Account<'info, ClockAccount>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(ctx: Context<SettleConfidentialTrade>, computation_offset: u64) -> Result<()> {
+    // Set the sign_pda_account bump for CPI signing
+    ctx.accounts.sign_pda_account.bump = ctx.bumps.sign_pda_account;
+
+    require!(
+        ctx.accounts.market.confidential_balances,
+        DuskError::NotConfidentialMarket
+    );
+
+    let now = Clock::get()?.unix_timestamp;
+
+    // An order reclaimed before it reached the book can't back a fill
+    if ctx.accounts.maker_order.status == OrderStatus::Void
+        || ctx.accounts.taker_order.status == OrderStatus::Void
+    {
+        let settlement = &mut ctx.accounts.settlement;
+        let market = &mut ctx.accounts.market;
+        settlement.settled = true;
+        settlement.settled_at = now;
+        market.open_settlements = market.open_settlements.saturating_sub(1);
+
+        emit!(SettlementVoided {
+            market: market.key(),
+            settlement: settlement.key(),
+        });
+
+        msg!("Settlement {} voided: a matched order was reclaimed", settlement.key());
+
+        // Only a circuit can release an encrypted lock, so a filled order whose
+        // last fill this was goes back to Resting for its owner to cancel
+        if ctx.accounts.maker_order.finish_fill() {
+            ctx.accounts.maker_order.status = OrderStatus::Resting;
+        }
+        if ctx.accounts.taker_order.finish_fill() {
+            ctx.accounts.taker_order.status = OrderStatus::Resting;
+        }
+        return Ok(());
+    }
+
+    // Both balances are rewritten by the computation, so neither may have
+    // another one in flight; this also keeps a settlement from being queued twice
+    require!(
+        ctx.accounts.maker_balance.initialized && ctx.accounts.taker_balance.initialized,
+        DuskError::ConfidentialBalanceNotInitialized
+    );
+    require!(
+        !ctx.accounts.maker_balance.is_busy(now) && !ctx.accounts.taker_balance.is_busy(now),
+        DuskError::ConfidentialBalanceBusy
+    );
+
+    let settlement = &ctx.accounts.settlement;
+    let base_amount = settlement.execution_amount;
+    let quote_amount = settlement.calculate_quote_amount();
+    let fee = ctx.accounts.market.calculate_fee(quote_amount);
+    let maker_is_buy = settlement.maker_is_buy;
+
+    // Reserve this market's next computation offset for the settle_fill job
+    let market_key = ctx.accounts.market.key();
+    ctx.accounts.market.claim_computation_offset(&market_key, computation_offset)?;
+    ctx.accounts.maker_balance.begin_computation(computation_offset, now);
+    ctx.accounts.taker_balance.begin_computation(computation_offset, now);
+
+    // maker_is_buy: if true, maker was buying (so taker is selling)
+    let (buyer_position, buyer_balance, buyer_order, seller_position, seller_balance, seller_order) =
+        if maker_is_buy {
+            (
+                &ctx.accounts.maker_position,
+                &ctx.accounts.maker_balance,
+                &ctx.accounts.maker_order,
+                &ctx.accounts.taker_position,
+                &ctx.accounts.taker_balance,
+                &ctx.accounts.taker_order,
+            )
+        } else {
+            (
+                &ctx.accounts.taker_position,
+                &ctx.accounts.taker_balance,
+                &ctx.accounts.taker_order,
+                &ctx.accounts.maker_position,
+                &ctx.accounts.maker_balance,
+                &ctx.accounts.maker_order,
+            )
+        };
+
+    let computation_args = ArgBuilder::new()
+        .plaintext_u128(buyer_balance.state_nonce)
+        .account(
+            buyer_balance.key(),
+            ConfidentialBalance::STATE_OFFSET,
+            ConfidentialBalance::STATE_LEN,
+        )
+        .plaintext_u128(buyer_order.lock_nonce)
+        .account(buyer_order.key(), OpenOrder::ENCRYPTED_LOCK_OFFSET, 32)
+        .plaintext_u128(seller_balance.state_nonce)
+        .account(
+            seller_balance.key(),
+            ConfidentialBalance::STATE_OFFSET,
+            ConfidentialBalance::STATE_LEN,
+        )
+        .plaintext_u128(seller_order.lock_nonce)
+        .account(seller_order.key(), OpenOrder::ENCRYPTED_LOCK_OFFSET, 32)
+        .plaintext_u64(base_amount)
+        .plaintext_u64(quote_amount)
+        .plaintext_u64(fee)
+        // An order settling its last fill gives back what is left of its lock
+        .plaintext_bool(buyer_order.is_last_fill())
+        .plaintext_bool(seller_order.is_last_fill())
+        .build();
+
+    // Define callback accounts
+    let callback_accounts = vec![
+        CallbackAccount {
+            pubkey: market_key,
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.exchange_config.key(),
+            is_writable: false,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.settlement.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: buyer_balance.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: buyer_order.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: seller_balance.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: seller_order.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: buyer_position.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: buyer_position.owner,
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: seller_position.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: seller_position.owner,
+            is_writable: true,
+        },
+    ];
+
+    // Queue the encrypted computation
+    queue_computation(
+        ctx.accounts,
+        computation_offset,
+        computation_args,
+        None,
+        vec![SettleFillCallback::callback_ix(
+            computation_offset,
+            &ctx.accounts.mxe_account,
+            &callback_accounts,
+        )?],
+        1, // returns both balances and locks, and whether the fill was paid
+        0, // tip
+    )?;
+
+    msg!(
+        "Confidential settlement {} queued: {} base for {} quote (fee: {})",
+        ctx.accounts.settlement.key(),
+        base_amount,
+        quote_amount,
+        fee
+    );
+
+    Ok(())
+}
+
+/// Callback for settle_fill computation
+#[callback_accounts("settle_fill")]
+#[derive(Accounts)]
+pub struct SettleFillCallback<'info> {
+    pub arcium_program: Program<'info, Arcium>,
+
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_SETTLE_FILL))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Computation account, must be the latest one queued on both balances
+    #[account(
+        address = derive_comp_pda!(buyer_balance.computation_offset, mxe_account, DuskError::ClusterNotSet)
+            @ DuskError::ComputationMismatch,
+        constraint = seller_balance.computation_offset == buyer_balance.computation_offset
+            @ DuskError::ComputationMismatch
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// CHECK: Instructions sysvar, checked by the address constraint
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// Global config holding the protocol fee share
+    #[account(
+        seeds = [ExchangeConfig::SEED_PREFIX],
+        bump = exchange_config.bump
+    )]
+    pub exchange_config: Account<'info, ExchangeConfig>,
+
+    #[account(
+        mut,
+        constraint = settlement.market == market.key() @ DuskError::InvalidMarketConfig
+    )]
+    pub settlement: Account<'info, TradeSettlement>,
+
+    #[account(mut)]
+    pub buyer_balance: Account<'info, ConfidentialBalance>,
+
+    #[account(
+        mut,
+        constraint = buyer_order.position == buyer_balance.position @ DuskError::OrderNotFound
+    )]
+    pub buyer_order: Account<'info, OpenOrder>,
+
+    #[account(mut)]
+    pub seller_balance: Account<'info, ConfidentialBalance>,
+
+    #[account(
+        mut,
+        constraint = seller_order.position == seller_balance.position @ DuskError::OrderNotFound
+    )]
+    pub seller_order: Account<'info, OpenOrder>,
+
+    #[account(mut, address = buyer_balance.position @ DuskError::Unauthorized)]
+    pub buyer_position: Account<'info, UserPosition>,
+
+    /// CHECK: Buyer position owner, receives the buyer order's rent once it is filled
+    #[account(mut, address = buyer_position.owner @ DuskError::Unauthorized)]
+    pub buyer_owner: UncheckedAccount<'info>,
+
+    #[account(mut, address = seller_balance.position @ DuskError::Unauthorized)]
+    pub seller_position: Account<'info, UserPosition>,
+
+    /// CHECK: Seller position owner, receives the seller order's rent once it is filled
+    #[account(mut, address = seller_position.owner @ DuskError::Unauthorized)]
+    pub seller_owner: UncheckedAccount<'info>,
+}
+
+pub fn callback_handler(
+    ctx: Context<SettleFillCallback>,
+    output: SignedComputationOutputs<SettleFillOutput>,
+) -> Result<()> {
+    // Only an output signed by the cluster for this computation is accepted
+    let (buyer, buyer_lock, seller, seller_lock, paid) = match output.verify_output(
+        &ctx.accounts.cluster_account,
+        &ctx.accounts.computation_account,
+    ) {
+        Ok(SettleFillOutput {
+            field_0: SettleFillOutputStruct0 { field_0, field_1, field_2, field_3, field_4 },
+        }) => (field_0, field_1, field_2, field_3, field_4),
+        Err(_) => return Err(DuskError::AbortedComputation.into()),
+    };
+
+    let settlement = &mut ctx.accounts.settlement;
+    let market = &mut ctx.accounts.market;
+
+    // A retried settlement may already have been applied by an earlier callback;
+    // its result is dropped, but the balances must not stay busy until the timeout
+    if settlement.settled {
+        ctx.accounts.buyer_balance.end_computation();
+        ctx.accounts.seller_balance.end_computation();
+        msg!("Ignoring duplicate settle_fill result for settlement {}", settlement.key());
+        return Ok(());
+    }
+
+    // Balances and locks are written back either way: an unpaid fill leaves them unchanged
+    ctx.accounts.buyer_balance.store_state(buyer.nonce, buyer.ciphertexts);
+    ctx.accounts.seller_balance.store_state(seller.nonce, seller.ciphertexts);
+    ctx.accounts.buyer_order.lock_nonce = buyer_lock.nonce;
+    ctx.accounts.buyer_order.encrypted_lock = buyer_lock.ciphertexts[0];
+    ctx.accounts.seller_order.lock_nonce = seller_lock.nonce;
+    ctx.accounts.seller_order.encrypted_lock = seller_lock.ciphertexts[0];
+
+    settlement.settled = true;
+    settlement.settled_at = Clock::get()?.unix_timestamp;
+    market.open_settlements = market.open_settlements.saturating_sub(1);
+
+    // The circuit already released the lock of an order whose last fill this
+    // was, so only the plaintext bookkeeping is left
+    if ctx.accounts.buyer_order.finish_fill() {
+        let buyer_position = &mut ctx.accounts.buyer_position;
+        buyer_position.active_order_count = buyer_position.active_order_count.saturating_sub(1);
+        ctx.accounts.buyer_order.close(ctx.accounts.buyer_owner.to_account_info())?;
+    }
+    if ctx.accounts.seller_order.finish_fill() {
+        let seller_position = &mut ctx.accounts.seller_position;
+        seller_position.active_order_count = seller_position.active_order_count.saturating_sub(1);
+        ctx.accounts.seller_order.close(ctx.accounts.seller_owner.to_account_info())?;
+    }
+
+    if !paid {
+        // Neither side could cover the fill, so it is dropped like a voided one
+        emit!(SettlementVoided {
+            market: market.key(),
+            settlement: settlement.key(),
+        });
+
+        msg!("Settlement {} voided: a balance could not cover the fill", settlement.key());
+        return Ok(());
+    }
+
+    let base_amount = settlement.execution_amount;
+    let quote_amount = settlement.calculate_quote_amount();
+
+    // The fee stays in the quote vault, split the same way as public settlements
+    let fee = market.calculate_fee(quote_amount);
+    market.debit_deposit(fee, false)?;
+    let protocol_fee = ctx.accounts.exchange_config.protocol_fee(fee);
+    market.protocol_fees_accrued = market
+        .protocol_fees_accrued
+        .checked_add(protocol_fee)
+        .ok_or(DuskError::MathOverflow)?;
+    market.fees_accrued = market
+        .fees_accrued
+        .checked_add(fee - protocol_fee)
+        .ok_or(DuskError::MathOverflow)?;
+
+    emit!(TradeSettled {
+        market: market.key(),
+        settlement: settlement.key(),
+        maker: settlement.maker,
+        taker: settlement.taker,
+        base_transferred: base_amount,
+        quote_transferred: quote_amount,
+    });
+
+    msg!(
+        "Confidential trade settled: {} base for {} quote (fee: {})",
+        base_amount,
+        quote_amount,
+        fee
+    );
+
+    Ok(())
+}
@@ -0,0 +1,454 @@
+use anchor_lang::prelude::*;
+use arcium_anchor::prelude::*;
+use arcium_client::idl::arcium::{types::CallbackAccount, ID_CONST};
+
+use crate::ID;
+use crate::state::{ExchangeConfig, Market, OpenOrder, OrderStatus, TradeSettlement, UserPosition};
+use crate::events::{SealedTradeSettled, SettlementVoided};
+use crate::errors::DuskError;
+use crate::instructions::place_order::SignerAccount;
+use crate::instructions::settle_trade::{apply_fill, finish_fill};
+use crate::{RevealFillOutput, RevealFillOutputStruct0, RevealFillOutputStruct02};
+
+/// Computation definition offset for reveal_fill
+pub const COMP_DEF_OFFSET_REVEAL_FILL: u8 = 15;
+
+/// Settle a sealed fill
+/// The MXE hands the fill terms to both counterparties under their order keys
+/// and reveals only the base and quote amounts that move between the positions
+/// Those amounts give the execution price away, so settling makes the fill public
+#[queue_computation_accounts("reveal_fill", caller)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct SettleSealedTrade<'info> {
+    /// Anyone can settle (usually maker, taker, or keeper)
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// Global config holding the protocol fee share, applied on callback
+    #[account(
+        seeds = [ExchangeConfig::SEED_PREFIX],
+        bump = exchange_config.bump
+    )]
+    pub exchange_config: Account<'info, ExchangeConfig>,
+
+    #[account(
+        mut,
+        constraint = settlement.market == market.key() @ DuskError::InvalidMarketConfig,
+        constraint = !settlement.settled @ DuskError::TradeAlreadySettled,
+        constraint = settlement.sealed_fill @ DuskError::NotSealedFillMarket
+    )]
+    pub settlement: Account<'info, TradeSettlement>,
+
+    /// Maker sub-account position recorded by the match
+    #[account(
+        mut,
+        address = settlement.maker @ DuskError::Unauthorized,
+        constraint = maker_position.market == market.key() @ DuskError::InvalidMarketConfig
+    )]
+    pub maker_position: Account<'info, UserPosition>,
+
+    /// Taker sub-account position recorded by the match
+    #[account(
+        mut,
+        address = settlement.taker @ DuskError::Unauthorized,
+        constraint = taker_position.market == market.key() @ DuskError::InvalidMarketConfig
+    )]
+    pub taker_position: Account<'info, UserPosition>,
+
+    /// Maker order, whose client key receives the maker's copy of the fill
+    #[account(
+        mut,
+        seeds = [
+            OpenOrder::SEED_PREFIX,
+            maker_position.key().as_ref(),
+            settlement.maker_order_id.to_le_bytes().as_ref()
+        ],
+        bump = maker_order.bump
+    )]
+    pub maker_order: Account<'info, OpenOrder>,
+
+    /// Taker order, whose client key receives the taker's copy of the fill
+    #[account(
+        mut,
+        seeds = [
+            OpenOrder::SEED_PREFIX,
+            taker_position.key().as_ref(),
+            settlement.taker_order_id.to_le_bytes().as_ref()
+        ],
+        bump = taker_order.bump
+    )]
+    pub taker_order: Account<'info, OpenOrder>,
+
+    /// CHECK: Maker position owner, receives the maker order's rent once it is filled
+    #[account(mut, address = maker_position.owner @ DuskError::Unauthorized)]
+    pub maker_owner: UncheckedAccount<'info>,
+
+    /// CHECK: Taker position owner, receives the taker order's rent once it is filled
+    #[account(mut, address = taker_position.owner @ DuskError::Unauthorized)]
+    pub taker_owner: UncheckedAccount<'info>,
+
+    /// Signer PDA for CPI to Arcium
+    #[account(
+        init_if_needed,
+        space = 9,
+        payer = caller,
+        seeds = [&SIGN_PDA_SEED],
+        bump
+    )]
+    pub sign_pda_account: Account<'info, SignerAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Mempool account
+    #[account(mut)]
+    pub mempool_account: UncheckedAccount<'info>,
+
+    /// CHECK: Executing pool account
+    #[account(mut)]
+    pub executing_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Computation account at the PDA of `computation_offset` (will be initialized)
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset, mxe_account, DuskError::ClusterNotSet)
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    /// Computation definition account for reveal_fill
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_REVEAL_FILL))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    /// Cluster account
+    #[account(mut, address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// Pool account (Arcium fee pool)
+    #[account(mut)]
+    pub pool_account: Account<'info, FeePool>,
+
+    /// Clock account
+    pub clock_account: Account<'info, ClockAccount>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(ctx: Context<SettleSealedTrade>, computation_offset: u64) -> Result<()> {
+    // Set the sign_pda_account bump for CPI signing
+    ctx.accounts.sign_pda_account.bump = ctx.bumps.sign_pda_account;
+
+    let now = Clock::get()?.unix_timestamp;
+
+    // A voided order was reclaimed before it reached the book and holds no
+    // lock, so a fill naming it is dropped instead of settled
+    if ctx.accounts.maker_order.status == OrderStatus::Void
+        || ctx.accounts.taker_order.status == OrderStatus::Void
+    {
+        let settlement = &mut ctx.accounts.settlement;
+        let market = &mut ctx.accounts.market;
+        settlement.settled = true;
+        settlement.settled_at = now;
+        market.open_settlements = market.open_settlements.saturating_sub(1);
+
+        emit!(SettlementVoided {
+            market: market.key(),
+            settlement: settlement.key(),
+        });
+
+        msg!("Settlement {} voided: a matched order was reclaimed", settlement.key());
+
+        finish_fill(
+            market,
+            &mut ctx.accounts.maker_position,
+            &mut ctx.accounts.maker_order,
+            &ctx.accounts.maker_owner,
+        )?;
+        finish_fill(
+            market,
+            &mut ctx.accounts.taker_position,
+            &mut ctx.accounts.taker_order,
+            &ctx.accounts.taker_owner,
+        )?;
+        return Ok(());
+    }
+
+    // One reveal at a time; a stuck one can be retried after the timeout
+    require!(
+        !ctx.accounts.settlement.is_busy(now),
+        DuskError::SettlementBusy
+    );
+
+    // Reserve this market's next computation offset for the reveal_fill job
+    let market_key = ctx.accounts.market.key();
+    ctx.accounts.market.claim_computation_offset(&market_key, computation_offset)?;
+    ctx.accounts.settlement.begin_computation(computation_offset, now);
+
+    // The offset is unique per computation, so it serves as the output nonce
+    // for both copies; each is under a different shared secret
+    let nonce = computation_offset as u128;
+    let settlement = &ctx.accounts.settlement;
+    let computation_args = ArgBuilder::new()
+        .x25519_pubkey(ctx.accounts.maker_order.client_pubkey)
+        .plaintext_u128(nonce)
+        .x25519_pubkey(ctx.accounts.taker_order.client_pubkey)
+        .plaintext_u128(nonce)
+        .plaintext_u128(settlement.fill_nonce)
+        .account(
+            settlement.key(),
+            TradeSettlement::ENCRYPTED_FILL_OFFSET,
+            TradeSettlement::ENCRYPTED_FILL_LEN,
+        )
+        .build();
+
+    // Define callback accounts
+    let callback_accounts = vec![
+        CallbackAccount {
+            pubkey: market_key,
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.exchange_config.key(),
+            is_writable: false,
+        },
+        CallbackAccount {
+            pubkey: settlement.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.maker_position.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.taker_position.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.maker_order.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.taker_order.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.maker_owner.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.taker_owner.key(),
+            is_writable: true,
+        },
+    ];
+
+    // Queue the encrypted computation
+    queue_computation(
+        ctx.accounts,
+        computation_offset,
+        computation_args,
+        None,
+        vec![RevealFillCallback::callback_ix(
+            computation_offset,
+            &ctx.accounts.mxe_account,
+            &callback_accounts,
+        )?],
+        1, // returns both copies of the fill and the amounts to move
+        0, // tip
+    )?;
+
+    msg!("Sealed settlement {} queued", ctx.accounts.settlement.key());
+
+    Ok(())
+}
+
+/// Callback for reveal_fill computation
+#[callback_accounts("reveal_fill")]
+#[derive(Accounts)]
+pub struct RevealFillCallback<'info> {
+    pub arcium_program: Program<'info, Arcium>,
+
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_REVEAL_FILL))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Computation account, must be the latest one queued on this settlement
+    #[account(
+        address = derive_comp_pda!(settlement.computation_offset, mxe_account, DuskError::ClusterNotSet)
+            @ DuskError::ComputationMismatch
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// CHECK: Instructions sysvar, checked by the address constraint
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// Global config holding the protocol fee share
+    #[account(
+        seeds = [ExchangeConfig::SEED_PREFIX],
+        bump = exchange_config.bump
+    )]
+    pub exchange_config: Account<'info, ExchangeConfig>,
+
+    #[account(
+        mut,
+        constraint = settlement.market == market.key() @ DuskError::InvalidMarketConfig
+    )]
+    pub settlement: Account<'info, TradeSettlement>,
+
+    #[account(mut, address = settlement.maker @ DuskError::Unauthorized)]
+    pub maker_position: Account<'info, UserPosition>,
+
+    #[account(mut, address = settlement.taker @ DuskError::Unauthorized)]
+    pub taker_position: Account<'info, UserPosition>,
+
+    #[account(
+        mut,
+        constraint = maker_order.position == maker_position.key() @ DuskError::OrderNotFound,
+        constraint = maker_order.order_id == settlement.maker_order_id @ DuskError::OrderNotFound
+    )]
+    pub maker_order: Account<'info, OpenOrder>,
+
+    #[account(
+        mut,
+        constraint = taker_order.position == taker_position.key() @ DuskError::OrderNotFound,
+        constraint = taker_order.order_id == settlement.taker_order_id @ DuskError::OrderNotFound
+    )]
+    pub taker_order: Account<'info, OpenOrder>,
+
+    /// CHECK: Maker position owner, receives the maker order's rent once it is filled
+    #[account(mut, address = maker_position.owner @ DuskError::Unauthorized)]
+    pub maker_owner: UncheckedAccount<'info>,
+
+    /// CHECK: Taker position owner, receives the taker order's rent once it is filled
+    #[account(mut, address = taker_position.owner @ DuskError::Unauthorized)]
+    pub taker_owner: UncheckedAccount<'info>,
+}
+
+pub fn callback_handler(
+    ctx: Context<RevealFillCallback>,
+    output: SignedComputationOutputs<RevealFillOutput>,
+) -> Result<()> {
+    // Only an output signed by the cluster for this computation is accepted
+    let (maker_fill, taker_fill, deltas) = match output.verify_output(
+        &ctx.accounts.cluster_account,
+        &ctx.accounts.computation_account,
+    ) {
+        Ok(RevealFillOutput {
+            field_0: RevealFillOutputStruct0 { field_0, field_1, field_2 },
+        }) => (field_0, field_1, field_2),
+        Err(_) => return Err(DuskError::AbortedComputation.into()),
+    };
+    // Fields follow the FillDeltas circuit struct
+    let RevealFillOutputStruct02 {
+        field_0: base_amount,
+        field_1: quote_amount,
+    } = deltas;
+
+    let settlement = &mut ctx.accounts.settlement;
+    let market = &mut ctx.accounts.market;
+
+    if settlement.settled {
+        msg!("Ignoring duplicate reveal_fill result for settlement {}", settlement.key());
+        return Ok(());
+    }
+    settlement.pending_until = 0;
+
+    // Same check as the handler, for an order voided while the reveal was in flight
+    if ctx.accounts.maker_order.status == OrderStatus::Void
+        || ctx.accounts.taker_order.status == OrderStatus::Void
+    {
+        settlement.settled = true;
+        settlement.settled_at = Clock::get()?.unix_timestamp;
+        market.open_settlements = market.open_settlements.saturating_sub(1);
+
+        emit!(SettlementVoided {
+            market: market.key(),
+            settlement: settlement.key(),
+        });
+
+        msg!("Settlement {} voided: a matched order was reclaimed", settlement.key());
+
+        finish_fill(
+            market,
+            &mut ctx.accounts.maker_position,
+            &mut ctx.accounts.maker_order,
+            &ctx.accounts.maker_owner,
+        )?;
+        finish_fill(
+            market,
+            &mut ctx.accounts.taker_position,
+            &mut ctx.accounts.taker_order,
+            &ctx.accounts.taker_owner,
+        )?;
+        return Ok(());
+    }
+
+    // Maker is always the ask in our circuit design, as in settle_trade
+    let maker_order = &mut ctx.accounts.maker_order;
+    let taker_order = &mut ctx.accounts.taker_order;
+    let maker_position = &mut ctx.accounts.maker_position;
+    let taker_position = &mut ctx.accounts.taker_position;
+    let (buyer_position, seller_position, buyer_order, seller_order) = if settlement.maker_is_buy {
+        (maker_position, taker_position, maker_order, taker_order)
+    } else {
+        (taker_position, maker_position, taker_order, maker_order)
+    };
+
+    let fee = apply_fill(
+        market,
+        &ctx.accounts.exchange_config,
+        buyer_position,
+        buyer_order,
+        seller_position,
+        seller_order,
+        base_amount,
+        quote_amount,
+    )?;
+
+    settlement.settled = true;
+    settlement.settled_at = Clock::get()?.unix_timestamp;
+    market.open_settlements = market.open_settlements.saturating_sub(1);
+
+    emit!(SealedTradeSettled {
+        market: market.key(),
+        settlement: settlement.key(),
+        maker: settlement.maker,
+        taker: settlement.taker,
+        fill_commitment: settlement.fill_commitment,
+        base_transferred: base_amount,
+        quote_transferred: quote_amount,
+        maker_fill_nonce: maker_fill.nonce,
+        maker_fill: maker_fill.ciphertexts,
+        taker_fill_nonce: taker_fill.nonce,
+        taker_fill: taker_fill.ciphertexts,
+    });
+
+    msg!("Sealed trade settled (fee: {})", fee);
+
+    finish_fill(
+        market,
+        &mut ctx.accounts.maker_position,
+        &mut ctx.accounts.maker_order,
+        &ctx.accounts.maker_owner,
+    )?;
+    finish_fill(
+        market,
+        &mut ctx.accounts.taker_position,
+        &mut ctx.accounts.taker_order,
+        &ctx.accounts.taker_owner,
+    )?;
+
+    Ok(())
+}
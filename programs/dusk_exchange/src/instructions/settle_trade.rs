@@ -1,8 +1,8 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{Token, TokenAccount};
+use anchor_spl::token_interface::{TokenAccount, TokenInterface};
 
-use crate::state::{Market, UserPosition, TradeSettlement};
-use crate::events::TradeSettled;
+use crate::state::{ExchangeConfig, KeeperCrank, Market, OpenOrder, OrderStatus, UserPosition, TradeSettlement};
+use crate::events::{KeeperRewardSkipped, KeeperRewarded, SettlementVoided, TradeSettled};
 use crate::errors::DuskError;
 
 /// Settle a matched trade by transferring tokens
@@ -14,6 +14,13 @@ pub struct SettleTrade<'info> {
     #[account(mut)]
     pub market: Account<'info, Market>,
 
+    /// Global config holding the protocol fee share
+    #[account(
+        seeds = [ExchangeConfig::SEED_PREFIX],
+        bump = exchange_config.bump
+    )]
+    pub exchange_config: Account<'info, ExchangeConfig>,
+
     #[account(
         mut,
         constraint = settlement.market == market.key() @ DuskError::InvalidMarketConfig,
@@ -21,62 +28,269 @@ pub struct SettleTrade<'info> {
     )]
     pub settlement: Account<'info, TradeSettlement>,
 
+    /// Maker sub-account position recorded by the match
+    #[account(
+        mut,
+        address = settlement.maker @ DuskError::Unauthorized,
+        constraint = maker_position.market == market.key() @ DuskError::InvalidMarketConfig
+    )]
+    pub maker_position: Account<'info, UserPosition>,
+
+    /// Taker sub-account position recorded by the match
+    #[account(
+        mut,
+        address = settlement.taker @ DuskError::Unauthorized,
+        constraint = taker_position.market == market.key() @ DuskError::InvalidMarketConfig
+    )]
+    pub taker_position: Account<'info, UserPosition>,
+
+    /// Maker order, whose lock backs the maker side of the fill
     #[account(
         mut,
         seeds = [
-            UserPosition::SEED_PREFIX,
-            market.key().as_ref(),
-            settlement.maker.as_ref()
+            OpenOrder::SEED_PREFIX,
+            maker_position.key().as_ref(),
+            settlement.maker_order_id.to_le_bytes().as_ref()
         ],
-        bump
+        bump = maker_order.bump
     )]
-    pub maker_position: Account<'info, UserPosition>,
+    pub maker_order: Account<'info, OpenOrder>,
+
+    /// Taker order, whose lock backs the taker side of the fill
+    #[account(
+        mut,
+        seeds = [
+            OpenOrder::SEED_PREFIX,
+            taker_position.key().as_ref(),
+            settlement.taker_order_id.to_le_bytes().as_ref()
+        ],
+        bump = taker_order.bump
+    )]
+    pub taker_order: Account<'info, OpenOrder>,
+
+    /// CHECK: Maker position owner, receives the maker order's rent once it is filled
+    #[account(mut, address = maker_position.owner @ DuskError::Unauthorized)]
+    pub maker_owner: UncheckedAccount<'info>,
 
+    /// CHECK: Taker position owner, receives the taker order's rent once it is filled
+    #[account(mut, address = taker_position.owner @ DuskError::Unauthorized)]
+    pub taker_owner: UncheckedAccount<'info>,
+
+    /// Caller's position on this market, credited with the keeper reward
+    /// Must not be the maker or taker position; counterparties settling their
+    /// own trade simply omit it
     #[account(
         mut,
         seeds = [
             UserPosition::SEED_PREFIX,
             market.key().as_ref(),
-            settlement.taker.as_ref()
+            caller.key().as_ref(),
+            keeper_position.sub_account_id.to_le_bytes().as_ref()
         ],
-        bump
+        bump = keeper_position.bump,
+        constraint = keeper_position.key() != maker_position.key() @ DuskError::InvalidKeeperPosition,
+        constraint = keeper_position.key() != taker_position.key() @ DuskError::InvalidKeeperPosition
     )]
-    pub taker_position: Account<'info, UserPosition>,
+    pub keeper_position: Option<Account<'info, UserPosition>>,
 
     /// Base token vault
     #[account(
         mut,
         constraint = base_vault.key() == market.base_vault
     )]
-    pub base_vault: Account<'info, TokenAccount>,
+    pub base_vault: InterfaceAccount<'info, TokenAccount>,
 
     /// Quote token vault
     #[account(
         mut,
         constraint = quote_vault.key() == market.quote_vault
     )]
-    pub quote_vault: Account<'info, TokenAccount>,
+    pub quote_vault: InterfaceAccount<'info, TokenAccount>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 pub fn handler(ctx: Context<SettleTrade>) -> Result<()> {
+    // Confidential balances are settled inside the MXE by settle_confidential_trade
+    require!(
+        !ctx.accounts.market.confidential_balances,
+        DuskError::ConfidentialMarket
+    );
+
+    // Sealed fill terms are only known to the MXE; settle_sealed_trade reveals the amounts
+    require!(
+        !ctx.accounts.settlement.sealed_fill,
+        DuskError::SealedFillMarket
+    );
+
     let settlement = &mut ctx.accounts.settlement;
-    let market = &ctx.accounts.market;
+    let market = &mut ctx.accounts.market;
     let maker_position = &mut ctx.accounts.maker_position;
     let taker_position = &mut ctx.accounts.taker_position;
 
+    // A voided order was reclaimed before it reached the book and holds no
+    // lock, so a fill naming it is dropped instead of settled
+    if ctx.accounts.maker_order.status == OrderStatus::Void
+        || ctx.accounts.taker_order.status == OrderStatus::Void
+    {
+        settlement.settled = true;
+        settlement.settled_at = Clock::get()?.unix_timestamp;
+        market.open_settlements = market.open_settlements.saturating_sub(1);
+
+        emit!(SettlementVoided {
+            market: market.key(),
+            settlement: settlement.key(),
+        });
+
+        msg!("Settlement {} voided: a matched order was reclaimed", settlement.key());
+
+        finish_fill(market, maker_position, &mut ctx.accounts.maker_order, &ctx.accounts.maker_owner)?;
+        finish_fill(market, taker_position, &mut ctx.accounts.taker_order, &ctx.accounts.taker_owner)?;
+        return Ok(());
+    }
+
     let base_amount = settlement.execution_amount;
     let quote_amount = settlement.calculate_quote_amount();
 
     // Determine who is buying and who is selling
     // maker_is_buy: if true, maker was buying (so taker is selling)
-    let (buyer_position, seller_position) = if settlement.maker_is_buy {
-        (maker_position, taker_position)
+    let maker_order = &mut ctx.accounts.maker_order;
+    let taker_order = &mut ctx.accounts.taker_order;
+    let (buyer_position, seller_position, buyer_order, seller_order) = if settlement.maker_is_buy {
+        (maker_position, taker_position, maker_order, taker_order)
     } else {
-        (taker_position, maker_position)
+        (taker_position, maker_position, taker_order, maker_order)
     };
 
+    let fee = apply_fill(
+        market,
+        &ctx.accounts.exchange_config,
+        buyer_position,
+        buyer_order,
+        seller_position,
+        seller_order,
+        base_amount,
+        quote_amount,
+    )?;
+
+    // Mark settlement as complete
+    settlement.settled = true;
+    market.open_settlements = market.open_settlements.saturating_sub(1);
+    settlement.settled_at = Clock::get()?.unix_timestamp;
+
+    emit!(TradeSettled {
+        market: market.key(),
+        settlement: settlement.key(),
+        maker: settlement.maker,
+        taker: settlement.taker,
+        base_transferred: base_amount,
+        quote_transferred: quote_amount,
+    });
+
+    if let Some(keeper_position) = ctx.accounts.keeper_position.as_mut() {
+        let reward = market.pay_keeper_reward(keeper_position)?;
+        if reward > 0 {
+            emit!(KeeperRewarded {
+                market: market.key(),
+                keeper: keeper_position.owner,
+                crank: KeeperCrank::SettleTrade,
+                amount: reward,
+            });
+        }
+     else if market.keeper_reward > 0 {
+        emit!(KeeperRewardSkipped {
+            market: market.key(),
+            crank: KeeperCrank::SettleTrade,
+        });
+    }
+
+    msg!(
+        "Trade settled: {} base for {} quote (fee: {})",
+        base_amount,
+        quote_amount,
+        fee
+    );
+
+    finish_fill(
+        market,
+        &mut ctx.accounts.maker_position,
+        &mut ctx.accounts.maker_order,
+        &ctx.accounts.maker_owner,
+    )?;
+    finish_fill(
+        market,
+        &mut ctx.accounts.taker_position,
+        &mut ctx.accounts.taker_order,
+        &ctx.accounts.taker_owner,
+    )?;
+
+    Ok(())
+}
+
+/// Count a settled or voided fill against an order
+/// Once a filled order has no fill left to settle, what is left of its lock
+/// (price improvement on a buy, or a voided fill) goes back to the position,
+/// the order stops counting as active and its account is closed to the owner
+pub fn finish_fill<'info>(
+    market: &mut Market,
+    position: &mut UserPosition,
+    order: &mut Account<'info, OpenOrder>,
+    owner: &AccountInfo<'info>,
+) -> Result<()> {
+    if !order.finish_fill() {
+        return Ok(());
+    }
+
+    let released = order.locked_amount;
+    if order.side_hidden {
+        // Only a voided fill leaves the side unrevealed, with both locks held
+        position.unlock_hidden_for_cancel(order.locked_base, released);
+        market.unlock(order.locked_base, false);
+        market.unlock(released, true);
+    } else {
+        position.unlock_for_cancel(released, order.is_buy);
+        market.unlock(released, order.is_buy);
+    }
+
+    msg!("Order {} filled and closed (released: {})", order.order_id, released);
+
+    order.close(owner.clone())
+}
+
+/// Move a fill between the buyer's and seller's positions, release the order
+/// locks it used and book the fee
+/// Returns the fee taken from the seller's proceeds
+#[allow(clippy::too_many_arguments)]
+pub fn apply_fill(
+    market: &mut Market,
+    exchange_config: &ExchangeConfig,
+    buyer_position: &mut UserPosition,
+    buyer_order: &mut OpenOrder,
+    seller_position: &mut UserPosition,
+    seller_order: &mut OpenOrder,
+    base_amount: u64,
+    quote_amount: u64,
+) -> Result<u64> {
+    // A fill reveals the side of a side-hidden order, so the lock of the
+    // other side is released before the fill consumes the one it uses
+    // If the order still rests, it now counts on the revealed side
+    if buyer_order.side_hidden {
+        let released = buyer_order.reveal_side(true);
+        buyer_position.base_locked = buyer_position.base_locked.saturating_sub(released);
+        market.unlock(released, false);
+        if buyer_order.status != OrderStatus::Filled {
+            market.reveal_hidden_order(true);
+        }
+    }
+    if seller_order.side_hidden {
+        let released = seller_order.reveal_side(false);
+        seller_position.quote_locked = seller_position.quote_locked.saturating_sub(released);
+        market.unlock(released, true);
+        if seller_order.status != OrderStatus::Filled {
+            market.reveal_hidden_order(false);
+        }
+    }
+
     // Verify seller has enough base tokens
     require!(
         seller_position.base_deposited >= base_amount,
@@ -99,9 +313,12 @@ pub fn handler(ctx: Context<SettleTrade>) -> Result<()> {
         .base_deposited
         .checked_sub(base_amount)
         .ok_or(DuskError::MathOverflow)?;
+    // Release only what the seller's order actually locked
+    let seller_unlocked = seller_order.consume_lock(base_amount);
     seller_position.base_locked = seller_position
         .base_locked
-        .saturating_sub(base_amount);
+        .saturating_sub(seller_unlocked);
+    market.unlock(seller_unlocked, false);
     seller_position.quote_deposited = seller_position
         .quote_deposited
         .checked_add(quote_after_fee)
@@ -112,33 +329,30 @@ pub fn handler(ctx: Context<SettleTrade>) -> Result<()> {
         .quote_deposited
         .checked_sub(quote_amount)
         .ok_or(DuskError::MathOverflow)?;
+    // Release only what the buyer's order actually locked
+    let buyer_unlocked = buyer_order.consume_lock(quote_amount);
     buyer_position.quote_locked = buyer_position
         .quote_locked
-        .saturating_sub(quote_amount);
+        .saturating_sub(buyer_unlocked);
+    market.unlock(buyer_unlocked, true);
     buyer_position.base_deposited = buyer_position
         .base_deposited
         .checked_add(base_amount)
         .ok_or(DuskError::MathOverflow)?;
 
-    // Mark settlement as complete
-    settlement.settled = true;
-    settlement.settled_at = Clock::get()?.unix_timestamp;
-
-    emit!(TradeSettled {
-        market: market.key(),
-        settlement: settlement.key(),
-        maker: settlement.maker,
-        taker: settlement.taker,
-        base_transferred: base_amount,
-        quote_transferred: quote_amount,
-    });
-
-    msg!(
-        "Trade settled: {} base for {} quote (fee: {})",
-        base_amount,
-        quote_amount,
-        fee
-    );
+    // The fee stays in the quote vault: the protocol share is set aside for
+    // collection and the rest funds keeper rewards
+    // The fee leaves the positions, so total deposits shrink by it
+    market.debit_deposit(fee, false)?;
+    let protocol_fee = exchange_config.protocol_fee(fee);
+    market.protocol_fees_accrued = market
+        .protocol_fees_accrued
+        .checked_add(protocol_fee)
+        .ok_or(DuskError::MathOverflow)?;
+    market.fees_accrued = market
+        .fees_accrued
+        .checked_add(fee - protocol_fee)
+        .ok_or(DuskError::MathOverflow)?;
 
-    Ok(())
+    Ok(fee)
 }
@@ -0,0 +1,96 @@
+use anchor_lang::prelude::*;
+
+use crate::state::{Market, UserPosition};
+use crate::events::{BalanceTransferred, SubAccountTransfer};
+use crate::errors::DuskError;
+
+/// Move available funds from the caller's position to another position on
+/// the same market, without any token transfer out of the vaults
+/// The other position may be one of the caller's own sub-accounts
+#[derive(Accounts)]
+pub struct TransferBalance<'info> {
+    pub user: Signer<'info>,
+
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            user.key().as_ref(),
+            from_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = from_position.bump,
+        constraint = from_position.owner == user.key() @ DuskError::Unauthorized
+    )]
+    pub from_position: Account<'info, UserPosition>,
+
+    /// Recipient position, another owner's or one of the caller's sub-accounts
+    #[account(
+        mut,
+        constraint = to_position.market == market.key() @ DuskError::InvalidMarketConfig,
+        constraint = to_position.key() != from_position.key() @ DuskError::SameAccountTransfer
+    )]
+    pub to_position: Account<'info, UserPosition>,
+}
+
+pub fn handler(ctx: Context<TransferBalance>, amount: u64, is_base: bool) -> Result<()> {
+    let from_position = &mut ctx.accounts.from_position;
+    let to_position = &mut ctx.accounts.to_position;
+
+    // Another owner's position is a way out of this one, so the withdrawal
+    // allowlist applies to it like to a token account; the owner's own
+    // sub-accounts must carry the same allowlist
+    let now = Clock::get()?.unix_timestamp;
+    let same_owner = to_position.owner == from_position.owner;
+    let allowed = if same_owner {
+        from_position.allowlist_covers(to_position, now)
+    } else {
+        from_position.can_withdraw_to(&to_position.owner, now)
+    };
+    require!(allowed, DuskError::RecipientNotAllowed);
+
+    from_position.transfer_available(to_position, amount, is_base)?;
+
+    if same_owner {
+        emit!(SubAccountTransfer {
+            market: ctx.accounts.market.key(),
+            owner: from_position.owner,
+            from_sub_account: from_position.sub_account_id,
+            to_sub_account: to_position.sub_account_id,
+            amount,
+            is_base,
+        });
+
+        msg!(
+            "Transferred {} {} tokens from sub-account {} to {} on market {}",
+            amount,
+            if is_base { "base" } else { "quote" },
+            from_position.sub_account_id,
+            to_position.sub_account_id,
+            ctx.accounts.market.market_id
+        );
+    } else {
+        emit!(BalanceTransferred {
+            market: ctx.accounts.market.key(),
+            from: from_position.key(),
+            to: to_position.key(),
+            from_owner: from_position.owner,
+            to_owner: to_position.owner,
+            amount,
+            is_base,
+        });
+
+        msg!(
+            "Transferred {} {} tokens from {} to {} on market {}",
+            amount,
+            if is_base { "base" } else { "quote" },
+            from_position.owner,
+            to_position.owner,
+            ctx.accounts.market.market_id
+        );
+    }
+
+    Ok(())
+}
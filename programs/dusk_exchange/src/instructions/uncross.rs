@@ -0,0 +1,306 @@
+use anchor_lang::prelude::*;
+use arcium_anchor::prelude::*;
+use arcium_client::idl::arcium::{types::CallbackAccount, ID_CONST};
+
+use crate::ID;
+use crate::state::{Market, MarketStatus, OrderBook};
+use crate::events::{AuctionUncrossed, OrdersMatched};
+use crate::errors::DuskError;
+use crate::instructions::place_order::SignerAccount;
+use crate::instructions::match_orders::reconstruct_pubkey;
+use crate::{
+    UncrossBookOutput, UncrossBookOutputStruct0, UncrossBookOutputStruct01,
+    UncrossBookOutputStruct02,
+};
+
+/// Computation definition offset for uncross_book
+pub const COMP_DEF_OFFSET_UNCROSS_BOOK: u8 = 3;
+
+/// End the opening auction by running the clearing-price circuit once
+#[queue_computation_accounts("uncross_book", caller)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct Uncross<'info> {
+    /// Anyone can uncross the auction (keeper, user, etc.)
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// Encrypted order book of this market
+    #[account(
+        mut,
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig,
+        constraint = order_book.initialized @ DuskError::OrderBookNotInitialized
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    /// Signer PDA for CPI to Arcium
+    #[account(
+        init_if_needed,
+        space = 9,
+        payer = caller,
+        seeds = [&SIGN_PDA_SEED],
+        bump
+    )]
+    pub sign_pda_account: Account<'info, SignerAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Mempool account
+    #[account(mut)]
+    pub mempool_account: UncheckedAccount<'info>,
+
+    /// CHECK: Executing pool account
+    #[account(mut)]
+    pub executing_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Computation account at the PDA of `computation_offset` (will be initialized)
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset, mxe_account, DuskError::ClusterNotSet)
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    /// Computation definition account for uncross_book
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_UNCROSS_BOOK))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    /// Cluster account
+    #[account(mut, address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// Pool account (Arcium fee pool)
+    #[account(mut)]
+    pub pool_account: Account<'info, FeePool>,
+
+    /// Clock account
+    pub clock_account: Account<'info, ClockAccount>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(ctx: Context<Uncross>, computation_offset: u64) -> Result<()> {
+    // Set the sign_pda_account bump for CPI signing
+    ctx.accounts.sign_pda_account.bump = ctx.bumps.sign_pda_account;
+
+    let market = &ctx.accounts.market;
+
+    require!(
+        market.status == MarketStatus::Auction,
+        DuskError::MarketNotInAuction
+    );
+
+    // The settlement slot must be free since the uncross may produce a match
+    require!(!market.has_pending_match, DuskError::ComputationNotReady);
+
+    // Every book job reads the state the previous one wrote
+    let now = Clock::get()?.unix_timestamp;
+    require!(!ctx.accounts.order_book.is_busy(now), DuskError::OrderBookBusy);
+
+    // Reserve this market's next computation offset for the uncross_book job
+    let market_key = market.key();
+    ctx.accounts.market.claim_computation_offset(&market_key, computation_offset)?;
+    ctx.accounts.order_book.begin_computation(computation_offset, now);
+
+    // uncross_book operates on this market's encrypted book
+    let computation_args = ArgBuilder::new()
+        .plaintext_u128(ctx.accounts.order_book.state_nonce)
+        .account(
+            ctx.accounts.order_book.key(),
+            OrderBook::STATE_OFFSET,
+            OrderBook::STATE_LEN,
+        )
+        .build();
+
+    // Define callback accounts
+    let callback_accounts = vec![
+        CallbackAccount {
+            pubkey: market_key,
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.order_book.key(),
+            is_writable: true,
+        },
+    ];
+
+    // Queue the encrypted computation
+    // uncross_book returns MatchResult at the auction clearing price and the
+    // orders it leaves in the book
+    queue_computation(
+        ctx.accounts,
+        computation_offset,
+        computation_args,
+        None,
+        vec![UncrossBookCallback::callback_ix(
+            computation_offset,
+            &ctx.accounts.mxe_account,
+            &callback_accounts,
+        )?],
+        1, // returns MatchResult and the orders left in the book
+        0, // tip
+    )?;
+
+    msg!(
+        "Uncross requested on market {} ({} bids, {} asks)",
+        market.market_id,
+        market.active_bids,
+        market.active_asks
+    );
+
+    Ok(())
+}
+
+/// Callback for uncross_book computation
+/// Stores the clearing match (if any) and the orders left in the book, and
+/// switches the market to continuous trading
+#[callback_accounts("uncross_book")]
+#[derive(Accounts)]
+pub struct UncrossBookCallback<'info> {
+    pub arcium_program: Program<'info, Arcium>,
+
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_UNCROSS_BOOK))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Computation account, must be the latest job queued on this order book
+    #[account(
+        address = derive_comp_pda!(order_book.computation_offset, mxe_account, DuskError::ClusterNotSet)
+            @ DuskError::ComputationMismatch
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// CHECK: Instructions sysvar, checked by the address constraint
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        address = market.orderbook_ref @ DuskError::InvalidMarketConfig
+    )]
+    pub order_book: Account<'info, OrderBook>,
+}
+
+pub fn callback_handler(
+    ctx: Context<UncrossBookCallback>,
+    output: SignedComputationOutputs<UncrossBookOutput>,
+) -> Result<()> {
+    // Only an output signed by the cluster for this computation is accepted
+    let (book, result, kept) = match output.verify_output(
+        &ctx.accounts.cluster_account,
+        &ctx.accounts.computation_account,
+    ) {
+        Ok(UncrossBookOutput {
+            field_0: UncrossBookOutputStruct0 { field_0, field_1, field_2 },
+        }) => (field_0, field_1, field_2),
+        Err(_) => return Err(DuskError::AbortedComputation.into()),
+    };
+    // Fields follow the MatchResult circuit struct
+    let UncrossBookOutputStruct01 {
+        field_0: matched,
+        field_1: maker_order_id,
+        field_2: taker_order_id,
+        field_3: clearing_price,
+        field_4: clearing_amount,
+        field_5: maker_lo,
+        field_6: maker_hi,
+        field_7: taker_lo,
+        field_8: taker_hi,
+        field_9: maker_filled,
+        field_10: taker_filled,
+    } = result;
+    // Fields follow the ClearedBook circuit struct
+    let UncrossBookOutputStruct02 {
+        field_0: bid_order_id,
+        field_1: bid_owner_lo,
+        field_2: bid_owner_hi,
+        field_3: ask_order_id,
+        field_4: ask_owner_lo,
+        field_5: ask_owner_hi,
+    } = kept;
+
+    let market = &mut ctx.accounts.market;
+    let clock = Clock::get()?;
+
+    // set_market_paused or delist_market may have moved the market out of its
+    // auction while the uncross was in flight; drop the result but free the book
+    if market.status != MarketStatus::Auction {
+        ctx.accounts.order_book.end_computation();
+        msg!("Market {} left its opening auction before the uncross landed", market.market_id);
+        return Ok(());
+    }
+
+    ctx.accounts.order_book.store_state(book.nonce, book.ciphertexts);
+
+    if matched {
+        let maker = reconstruct_pubkey(maker_lo, maker_hi);
+        let taker = reconstruct_pubkey(taker_lo, taker_hi);
+
+        market.record_pending_match(
+            maker,
+            taker,
+            maker_order_id,
+            taker_order_id,
+            clearing_price,
+            clearing_amount,
+            maker_filled,
+            taker_filled,
+            clock.unix_timestamp,
+        );
+
+        emit!(OrdersMatched {
+            market: market.key(),
+            maker,
+            taker,
+            maker_order_id,
+            taker_order_id,
+            execution_price: clearing_price,
+            execution_amount: clearing_amount,
+            timestamp: clock.unix_timestamp,
+        });
+    }
+
+    // Orders queued so far are either these two or out of the book, and
+    // release_locks can free the latter
+    market.uncross_order_seq = market.order_count;
+    market.uncross_bid_position = reconstruct_pubkey(bid_owner_lo, bid_owner_hi);
+    market.uncross_bid_order_id = bid_order_id;
+    market.uncross_ask_position = reconstruct_pubkey(ask_owner_lo, ask_owner_hi);
+    market.uncross_ask_order_id = ask_order_id;
+
+    market.status = MarketStatus::Continuous;
+
+    emit!(AuctionUncrossed {
+        market: market.key(),
+        matched,
+        clearing_price: if matched { clearing_price } else { 0 },
+        clearing_amount: if matched { clearing_amount } else { 0 },
+        bid_order_id,
+        bid_position: market.uncross_bid_position,
+        ask_order_id,
+        ask_position: market.uncross_ask_position,
+        timestamp: clock.unix_timestamp,
+    });
+
+    msg!(
+        "Auction uncrossed on market {} (matched: {}, price: {}, amount: {}). Continuous trading started.",
+        market.market_id,
+        matched,
+        clearing_price,
+        clearing_amount
+    );
+
+    Ok(())
+}
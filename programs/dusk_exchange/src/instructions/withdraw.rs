@@ -1,5 +1,5 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
 
 use crate::state::{Market, UserPosition};
 use crate::events::Withdrawn;
@@ -18,47 +18,65 @@ pub struct Withdraw<'info> {
         seeds = [
             UserPosition::SEED_PREFIX,
             market.key().as_ref(),
-            user.key().as_ref()
+            user.key().as_ref(),
+            user_position.sub_account_id.to_le_bytes().as_ref()
         ],
         bump = user_position.bump,
         constraint = user_position.owner == user.key() @ DuskError::Unauthorized
     )]
     pub user_position: Account<'info, UserPosition>,
 
-    /// User's token account to withdraw to
+    /// Mint of the token being withdrawn (base or quote)
+    #[account(
+        constraint = mint.key() == market.base_mint || mint.key() == market.quote_mint
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Token account to withdraw to: the owner's own, or one owned by anyone
+    /// when the position's withdrawal allowlist permits it
     #[account(
         mut,
-        constraint = user_token_account.owner == user.key()
+        constraint = user_token_account.mint == mint.key() @ DuskError::InvalidMarketConfig
     )]
-    pub user_token_account: Account<'info, TokenAccount>,
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
 
     /// Market vault to withdraw from (base or quote)
     #[account(
         mut,
         constraint = vault.key() == market.base_vault || vault.key() == market.quote_vault
     )]
-    pub vault: Account<'info, TokenAccount>,
+    pub vault: InterfaceAccount<'info, TokenAccount>,
 
-    pub token_program: Program<'info, Token>,
+    /// Token program of the mint (SPL Token or Token-2022)
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 pub fn handler(ctx: Context<Withdraw>, amount: u64, is_base: bool) -> Result<()> {
     require!(amount > 0, DuskError::AmountTooSmall);
 
     let user_position = &mut ctx.accounts.user_position;
-    let market = &ctx.accounts.market;
+    let market = &mut ctx.accounts.market;
 
-    // Verify correct vault
-    let expected_vault = if is_base {
-        market.base_vault
+    // Verify correct vault, mint and token program
+    let (expected_vault, expected_mint, expected_token_program) = if is_base {
+        (market.base_vault, market.base_mint, market.base_token_program)
     } else {
-        market.quote_vault
+        (market.quote_vault, market.quote_mint, market.quote_token_program)
     };
     require!(
-        ctx.accounts.vault.key() == expected_vault,
+        ctx.accounts.vault.key() == expected_vault
+            && ctx.accounts.mint.key() == expected_mint
+            && ctx.accounts.token_program.key() == expected_token_program,
         DuskError::InvalidMarketConfig
     );
 
+    // Recipient must be the owner or an active allowlist entry
+    let recipient = ctx.accounts.user_token_account.owner;
+    require!(
+        user_position.can_withdraw_to(&recipient, Clock::get()?.unix_timestamp),
+        DuskError::RecipientNotAllowed
+    );
+
     // Check available balance (not locked in orders)
     let available = if is_base {
         user_position.base_available()
@@ -76,19 +94,22 @@ pub fn handler(ctx: Context<Withdraw>, amount: u64, is_base: bool) -> Result<()>
     ];
     let signer_seeds = &[&market_seeds[..]];
 
-    let transfer_accounts = Transfer {
+    // Any Token-2022 transfer fee is withheld from what the user receives
+    let transfer_accounts = TransferChecked {
         from: ctx.accounts.vault.to_account_info(),
+        mint: ctx.accounts.mint.to_account_info(),
         to: ctx.accounts.user_token_account.to_account_info(),
         authority: market.to_account_info(),
     };
 
-    token::transfer(
+    token_interface::transfer_checked(
         CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
             transfer_accounts,
             signer_seeds,
         ),
         amount,
+        ctx.accounts.mint.decimals,
     )?;
 
     // Update user position
@@ -103,19 +124,22 @@ pub fn handler(ctx: Context<Withdraw>, amount: u64, is_base: bool) -> Result<()>
             .checked_sub(amount)
             .ok_or(DuskError::MathOverflow)?;
     }
+    market.debit_deposit(amount, is_base)?;
 
     emit!(Withdrawn {
         market: market.key(),
         user: ctx.accounts.user.key(),
+        recipient,
         amount,
         is_base,
     });
 
     msg!(
-        "Withdrawn {} {} tokens from market {}",
+        "Withdrawn {} {} tokens from market {} to {}",
         amount,
         if is_base { "base" } else { "quote" },
-        market.market_id
+        market.market_id,
+        recipient
     );
 
     Ok(())
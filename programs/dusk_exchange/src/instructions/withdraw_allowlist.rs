@@ -0,0 +1,139 @@
+use anchor_lang::prelude::*;
+
+use crate::state::{Market, UserPosition, WithdrawRecipient};
+use crate::events::{WithdrawAllowlistUpdated, WithdrawRecipientAdded, WithdrawRecipientRemoved};
+use crate::errors::DuskError;
+
+/// Manage the withdrawal allowlist of a position
+/// Additions and disabling only take effect after `UserPosition::ALLOWLIST_DELAY`,
+/// so a compromised key cannot immediately redirect withdrawals
+#[derive(Accounts)]
+pub struct ManageWithdrawAllowlist<'info> {
+    pub user: Signer<'info>,
+
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            user.key().as_ref(),
+            user_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = user_position.bump,
+        constraint = user_position.owner == user.key() @ DuskError::Unauthorized
+    )]
+    pub user_position: Account<'info, UserPosition>,
+}
+
+pub fn add_handler(ctx: Context<ManageWithdrawAllowlist>, recipient: Pubkey) -> Result<()> {
+    let user_position = &mut ctx.accounts.user_position;
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(
+        recipient != Pubkey::default() && recipient != user_position.owner,
+        DuskError::InvalidAllowlistRecipient
+    );
+    require!(
+        !user_position
+            .withdraw_allowlist
+            .iter()
+            .any(|entry| entry.recipient == recipient),
+        DuskError::RecipientAlreadyAllowlisted
+    );
+
+    let slot = user_position
+        .withdraw_allowlist
+        .iter_mut()
+        .find(|entry| entry.recipient == Pubkey::default())
+        .ok_or(DuskError::AllowlistFull)?;
+
+    let active_at = now
+        .checked_add(UserPosition::ALLOWLIST_DELAY)
+        .ok_or(DuskError::MathOverflow)?;
+    *slot = WithdrawRecipient {
+        recipient,
+        active_at,
+    };
+
+    emit!(WithdrawRecipientAdded {
+        market: ctx.accounts.market.key(),
+        owner: user_position.owner,
+        recipient,
+        active_at,
+    });
+
+    msg!(
+        "Withdrawal recipient {} added for user {}, active at {}",
+        recipient,
+        user_position.owner,
+        active_at
+    );
+
+    Ok(())
+}
+
+pub fn remove_handler(ctx: Context<ManageWithdrawAllowlist>, recipient: Pubkey) -> Result<()> {
+    let user_position = &mut ctx.accounts.user_position;
+
+    // Removal only narrows the allowlist, so it applies immediately
+    let slot = user_position
+        .withdraw_allowlist
+        .iter_mut()
+        .find(|entry| entry.recipient == recipient && recipient != Pubkey::default())
+        .ok_or(DuskError::RecipientNotAllowed)?;
+    *slot = WithdrawRecipient::default();
+
+    emit!(WithdrawRecipientRemoved {
+        market: ctx.accounts.market.key(),
+        owner: user_position.owner,
+        recipient,
+    });
+
+    msg!(
+        "Withdrawal recipient {} removed for user {}",
+        recipient,
+        user_position.owner
+    );
+
+    Ok(())
+}
+
+pub fn set_enabled_handler(ctx: Context<ManageWithdrawAllowlist>, enabled: bool) -> Result<()> {
+    let user_position = &mut ctx.accounts.user_position;
+    let now = Clock::get()?.unix_timestamp;
+
+    let effective_at = if enabled {
+        // Enabling only narrows recipients, so it applies immediately
+        user_position.withdraw_allowlist_enabled = true;
+        user_position.withdraw_allowlist_disable_at = 0;
+        now
+    } else {
+        require!(
+            user_position.withdraw_allowlist_enabled,
+            DuskError::AllowlistNotEnabled
+        );
+        let disable_at = now
+            .checked_add(UserPosition::ALLOWLIST_DELAY)
+            .ok_or(DuskError::MathOverflow)?;
+        user_position.withdraw_allowlist_disable_at = disable_at;
+        disable_at
+    };
+
+    emit!(WithdrawAllowlistUpdated {
+        market: ctx.accounts.market.key(),
+        owner: user_position.owner,
+        enabled,
+        effective_at,
+    });
+
+    msg!(
+        "Withdrawal allowlist for user {} {} at {}",
+        user_position.owner,
+        if enabled { "enabled" } else { "disabled" },
+        effective_at
+    );
+
+    Ok(())
+}
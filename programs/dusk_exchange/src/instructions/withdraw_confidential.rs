@@ -0,0 +1,387 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use arcium_anchor::prelude::*;
+use arcium_client::idl::arcium::{types::CallbackAccount, ID_CONST};
+
+use crate::ID;
+use crate::state::{ConfidentialBalance, Market, UserPosition};
+use crate::events::{ConfidentialWithdrawalRejected, Withdrawn};
+use crate::errors::DuskError;
+use crate::instructions::place_order::SignerAccount;
+use crate::{DebitBalanceOutput, DebitBalanceOutputStruct0};
+
+/// Computation definition offset for debit_balance
+pub const COMP_DEF_OFFSET_DEBIT_BALANCE: u8 = 8;
+
+/// Withdraw tokens from a confidential balance
+/// The MXE checks the encrypted available balance; tokens leave the vault on callback
+#[queue_computation_accounts("debit_balance", user)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct WithdrawConfidential<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            user.key().as_ref(),
+            user_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = user_position.bump,
+        constraint = user_position.owner == user.key() @ DuskError::Unauthorized
+    )]
+    pub user_position: Account<'info, UserPosition>,
+
+    #[account(
+        mut,
+        seeds = [ConfidentialBalance::SEED_PREFIX, user_position.key().as_ref()],
+        bump = confidential_balance.bump,
+        constraint = confidential_balance.initialized @ DuskError::ConfidentialBalanceNotInitialized
+    )]
+    pub confidential_balance: Account<'info, ConfidentialBalance>,
+
+    /// Mint of the token being withdrawn (base or quote)
+    #[account(
+        constraint = mint.key() == market.base_mint || mint.key() == market.quote_mint
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Token account to withdraw to: the owner's own, or one owned by anyone
+    /// when the position's withdrawal allowlist permits it
+    #[account(
+        constraint = user_token_account.mint == mint.key() @ DuskError::InvalidMarketConfig
+    )]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Market vault to withdraw from (base or quote)
+    #[account(
+        constraint = vault.key() == market.base_vault || vault.key() == market.quote_vault
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Token program of the mint (SPL Token or Token-2022)
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// Signer PDA for CPI to Arcium
+    #[account(
+        init_if_needed,
+        space = 9,
+        payer = user,
+        seeds = [&SIGN_PDA_SEED],
+        bump
+    )]
+    pub sign_pda_account: Account<'info, SignerAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Mempool account
+    #[account(mut)]
+    pub mempool_account: UncheckedAccount<'info>,
+
+    /// CHECK: Executing pool account
+    #[account(mut)]
+    pub executing_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Computation account at the PDA of `computation_offset` (will be initialized)
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset, mxe_account, DuskError::ClusterNotSet)
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    /// Computation definition account for debit_balance
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_DEBIT_BALANCE))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    /// Cluster account
+    #[account(mut, address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// Pool account (Arcium fee pool)
+    #[account(mut)]
+    pub pool_account: Account<'info, FeePool>,
+
+    /// Clock account
+    pub clock_account: Account<'info, ClockAccount>,
+
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(
+    ctx: Context<WithdrawConfidential>,
+    computation_offset: u64,
+    amount: u64,
+    is_base: bool,
+) -> Result<()> {
+    // Set the sign_pda_account bump for CPI signing
+    ctx.accounts.sign_pda_account.bump = ctx.bumps.sign_pda_account;
+
+    require!(amount > 0, DuskError::AmountTooSmall);
+
+    let market = &mut ctx.accounts.market;
+    require!(market.confidential_balances, DuskError::NotConfidentialMarket);
+
+    // Verify correct vault, mint and token program
+    require!(
+        ctx.accounts.vault.key() == market.vault(is_base)
+            && ctx.accounts.mint.key() == market.mint(is_base)
+            && ctx.accounts.token_program.key() == market.token_program(is_base),
+        DuskError::InvalidMarketConfig
+    );
+
+    // Recipient must be the owner or an active allowlist entry
+    let now = Clock::get()?.unix_timestamp;
+    let recipient = ctx.accounts.user_token_account.owner;
+    require!(
+        ctx.accounts.user_position.can_withdraw_to(&recipient, now),
+        DuskError::RecipientNotAllowed
+    );
+
+    require!(
+        !ctx.accounts.confidential_balance.is_busy(now),
+        DuskError::ConfidentialBalanceBusy
+    );
+
+    // Reserve this market's next computation offset for the debit_balance job
+    let market_key = market.key();
+    market.claim_computation_offset(&market_key, computation_offset)?;
+
+    let confidential_balance = &mut ctx.accounts.confidential_balance;
+    confidential_balance.pending_withdrawal = amount;
+    confidential_balance.pending_withdrawal_is_base = is_base;
+    confidential_balance.pending_withdrawal_recipient = ctx.accounts.user_token_account.key();
+    confidential_balance.begin_computation(computation_offset, now);
+
+    let computation_args = ArgBuilder::new()
+        .plaintext_u64(amount)
+        .plaintext_bool(is_base)
+        .plaintext_u128(confidential_balance.state_nonce)
+        .account(
+            confidential_balance.key(),
+            ConfidentialBalance::STATE_OFFSET,
+            ConfidentialBalance::STATE_LEN,
+        )
+        .build();
+
+    // Define callback accounts
+    let callback_accounts = vec![
+        CallbackAccount {
+            pubkey: market_key,
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.user_position.key(),
+            is_writable: false,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.confidential_balance.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.mint.key(),
+            is_writable: false,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.user_token_account.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.vault.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.token_program.key(),
+            is_writable: false,
+        },
+    ];
+
+    // Queue the encrypted computation
+    queue_computation(
+        ctx.accounts,
+        computation_offset,
+        computation_args,
+        None,
+        vec![DebitBalanceCallback::callback_ix(
+            computation_offset,
+            &ctx.accounts.mxe_account,
+            &callback_accounts,
+        )?],
+        1, // returns the debited balance and whether it was sufficient
+        0, // tip
+    )?;
+
+    msg!(
+        "Confidential withdrawal of {} {} tokens queued on market {}",
+        amount,
+        if is_base { "base" } else { "quote" },
+        ctx.accounts.market.market_id
+    );
+
+    Ok(())
+}
+
+/// Callback for debit_balance computation
+#[callback_accounts("debit_balance")]
+#[derive(Accounts)]
+pub struct DebitBalanceCallback<'info> {
+    pub arcium_program: Program<'info, Arcium>,
+
+    #[account(address = derive_comp_def_pda!(COMP_DEF_OFFSET_DEBIT_BALANCE))]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(address = derive_mxe_pda!())]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+
+    /// CHECK: Computation account, must be the latest one queued on this balance
+    #[account(
+        address = derive_comp_pda!(confidential_balance.computation_offset, mxe_account, DuskError::ClusterNotSet)
+            @ DuskError::ComputationMismatch
+    )]
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(address = derive_cluster_pda!(mxe_account, DuskError::ClusterNotSet))]
+    pub cluster_account: Account<'info, Cluster>,
+
+    /// CHECK: Instructions sysvar, checked by the address constraint
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        constraint = user_position.market == market.key() @ DuskError::InvalidMarketConfig
+    )]
+    pub user_position: Account<'info, UserPosition>,
+
+    #[account(
+        mut,
+        constraint = confidential_balance.position == user_position.key() @ DuskError::Unauthorized
+    )]
+    pub confidential_balance: Account<'info, ConfidentialBalance>,
+
+    /// Mint of the side being withdrawn
+    #[account(
+        address = market.mint(confidential_balance.pending_withdrawal_is_base)
+            @ DuskError::InvalidMarketConfig
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Token account the withdrawal was queued to, checked against the
+    /// owner and allowlist at queue time
+    #[account(
+        mut,
+        address = confidential_balance.pending_withdrawal_recipient @ DuskError::RecipientNotAllowed,
+        token::mint = mint,
+        token::token_program = token_program,
+    )]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Market vault of the side being withdrawn
+    #[account(
+        mut,
+        address = market.vault(confidential_balance.pending_withdrawal_is_base)
+            @ DuskError::InvalidMarketConfig,
+        token::token_program = token_program,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        address = market.token_program(confidential_balance.pending_withdrawal_is_base)
+            @ DuskError::InvalidMarketConfig
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+pub fn callback_handler(
+    ctx: Context<DebitBalanceCallback>,
+    output: SignedComputationOutputs<DebitBalanceOutput>,
+) -> Result<()> {
+    // Only an output signed by the cluster for this computation is accepted
+    let (balance, ok) = match output.verify_output(
+        &ctx.accounts.cluster_account,
+        &ctx.accounts.computation_account,
+    ) {
+        Ok(DebitBalanceOutput {
+            field_0: DebitBalanceOutputStruct0 { field_0, field_1 },
+        }) => (field_0, field_1),
+        Err(_) => return Err(DuskError::AbortedComputation.into()),
+    };
+
+    let confidential_balance = &mut ctx.accounts.confidential_balance;
+    confidential_balance.store_state(balance.nonce, balance.ciphertexts);
+
+    let amount = confidential_balance.pending_withdrawal;
+    let is_base = confidential_balance.pending_withdrawal_is_base;
+    confidential_balance.pending_withdrawal = 0;
+
+    let market = &mut ctx.accounts.market;
+    let user = ctx.accounts.user_position.owner;
+
+    if !ok {
+        emit!(ConfidentialWithdrawalRejected {
+            market: market.key(),
+            user,
+            amount,
+            is_base,
+        });
+
+        msg!("Confidential withdrawal of {} rejected: insufficient balance", amount);
+        return Ok(());
+    }
+
+    // Transfer tokens from vault to user using market PDA authority
+    let market_id_bytes = market.market_id.to_le_bytes();
+    let market_seeds = &[
+        Market::SEED_PREFIX,
+        market_id_bytes.as_ref(),
+        &[market.bump],
+    ];
+    let signer_seeds = &[&market_seeds[..]];
+
+    // Any Token-2022 transfer fee is withheld from what the user receives
+    let transfer_accounts = TransferChecked {
+        from: ctx.accounts.vault.to_account_info(),
+        mint: ctx.accounts.mint.to_account_info(),
+        to: ctx.accounts.user_token_account.to_account_info(),
+        authority: market.to_account_info(),
+    };
+
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_accounts,
+            signer_seeds,
+        ),
+        amount,
+        ctx.accounts.mint.decimals,
+    )?;
+
+    market.debit_deposit(amount, is_base)?;
+
+    let recipient = ctx.accounts.user_token_account.owner;
+    emit!(Withdrawn {
+        market: market.key(),
+        user,
+        recipient,
+        amount,
+        is_base,
+    });
+
+    msg!(
+        "Withdrawn {} {} tokens from confidential balance on market {} to {}",
+        amount,
+        if is_base { "base" } else { "quote" },
+        market.market_id,
+        recipient
+    );
+
+    Ok(())
+}
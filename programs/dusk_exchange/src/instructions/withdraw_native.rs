@@ -0,0 +1,155 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::spl_token::native_mint;
+use anchor_spl::token_interface::{
+    self, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked,
+};
+
+use crate::state::{Market, UserPosition};
+use crate::events::Withdrawn;
+use crate::errors::DuskError;
+
+/// Withdraw wSOL from a market as native SOL
+/// Tokens are moved into a temporary wSOL account that is closed to the user,
+/// so the user receives lamports (plus the temporary account's rent back)
+#[derive(Accounts)]
+pub struct WithdrawNative<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        seeds = [
+            UserPosition::SEED_PREFIX,
+            market.key().as_ref(),
+            user.key().as_ref(),
+            user_position.sub_account_id.to_le_bytes().as_ref()
+        ],
+        bump = user_position.bump,
+        constraint = user_position.owner == user.key() @ DuskError::Unauthorized
+    )]
+    pub user_position: Account<'info, UserPosition>,
+
+    #[account(address = native_mint::ID)]
+    pub native_mint: InterfaceAccount<'info, Mint>,
+
+    /// Market wSOL vault (base or quote)
+    #[account(
+        mut,
+        constraint = vault.key() == market.base_vault || vault.key() == market.quote_vault
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Temporary wSOL account, closed within this instruction
+    #[account(
+        init,
+        payer = user,
+        token::mint = native_mint,
+        token::authority = market,
+        token::token_program = token_program,
+        seeds = [b"unwrap", market.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub unwrap_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Token program of the wSOL side
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(ctx: Context<WithdrawNative>, amount: u64) -> Result<()> {
+    require!(amount > 0, DuskError::AmountTooSmall);
+
+    let user_position = &mut ctx.accounts.user_position;
+    let market = &mut ctx.accounts.market;
+
+    let is_base = market.native_side().ok_or(DuskError::NotNativeMarket)?;
+
+    // Verify correct vault and token program
+    let (expected_vault, expected_token_program) = if is_base {
+        (market.base_vault, market.base_token_program)
+    } else {
+        (market.quote_vault, market.quote_token_program)
+    };
+    require!(
+        ctx.accounts.vault.key() == expected_vault
+            && ctx.accounts.token_program.key() == expected_token_program,
+        DuskError::InvalidMarketConfig
+    );
+
+    // Check available balance (not locked in orders)
+    let available = if is_base {
+        user_position.base_available()
+    } else {
+        user_position.quote_available()
+    };
+    require!(available >= amount, DuskError::InsufficientBalance);
+
+    let market_id_bytes = market.market_id.to_le_bytes();
+    let market_seeds = &[
+        Market::SEED_PREFIX,
+        market_id_bytes.as_ref(),
+        &[market.bump],
+    ];
+    let signer_seeds = &[&market_seeds[..]];
+
+    // Move wSOL from the vault into the temporary account
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.vault.to_account_info(),
+                mint: ctx.accounts.native_mint.to_account_info(),
+                to: ctx.accounts.unwrap_account.to_account_info(),
+                authority: market.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        amount,
+        ctx.accounts.native_mint.decimals,
+    )?;
+
+    // Closing the wSOL account unwraps it: all lamports go to the user
+    token_interface::close_account(CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        CloseAccount {
+            account: ctx.accounts.unwrap_account.to_account_info(),
+            destination: ctx.accounts.user.to_account_info(),
+            authority: market.to_account_info(),
+        },
+        signer_seeds,
+    ))?;
+
+    // Update user position
+    if is_base {
+        user_position.base_deposited = user_position
+            .base_deposited
+            .checked_sub(amount)
+            .ok_or(DuskError::MathOverflow)?;
+    } else {
+        user_position.quote_deposited = user_position
+            .quote_deposited
+            .checked_sub(amount)
+            .ok_or(DuskError::MathOverflow)?;
+    }
+    market.debit_deposit(amount, is_base)?;
+
+    emit!(Withdrawn {
+        market: market.key(),
+        user: ctx.accounts.user.key(),
+        recipient: ctx.accounts.user.key(),
+        amount,
+        is_base,
+    });
+
+    msg!(
+        "Withdrawn {} lamports of {} wSOL from market {}",
+        amount,
+        if is_base { "base" } else { "quote" },
+        market.market_id
+    );
+
+    Ok(())
+}
@@ -15,23 +15,82 @@ declare_id!("7LyfNf3Q7weRFCA316BepiMGWkKVY5aE4xYPrNzSFTRQ");
 pub const ADD_ORDER_COMP_DEF_OFFSET: u8 = 0;
 pub const REMOVE_ORDER_COMP_DEF_OFFSET: u8 = 1;
 pub const MATCH_BOOK_COMP_DEF_OFFSET: u8 = 2;
+pub const UNCROSS_BOOK_COMP_DEF_OFFSET: u8 = 3;
+pub const CLEAR_BOOK_COMP_DEF_OFFSET: u8 = 4;
+pub const INIT_BOOK_COMP_DEF_OFFSET: u8 = 5;
+pub const INIT_BALANCE_COMP_DEF_OFFSET: u8 = 6;
+pub const CREDIT_BALANCE_COMP_DEF_OFFSET: u8 = 7;
+pub const DEBIT_BALANCE_COMP_DEF_OFFSET: u8 = 8;
+pub const ADD_ORDER_LOCKED_COMP_DEF_OFFSET: u8 = 9;
+pub const REMOVE_ORDER_UNLOCKED_COMP_DEF_OFFSET: u8 = 10;
+pub const SETTLE_FILL_COMP_DEF_OFFSET: u8 = 11;
+pub const BALANCE_IS_EMPTY_COMP_DEF_OFFSET: u8 = 12;
+pub const ADD_HIDDEN_ORDER_COMP_DEF_OFFSET: u8 = 13;
+pub const MATCH_BOOK_SEALED_COMP_DEF_OFFSET: u8 = 14;
+pub const REVEAL_FILL_COMP_DEF_OFFSET: u8 = 15;
+pub const DISCLOSE_ORDERS_COMP_DEF_OFFSET: u8 = 16;
+pub const DISCLOSE_FILL_COMP_DEF_OFFSET: u8 = 17;
+pub const QUERY_MY_ORDERS_COMP_DEF_OFFSET: u8 = 18;
+pub const PROBE_CROSS_COMP_DEF_OFFSET: u8 = 19;
+pub const OPEN_SEALED_MATCH_COMP_DEF_OFFSET: u8 = 20;
 
-#[program]
+#[arcium_program]
 pub mod dusk_exchange {
     use super::*;
 
+    /// Create the global exchange config (admin, market creation policy, protocol fee share)
+    pub fn initialize_exchange(
+        ctx: Context<InitializeExchange>,
+        permissionless_markets: bool,
+        protocol_fee_share_bps: u16,
+    ) -> Result<()> {
+        instructions::exchange_config::initialize_handler(
+            ctx,
+            permissionless_markets,
+            protocol_fee_share_bps,
+        )
+    }
+
+    /// Update the global exchange config (admin only)
+    pub fn update_exchange_config(
+        ctx: Context<UpdateExchangeConfig>,
+        new_admin: Pubkey,
+        permissionless_markets: bool,
+        protocol_fee_share_bps: u16,
+    ) -> Result<()> {
+        instructions::exchange_config::update_handler(
+            ctx,
+            new_admin,
+            permissionless_markets,
+            protocol_fee_share_bps,
+        )
+    }
+
+    /// Collect the protocol share of a market's trading fees (admin only)
+    pub fn collect_protocol_fees(ctx: Context<CollectProtocolFees>) -> Result<()> {
+        instructions::collect_protocol_fees::handler(ctx)
+    }
+
     /// Initialize a new trading market (e.g., SOL/USDC)
+    /// Also queues the init_book computation that writes its empty order book
     pub fn initialize_market(
         ctx: Context<InitializeMarket>,
+        computation_offset: u64,
         market_id: u64,
         fee_rate_bps: u16,
     ) -> Result<()> {
-        instructions::initialize_market::handler(ctx, market_id, fee_rate_bps)
+        instructions::initialize_market::handler(ctx, computation_offset, market_id, fee_rate_bps)
     }
 
     /// Deposit tokens into the exchange for trading
-    pub fn deposit(ctx: Context<Deposit>, amount: u64, is_base: bool) -> Result<()> {
-        instructions::deposit::handler(ctx, amount, is_base)
+    /// Creates the owner's sub-account position on first deposit
+    pub fn deposit(
+        ctx: Context<Deposit>,
+        amount: u64,
+        is_base: bool,
+        sub_account_id: u16,
+    ) -> Result<()> {
+        instructions::deposit::handler(ctx, amount, is_base, sub_account_id)
     }
 
     /// Withdraw tokens from the exchange
@@ -39,6 +98,70 @@ pub mod dusk_exchange {
         instructions::withdraw::handler(ctx, amount, is_base)
     }
 
+    /// Deposit native SOL into a wSOL market, wrapping it into the vault
+    pub fn deposit_native(
+        ctx: Context<DepositNative>,
+        amount: u64,
+        sub_account_id: u16,
+    ) -> Result<()> {
+        instructions::deposit_native::handler(ctx, amount, sub_account_id)
+    }
+
+    /// Withdraw from a wSOL market as native SOL
+    pub fn withdraw_native(ctx: Context<WithdrawNative>, amount: u64) -> Result<()> {
+        instructions::withdraw_native::handler(ctx, amount)
+    }
+
+    /// Move available funds to another position on the same market, the
+    /// caller's own sub-accounts included
+    pub fn transfer_balance(
+        ctx: Context<TransferBalance>,
+        amount: u64,
+        is_base: bool,
+    ) -> Result<()> {
+        instructions::transfer_balance::handler(ctx, amount, is_base)
+    }
+
+    /// Close an empty user position and refund rent to the owner
+    pub fn close_position(ctx: Context<ClosePosition>) -> Result<()> {
+        instructions::close_position::handler(ctx)
+    }
+
+    /// Authorize a delegate to place and cancel orders for the caller's position
+    /// Delegates cannot withdraw; expires_at = 0 means no expiry
+    pub fn set_delegate(ctx: Context<ManageDelegate>, delegate: Pubkey, expires_at: i64) -> Result<()> {
+        instructions::delegate::set_handler(ctx, delegate, expires_at)
+    }
+
+    /// Revoke the trading delegate of the caller's position
+    pub fn revoke_delegate(ctx: Context<ManageDelegate>) -> Result<()> {
+        instructions::delegate::revoke_handler(ctx)
+    }
+
+    /// Add a withdrawal recipient to a position's allowlist (active after a delay)
+    pub fn add_withdraw_recipient(
+        ctx: Context<ManageWithdrawAllowlist>,
+        recipient: Pubkey,
+    ) -> Result<()> {
+        instructions::withdraw_allowlist::add_handler(ctx, recipient)
+    }
+
+    /// Remove a withdrawal recipient from a position's allowlist
+    pub fn remove_withdraw_recipient(
+        ctx: Context<ManageWithdrawAllowlist>,
+        recipient: Pubkey,
+    ) -> Result<()> {
+        instructions::withdraw_allowlist::remove_handler(ctx, recipient)
+    }
+
+    /// Enable a position's withdrawal allowlist, or request that it be disabled after a delay
+    pub fn set_withdraw_allowlist(
+        ctx: Context<ManageWithdrawAllowlist>,
+        enabled: bool,
+    ) -> Result<()> {
+        instructions::withdraw_allowlist::set_enabled_handler(ctx, enabled)
+    }
+
     /// Initialize the computation definition for adding orders
     /// This must be called once before any orders can be placed
     pub fn init_add_order_comp_def(ctx: Context<InitAddOrderCompDef>) -> Result<()> {
@@ -55,61 +178,249 @@ pub mod dusk_exchange {
         instructions::init_comp_defs::init_match_book_handler(ctx)
     }
 
+    /// Initialize the computation definition for the opening auction uncross
+    pub fn init_uncross_book_comp_def(ctx: Context<InitUncrossBookCompDef>) -> Result<()> {
+        instructions::init_comp_defs::init_uncross_book_handler(ctx)
+    }
+
+    /// Initialize the computation definition for clearing a delisted book
+    pub fn init_clear_book_comp_def(ctx: Context<InitClearBookCompDef>) -> Result<()> {
+        instructions::init_comp_defs::init_clear_book_handler(ctx)
+    }
+
+    /// Initialize the computation definition for empty order book states
+    pub fn init_init_book_comp_def(ctx: Context<InitInitBookCompDef>) -> Result<()> {
+        instructions::init_comp_defs::init_init_book_handler(ctx)
+    }
+
+    /// Initialize the computation definition for empty confidential balances
+    pub fn init_init_balance_comp_def(ctx: Context<InitInitBalanceCompDef>) -> Result<()> {
+        instructions::init_comp_defs::init_init_balance_handler(ctx)
+    }
+
+    /// Initialize the computation definition for crediting confidential deposits
+    pub fn init_credit_balance_comp_def(ctx: Context<InitCreditBalanceCompDef>) -> Result<()> {
+        instructions::init_comp_defs::init_credit_balance_handler(ctx)
+    }
+
+    /// Initialize the computation definition for confidential withdrawals
+    pub fn init_debit_balance_comp_def(ctx: Context<InitDebitBalanceCompDef>) -> Result<()> {
+        instructions::init_comp_defs::init_debit_balance_handler(ctx)
+    }
+
+    /// Initialize the computation definition for placing confidential orders
+    pub fn init_add_order_locked_comp_def(ctx: Context<InitAddOrderLockedCompDef>) -> Result<()> {
+        instructions::init_comp_defs::init_add_order_locked_handler(ctx)
+    }
+
+    /// Initialize the computation definition for cancelling confidential orders
+    pub fn init_remove_order_unlocked_comp_def(
+        ctx: Context<InitRemoveOrderUnlockedCompDef>,
+    ) -> Result<()> {
+        instructions::init_comp_defs::init_remove_order_unlocked_handler(ctx)
+    }
+
+    /// Initialize the computation definition for settling confidential fills
+    pub fn init_settle_fill_comp_def(ctx: Context<InitSettleFillCompDef>) -> Result<()> {
+        instructions::init_comp_defs::init_settle_fill_handler(ctx)
+    }
+
+    /// Initialize the computation definition for closing confidential positions
+    pub fn init_balance_is_empty_comp_def(ctx: Context<InitBalanceIsEmptyCompDef>) -> Result<()> {
+        instructions::init_comp_defs::init_balance_is_empty_handler(ctx)
+    }
+
+    /// Initialize the computation definition for placing side-hidden orders
+    pub fn init_add_hidden_order_comp_def(ctx: Context<InitAddHiddenOrderCompDef>) -> Result<()> {
+        instructions::init_comp_defs::init_add_hidden_order_handler(ctx)
+    }
+
+    /// Initialize the computation definition for sealed matching
+    pub fn init_match_book_sealed_comp_def(ctx: Context<InitMatchBookSealedCompDef>) -> Result<()> {
+        instructions::init_comp_defs::init_match_book_sealed_handler(ctx)
+    }
+
+    /// Initialize the computation definition for settling sealed fills
+    pub fn init_reveal_fill_comp_def(ctx: Context<InitRevealFillCompDef>) -> Result<()> {
+        instructions::init_comp_defs::init_reveal_fill_handler(ctx)
+    }
+
+    /// Initialize the computation definition for disclosing orders to an auditor
+    pub fn init_disclose_orders_comp_def(ctx: Context<InitDiscloseOrdersCompDef>) -> Result<()> {
+        instructions::init_comp_defs::init_disclose_orders_handler(ctx)
+    }
+
+    /// Initialize the computation definition for disclosing sealed fills to an auditor
+    pub fn init_disclose_fill_comp_def(ctx: Context<InitDiscloseFillCompDef>) -> Result<()> {
+        instructions::init_comp_defs::init_disclose_fill_handler(ctx)
+    }
+
+    /// Initialize the computation definition for reading a position's own orders
+    pub fn init_query_my_orders_comp_def(ctx: Context<InitQueryMyOrdersCompDef>) -> Result<()> {
+        instructions::init_comp_defs::init_query_my_orders_handler(ctx)
+    }
+
+    /// Initialize the computation definition for probing whether the book is crossed
+    pub fn init_probe_cross_comp_def(ctx: Context<InitProbeCrossCompDef>) -> Result<()> {
+        instructions::init_comp_defs::init_probe_cross_handler(ctx)
+    }
+
+    /// Initialize the computation definition for opening the parties of a sealed match
+    pub fn init_open_sealed_match_comp_def(ctx: Context<InitOpenSealedMatchCompDef>) -> Result<()> {
+        instructions::init_comp_defs::init_open_sealed_match_handler(ctx)
+    }
+
+    /// Queue init_book again for a market whose order book is still empty
+    /// Only needed when the computation queued by initialize_market did not call back
+    pub fn init_order_book(ctx: Context<InitOrderBook>, computation_offset: u64) -> Result<()> {
+        instructions::init_order_book::handler(ctx, computation_offset)
+    }
+
+    /// Callback handler for init_book computation
+    #[arcium_callback(encrypted_ix = "init_book")]
+    pub fn init_book_callback(
+        ctx: Context<InitBookCallback>,
+        output: SignedComputationOutputs<InitBookOutput>,
+    ) -> Result<()> {
+        instructions::init_order_book::callback_handler(ctx, output)
+    }
+
+    /// Pause a market, or resume it into a fresh opening auction
+    /// Only the market authority can call this
+    pub fn set_market_paused(ctx: Context<SetMarketPaused>, paused: bool) -> Result<()> {
+        instructions::set_market_paused::handler(ctx, paused)
+    }
+
+    /// Start delisting a market: only cancellations are accepted afterwards
+    /// Only the market authority can call this
+    pub fn delist_market(ctx: Context<DelistMarket>) -> Result<()> {
+        instructions::delist_market::handler(ctx)
+    }
+
+    /// Clear the encrypted book of a cancel-only market
+    /// The market becomes withdraw-only on callback
+    pub fn clear_book(ctx: Context<ClearBook>, computation_offset: u64) -> Result<()> {
+        instructions::clear_book::handler(ctx, computation_offset)
+    }
+
+    /// Callback handler for clear_book computation
+    #[arcium_callback(encrypted_ix = "clear_book")]
+    pub fn clear_book_callback(
+        ctx: Context<ClearBookCallback>,
+        output: SignedComputationOutputs<ClearBookOutput>,
+    ) -> Result<()> {
+        instructions::clear_book::callback_handler(ctx, output)
+    }
+
+    /// Release the lock of an order left on a withdraw-only market, or left out
+    /// of the book by the latest uncross, and close it
+    /// Anyone can call this; the order's rent goes to the position owner
+    pub fn release_locks(ctx: Context<ReleaseLocks>) -> Result<()> {
+        instructions::release_locks::handler(ctx)
+    }
+
+    /// Compare vault balances with total deposits and accrued fees
+    /// Anyone can call this; a discrepancy is reported as an event
+    pub fn audit_market(ctx: Context<AuditMarket>) -> Result<()> {
+        instructions::audit_market::handler(ctx)
+    }
+
+    /// Close a withdraw-only market and its vaults once all positions are closed
+    pub fn close_market(ctx: Context<CloseMarket>) -> Result<()> {
+        instructions::close_market::handler(ctx)
+    }
+
     /// Place an encrypted limit order
-    /// Order details (price, amount) are encrypted with Arcium
+    /// Order details (price, amount) are encrypted with the x25519 secret shared
+    /// between `client_pubkey` and the MXE, under `nonce`
     /// lock_amount specifies how many tokens to lock (quote for buy, base for sell)
     pub fn place_order(
         ctx: Context<PlaceOrder>,
+        computation_offset: u64,
         order_id: u64,
         is_buy: bool,
         encrypted_price: Vec<u8>,
         encrypted_amount: Vec<u8>,
-        nonce: [u8; 12],
+        client_pubkey: [u8; 32],
+        nonce: u128,
         lock_amount: u64,
     ) -> Result<()> {
-        instructions::place_order::handler(ctx, order_id, is_buy, encrypted_price, encrypted_amount, nonce, lock_amount)
+        instructions::place_order::handler(ctx, computation_offset, order_id, is_buy, encrypted_price, encrypted_amount, client_pubkey, nonce, lock_amount)
     }
 
     /// Callback handler for add_order computation
-    pub fn add_order_callback(ctx: Context<AddOrderCallback>) -> Result<()> {
-        instructions::place_order::callback_handler(ctx)
+    #[arcium_callback(encrypted_ix = "add_order")]
+    pub fn add_order_callback(
+        ctx: Context<AddOrderCallback>,
+        output: SignedComputationOutputs<AddOrderOutput>,
+    ) -> Result<()> {
+        instructions::place_order::callback_handler(ctx, output)
+    }
+
+    /// Release and close an order whose add is past its deadline, or reopen one
+    /// whose cancel is
+    /// Only the position owner can call this; the closed order's rent goes back to them
+    pub fn reclaim_stuck_computation(ctx: Context<ReclaimStuckComputation>, order_id: u64) -> Result<()> {
+        instructions::reclaim_stuck_computation::handler(ctx, order_id)
     }
 
     /// Cancel an existing order
-    pub fn cancel_order(ctx: Context<CancelOrder>, order_id: u64) -> Result<()> {
-        instructions::cancel_order::handler(ctx, order_id)
+    /// The order's unfilled amount is returned encrypted to `client_pubkey` under `nonce`
+    pub fn cancel_order(
+        ctx: Context<CancelOrder>,
+        computation_offset: u64,
+        order_id: u64,
+        client_pubkey: [u8; 32],
+        nonce: u128,
+    ) -> Result<()> {
+        instructions::cancel_order::handler(ctx, computation_offset, order_id, client_pubkey, nonce)
     }
 
     /// Callback handler for remove_order computation
-    pub fn remove_order_callback(ctx: Context<RemoveOrderCallback>) -> Result<()> {
-        instructions::cancel_order::callback_handler(ctx)
+    #[arcium_callback(encrypted_ix = "remove_order")]
+    pub fn remove_order_callback(
+        ctx: Context<RemoveOrderCallback>,
+        output: SignedComputationOutputs<RemoveOrderOutput>,
+    ) -> Result<()> {
+        instructions::cancel_order::callback_handler(ctx, output)
     }
 
     /// Trigger order matching via MPC
     /// Anyone can call this to match crossing orders
-    pub fn match_orders(ctx: Context<MatchOrders>) -> Result<()> {
-        instructions::match_orders::handler(ctx)
+    pub fn match_orders(ctx: Context<MatchOrders>, computation_offset: u64) -> Result<()> {
+        instructions::match_orders::handler(ctx, computation_offset)
     }
 
     /// Callback handler for match_book computation
     /// Receives revealed execution details and stores pending match in market
+    #[arcium_callback(encrypted_ix = "match_book")]
     pub fn match_book_callback(
         ctx: Context<MatchBookCallback>,
-        matched: bool,
-        execution_price: u64,
-        execution_amount: u64,
-        maker_order_id: u64,
-        taker_order_id: u64,
-        maker_lo: u128,
-        maker_hi: u128,
-        taker_lo: u128,
-        taker_hi: u128,
-    ) -> Result<()> {
-        instructions::match_orders::callback_handler(
-            ctx, matched, execution_price, execution_amount,
-            maker_order_id, taker_order_id,
-            maker_lo, maker_hi, taker_lo, taker_hi
-        )
+        output: SignedComputationOutputs<MatchBookOutput>,
+    ) -> Result<()> {
+        instructions::match_orders::callback_handler(ctx, output)
+    }
+
+    /// Set the reward paid to keepers for each successful crank
+    /// Rewards are funded from accrued trading fees and credited as quote to the
+    /// keeper position the cranking signer passes; only the market authority can call this
+    pub fn set_keeper_reward(ctx: Context<SetKeeperReward>, keeper_reward: u64) -> Result<()> {
+        instructions::set_keeper_reward::handler(ctx, keeper_reward)
+    }
+
+    /// End the opening auction at a single clearing price
+    /// Anyone can call this; the market switches to continuous trading on callback
+    pub fn uncross(ctx: Context<Uncross>, computation_offset: u64) -> Result<()> {
+        instructions::uncross::handler(ctx, computation_offset)
+    }
+
+    /// Callback handler for uncross_book computation
+    #[arcium_callback(encrypted_ix = "uncross_book")]
+    pub fn uncross_book_callback(
+        ctx: Context<UncrossBookCallback>,
+        output: SignedComputationOutputs<UncrossBookOutput>,
+    ) -> Result<()> {
+        instructions::uncross::callback_handler(ctx, output)
     }
 
     /// Create a settlement account from a pending match
@@ -122,4 +433,312 @@ pub mod dusk_exchange {
     pub fn settle_trade(ctx: Context<SettleTrade>) -> Result<()> {
         instructions::settle_trade::handler(ctx)
     }
+
+    /// Close a settled trade account after the retention period
+    /// Rent is returned to whoever paid for create_settlement; the caller earns the keeper reward
+    pub fn close_settlement(ctx: Context<CloseSettlement>) -> Result<()> {
+        instructions::close_settlement::handler(ctx)
+    }
+
+    /// Switch a market to confidential balances, or back
+    /// Only the market authority can call this, before any position is opened
+    pub fn set_confidential_balances(
+        ctx: Context<SetConfidentialBalances>,
+        enabled: bool,
+    ) -> Result<()> {
+        instructions::set_confidential_balances::handler(ctx, enabled)
+    }
+
+    /// Open a position on a confidential market with an empty encrypted balance
+    pub fn init_confidential_balance(
+        ctx: Context<InitConfidentialBalance>,
+        computation_offset: u64,
+        sub_account_id: u16,
+    ) -> Result<()> {
+        instructions::init_confidential_balance::handler(ctx, computation_offset, sub_account_id)
+    }
+
+    /// Callback handler for init_balance computation
+    #[arcium_callback(encrypted_ix = "init_balance")]
+    pub fn init_balance_callback(
+        ctx: Context<InitBalanceCallback>,
+        output: SignedComputationOutputs<InitBalanceOutput>,
+    ) -> Result<()> {
+        instructions::init_confidential_balance::callback_handler(ctx, output)
+    }
+
+    /// Deposit tokens into a confidential balance
+    /// amount = 0 retries the credit of earlier deposits
+    pub fn deposit_confidential(
+        ctx: Context<DepositConfidential>,
+        computation_offset: u64,
+        amount: u64,
+        is_base: bool,
+    ) -> Result<()> {
+        instructions::deposit_confidential::handler(ctx, computation_offset, amount, is_base)
+    }
+
+    /// Callback handler for credit_balance computation
+    #[arcium_callback(encrypted_ix = "credit_balance")]
+    pub fn credit_balance_callback(
+        ctx: Context<CreditBalanceCallback>,
+        output: SignedComputationOutputs<CreditBalanceOutput>,
+    ) -> Result<()> {
+        instructions::deposit_confidential::callback_handler(ctx, output)
+    }
+
+    /// Withdraw tokens from a confidential balance
+    /// Tokens are transferred on callback if the encrypted balance covers them
+    pub fn withdraw_confidential(
+        ctx: Context<WithdrawConfidential>,
+        computation_offset: u64,
+        amount: u64,
+        is_base: bool,
+    ) -> Result<()> {
+        instructions::withdraw_confidential::handler(ctx, computation_offset, amount, is_base)
+    }
+
+    /// Callback handler for debit_balance computation
+    #[arcium_callback(encrypted_ix = "debit_balance")]
+    pub fn debit_balance_callback(
+        ctx: Context<DebitBalanceCallback>,
+        output: SignedComputationOutputs<DebitBalanceOutput>,
+    ) -> Result<()> {
+        instructions::withdraw_confidential::callback_handler(ctx, output)
+    }
+
+    /// Place an encrypted limit order backed by a confidential balance
+    /// The lock is taken inside the MXE; the order is rejected on callback if it can't be covered
+    pub fn place_confidential_order(
+        ctx: Context<PlaceConfidentialOrder>,
+        computation_offset: u64,
+        order_id: u64,
+        is_buy: bool,
+        encrypted_price: Vec<u8>,
+        encrypted_amount: Vec<u8>,
+        client_pubkey: [u8; 32],
+        nonce: u128,
+    ) -> Result<()> {
+        instructions::place_confidential_order::handler(ctx, computation_offset, order_id, is_buy, encrypted_price, encrypted_amount, client_pubkey, nonce)
+    }
+
+    /// Callback handler for add_order_locked computation
+    #[arcium_callback(encrypted_ix = "add_order_locked")]
+    pub fn add_order_locked_callback(
+        ctx: Context<AddOrderLockedCallback>,
+        output: SignedComputationOutputs<AddOrderLockedOutput>,
+    ) -> Result<()> {
+        instructions::place_confidential_order::callback_handler(ctx, output)
+    }
+
+    /// Cancel a confidential order and release its encrypted lock
+    /// The order's unfilled amount is returned encrypted to `client_pubkey` under `nonce`
+    pub fn cancel_confidential_order(
+        ctx: Context<CancelConfidentialOrder>,
+        computation_offset: u64,
+        order_id: u64,
+        client_pubkey: [u8; 32],
+        nonce: u128,
+    ) -> Result<()> {
+        instructions::cancel_confidential_order::handler(ctx, computation_offset, order_id, client_pubkey, nonce)
+    }
+
+    /// Callback handler for remove_order_unlocked computation
+    #[arcium_callback(encrypted_ix = "remove_order_unlocked")]
+    pub fn remove_order_unlocked_callback(
+        ctx: Context<RemoveOrderUnlockedCallback>,
+        output: SignedComputationOutputs<RemoveOrderUnlockedOutput>,
+    ) -> Result<()> {
+        instructions::cancel_confidential_order::callback_handler(ctx, output)
+    }
+
+    /// Settle a matched trade between two confidential balances
+    /// Anyone can call this; keeper rewards are not paid on confidential markets
+    pub fn settle_confidential_trade(
+        ctx: Context<SettleConfidentialTrade>,
+        computation_offset: u64,
+    ) -> Result<()> {
+        instructions::settle_confidential_trade::handler(ctx, computation_offset)
+    }
+
+    /// Callback handler for settle_fill computation
+    #[arcium_callback(encrypted_ix = "settle_fill")]
+    pub fn settle_fill_callback(
+        ctx: Context<SettleFillCallback>,
+        output: SignedComputationOutputs<SettleFillOutput>,
+    ) -> Result<()> {
+        instructions::settle_confidential_trade::callback_handler(ctx, output)
+    }
+
+    /// Close a confidential position once its encrypted balance is empty
+    pub fn close_confidential_position(
+        ctx: Context<CloseConfidentialPosition>,
+        computation_offset: u64,
+    ) -> Result<()> {
+        instructions::close_confidential_position::handler(ctx, computation_offset)
+    }
+
+    /// Callback handler for balance_is_empty computation
+    #[arcium_callback(encrypted_ix = "balance_is_empty")]
+    pub fn balance_is_empty_callback(
+        ctx: Context<BalanceIsEmptyCallback>,
+        output: SignedComputationOutputs<BalanceIsEmptyOutput>,
+    ) -> Result<()> {
+        instructions::close_confidential_position::callback_handler(ctx, output)
+    }
+
+    /// Place an encrypted limit order whose side is encrypted too
+    /// Both `base_lock` and `quote_lock` are taken; the lock of the side the order
+    /// doesn't use is released when it is first filled
+    pub fn place_hidden_order(
+        ctx: Context<PlaceHiddenOrder>,
+        computation_offset: u64,
+        order_id: u64,
+        encrypted_price: Vec<u8>,
+        encrypted_amount: Vec<u8>,
+        encrypted_side: Vec<u8>,
+        client_pubkey: [u8; 32],
+        nonce: u128,
+        base_lock: u64,
+        quote_lock: u64,
+    ) -> Result<()> {
+        instructions::place_hidden_order::handler(ctx, computation_offset, order_id, encrypted_price, encrypted_amount, encrypted_side, client_pubkey, nonce, base_lock, quote_lock)
+    }
+
+    /// Callback handler for add_hidden_order computation
+    #[arcium_callback(encrypted_ix = "add_hidden_order")]
+    pub fn add_hidden_order_callback(
+        ctx: Context<AddHiddenOrderCallback>,
+        output: SignedComputationOutputs<AddHiddenOrderOutput>,
+    ) -> Result<()> {
+        instructions::place_hidden_order::callback_handler(ctx, output)
+    }
+
+    /// Switch a market between revealed and sealed fills (authority only)
+    pub fn set_sealed_fills(ctx: Context<SetSealedFills>, enabled: bool) -> Result<()> {
+        instructions::set_sealed_fills::handler(ctx, enabled)
+    }
+
+    /// Trigger matching on a sealed-fill market
+    /// Price and amount of a match stay encrypted until settlement, its orders
+    /// and owners until a party opens it
+    pub fn match_sealed_orders(ctx: Context<MatchSealedOrders>, computation_offset: u64) -> Result<()> {
+        instructions::match_sealed_orders::handler(ctx, computation_offset)
+    }
+
+    /// Callback handler for match_book_sealed computation
+    #[arcium_callback(encrypted_ix = "match_book_sealed")]
+    pub fn match_book_sealed_callback(
+        ctx: Context<MatchBookSealedCallback>,
+        output: SignedComputationOutputs<MatchBookSealedOutput>,
+    ) -> Result<()> {
+        instructions::match_sealed_orders::callback_handler(ctx, output)
+    }
+
+    /// Reveal the parties of the pending sealed match so it can be settled
+    /// Only a party may open it until the open delay has passed
+    pub fn open_sealed_match(ctx: Context<OpenSealedMatch>, computation_offset: u64) -> Result<()> {
+        instructions::open_sealed_match::handler(ctx, computation_offset)
+    }
+
+    /// Callback handler for open_sealed_match computation
+    #[arcium_callback(encrypted_ix = "open_sealed_match")]
+    pub fn open_sealed_match_callback(
+        ctx: Context<OpenSealedMatchCallback>,
+        output: SignedComputationOutputs<OpenSealedMatchOutput>,
+    ) -> Result<()> {
+        instructions::open_sealed_match::callback_handler(ctx, output)
+    }
+
+    /// Settle a sealed fill
+    /// Each counterparty receives the fill terms encrypted to its order's client key
+    pub fn settle_sealed_trade(ctx: Context<SettleSealedTrade>, computation_offset: u64) -> Result<()> {
+        instructions::settle_sealed_trade::handler(ctx, computation_offset)
+    }
+
+    /// Callback handler for reveal_fill computation
+    #[arcium_callback(encrypted_ix = "reveal_fill")]
+    pub fn reveal_fill_callback(
+        ctx: Context<RevealFillCallback>,
+        output: SignedComputationOutputs<RevealFillOutput>,
+    ) -> Result<()> {
+        instructions::settle_sealed_trade::callback_handler(ctx, output)
+    }
+
+    /// Register the x25519 key of the position's auditor (all zeros removes it)
+    pub fn set_auditor_key(ctx: Context<SetAuditorKey>, auditor_pubkey: [u8; 32]) -> Result<()> {
+        instructions::set_auditor_key::handler(ctx, auditor_pubkey)
+    }
+
+    /// Disclose the position's resting orders to its auditor
+    /// The result is kept encrypted to the auditor key in a new disclosure record
+    pub fn disclose_orders(ctx: Context<DiscloseOrders>, computation_offset: u64, nonce: u128) -> Result<()> {
+        instructions::disclose_orders::handler(ctx, computation_offset, nonce)
+    }
+
+    /// Callback handler for disclose_orders computation
+    #[arcium_callback(encrypted_ix = "disclose_orders")]
+    pub fn disclose_orders_callback(
+        ctx: Context<DiscloseOrdersCallback>,
+        output: SignedComputationOutputs<DiscloseOrdersOutput>,
+    ) -> Result<()> {
+        instructions::disclose_orders::callback_handler(ctx, output)
+    }
+
+    /// Disclose the terms of one of the position's sealed fills to its auditor
+    pub fn disclose_fill(ctx: Context<DiscloseFill>, computation_offset: u64, nonce: u128) -> Result<()> {
+        instructions::disclose_fill::handler(ctx, computation_offset, nonce)
+    }
+
+    /// Callback handler for disclose_fill computation
+    #[arcium_callback(encrypted_ix = "disclose_fill")]
+    pub fn disclose_fill_callback(
+        ctx: Context<DiscloseFillCallback>,
+        output: SignedComputationOutputs<DiscloseFillOutput>,
+    ) -> Result<()> {
+        instructions::disclose_fill::callback_handler(ctx, output)
+    }
+
+    /// Read the position's live orders, with their remaining amounts, from the encrypted book
+    /// The result is emitted encrypted to `client_pubkey`
+    pub fn query_my_orders(
+        ctx: Context<QueryMyOrders>,
+        computation_offset: u64,
+        client_pubkey: [u8; 32],
+        nonce: u128,
+    ) -> Result<()> {
+        instructions::query_my_orders::handler(ctx, computation_offset, client_pubkey, nonce)
+    }
+
+    /// Callback handler for query_my_orders computation
+    #[arcium_callback(encrypted_ix = "query_my_orders")]
+    pub fn query_my_orders_callback(
+        ctx: Context<QueryMyOrdersCallback>,
+        output: SignedComputationOutputs<QueryMyOrdersOutput>,
+    ) -> Result<()> {
+        instructions::query_my_orders::callback_handler(ctx, output)
+    }
+
+    /// Check whether the book is crossed, without matching it
+    /// Keepers use the result to decide when a match is worth paying for
+    pub fn probe_cross(ctx: Context<ProbeCross>, computation_offset: u64) -> Result<()> {
+        instructions::probe_cross::handler(ctx, computation_offset)
+    }
+
+    /// Callback handler for probe_cross computation
+    #[arcium_callback(encrypted_ix = "probe_cross")]
+    pub fn probe_cross_callback(
+        ctx: Context<ProbeCrossCallback>,
+        output: SignedComputationOutputs<ProbeCrossOutput>,
+    ) -> Result<()> {
+        instructions::probe_cross::callback_handler(ctx, output)
+    }
+
+    /// Close many settled trade accounts at once, earning one keeper reward per settlement
+    /// Remaining accounts: (settlement, rent_receiver) pairs
+    pub fn close_settlements<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CloseSettlements<'info>>,
+    ) -> Result<()> {
+        instructions::close_settlement::batch_handler(ctx)
+    }
 }
@@ -0,0 +1,106 @@
+use anchor_lang::prelude::*;
+
+/// Number of encrypted fields in the circuit's Balances
+pub const CONFIDENTIAL_BALANCE_FIELDS: usize = 4;
+
+/// MXE-encrypted balances of a position on a confidential market
+/// Replaces the public deposited/locked amounts of its `UserPosition`
+/// Seeds: ["confidential_balance", user_position]
+#[account]
+#[derive(Default)]
+pub struct ConfidentialBalance {
+    /// Position these balances belong to
+    pub position: Pubkey,
+
+    /// Whether `init_balance` has written an empty state
+    pub initialized: bool,
+
+    /// Nonce of the current encryption
+    pub state_nonce: u128,
+
+    /// Encrypted Balances fields, in circuit declaration order
+    pub state: [[u8; 32]; CONFIDENTIAL_BALANCE_FIELDS],
+
+    /// Offset of the latest computation queued on these balances
+    pub computation_offset: u64,
+
+    /// Time until which that computation may still call back, 0 when idle
+    pub pending_until: i64,
+
+    /// Deposited base tokens not yet credited by a `credit_balance` callback
+    pub pending_base_credit: u64,
+
+    /// Deposited quote tokens not yet credited by a `credit_balance` callback
+    pub pending_quote_credit: u64,
+
+    /// Amount of the queued `debit_balance` withdrawal, paid out on callback
+    pub pending_withdrawal: u64,
+
+    /// Whether the queued withdrawal is in base (true) or quote (false) tokens
+    pub pending_withdrawal_is_base: bool,
+
+    /// Token account the queued withdrawal is paid to
+    pub pending_withdrawal_recipient: Pubkey,
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+impl ConfidentialBalance {
+    pub const LEN: usize = 8 +  // discriminator
+        32 +  // position
+        1 +   // initialized
+        16 +  // state_nonce
+        32 * CONFIDENTIAL_BALANCE_FIELDS + // state
+        8 +   // computation_offset
+        8 +   // pending_until
+        8 +   // pending_base_credit
+        8 +   // pending_quote_credit
+        8 +   // pending_withdrawal
+        1 +   // pending_withdrawal_is_base
+        32 +  // pending_withdrawal_recipient
+        1;    // bump
+
+    pub const SEED_PREFIX: &'static [u8] = b"confidential_balance";
+
+    /// Byte offset of the encrypted state within the account data
+    pub const STATE_OFFSET: u32 = 8 + 32 + 1 + 16;
+
+    /// Byte length of the encrypted state
+    pub const STATE_LEN: u32 = 32 * CONFIDENTIAL_BALANCE_FIELDS as u32;
+
+    /// How long a queued computation may take before another can replace it (10 minutes)
+    pub const COMPUTATION_TIMEOUT: i64 = 10 * 60;
+
+    /// Whether a computation on these balances may still call back
+    /// Only one runs at a time, since each one reads the state the previous one wrote
+    pub fn is_busy(&self, now: i64) -> bool {
+        now < self.pending_until
+    }
+
+    /// Whether deposits are waiting for their `credit_balance` callback
+    pub fn has_pending_credit(&self) -> bool {
+        self.pending_base_credit > 0 || self.pending_quote_credit > 0
+    }
+
+    /// Record a newly queued computation
+    /// Its offset replaces the previous one, so a late callback of an expired
+    /// computation no longer matches and is rejected
+    pub fn begin_computation(&mut self, computation_offset: u64, now: i64) {
+        self.computation_offset = computation_offset;
+        self.pending_until = now.saturating_add(Self::COMPUTATION_TIMEOUT);
+    }
+
+    /// Store the state returned by a circuit and mark the balances idle
+    pub fn store_state(&mut self, nonce: u128, state: [[u8; 32]; CONFIDENTIAL_BALANCE_FIELDS]) {
+        self.state_nonce = nonce;
+        self.state = state;
+        self.pending_until = 0;
+    }
+
+    /// Mark the balances idle without storing a new state
+    /// For callbacks whose result no longer applies; the current state stays valid
+    pub fn end_computation(&mut self) {
+        self.pending_until = 0;
+    }
+}
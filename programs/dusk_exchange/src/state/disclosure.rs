@@ -0,0 +1,71 @@
+use anchor_lang::prelude::*;
+
+/// Number of encrypted fields in the circuit's OwnedOrders
+/// A disclosed fill uses the first three (price, amount, salt)
+pub const DISCLOSURE_FIELDS: usize = 6;
+
+/// What a disclosure covers
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum DisclosureKind {
+    /// The position's orders resting in the book
+    #[default]
+    Orders,
+    /// The terms of one sealed fill of the position
+    Fill,
+}
+
+/// On-chain record of a disclosure to a position's auditor
+/// Holds the disclosed data encrypted to the auditor key once the MXE calls back
+/// Seeds: ["disclosure", user_position, computation_offset]
+#[account]
+#[derive(Default)]
+pub struct DisclosureRecord {
+    /// Market the position belongs to
+    pub market: Pubkey,
+
+    /// Position whose data is disclosed
+    pub position: Pubkey,
+
+    /// Auditor x25519 key the data is encrypted to
+    pub auditor_pubkey: [u8; 32],
+
+    pub kind: DisclosureKind,
+
+    /// Settlement whose fill is disclosed (Pubkey::default() for orders)
+    pub settlement: Pubkey,
+
+    /// Offset of the disclosure computation
+    pub computation_offset: u64,
+
+    /// When the disclosure was requested
+    pub requested_at: i64,
+
+    /// Whether the MXE has returned the disclosed data
+    pub completed: bool,
+
+    /// Nonce of the encrypted data
+    pub result_nonce: u128,
+
+    /// Disclosed data encrypted to `auditor_pubkey`, in circuit declaration order
+    pub result: [[u8; 32]; DISCLOSURE_FIELDS],
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+impl DisclosureRecord {
+    pub const LEN: usize = 8 +  // discriminator
+        32 +  // market
+        32 +  // position
+        32 +  // auditor_pubkey
+        1 +   // kind
+        32 +  // settlement
+        8 +   // computation_offset
+        8 +   // requested_at
+        1 +   // completed
+        16 +  // result_nonce
+        32 * DISCLOSURE_FIELDS + // result
+        1;    // bump
+
+    pub const SEED_PREFIX: &'static [u8] = b"disclosure";
+}
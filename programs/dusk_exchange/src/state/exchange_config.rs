@@ -0,0 +1,79 @@
+use anchor_lang::prelude::*;
+
+/// Global exchange configuration
+/// Seeds: ["exchange_config"]
+#[account]
+#[derive(Default)]
+pub struct ExchangeConfig {
+    /// Protocol admin
+    pub admin: Pubkey,
+
+    /// Whether anyone may create markets (otherwise only the admin)
+    pub permissionless_markets: bool,
+
+    /// Share of every trading fee reserved for the protocol, in basis points of the fee
+    pub protocol_fee_share_bps: u16,
+
+    /// Number of markets created through the registry and not closed yet
+    pub market_count: u64,
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+impl ExchangeConfig {
+    pub const LEN: usize = 8 +  // discriminator
+        32 +  // admin
+        1 +   // permissionless_markets
+        2 +   // protocol_fee_share_bps
+        8 +   // market_count
+        1;    // bump
+
+    pub const SEED_PREFIX: &'static [u8] = b"exchange_config";
+
+    /// Maximum protocol fee share (100% of the trading fee)
+    pub const MAX_PROTOCOL_FEE_SHARE_BPS: u16 = 10_000;
+
+    /// Whether `creator` may create a new market
+    pub fn can_create_market(&self, creator: &Pubkey) -> bool {
+        self.permissionless_markets || *creator == self.admin
+    }
+
+    /// Protocol portion of a trading fee
+    pub fn protocol_fee(&self, fee: u64) -> u64 {
+        (fee as u128 * self.protocol_fee_share_bps as u128 / 10_000) as u64
+    }
+}
+
+/// Registry entry mapping a mint pair to its market
+/// One entry per (base, quote) pair, so duplicate markets cannot be created
+/// Seeds: ["market_registry", base_mint, quote_mint]
+#[account]
+#[derive(Default)]
+pub struct MarketRegistryEntry {
+    /// Base token mint
+    pub base_mint: Pubkey,
+
+    /// Quote token mint
+    pub quote_mint: Pubkey,
+
+    /// Market for this pair
+    pub market: Pubkey,
+
+    /// Market identifier
+    pub market_id: u64,
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+impl MarketRegistryEntry {
+    pub const LEN: usize = 8 +  // discriminator
+        32 +  // base_mint
+        32 +  // quote_mint
+        32 +  // market
+        8 +   // market_id
+        1;    // bump
+
+    pub const SEED_PREFIX: &'static [u8] = b"market_registry";
+}
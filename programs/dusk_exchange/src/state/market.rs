@@ -1,4 +1,33 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hashv;
+use anchor_spl::token::spl_token::native_mint;
+
+use super::{UserPosition, FILL_TERMS_FIELDS, MATCH_PARTIES_FIELDS};
+
+/// Trading phase of a market
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum MarketStatus {
+    /// Opening auction: orders are collected but not matched until `uncross` runs
+    #[default]
+    Auction,
+    /// Continuous trading: crossing orders are matched via `match_orders`
+    Continuous,
+    /// No new orders are accepted; resuming starts a new opening auction
+    Paused,
+    /// Delisting: orders can only be cancelled until the book is cleared
+    CancelOnly,
+    /// Delisted: the book is cleared and funds can only be withdrawn
+    WithdrawOnly,
+}
+
+/// Permissionless crank that earns a keeper reward
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeeperCrank {
+    MatchOrders,
+    CreateSettlement,
+    SettleTrade,
+    CloseSettlement,
+}
 
 /// Market account representing a trading pair (e.g., SOL/USDC)
 /// Seeds: ["market", market_id]
@@ -20,6 +49,12 @@ pub struct Market {
     /// Token vault for quote tokens
     pub quote_vault: Pubkey,
 
+    /// Token program owning the base mint (SPL Token or Token-2022)
+    pub base_token_program: Pubkey,
+
+    /// Token program owning the quote mint (SPL Token or Token-2022)
+    pub quote_token_program: Pubkey,
+
     /// Unique market identifier
     pub market_id: u64,
 
@@ -29,13 +64,13 @@ pub struct Market {
     /// Counter for generating unique order IDs
     pub order_count: u64,
 
-    /// Reference to the encrypted orderbook (MXE-managed)
+    /// OrderBook account holding this market's encrypted book state
     pub orderbook_ref: Pubkey,
 
-    /// Total base tokens locked in open orders
+    /// Total base tokens locked in open orders across all positions
     pub base_locked: u64,
 
-    /// Total quote tokens locked in open orders
+    /// Total quote tokens locked in open orders across all positions
     pub quote_locked: u64,
 
     /// Number of active buy orders
@@ -47,10 +82,10 @@ pub struct Market {
     /// Counter for generating unique settlement IDs
     pub settlement_count: u64,
 
-    /// Pending match - maker pubkey (from last match, needs settlement creation)
+    /// Pending match - maker position (from last match, needs settlement creation)
     pub pending_maker: Pubkey,
 
-    /// Pending match - taker pubkey
+    /// Pending match - taker position
     pub pending_taker: Pubkey,
 
     /// Pending match - maker order ID
@@ -71,6 +106,104 @@ pub struct Market {
     /// Whether there's a pending match that needs settlement creation
     pub has_pending_match: bool,
 
+    /// Current trading phase
+    pub status: MarketStatus,
+
+    /// Reward per successful crank, in quote tokens, paid out of accrued fees
+    pub keeper_reward: u64,
+
+    /// Trading fees collected in the quote vault and not yet paid out
+    pub fees_accrued: u64,
+
+    /// Total quote tokens paid to keepers
+    pub keeper_rewards_paid: u64,
+
+    /// Protocol share of trading fees held in the quote vault, not yet collected
+    pub protocol_fees_accrued: u64,
+
+    /// Number of open user positions on this market
+    pub open_position_count: u32,
+
+    /// Number of created settlements that have not been settled yet
+    pub open_settlements: u32,
+
+    /// Total base tokens deposited across all positions
+    pub base_deposited: u64,
+
+    /// Total quote tokens deposited across all positions
+    pub quote_deposited: u64,
+
+    /// Number of MPC computations queued for this market
+    pub computation_count: u64,
+
+    /// Whether position balances and order locks are kept encrypted in the MXE
+    pub confidential_balances: bool,
+
+    /// Number of active side-hidden orders, counted apart from bids and asks
+    pub active_hidden: u32,
+
+    /// Whether matches keep their price and amount encrypted until settlement
+    pub sealed_fills: bool,
+
+    /// Pending sealed match - nonce of the encrypted fill terms
+    pub pending_fill_nonce: u128,
+
+    /// Pending sealed match - MXE-encrypted price and amount
+    pub pending_fill: [[u8; 32]; FILL_TERMS_FIELDS],
+
+    /// Pending sealed match - commitment to the fill terms
+    pub pending_fill_commitment: [u8; 32],
+
+    /// Computation offset of the latest probe_cross job
+    pub probe_computation_offset: u64,
+
+    /// Book state nonce the latest probe read
+    /// When it no longer matches the order book, the probe result is stale
+    pub probe_state_nonce: u128,
+
+    /// Whether the latest probe found the book crossed
+    /// False while a probe is in flight
+    pub book_crossed: bool,
+
+    /// Pending match - whether the maker order left the book with this fill
+    pub pending_maker_filled: bool,
+
+    /// Pending match - whether the taker order left the book with this fill
+    pub pending_taker_filled: bool,
+
+    /// `order_count` when the latest uncross landed; every order queued up to
+    /// then, other than the two the uncross left in the book, is out of the book
+    pub uncross_order_seq: u64,
+
+    /// Position of the bid the latest uncross left in the book
+    pub uncross_bid_position: Pubkey,
+
+    /// Order id of that bid, 0 if the bid side was empty
+    pub uncross_bid_order_id: u64,
+
+    /// Position of the ask the latest uncross left in the book
+    pub uncross_ask_position: Pubkey,
+
+    /// Order id of that ask, 0 if the ask side was empty
+    pub uncross_ask_order_id: u64,
+
+    /// Whether the orders and owners of the pending sealed match are still
+    /// encrypted in `pending_parties`; `open_sealed_match` reveals them
+    pub pending_match_sealed: bool,
+
+    /// Computation offset of the latest open_sealed_match job
+    pub open_computation_offset: u64,
+
+    /// Time until which that computation may still call back
+    pub open_pending_until: i64,
+
+    /// Pending sealed match - nonce of the encrypted parties
+    pub pending_parties_nonce: u128,
+
+    /// Pending sealed match - MXE-encrypted MatchParties
+    /// Kept right before the bump, see `PENDING_PARTIES_OFFSET`
+    pub pending_parties: [[u8; 32]; MATCH_PARTIES_FIELDS],
+
     /// PDA bump seed
     pub bump: u8,
 }
@@ -82,6 +215,8 @@ impl Market {
         32 +  // quote_mint
         32 +  // base_vault
         32 +  // quote_vault
+        32 +  // base_token_program
+        32 +  // quote_token_program
         8 +   // market_id
         2 +   // fee_rate_bps
         8 +   // order_count
@@ -99,20 +234,319 @@ impl Market {
         8 +   // pending_execution_amount
         8 +   // pending_matched_at
         1 +   // has_pending_match
+        1 +   // status
+        8 +   // keeper_reward
+        8 +   // fees_accrued
+        8 +   // keeper_rewards_paid
+        8 +   // protocol_fees_accrued
+        4 +   // open_position_count
+        4 +   // open_settlements
+        8 +   // base_deposited
+        8 +   // quote_deposited
+        8 +   // computation_count
+        1 +   // confidential_balances
+        4 +   // active_hidden
+        1 +   // sealed_fills
+        16 +  // pending_fill_nonce
+        32 * FILL_TERMS_FIELDS + // pending_fill
+        32 +  // pending_fill_commitment
+        8 +   // probe_computation_offset
+        16 +  // probe_state_nonce
+        1 +   // book_crossed
+        1 +   // pending_maker_filled
+        1 +   // pending_taker_filled
+        8 +   // uncross_order_seq
+        32 +  // uncross_bid_position
+        8 +   // uncross_bid_order_id
+        32 +  // uncross_ask_position
+        8 +   // uncross_ask_order_id
+        1 +   // pending_match_sealed
+        8 +   // open_computation_offset
+        8 +   // open_pending_until
+        16 +  // pending_parties_nonce
+        32 * MATCH_PARTIES_FIELDS + // pending_parties
         1;    // bump
 
     pub const SEED_PREFIX: &'static [u8] = b"market";
 
+    /// Byte offset of the encrypted parties of a pending sealed match
+    pub const PENDING_PARTIES_OFFSET: u32 = (Self::LEN - 1 - Self::PENDING_PARTIES_LEN as usize) as u32;
+
+    /// Byte length of the encrypted parties of a pending sealed match
+    pub const PENDING_PARTIES_LEN: u32 = 32 * MATCH_PARTIES_FIELDS as u32;
+
+    /// How long only the parties of a sealed match may open it (seconds)
+    /// After that anyone may, so a match no party opens can't hold the market
+    pub const SEALED_MATCH_OPEN_DELAY: i64 = 10 * 60;
+
+    /// How long a queued open_sealed_match computation blocks another one (seconds)
+    pub const OPEN_COMPUTATION_TIMEOUT: i64 = 10 * 60;
+
+    /// Cranks that each pay a keeper reward for one fill: match, create, settle and close
+    pub const KEEPER_CRANKS_PER_FILL: u64 = 4;
+
+    /// Quote amount of the fill whose fee caps the keeper reward (100 units of a 6-decimal token)
+    pub const KEEPER_REWARD_REFERENCE_FILL: u64 = 100_000_000;
+
+    /// Largest keeper reward the market allows: the fee on the reference fill,
+    /// split across the cranks that fill pays for, so keepers never cost more than it earns
+    pub fn max_keeper_reward(&self) -> u64 {
+        self.calculate_fee(Self::KEEPER_REWARD_REFERENCE_FILL) / Self::KEEPER_CRANKS_PER_FILL
+    }
+
     /// Calculate fee amount for a given trade amount
     pub fn calculate_fee(&self, amount: u64) -> u64 {
         (amount as u128 * self.fee_rate_bps as u128 / 10_000) as u64
     }
 
+    /// Whether an order queued `book_seq`-th on this market is known to be out
+    /// of the book since the latest uncross: it was queued before it, the
+    /// uncross did not leave it in the book, and no pending match names it
+    pub fn left_book_at_uncross(&self, position: &Pubkey, order_id: u64, book_seq: u64) -> bool {
+        let is = |p: &Pubkey, id: u64| *p == *position && id == order_id;
+
+        book_seq != 0
+            && book_seq <= self.uncross_order_seq
+            && !is(&self.uncross_bid_position, self.uncross_bid_order_id)
+            && !is(&self.uncross_ask_position, self.uncross_ask_order_id)
+            && !(self.has_pending_match
+                && (is(&self.pending_maker, self.pending_maker_order_id)
+                    || is(&self.pending_taker, self.pending_taker_order_id)))
+    }
+
+    /// Whether the market is being wound down (cancel-only or withdraw-only)
+    pub fn is_delisted(&self) -> bool {
+        matches!(
+            self.status,
+            MarketStatus::CancelOnly | MarketStatus::WithdrawOnly
+        )
+    }
+
+    /// Which side of the market trades native SOL (wSOL), if any
+    /// Returns Some(true) for base, Some(false) for quote
+    pub fn native_side(&self) -> Option<bool> {
+        if self.base_mint == native_mint::ID {
+            Some(true)
+        } else if self.quote_mint == native_mint::ID {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// Vault of the base (true) or quote (false) token
+    pub fn vault(&self, is_base: bool) -> Pubkey {
+        if is_base { self.base_vault } else { self.quote_vault }
+    }
+
+    /// Mint of the base (true) or quote (false) token
+    pub fn mint(&self, is_base: bool) -> Pubkey {
+        if is_base { self.base_mint } else { self.quote_mint }
+    }
+
+    /// Token program of the base (true) or quote (false) mint
+    pub fn token_program(&self, is_base: bool) -> Pubkey {
+        if is_base { self.base_token_program } else { self.quote_token_program }
+    }
+
+    /// Whether the book may hold a bid and an ask at once, so a match is worth attempting
+    /// Side-hidden orders may be on either side
+    pub fn may_cross(&self) -> bool {
+        let bids = self.active_bids.saturating_add(self.active_hidden);
+        let asks = self.active_asks.saturating_add(self.active_hidden);
+        let total = self.active_bids
+            .saturating_add(self.active_asks)
+            .saturating_add(self.active_hidden);
+        bids > 0 && asks > 0 && total >= 2
+    }
+
+    /// Stop counting an order that a fill or a cancel took out of the book
+    pub fn remove_active_order(&mut self, is_buy: bool, side_hidden: bool) {
+        if side_hidden {
+            self.active_hidden = self.active_hidden.saturating_sub(1);
+        } else if is_buy {
+            self.active_bids = self.active_bids.saturating_sub(1);
+        } else {
+            self.active_asks = self.active_asks.saturating_sub(1);
+        }
+    }
+
+    /// Move a side-hidden order that is still resting to the counter of the
+    /// side a fill revealed
+    /// A filled one was already taken off `active_hidden` by `create_settlement`
+    pub fn reveal_hidden_order(&mut self, is_buy: bool) {
+        self.active_hidden = self.active_hidden.saturating_sub(1);
+        if is_buy {
+            self.active_bids = self.active_bids.saturating_add(1);
+        } else {
+            self.active_asks = self.active_asks.saturating_add(1);
+        }
+    }
+
+    /// Track tokens locked by a new order
+    pub fn lock(&mut self, amount: u64, is_buy: bool) -> Result<()> {
+        if is_buy {
+            self.quote_locked = self.quote_locked.checked_add(amount)
+                .ok_or(crate::errors::DuskError::MathOverflow)?;
+        } else {
+            self.base_locked = self.base_locked.checked_add(amount)
+                .ok_or(crate::errors::DuskError::MathOverflow)?;
+        }
+        Ok(())
+    }
+
+    /// Track tokens released from orders (fills, cancellations, clearing)
+    pub fn unlock(&mut self, amount: u64, is_buy: bool) {
+        if is_buy {
+            self.quote_locked = self.quote_locked.saturating_sub(amount);
+        } else {
+            self.base_locked = self.base_locked.saturating_sub(amount);
+        }
+    }
+
+    /// Track tokens credited to positions (deposits)
+    pub fn credit_deposit(&mut self, amount: u64, is_base: bool) -> Result<()> {
+        if is_base {
+            self.base_deposited = self.base_deposited.checked_add(amount)
+                .ok_or(crate::errors::DuskError::MathOverflow)?;
+        } else {
+            self.quote_deposited = self.quote_deposited.checked_add(amount)
+                .ok_or(crate::errors::DuskError::MathOverflow)?;
+        }
+        Ok(())
+    }
+
+    /// Track tokens debited from positions (withdrawals, fees)
+    pub fn debit_deposit(&mut self, amount: u64, is_base: bool) -> Result<()> {
+        if is_base {
+            self.base_deposited = self.base_deposited.checked_sub(amount)
+                .ok_or(crate::errors::DuskError::MathOverflow)?;
+        } else {
+            self.quote_deposited = self.quote_deposited.checked_sub(amount)
+                .ok_or(crate::errors::DuskError::MathOverflow)?;
+        }
+        Ok(())
+    }
+
+    /// Computation offset of the `index`-th job queued for `market`
+    /// Offsets share one namespace across all markets, so the market key is mixed in
+    pub fn computation_offset(market: &Pubkey, index: u64) -> u64 {
+        let hash = hashv(&[market.as_ref(), &index.to_le_bytes()]);
+        u64::from_le_bytes(hash.to_bytes()[..8].try_into().unwrap())
+    }
+
+    /// Check a client-supplied offset against the next one for this market
+    /// and reserve it
+    pub fn claim_computation_offset(&mut self, market: &Pubkey, offset: u64) -> Result<()> {
+        let index = self.computation_count
+            .checked_add(1)
+            .ok_or(crate::errors::DuskError::MathOverflow)?;
+        require!(
+            offset == Self::computation_offset(market, index),
+            crate::errors::DuskError::InvalidComputationOffset
+        );
+        self.computation_count = index;
+        Ok(())
+    }
+
     /// Generate next order ID
     pub fn next_order_id(&mut self) -> u64 {
         self.order_count += 1;
         self.order_count
     }
+
+    /// Credit the keeper reward to a keeper's position, funded from accrued fees
+    /// Returns the amount paid, which is capped by the fees available
+    ///
+    /// The reward is paid into the signer's own position rather than to a token
+    /// account: the fees already sit in the quote vault, so crediting the
+    /// position keeps `quote_deposited` matching the vault without a transfer,
+    /// and the match_book callback, which pays the match reward, has no
+    /// token account to send to. The keeper takes the reward out with `withdraw`
+    pub fn pay_keeper_reward(&mut self, keeper_position: &mut UserPosition) -> Result<u64> {
+        let reward = self.keeper_reward.min(self.fees_accrued);
+        if reward == 0 {
+            return Ok(0);
+        }
+
+        keeper_position.quote_deposited = keeper_position
+            .quote_deposited
+            .checked_add(reward)
+            .ok_or(crate::errors::DuskError::MathOverflow)?;
+        self.fees_accrued -= reward;
+        self.quote_deposited = self.quote_deposited
+            .checked_add(reward)
+            .ok_or(crate::errors::DuskError::MathOverflow)?;
+        self.keeper_rewards_paid = self.keeper_rewards_paid
+            .checked_add(reward)
+            .ok_or(crate::errors::DuskError::MathOverflow)?;
+
+        Ok(reward)
+    }
+
+    /// Store a revealed match so that `create_settlement` can pick it up
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_pending_match(
+        &mut self,
+        maker: Pubkey,
+        taker: Pubkey,
+        maker_order_id: u64,
+        taker_order_id: u64,
+        execution_price: u64,
+        execution_amount: u64,
+        maker_filled: bool,
+        taker_filled: bool,
+        matched_at: i64,
+    ) {
+        self.pending_maker = maker;
+        self.pending_taker = taker;
+        self.pending_maker_order_id = maker_order_id;
+        self.pending_taker_order_id = taker_order_id;
+        self.pending_execution_price = execution_price;
+        self.pending_execution_amount = execution_amount;
+        self.pending_maker_filled = maker_filled;
+        self.pending_taker_filled = taker_filled;
+        self.pending_matched_at = matched_at;
+        self.has_pending_match = true;
+    }
+
+    /// Whether the match waiting for `create_settlement` fills this order
+    /// While its parties are still sealed, any order may be one of them
+    pub fn pending_match_fills(&self, position: &Pubkey, order_id: u64) -> bool {
+        self.has_pending_match
+            && (self.pending_match_sealed
+                || (self.pending_maker == *position && self.pending_maker_order_id == order_id)
+                || (self.pending_taker == *position && self.pending_taker_order_id == order_id))
+    }
+
+    /// Store a sealed match whose orders and owners stay encrypted until
+    /// `open_sealed_match` reveals them
+    pub fn record_sealed_match(
+        &mut self,
+        parties_nonce: u128,
+        parties: [[u8; 32]; MATCH_PARTIES_FIELDS],
+        matched_at: i64,
+    ) {
+        self.record_pending_match(
+            Pubkey::default(),
+            Pubkey::default(),
+            0,
+            0,
+            0,
+            0,
+            false,
+            false,
+            matched_at,
+        );
+        self.pending_match_sealed = true;
+        self.pending_parties_nonce = parties_nonce;
+        self.pending_parties = parties;
+    }
+
+    /// Whether an open_sealed_match computation may still call back
+    pub fn is_opening(&self, now: i64) -> bool {
+        now < self.open_pending_until
+    }
 }
 
 /// Seeds for deriving market PDA
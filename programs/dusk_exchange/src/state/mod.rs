@@ -1,7 +1,17 @@
 pub mod market;
 pub mod user_position;
 pub mod settlement;
+pub mod exchange_config;
+pub mod order_book;
+pub mod open_order;
+pub mod confidential_balance;
+pub mod disclosure;
 
 pub use market::*;
 pub use user_position::*;
 pub use settlement::*;
+pub use exchange_config::*;
+pub use order_book::*;
+pub use open_order::*;
+pub use confidential_balance::*;
+pub use disclosure::*;
@@ -0,0 +1,163 @@
+use anchor_lang::prelude::*;
+
+/// Lifecycle of an order's MPC computations
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum OrderStatus {
+    /// add_order is queued; the order is not in the book yet
+    #[default]
+    PendingAdd,
+    /// The order is in the book
+    Resting,
+    /// remove_order is queued
+    PendingCancel,
+    /// Left behind by earlier versions for an order that never entered the
+    /// book; reclaimed and rejected adds are now closed instead
+    Void,
+    /// A match took the rest of the order out of the book; it closes once its
+    /// last fill is settled
+    Filled,
+}
+
+/// Plaintext bookkeeping for an order resting in the encrypted book
+/// Tracks how much of the position's lock still backs this order
+/// Seeds: ["order", user_position, order_id]
+#[account]
+#[derive(Default)]
+pub struct OpenOrder {
+    /// Market the order was placed on
+    pub market: Pubkey,
+
+    /// Position that owns the order
+    pub position: Pubkey,
+
+    /// Order ID within the position
+    pub order_id: u64,
+
+    /// true = buy (locks quote), false = sell (locks base)
+    /// Meaningless while `side_hidden` is set
+    pub is_buy: bool,
+
+    /// Tokens still locked for this order (quote tokens for a side-hidden order)
+    pub locked_amount: u64,
+
+    /// Offset of the latest computation queued for this order (add or cancel)
+    pub computation_offset: u64,
+
+    /// Computation currently pending for this order, if any
+    pub status: OrderStatus,
+
+    /// Time after which a pending computation can be reclaimed
+    pub deadline: i64,
+
+    /// Nonce of the encrypted lock (confidential markets only)
+    pub lock_nonce: u128,
+
+    /// MXE-encrypted remaining lock, used instead of `locked_amount` on
+    /// confidential markets
+    pub encrypted_lock: [u8; 32],
+
+    /// Whether the side is encrypted in the order; it locks both base and quote
+    /// until a fill reveals which one it uses
+    pub side_hidden: bool,
+
+    /// Base tokens locked by a side-hidden order
+    pub locked_base: u64,
+
+    /// x25519 key the order was encrypted with; a sealed fill's terms are
+    /// encrypted to it for the owner
+    pub client_pubkey: [u8; 32],
+
+    /// Settlements created against this order and not settled yet
+    pub open_fills: u32,
+
+    /// Market's `order_count` once this order was queued for the book
+    pub book_seq: u64,
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+impl OpenOrder {
+    pub const LEN: usize = 8 +  // discriminator
+        32 +  // market
+        32 +  // position
+        8 +   // order_id
+        1 +   // is_buy
+        8 +   // locked_amount
+        8 +   // computation_offset
+        1 +   // status
+        8 +   // deadline
+        16 +  // lock_nonce
+        32 +  // encrypted_lock
+        1 +   // side_hidden
+        8 +   // locked_base
+        32 +  // client_pubkey
+        4 +   // open_fills
+        8 +   // book_seq
+        1;    // bump
+
+    pub const SEED_PREFIX: &'static [u8] = b"order";
+
+    /// Byte offset of the encrypted lock within the account data
+    pub const ENCRYPTED_LOCK_OFFSET: u32 = 8 + 32 + 32 + 8 + 1 + 8 + 8 + 1 + 8 + 16;
+
+    /// How long a queued computation may take before the owner can reclaim it (10 minutes)
+    pub const COMPUTATION_TIMEOUT: i64 = 10 * 60;
+
+    /// Record a newly queued add or cancel computation
+    pub fn begin_computation(&mut self, status: OrderStatus, now: i64) {
+        self.status = status;
+        self.deadline = now.saturating_add(Self::COMPUTATION_TIMEOUT);
+    }
+
+    /// Whether a queued computation has outlived its deadline without a callback
+    pub fn is_stuck(&self, now: i64) -> bool {
+        matches!(self.status, OrderStatus::PendingAdd | OrderStatus::PendingCancel)
+            && now >= self.deadline
+    }
+
+    /// Fix the side of a side-hidden order once a fill reveals it
+    /// The lock of the other side is no longer needed; returns how much of it is released
+    pub fn reveal_side(&mut self, is_buy: bool) -> u64 {
+        let released = if is_buy {
+            self.locked_base
+        } else {
+            let quote = self.locked_amount;
+            self.locked_amount = self.locked_base;
+            quote
+        };
+        self.locked_base = 0;
+        self.is_buy = is_buy;
+        self.side_hidden = false;
+        released
+    }
+
+    /// Count a settlement created against this order
+    /// `filled` is set when the match took the rest of the order out of the book
+    pub fn record_fill(&mut self, filled: bool) {
+        self.open_fills = self.open_fills.saturating_add(1);
+        if filled && self.status == OrderStatus::Resting {
+            self.status = OrderStatus::Filled;
+        }
+    }
+
+    /// Count a settled or voided fill
+    /// Returns whether the order is out of the book with no fill left to settle
+    pub fn finish_fill(&mut self) -> bool {
+        self.open_fills = self.open_fills.saturating_sub(1);
+        self.status == OrderStatus::Filled && self.open_fills == 0
+    }
+
+    /// Whether settling one more fill finishes the order
+    pub fn is_last_fill(&self) -> bool {
+        self.status == OrderStatus::Filled && self.open_fills == 1
+    }
+
+    /// Consume up to `amount` of this order's lock for a fill
+    /// Returns how much was actually released
+    pub fn consume_lock(&mut self, amount: u64) -> u64 {
+        let consumed = amount.min(self.locked_amount);
+        self.locked_amount -= consumed;
+        consumed
+    }
+}
@@ -0,0 +1,88 @@
+use anchor_lang::prelude::*;
+
+/// Number of encrypted fields in the circuit's OrderBookState
+pub const ORDER_BOOK_STATE_FIELDS: usize = 11;
+
+/// Encrypted order book state of a single market
+/// Holds the MXE-encrypted `OrderBookState` that every circuit reads and writes
+/// Seeds: ["orderbook", market]
+#[account]
+#[derive(Default)]
+pub struct OrderBook {
+    /// Market this book belongs to
+    pub market: Pubkey,
+
+    /// Whether `init_book` has written an empty state
+    pub initialized: bool,
+
+    /// Nonce of the current encryption
+    pub state_nonce: u128,
+
+    /// Encrypted OrderBookState fields, in circuit declaration order
+    pub state: [[u8; 32]; ORDER_BOOK_STATE_FIELDS],
+
+    /// Offset of the computation allowed to write the next state
+    pub computation_offset: u64,
+
+    /// Time until which that computation may still call back
+    /// Zero when no computation is in flight
+    pub pending_until: i64,
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+impl OrderBook {
+    pub const LEN: usize = 8 +  // discriminator
+        32 +  // market
+        1 +   // initialized
+        16 +  // state_nonce
+        32 * ORDER_BOOK_STATE_FIELDS + // state
+        8 +   // computation_offset
+        8 +   // pending_until
+        1;    // bump
+
+    pub const SEED_PREFIX: &'static [u8] = b"orderbook";
+
+    /// Byte offset of the encrypted state within the account data
+    pub const STATE_OFFSET: u32 = 8 + 32 + 1 + 16;
+
+    /// Byte length of the encrypted state
+    pub const STATE_LEN: u32 = 32 * ORDER_BOOK_STATE_FIELDS as u32;
+
+    /// How long a queued computation may take before another can replace it (10 minutes)
+    pub const COMPUTATION_TIMEOUT: i64 = 10 * 60;
+
+    /// Whether a computation on this book may still call back
+    /// Only one runs at a time, since each one reads the state the previous one wrote
+    pub fn is_busy(&self, now: i64) -> bool {
+        now < self.pending_until
+    }
+
+    /// Whether the latest queued computation has not called back yet
+    /// Its callback marks the book idle, so a re-delivered result finds it idle
+    pub fn is_pending(&self) -> bool {
+        self.pending_until != 0
+    }
+
+    /// Record a newly queued computation
+    /// Its offset replaces the previous one, so a late callback of an expired
+    /// computation no longer matches and is rejected
+    pub fn begin_computation(&mut self, computation_offset: u64, now: i64) {
+        self.computation_offset = computation_offset;
+        self.pending_until = now.saturating_add(Self::COMPUTATION_TIMEOUT);
+    }
+
+    /// Store the state returned by a circuit and mark the book idle
+    pub fn store_state(&mut self, nonce: u128, state: [[u8; 32]; ORDER_BOOK_STATE_FIELDS]) {
+        self.state_nonce = nonce;
+        self.state = state;
+        self.pending_until = 0;
+    }
+
+    /// Mark the book idle without storing a new state
+    /// For callbacks whose result no longer applies; the current state stays valid
+    pub fn end_computation(&mut self) {
+        self.pending_until = 0;
+    }
+}
@@ -1,18 +1,26 @@
 use anchor_lang::prelude::*;
 
+/// Number of encrypted fields in the circuit's FillTerms (price, amount, salt)
+pub const FILL_TERMS_FIELDS: usize = 3;
+
+/// Number of encrypted fields in the circuit's MatchParties (both order ids,
+/// both owners as two halves each, and both filled flags)
+pub const MATCH_PARTIES_FIELDS: usize = 8;
+
 /// Trade settlement account created when orders are matched
-/// Contains revealed execution details
-/// Seeds: ["settlement", market, maker_order_id, taker_order_id]
+/// Contains revealed execution details, or the encrypted terms of a sealed fill
+/// Seeds: ["settlement", market, settlement_id], where settlement_id is the
+/// market's `settlement_count` after this settlement, starting at 1
 #[account]
 #[derive(Default)]
 pub struct TradeSettlement {
     /// Market where the trade occurred
     pub market: Pubkey,
 
-    /// Maker (limit order that was resting)
+    /// Maker position (limit order that was resting)
     pub maker: Pubkey,
 
-    /// Taker (order that crossed the spread)
+    /// Taker position (order that crossed the spread)
     pub taker: Pubkey,
 
     /// Maker's order ID
@@ -39,6 +47,29 @@ pub struct TradeSettlement {
     /// Timestamp of settlement (0 if not settled)
     pub settled_at: i64,
 
+    /// Account that paid rent in `create_settlement` and receives it back on close
+    pub payer: Pubkey,
+
+    /// Whether price and amount are encrypted in `encrypted_fill` instead of revealed
+    /// `execution_price` and `execution_amount` then stay 0
+    pub sealed_fill: bool,
+
+    /// SHA3-256(price || amount || salt) of a sealed fill, computed by the MXE
+    /// Each counterparty gets the salt with its copy of the terms and can open it
+    pub fill_commitment: [u8; 32],
+
+    /// Nonce of the encrypted fill terms
+    pub fill_nonce: u128,
+
+    /// MXE-encrypted FillTerms fields (price, amount, salt) of a sealed fill
+    pub encrypted_fill: [[u8; 32]; FILL_TERMS_FIELDS],
+
+    /// Offset of the latest reveal_fill computation queued for this settlement
+    pub computation_offset: u64,
+
+    /// Time until which that computation may still call back
+    pub pending_until: i64,
+
     /// PDA bump seed
     pub bump: u8,
 }
@@ -56,10 +87,45 @@ impl TradeSettlement {
         1 +   // settled
         8 +   // matched_at
         8 +   // settled_at
+        32 +  // payer
+        1 +   // sealed_fill
+        32 +  // fill_commitment
+        16 +  // fill_nonce
+        32 * FILL_TERMS_FIELDS + // encrypted_fill
+        8 +   // computation_offset
+        8 +   // pending_until
         1;    // bump
 
     pub const SEED_PREFIX: &'static [u8] = b"settlement";
 
+    /// Byte offset of the encrypted fill terms within the account data
+    pub const ENCRYPTED_FILL_OFFSET: u32 = 8 + 32 + 32 + 32 + 8 + 8 + 8 + 8 + 1 + 1 + 8 + 8 + 32 + 1 + 32 + 16;
+
+    /// Byte length of the encrypted fill terms
+    pub const ENCRYPTED_FILL_LEN: u32 = 32 * FILL_TERMS_FIELDS as u32;
+
+    /// How long a queued reveal_fill computation blocks another one (seconds)
+    pub const COMPUTATION_TIMEOUT: i64 = 10 * 60;
+
+    /// How long a settled trade stays on chain before it can be closed (seconds)
+    pub const RETENTION_PERIOD: i64 = 7 * 24 * 60 * 60;
+
+    /// Whether the settled account has outlived the retention period
+    pub fn retention_elapsed(&self, now: i64) -> bool {
+        self.settled && now >= self.settled_at.saturating_add(Self::RETENTION_PERIOD)
+    }
+
+    /// Whether a reveal_fill computation on this settlement may still call back
+    pub fn is_busy(&self, now: i64) -> bool {
+        now < self.pending_until
+    }
+
+    /// Record a newly queued reveal_fill computation
+    pub fn begin_computation(&mut self, computation_offset: u64, now: i64) {
+        self.computation_offset = computation_offset;
+        self.pending_until = now.saturating_add(Self::COMPUTATION_TIMEOUT);
+    }
+
     /// Calculate quote amount from price and base amount
     /// price is scaled by 10^6 (e.g., $100 = 100_000_000)
     pub fn calculate_quote_amount(&self) -> u64 {
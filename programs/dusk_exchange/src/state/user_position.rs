@@ -1,7 +1,18 @@
 use anchor_lang::prelude::*;
 
+/// Recipient wallet that may receive withdrawals from a position
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct WithdrawRecipient {
+    /// Wallet owning the destination token account
+    pub recipient: Pubkey,
+
+    /// Unix timestamp from which withdrawals to this recipient are allowed
+    pub active_at: i64,
+}
+
 /// User position account tracking deposits and orders for a specific market
-/// Seeds: ["user_position", market, owner]
+/// An owner can hold several numbered sub-accounts per market
+/// Seeds: ["user_position", market, owner, sub_account_id]
 #[account]
 #[derive(Default)]
 pub struct UserPosition {
@@ -11,6 +22,9 @@ pub struct UserPosition {
     /// Market this position belongs to
     pub market: Pubkey,
 
+    /// Sub-account index of this position under the owner (0 = default)
+    pub sub_account_id: u16,
+
     /// Total base tokens deposited (available + locked in orders)
     pub base_deposited: u64,
 
@@ -26,6 +40,29 @@ pub struct UserPosition {
     /// Number of active orders
     pub active_order_count: u8,
 
+    /// Key allowed to place and cancel orders on the owner's behalf (cannot withdraw)
+    /// Pubkey::default() when no delegate is set
+    pub delegate: Pubkey,
+
+    /// Unix timestamp after which the delegate loses access (0 = no expiry)
+    pub delegate_expires_at: i64,
+
+    /// When enabled, withdrawals may only go to the owner or to active allowlist entries
+    pub withdraw_allowlist_enabled: bool,
+
+    /// Unix timestamp at which a requested allowlist disable takes effect (0 = none pending)
+    pub withdraw_allowlist_disable_at: i64,
+
+    /// Allowed withdrawal recipients (empty slots use Pubkey::default())
+    pub withdraw_allowlist: [WithdrawRecipient; MAX_WITHDRAW_RECIPIENTS],
+
+    /// x25519 key of the auditor the owner discloses orders and fills to
+    /// All zeros when no auditor is registered
+    pub auditor_pubkey: [u8; 32],
+
+    /// Offset of the latest query_my_orders computation; only its callback is accepted
+    pub query_computation_offset: u64,
+
     /// PDA bump seed
     pub bump: u8,
 }
@@ -34,15 +71,27 @@ impl UserPosition {
     pub const LEN: usize = 8 +  // discriminator
         32 +  // owner
         32 +  // market
+        2 +   // sub_account_id
         8 +   // base_deposited
         8 +   // quote_deposited
         8 +   // base_locked
         8 +   // quote_locked
         1 +   // active_order_count
+        32 +  // delegate
+        8 +   // delegate_expires_at
+        1 +   // withdraw_allowlist_enabled
+        8 +   // withdraw_allowlist_disable_at
+        40 * MAX_WITHDRAW_RECIPIENTS + // withdraw_allowlist
+        32 +  // auditor_pubkey
+        8 +   // query_computation_offset
         1;    // bump
 
     pub const SEED_PREFIX: &'static [u8] = b"user_position";
 
+    /// Delay before a new withdrawal recipient, or an allowlist disable, takes effect
+    /// Gives the owner time to react if a hot key is compromised
+    pub const ALLOWLIST_DELAY: i64 = 24 * 60 * 60;
+
     /// Available base tokens (not locked in orders)
     pub fn base_available(&self) -> u64 {
         self.base_deposited.saturating_sub(self.base_locked)
@@ -53,6 +102,110 @@ impl UserPosition {
         self.quote_deposited.saturating_sub(self.quote_locked)
     }
 
+    /// Whether `signer` may place and cancel orders for this position
+    pub fn can_trade(&self, signer: &Pubkey, now: i64) -> bool {
+        if *signer == self.owner {
+            return true;
+        }
+        self.delegate != Pubkey::default()
+            && *signer == self.delegate
+            && (self.delegate_expires_at == 0 || now < self.delegate_expires_at)
+    }
+
+    /// Whether an auditor key is registered
+    pub fn has_auditor(&self) -> bool {
+        self.auditor_pubkey != [0u8; 32]
+    }
+
+    /// Whether the withdrawal allowlist currently restricts recipients
+    pub fn withdraw_allowlist_active(&self, now: i64) -> bool {
+        self.withdraw_allowlist_enabled
+            && (self.withdraw_allowlist_disable_at == 0 || now < self.withdraw_allowlist_disable_at)
+    }
+
+    /// Whether a withdrawal to the wallet `recipient` is permitted
+    /// The owner's own token accounts are always allowed
+    pub fn can_withdraw_to(&self, recipient: &Pubkey, now: i64) -> bool {
+        if *recipient == self.owner || !self.withdraw_allowlist_active(now) {
+            return true;
+        }
+        self.withdraw_allowlist.iter().any(|entry| {
+            entry.recipient != Pubkey::default()
+                && entry.recipient == *recipient
+                && now >= entry.active_at
+        })
+    }
+
+    /// Whether funds moved into `to`, another position of the same owner, stay
+    /// behind this position's withdrawal allowlist
+    /// While the allowlist is active, `to` must keep its own allowlist active at
+    /// least as long and only allow recipients this position already allows,
+    /// from no earlier time; otherwise a sibling sub-account would be a way around it
+    pub fn allowlist_covers(&self, to: &UserPosition, now: i64) -> bool {
+        if !self.withdraw_allowlist_active(now) {
+            return true;
+        }
+        if !to.withdraw_allowlist_active(now) {
+            return false;
+        }
+        let disables_no_earlier = to.withdraw_allowlist_disable_at == 0
+            || (self.withdraw_allowlist_disable_at != 0
+                && to.withdraw_allowlist_disable_at >= self.withdraw_allowlist_disable_at);
+        disables_no_earlier
+            && to.withdraw_allowlist.iter().all(|entry| {
+                entry.recipient == Pubkey::default()
+                    || self.withdraw_allowlist.iter().any(|allowed| {
+                        allowed.recipient == entry.recipient && allowed.active_at <= entry.active_at
+                    })
+            })
+    }
+
+    /// Move available (unlocked) base or quote to another position on the same market
+    pub fn transfer_available(
+        &mut self,
+        to: &mut UserPosition,
+        amount: u64,
+        is_base: bool,
+    ) -> Result<()> {
+        require!(amount > 0, crate::errors::DuskError::AmountTooSmall);
+        if is_base {
+            require!(
+                self.base_available() >= amount,
+                crate::errors::DuskError::InsufficientBalance
+            );
+            self.base_deposited -= amount;
+            to.base_deposited = to.base_deposited.checked_add(amount)
+                .ok_or(crate::errors::DuskError::MathOverflow)?;
+        } else {
+            require!(
+                self.quote_available() >= amount,
+                crate::errors::DuskError::InsufficientBalance
+            );
+            self.quote_deposited -= amount;
+            to.quote_deposited = to.quote_deposited.checked_add(amount)
+                .ok_or(crate::errors::DuskError::MathOverflow)?;
+        }
+        Ok(())
+    }
+
+    /// Split this position's address into two u128 values, the identity
+    /// used for orders in the encrypted book
+    pub fn split_key(key: &Pubkey) -> (u128, u128) {
+        let bytes = key.to_bytes();
+        let lo = u128::from_le_bytes(bytes[..16].try_into().unwrap());
+        let hi = u128::from_le_bytes(bytes[16..].try_into().unwrap());
+        (lo, hi)
+    }
+
+    /// Whether the position holds no funds, locks or orders and can be closed
+    pub fn is_empty(&self) -> bool {
+        self.base_deposited == 0
+            && self.quote_deposited == 0
+            && self.base_locked == 0
+            && self.quote_locked == 0
+            && self.active_order_count == 0
+    }
+
     /// Lock tokens for a new order
     pub fn lock_for_order(&mut self, amount: u64, is_buy: bool) -> Result<()> {
         if is_buy {
@@ -75,6 +228,28 @@ impl UserPosition {
         Ok(())
     }
 
+    /// Lock both base and quote for a side-hidden order
+    pub fn lock_for_hidden_order(&mut self, base_amount: u64, quote_amount: u64) -> Result<()> {
+        require!(
+            self.base_available() >= base_amount && self.quote_available() >= quote_amount,
+            crate::errors::DuskError::InsufficientBalance
+        );
+        self.base_locked = self.base_locked.checked_add(base_amount)
+            .ok_or(crate::errors::DuskError::MathOverflow)?;
+        self.quote_locked = self.quote_locked.checked_add(quote_amount)
+            .ok_or(crate::errors::DuskError::MathOverflow)?;
+        self.active_order_count = self.active_order_count.checked_add(1)
+            .ok_or(crate::errors::DuskError::TooManyOrders)?;
+        Ok(())
+    }
+
+    /// Unlock both sides of a side-hidden order when it is cancelled
+    pub fn unlock_hidden_for_cancel(&mut self, base_amount: u64, quote_amount: u64) {
+        self.base_locked = self.base_locked.saturating_sub(base_amount);
+        self.quote_locked = self.quote_locked.saturating_sub(quote_amount);
+        self.active_order_count = self.active_order_count.saturating_sub(1);
+    }
+
     /// Unlock tokens when order is cancelled
     pub fn unlock_for_cancel(&mut self, amount: u64, is_buy: bool) {
         if is_buy {
@@ -86,11 +261,19 @@ impl UserPosition {
     }
 }
 
+/// Maximum number of withdrawal recipients per position
+pub const MAX_WITHDRAW_RECIPIENTS: usize = 4;
+
 /// Seeds for deriving user position PDA
-pub fn user_position_seeds<'a>(market: &'a Pubkey, owner: &'a Pubkey) -> [&'a [u8]; 3] {
+pub fn user_position_seeds<'a>(
+    market: &'a Pubkey,
+    owner: &'a Pubkey,
+    sub_account_id: &'a [u8; 2],
+) -> [&'a [u8]; 4] {
     [
         UserPosition::SEED_PREFIX,
         market.as_ref(),
         owner.as_ref(),
+        sub_account_id,
     ]
 }
@@ -0,0 +1,1074 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import {
+  Keypair,
+  PublicKey,
+  SystemProgram,
+  LAMPORTS_PER_SOL,
+} from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  ACCOUNT_SIZE,
+  AccountLayout,
+  MINT_SIZE,
+  MintLayout,
+} from "@solana/spl-token";
+import { start, Clock, ProgramTestContext } from "solana-bankrun";
+import { BankrunProvider } from "anchor-bankrun";
+import { expect } from "chai";
+import { DuskExchange } from "../target/types/dusk_exchange";
+import IDL from "../target/idl/dusk_exchange.json";
+
+// Paths that need state no MPC-free flow can produce (settled trades, filled
+// orders, expired computations). Accounts are written directly into a bankrun
+// bank, whose clock can also be moved forward.
+describe("dusk_exchange account state", () => {
+  const programId = new PublicKey(IDL.address);
+
+  let context: ProgramTestContext;
+  let provider: BankrunProvider;
+  let program: Program<DuskExchange>;
+
+  let payer: Keypair;
+  let marketPda: PublicKey;
+
+  const MARKET_ID = new anchor.BN(7);
+  const DAY = 24 * 60 * 60;
+
+  // Zero value of an IDL type, so tests only spell out the fields they care about
+  const defaultValue = (type: any): any => {
+    if (typeof type === "string") {
+      switch (type) {
+        case "bool":
+          return false;
+        case "pubkey":
+          return PublicKey.default;
+        case "u8":
+        case "u16":
+        case "u32":
+        case "i8":
+        case "i16":
+        case "i32":
+          return 0;
+        default:
+          return new anchor.BN(0);
+      }
+    }
+    if ("array" in type) {
+      const [inner, len] = type.array;
+      return Array.from({ length: len }, () => defaultValue(inner));
+    }
+    if ("option" in type) {
+      return null;
+    }
+    const def = program.idl.types!.find((t) => t.name === type.defined.name)!;
+    if (def.type.kind === "enum") {
+      const variant = def.type.variants[0].name;
+      return { [variant.charAt(0).toLowerCase() + variant.slice(1)]: {} };
+    }
+    return (def.type as any).fields.reduce((acc: any, field: any) => {
+      acc[field.name] = defaultValue(field.type);
+      return acc;
+    }, {});
+  };
+
+  // Write a program-owned account of the given type
+  const setAccount = async (
+    address: PublicKey,
+    accountName: string,
+    fields: Record<string, any>,
+    lamports = LAMPORTS_PER_SOL
+  ) => {
+    const idlAccount = program.idl.accounts!.find(
+      (a) => a.name.toLowerCase() === accountName.toLowerCase()
+    )!;
+    const data = await program.coder.accounts.encode(idlAccount.name, {
+      ...defaultValue({ defined: { name: idlAccount.name } }),
+      ...fields,
+    });
+    context.setAccount(address, {
+      lamports,
+      data,
+      owner: programId,
+      executable: false,
+    });
+  };
+
+  // Write an SPL token mint
+  const setMint = (address: PublicKey) => {
+    const data = Buffer.alloc(MINT_SIZE);
+    MintLayout.encode(
+      {
+        mintAuthorityOption: 0,
+        mintAuthority: PublicKey.default,
+        supply: BigInt(1_000_000_000),
+        decimals: 6,
+        isInitialized: true,
+        freezeAuthorityOption: 0,
+        freezeAuthority: PublicKey.default,
+      },
+      data
+    );
+    context.setAccount(address, {
+      lamports: LAMPORTS_PER_SOL,
+      data,
+      owner: TOKEN_PROGRAM_ID,
+      executable: false,
+    });
+  };
+
+  // Write an SPL token account holding `amount`
+  const setTokenAccount = (
+    address: PublicKey,
+    mint: PublicKey,
+    owner: PublicKey,
+    amount: number
+  ) => {
+    const data = Buffer.alloc(ACCOUNT_SIZE);
+    AccountLayout.encode(
+      {
+        mint,
+        owner,
+        amount: BigInt(amount),
+        delegateOption: 0,
+        delegate: PublicKey.default,
+        state: 1,
+        isNativeOption: 0,
+        isNative: BigInt(0),
+        delegatedAmount: BigInt(0),
+        closeAuthorityOption: 0,
+        closeAuthority: PublicKey.default,
+      },
+      data
+    );
+    context.setAccount(address, {
+      lamports: LAMPORTS_PER_SOL,
+      data,
+      owner: TOKEN_PROGRAM_ID,
+      executable: false,
+    });
+  };
+
+  // A funded system account that can sign
+  const fundedKeypair = () => {
+    const keypair = Keypair.generate();
+    context.setAccount(keypair.publicKey, {
+      lamports: LAMPORTS_PER_SOL,
+      data: Buffer.alloc(0),
+      owner: SystemProgram.programId,
+      executable: false,
+    });
+    return keypair;
+  };
+
+  const now = async () =>
+    Number((await context.banksClient.getClock()).unixTimestamp);
+
+  const warp = async (seconds: number) => {
+    const clock = await context.banksClient.getClock();
+    context.setClock(
+      new Clock(
+        clock.slot,
+        clock.epochStartTimestamp,
+        clock.epoch,
+        clock.leaderScheduleEpoch,
+        clock.unixTimestamp + BigInt(seconds)
+      )
+    );
+  };
+
+  const lamports = async (address: PublicKey) =>
+    Number((await context.banksClient.getBalance(address)).toString());
+
+  const expectError = async (tx: Promise<unknown>, code: string) => {
+    try {
+      await tx;
+      expect.fail(`Should have thrown ${code} error`);
+    } catch (error: any) {
+      expect(error.error?.errorCode?.code).to.equal(code);
+    }
+  };
+
+  before(async () => {
+    context = await start([{ name: "dusk_exchange", programId }], []);
+    provider = new BankrunProvider(context);
+    program = new Program<DuskExchange>(IDL as DuskExchange, provider);
+    payer = context.payer;
+
+    let marketBump: number;
+    [marketPda, marketBump] = PublicKey.findProgramAddressSync(
+      [Buffer.from("market"), MARKET_ID.toArrayLike(Buffer, "le", 8)],
+      programId
+    );
+    await setAccount(marketPda, "market", {
+      authority: payer.publicKey,
+      marketId: MARKET_ID,
+      status: { continuous: {} },
+      bump: marketBump,
+    });
+  });
+
+  describe("Settlement Closing", () => {
+    // Any address works; close_settlement does not re-derive the PDA
+    const settlementAt = async (fields: Record<string, any>) => {
+      const address = Keypair.generate().publicKey;
+      await setAccount(address, "tradeSettlement", {
+        market: marketPda,
+        payer: payer.publicKey,
+        ...fields,
+      });
+      return address;
+    };
+
+    const closeSettlement = (settlement: PublicKey, rentReceiver: PublicKey) =>
+      program.methods
+        .closeSettlement()
+        .accountsPartial({
+          caller: payer.publicKey,
+          market: marketPda,
+          settlement,
+          rentReceiver,
+          keeperPosition: null,
+        })
+        .rpc();
+
+    it("rejects closing an unsettled trade", async () => {
+      const settlement = await settlementAt({ settled: false });
+      await expectError(
+        closeSettlement(settlement, payer.publicKey),
+        "TradeNotSettled"
+      );
+    });
+
+    it("rejects closing before the retention period elapses", async () => {
+      const settlement = await settlementAt({
+        settled: true,
+        settledAt: new anchor.BN(await now()),
+      });
+      await expectError(
+        closeSettlement(settlement, payer.publicKey),
+        "RetentionPeriodActive"
+      );
+    });
+
+    it("rejects a rent receiver other than the payer", async () => {
+      const settlement = await settlementAt({
+        settled: true,
+        settledAt: new anchor.BN((await now()) - 8 * DAY),
+      });
+      await expectError(
+        closeSettlement(settlement, Keypair.generate().publicKey),
+        "Unauthorized"
+      );
+    });
+
+    it("refunds the payer once the retention period is over", async () => {
+      const rentPayer = Keypair.generate().publicKey;
+      const settlement = await settlementAt({
+        settled: true,
+        settledAt: new anchor.BN(await now()),
+        payer: rentPayer,
+      });
+
+      await warp(7 * DAY);
+      await closeSettlement(settlement, rentPayer);
+
+      expect(await context.banksClient.getAccount(settlement)).to.be.null;
+      expect(await lamports(rentPayer)).to.equal(LAMPORTS_PER_SOL);
+    });
+
+    it("refunds the payer after the settlement's market was closed", async () => {
+      // A delisted market can close while its settlements are still retained
+      const closedMarket = Keypair.generate().publicKey;
+      const rentPayer = Keypair.generate().publicKey;
+      const settlement = await settlementAt({
+        market: closedMarket,
+        settled: true,
+        settledAt: new anchor.BN((await now()) - 8 * DAY),
+        payer: rentPayer,
+      });
+
+      await program.methods
+        .closeSettlement()
+        .accountsPartial({
+          caller: payer.publicKey,
+          market: closedMarket,
+          settlement,
+          rentReceiver: rentPayer,
+          keeperPosition: null,
+        })
+        .rpc();
+
+      expect(await context.banksClient.getAccount(settlement)).to.be.null;
+      expect(await lamports(rentPayer)).to.equal(LAMPORTS_PER_SOL);
+    });
+
+    it("closes settlements in a batch", async () => {
+      const first = await settlementAt({
+        settled: true,
+        settledAt: new anchor.BN((await now()) - 8 * DAY),
+      });
+      const second = await settlementAt({
+        settled: true,
+        settledAt: new anchor.BN((await now()) - 8 * DAY),
+      });
+      const before = await lamports(payer.publicKey);
+
+      await program.methods
+        .closeSettlements()
+        .accountsPartial({ caller: payer.publicKey, market: marketPda, keeperPosition: null })
+        .remainingAccounts(
+          [first, second].flatMap((settlement) => [
+            { pubkey: settlement, isSigner: false, isWritable: true },
+            { pubkey: payer.publicKey, isSigner: false, isWritable: true },
+          ])
+        )
+        .rpc();
+
+      expect(await context.banksClient.getAccount(first)).to.be.null;
+      expect(await context.banksClient.getAccount(second)).to.be.null;
+      // Both rents come back, less the transaction fee
+      expect(await lamports(payer.publicKey)).to.be.greaterThan(
+        before + LAMPORTS_PER_SOL
+      );
+    });
+
+    it("rejects a batch that is not made of pairs", async () => {
+      const settlement = await settlementAt({
+        settled: true,
+        settledAt: new anchor.BN((await now()) - 8 * DAY),
+      });
+
+      await expectError(
+        program.methods
+          .closeSettlements()
+          .accountsPartial({ caller: payer.publicKey, market: marketPda, keeperPosition: null })
+          .remainingAccounts([
+            { pubkey: settlement, isSigner: false, isWritable: true },
+          ])
+          .rpc(),
+        "InvalidSettlementAccountList"
+      );
+    });
+
+    it("rejects a read-only settlement in a batch", async () => {
+      const settlement = await settlementAt({
+        settled: true,
+        settledAt: new anchor.BN((await now()) - 8 * DAY),
+      });
+
+      await expectError(
+        program.methods
+          .closeSettlements()
+          .accountsPartial({ caller: payer.publicKey, market: marketPda, keeperPosition: null })
+          .remainingAccounts([
+            { pubkey: settlement, isSigner: false, isWritable: false },
+            { pubkey: payer.publicKey, isSigner: false, isWritable: true },
+          ])
+          .rpc(),
+        "SettlementNotWritable"
+      );
+    });
+
+    it("pays the keeper reward for each expired settlement the caller closes", async () => {
+      const [keeperPosition, keeperBump] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_position"),
+          marketPda.toBuffer(),
+          payer.publicKey.toBuffer(),
+          Buffer.from([0, 0]),
+        ],
+        programId
+      );
+      await setAccount(keeperPosition, "userPosition", {
+        owner: payer.publicKey,
+        market: marketPda,
+        bump: keeperBump,
+      });
+      // 5 per crank out of 12 in fees
+      await setAccount(marketPda, "market", {
+        authority: payer.publicKey,
+        marketId: MARKET_ID,
+        status: { continuous: {} },
+        keeperReward: new anchor.BN(5),
+        feesAccrued: new anchor.BN(12),
+        bump: (await program.account.market.fetch(marketPda)).bump,
+      });
+      const settlements = [
+        await settlementAt({
+          settled: true,
+          settledAt: new anchor.BN((await now()) - 8 * DAY),
+        }),
+        await settlementAt({
+          settled: true,
+          settledAt: new anchor.BN((await now()) - 8 * DAY),
+        }),
+      ];
+
+      await program.methods
+        .closeSettlements()
+        .accountsPartial({ caller: payer.publicKey, market: marketPda, keeperPosition })
+        .remainingAccounts(
+          settlements.flatMap((settlement) => [
+            { pubkey: settlement, isSigner: false, isWritable: true },
+            { pubkey: payer.publicKey, isSigner: false, isWritable: true },
+          ])
+        )
+        .rpc();
+
+      const position = await program.account.userPosition.fetch(keeperPosition);
+      expect(position.quoteDeposited.toNumber()).to.equal(10);
+      const market = await program.account.market.fetch(marketPda);
+      expect(market.keeperRewardsPaid.toNumber()).to.equal(10);
+      expect(market.feesAccrued.toNumber()).to.equal(2);
+      expect(market.quoteDeposited.toNumber()).to.equal(10);
+
+      // A third close is paid only what is left of the fees
+      const third = await settlementAt({
+        settled: true,
+        settledAt: new anchor.BN((await now()) - 8 * DAY),
+      });
+      await program.methods
+        .closeSettlement()
+        .accountsPartial({
+          caller: payer.publicKey,
+          market: marketPda,
+          settlement: third,
+          rentReceiver: payer.publicKey,
+          keeperPosition,
+        })
+        .rpc();
+
+      const after = await program.account.market.fetch(marketPda);
+      expect(after.keeperRewardsPaid.toNumber()).to.equal(12);
+      expect(after.feesAccrued.toNumber()).to.equal(0);
+    });
+  });
+
+  describe("Filled Orders", () => {
+    const FILLED_MARKET_ID = new anchor.BN(8);
+    const PRICE = new anchor.BN(1_000_000); // 1.0, scaled by 10^6
+
+    let market: PublicKey;
+    let exchangeConfig: PublicKey;
+    const baseMint = Keypair.generate().publicKey;
+    const quoteMint = Keypair.generate().publicKey;
+    const baseVault = Keypair.generate().publicKey;
+    const quoteVault = Keypair.generate().publicKey;
+
+    const positionPda = (owner: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_position"),
+          market.toBuffer(),
+          owner.toBuffer(),
+          Buffer.from([0, 0]),
+        ],
+        programId
+      );
+
+    const orderPda = (position: PublicKey, orderId: anchor.BN) =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("order"),
+          position.toBuffer(),
+          orderId.toArrayLike(Buffer, "le", 8),
+        ],
+        programId
+      );
+
+    // A position with one order; `order` overrides the order's fields
+    const positionWithOrder = async (
+      position: Record<string, any>,
+      order: Record<string, any>
+    ) => {
+      const owner = fundedKeypair();
+      const [address, bump] = positionPda(owner.publicKey);
+      await setAccount(address, "userPosition", {
+        owner: owner.publicKey,
+        market,
+        activeOrderCount: 1,
+        bump,
+        ...position,
+      });
+      const orderId = new anchor.BN(1);
+      const [orderAddress, orderBump] = orderPda(address, orderId);
+      await setAccount(orderAddress, "openOrder", {
+        market,
+        position: address,
+        orderId,
+        status: { resting: {} },
+        bump: orderBump,
+        ...order,
+      });
+      return { owner, position: address, order: orderAddress, orderId };
+    };
+
+    // Market holding exactly what the given positions deposited and locked
+    const setMarket = async (fields: Record<string, any>) => {
+      const [address, bump] = PublicKey.findProgramAddressSync(
+        [Buffer.from("market"), FILLED_MARKET_ID.toArrayLike(Buffer, "le", 8)],
+        programId
+      );
+      await setAccount(address, "market", {
+        authority: payer.publicKey,
+        marketId: FILLED_MARKET_ID,
+        baseMint,
+        quoteMint,
+        baseVault,
+        quoteVault,
+        baseTokenProgram: TOKEN_PROGRAM_ID,
+        quoteTokenProgram: TOKEN_PROGRAM_ID,
+        status: { continuous: {} },
+        bump,
+        ...fields,
+      });
+    };
+
+    before(async () => {
+      [market] = PublicKey.findProgramAddressSync(
+        [Buffer.from("market"), FILLED_MARKET_ID.toArrayLike(Buffer, "le", 8)],
+        programId
+      );
+      let configBump: number;
+      [exchangeConfig, configBump] = PublicKey.findProgramAddressSync(
+        [Buffer.from("exchange_config")],
+        programId
+      );
+      await setAccount(exchangeConfig, "exchangeConfig", {
+        admin: payer.publicKey,
+        bump: configBump,
+      });
+      setMint(baseMint);
+      setMint(quoteMint);
+    });
+
+    // Seller sells 10 base to a buyer that locked 12 quote at a limit of 1.2;
+    // the fill clears at 1.0, so 2 quote of the buyer's lock is left over
+    const fillBetween = async (sellerOpenFills: number) => {
+      const seller = await positionWithOrder(
+        { baseDeposited: new anchor.BN(10), baseLocked: new anchor.BN(10) },
+        {
+          isBuy: false,
+          lockedAmount: new anchor.BN(10),
+          status: { filled: {} },
+          openFills: sellerOpenFills,
+        }
+      );
+      const buyer = await positionWithOrder(
+        { quoteDeposited: new anchor.BN(12), quoteLocked: new anchor.BN(12) },
+        {
+          isBuy: true,
+          lockedAmount: new anchor.BN(12),
+          status: { filled: {} },
+          openFills: 1,
+        }
+      );
+      await setMarket({
+        baseDeposited: new anchor.BN(10),
+        quoteDeposited: new anchor.BN(12),
+        baseLocked: new anchor.BN(10),
+        quoteLocked: new anchor.BN(12),
+        openSettlements: 1,
+      });
+      setTokenAccount(baseVault, baseMint, market, 10);
+      setTokenAccount(quoteVault, quoteMint, market, 12);
+
+      const settlement = Keypair.generate().publicKey;
+      await setAccount(settlement, "tradeSettlement", {
+        market,
+        maker: seller.position,
+        taker: buyer.position,
+        makerOrderId: seller.orderId,
+        takerOrderId: buyer.orderId,
+        executionPrice: PRICE,
+        executionAmount: new anchor.BN(10),
+        payer: payer.publicKey,
+      });
+
+      await program.methods
+        .settleTrade()
+        .accountsPartial({
+          caller: payer.publicKey,
+          market,
+          exchangeConfig,
+          settlement,
+          makerPosition: seller.position,
+          takerPosition: buyer.position,
+          makerOrder: seller.order,
+          takerOrder: buyer.order,
+          makerOwner: seller.owner.publicKey,
+          takerOwner: buyer.owner.publicKey,
+          keeperPosition: null,
+          baseVault,
+          quoteVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      return { seller, buyer };
+    };
+
+    // Same fill against a side-hidden seller that locked 20 base and 12 quote;
+    // the fill reveals it as the ask
+    const hiddenFill = async (sellerStatus: Record<string, any>) => {
+      const seller = await positionWithOrder(
+        {
+          baseDeposited: new anchor.BN(20),
+          baseLocked: new anchor.BN(20),
+          quoteDeposited: new anchor.BN(12),
+          quoteLocked: new anchor.BN(12),
+        },
+        {
+          sideHidden: true,
+          lockedBase: new anchor.BN(20),
+          lockedAmount: new anchor.BN(12),
+          status: sellerStatus,
+          openFills: 1,
+        }
+      );
+      const buyer = await positionWithOrder(
+        { quoteDeposited: new anchor.BN(12), quoteLocked: new anchor.BN(12) },
+        {
+          isBuy: true,
+          lockedAmount: new anchor.BN(12),
+          status: { filled: {} },
+          openFills: 1,
+        }
+      );
+      // Filled orders were already taken off the counters by create_settlement
+      const sellerRests = "resting" in sellerStatus;
+      await setMarket({
+        baseDeposited: new anchor.BN(20),
+        quoteDeposited: new anchor.BN(24),
+        baseLocked: new anchor.BN(20),
+        quoteLocked: new anchor.BN(24),
+        activeHidden: sellerRests ? 1 : 0,
+        openSettlements: 1,
+      });
+      setTokenAccount(baseVault, baseMint, market, 20);
+      setTokenAccount(quoteVault, quoteMint, market, 24);
+
+      const settlement = Keypair.generate().publicKey;
+      await setAccount(settlement, "tradeSettlement", {
+        market,
+        maker: seller.position,
+        taker: buyer.position,
+        makerOrderId: seller.orderId,
+        takerOrderId: buyer.orderId,
+        executionPrice: PRICE,
+        executionAmount: new anchor.BN(10),
+        payer: payer.publicKey,
+      });
+
+      await program.methods
+        .settleTrade()
+        .accountsPartial({
+          caller: payer.publicKey,
+          market,
+          exchangeConfig,
+          settlement,
+          makerPosition: seller.position,
+          takerPosition: buyer.position,
+          makerOrder: seller.order,
+          takerOrder: buyer.order,
+          makerOwner: seller.owner.publicKey,
+          takerOwner: buyer.owner.publicKey,
+          keeperPosition: null,
+          baseVault,
+          quoteVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      return { seller, buyer };
+    };
+
+    const createSettlement = async (
+      maker: any,
+      taker: any,
+      keeperPosition: PublicKey | null = null
+    ) => {
+      const marketAccount = await program.account.market.fetch(market);
+      const [settlement] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("settlement"),
+          market.toBuffer(),
+          marketAccount.settlementCount.addn(1).toArrayLike(Buffer, "le", 8),
+        ],
+        programId
+      );
+      await program.methods
+        .createSettlement()
+        .accountsPartial({
+          payer: payer.publicKey,
+          market,
+          settlement,
+          makerOrder: maker.order,
+          takerOrder: taker.order,
+          keeperPosition,
+        })
+        .rpc();
+    };
+
+    it("counts a new settlement on both orders and marks the filled one", async () => {
+      const maker = await positionWithOrder({}, { isBuy: false });
+      const taker = await positionWithOrder({}, { isBuy: true });
+      await setMarket({
+        hasPendingMatch: true,
+        pendingMaker: maker.position,
+        pendingTaker: taker.position,
+        pendingMakerOrderId: maker.orderId,
+        pendingTakerOrderId: taker.orderId,
+        pendingExecutionPrice: PRICE,
+        pendingExecutionAmount: new anchor.BN(10),
+        pendingMakerFilled: true,
+        pendingTakerFilled: false,
+      });
+
+      const [settlement] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("settlement"),
+          market.toBuffer(),
+          new anchor.BN(1).toArrayLike(Buffer, "le", 8),
+        ],
+        programId
+      );
+      await program.methods
+        .createSettlement()
+        .accountsPartial({
+          payer: payer.publicKey,
+          market,
+          settlement,
+          makerOrder: maker.order,
+          takerOrder: taker.order,
+          keeperPosition: null,
+        })
+        .rpc();
+
+      const makerOrder = await program.account.openOrder.fetch(maker.order);
+      const takerOrder = await program.account.openOrder.fetch(taker.order);
+      expect(makerOrder.status).to.deep.equal({ filled: {} });
+      expect(makerOrder.openFills).to.equal(1);
+      expect(takerOrder.status).to.deep.equal({ resting: {} });
+      expect(takerOrder.openFills).to.equal(1);
+    });
+
+    it("takes only the orders a match filled off the market's counters", async () => {
+      const maker = await positionWithOrder({}, { isBuy: false });
+      const taker = await positionWithOrder({}, { isBuy: true });
+      await setMarket({
+        activeBids: 1,
+        activeAsks: 1,
+        // Settlement 1 was created by the previous test
+        settlementCount: new anchor.BN(1),
+        hasPendingMatch: true,
+        pendingMaker: maker.position,
+        pendingTaker: taker.position,
+        pendingMakerOrderId: maker.orderId,
+        pendingTakerOrderId: taker.orderId,
+        pendingExecutionPrice: PRICE,
+        pendingExecutionAmount: new anchor.BN(10),
+        pendingMakerFilled: true,
+        pendingTakerFilled: false,
+      });
+
+      await createSettlement(maker, taker);
+
+      // The partly filled bid still rests in the book
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.activeBids).to.equal(1);
+      expect(marketAccount.activeAsks).to.equal(0);
+    });
+
+    it("takes a filled side-hidden order off the hidden counter", async () => {
+      const maker = await positionWithOrder({}, { sideHidden: true });
+      const taker = await positionWithOrder({}, { isBuy: true });
+      await setMarket({
+        activeBids: 1,
+        activeHidden: 1,
+        settlementCount: new anchor.BN(2),
+        hasPendingMatch: true,
+        pendingMaker: maker.position,
+        pendingTaker: taker.position,
+        pendingMakerOrderId: maker.orderId,
+        pendingTakerOrderId: taker.orderId,
+        pendingExecutionPrice: PRICE,
+        pendingExecutionAmount: new anchor.BN(10),
+        pendingMakerFilled: true,
+        pendingTakerFilled: true,
+      });
+
+      await createSettlement(maker, taker);
+
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.activeBids).to.equal(0);
+      expect(marketAccount.activeAsks).to.equal(0);
+      expect(marketAccount.activeHidden).to.equal(0);
+    });
+
+    it("credits the keeper reward to the position of the settlement's creator", async () => {
+      const maker = await positionWithOrder({}, { isBuy: false });
+      const taker = await positionWithOrder({}, { isBuy: true });
+      const [keeperPosition, keeperBump] = positionPda(payer.publicKey);
+      await setAccount(keeperPosition, "userPosition", {
+        owner: payer.publicKey,
+        market,
+        bump: keeperBump,
+      });
+      await setMarket({
+        keeperReward: new anchor.BN(3),
+        feesAccrued: new anchor.BN(5),
+        settlementCount: new anchor.BN(3),
+        hasPendingMatch: true,
+        pendingMaker: maker.position,
+        pendingTaker: taker.position,
+        pendingMakerOrderId: maker.orderId,
+        pendingTakerOrderId: taker.orderId,
+        pendingExecutionPrice: PRICE,
+        pendingExecutionAmount: new anchor.BN(10),
+      });
+
+      await createSettlement(maker, taker, keeperPosition);
+
+      const position = await program.account.userPosition.fetch(keeperPosition);
+      expect(position.quoteDeposited.toNumber()).to.equal(3);
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.keeperRewardsPaid.toNumber()).to.equal(3);
+      expect(marketAccount.feesAccrued.toNumber()).to.equal(2);
+      expect(marketAccount.quoteDeposited.toNumber()).to.equal(3);
+    });
+
+    it("counts a resting side-hidden order on the side its fill revealed", async () => {
+      const { seller } = await hiddenFill({ resting: {} });
+
+      const order = await program.account.openOrder.fetch(seller.order);
+      expect(order.sideHidden).to.be.false;
+      expect(order.isBuy).to.be.false;
+      expect(order.lockedAmount.toNumber()).to.equal(10);
+
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.activeHidden).to.equal(0);
+      expect(marketAccount.activeAsks).to.equal(1);
+      expect(marketAccount.activeBids).to.equal(0);
+    });
+
+    it("does not count a filled side-hidden order again when its fill settles", async () => {
+      const { seller } = await hiddenFill({ filled: {} });
+
+      expect(await context.banksClient.getAccount(seller.order)).to.be.null;
+
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.activeHidden).to.equal(0);
+      expect(marketAccount.activeAsks).to.equal(0);
+      expect(marketAccount.activeBids).to.equal(0);
+    });
+
+    it("closes filled orders once their last fill settles", async () => {
+      const { seller, buyer } = await fillBetween(1);
+
+      expect(await context.banksClient.getAccount(seller.order)).to.be.null;
+      expect(await context.banksClient.getAccount(buyer.order)).to.be.null;
+
+      // The price improvement left in the buyer's lock is released
+      const position = await program.account.userPosition.fetch(buyer.position);
+      expect(position.activeOrderCount).to.equal(0);
+      expect(position.quoteLocked.toNumber()).to.equal(0);
+      expect(position.quoteDeposited.toNumber()).to.equal(2);
+      expect(position.baseDeposited.toNumber()).to.equal(10);
+
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.baseLocked.toNumber()).to.equal(0);
+      expect(marketAccount.quoteLocked.toNumber()).to.equal(0);
+    });
+
+    it("lets a position close after its order was filled", async () => {
+      const { buyer } = await fillBetween(1);
+      const owner = buyer.owner;
+
+      const baseAccount = Keypair.generate().publicKey;
+      const quoteAccount = Keypair.generate().publicKey;
+      setTokenAccount(baseAccount, baseMint, owner.publicKey, 0);
+      setTokenAccount(quoteAccount, quoteMint, owner.publicKey, 0);
+
+      for (const [amount, isBase, mint, userTokenAccount, vault] of [
+        [10, true, baseMint, baseAccount, baseVault],
+        [2, false, quoteMint, quoteAccount, quoteVault],
+      ] as const) {
+        await program.methods
+          .withdraw(new anchor.BN(amount), isBase)
+          .accountsPartial({
+            user: owner.publicKey,
+            market,
+            userPosition: buyer.position,
+            mint,
+            userTokenAccount,
+            vault,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([owner])
+          .rpc();
+      }
+
+      await program.methods
+        .closePosition()
+        .accountsPartial({
+          user: owner.publicKey,
+          market,
+          userPosition: buyer.position,
+        })
+        .signers([owner])
+        .rpc();
+
+      expect(await context.banksClient.getAccount(buyer.position)).to.be.null;
+    });
+
+    it("keeps a filled order open while another of its fills is unsettled", async () => {
+      const { seller } = await fillBetween(2);
+
+      const order = await program.account.openOrder.fetch(seller.order);
+      expect(order.status).to.deep.equal({ filled: {} });
+      expect(order.openFills).to.equal(1);
+
+      const position = await program.account.userPosition.fetch(seller.position);
+      expect(position.activeOrderCount).to.equal(1);
+    });
+  });
+
+  describe("Stuck Computations", () => {
+    const STUCK_MARKET_ID = new anchor.BN(9);
+    const TIMEOUT = 10 * 60;
+
+    let market: PublicKey;
+
+    // A position whose one buy order locked 12 quote; `order` overrides the order's fields
+    const bidWithStatus = async (order: Record<string, any>) => {
+      const owner = fundedKeypair();
+      const [position, bump] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_position"),
+          market.toBuffer(),
+          owner.publicKey.toBuffer(),
+          Buffer.from([0, 0]),
+        ],
+        programId
+      );
+      await setAccount(position, "userPosition", {
+        owner: owner.publicKey,
+        market,
+        quoteDeposited: new anchor.BN(12),
+        quoteLocked: new anchor.BN(12),
+        activeOrderCount: 1,
+        bump,
+      });
+      const orderId = new anchor.BN(1);
+      const [openOrder, orderBump] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("order"),
+          position.toBuffer(),
+          orderId.toArrayLike(Buffer, "le", 8),
+        ],
+        programId
+      );
+      await setAccount(openOrder, "openOrder", {
+        market,
+        position,
+        orderId,
+        isBuy: true,
+        lockedAmount: new anchor.BN(12),
+        bump: orderBump,
+        ...order,
+      });
+      return { owner, position, openOrder, orderId };
+    };
+
+    const reclaim = (bid: Awaited<ReturnType<typeof bidWithStatus>>) =>
+      program.methods
+        .reclaimStuckComputation(bid.orderId)
+        .accountsPartial({
+          user: bid.owner.publicKey,
+          market,
+          userPosition: bid.position,
+          openOrder: bid.openOrder,
+        })
+        .signers([bid.owner])
+        .rpc();
+
+    beforeEach(async () => {
+      let bump: number;
+      [market, bump] = PublicKey.findProgramAddressSync(
+        [Buffer.from("market"), STUCK_MARKET_ID.toArrayLike(Buffer, "le", 8)],
+        programId
+      );
+      await setAccount(market, "market", {
+        authority: payer.publicKey,
+        marketId: STUCK_MARKET_ID,
+        quoteDeposited: new anchor.BN(12),
+        quoteLocked: new anchor.BN(12),
+        activeBids: 1,
+        status: { continuous: {} },
+        bump,
+      });
+    });
+
+    it("rejects reclaiming an order resting in the book", async () => {
+      const bid = await bidWithStatus({ status: { resting: {} } });
+      await expectError(reclaim(bid), "ComputationNotStuck");
+    });
+
+    it("rejects reclaiming a pending add before its deadline", async () => {
+      const bid = await bidWithStatus({
+        status: { pendingAdd: {} },
+        deadline: new anchor.BN((await now()) + TIMEOUT),
+      });
+      await expectError(reclaim(bid), "ComputationNotStuck");
+    });
+
+    it("releases a stuck add's lock and closes the order to its owner", async () => {
+      const bid = await bidWithStatus({
+        status: { pendingAdd: {} },
+        deadline: new anchor.BN((await now()) + TIMEOUT),
+      });
+      await warp(TIMEOUT);
+      const orderRent = await lamports(bid.openOrder);
+      const ownerBefore = await lamports(bid.owner.publicKey);
+
+      await reclaim(bid);
+
+      expect(await context.banksClient.getAccount(bid.openOrder)).to.be.null;
+      // The payer covers the fee, so the owner gets exactly the order's rent
+      expect(await lamports(bid.owner.publicKey)).to.equal(ownerBefore + orderRent);
+      const position = await program.account.userPosition.fetch(bid.position);
+      expect(position.quoteLocked.toNumber()).to.equal(0);
+      expect(position.activeOrderCount).to.equal(0);
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.quoteLocked.toNumber()).to.equal(0);
+      expect(marketAccount.activeBids).to.equal(0);
+
+      // The order is gone, so its lock cannot be released twice
+      await expectError(reclaim(bid), "AccountNotInitialized");
+    });
+
+    it("reopens a stuck cancel and keeps the order's lock", async () => {
+      const bid = await bidWithStatus({
+        status: { pendingCancel: {} },
+        deadline: new anchor.BN((await now()) + TIMEOUT),
+      });
+      await warp(TIMEOUT);
+
+      await reclaim(bid);
+
+      // The order is still in the book, so it stays live for another cancel
+      const order = await program.account.openOrder.fetch(bid.openOrder);
+      expect(order.status).to.deep.equal({ resting: {} });
+      expect(order.deadline.toNumber()).to.equal(0);
+      expect(order.lockedAmount.toNumber()).to.equal(12);
+      const position = await program.account.userPosition.fetch(bid.position);
+      expect(position.quoteLocked.toNumber()).to.equal(12);
+      expect(position.activeOrderCount).to.equal(1);
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.quoteLocked.toNumber()).to.equal(12);
+      expect(marketAccount.activeBids).to.equal(1);
+    });
+  });
+});
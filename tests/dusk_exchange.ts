@@ -5,16 +5,52 @@ import {
   PublicKey,
   SystemProgram,
   LAMPORTS_PER_SOL,
+  Transaction,
+  sendAndConfirmTransaction,
 } from "@solana/web3.js";
 import {
   TOKEN_PROGRAM_ID,
+  TOKEN_2022_PROGRAM_ID,
+  NATIVE_MINT,
+  ExtensionType,
+  getMintLen,
+  createInitializeMintInstruction,
+  createInitializeTransferFeeConfigInstruction,
+  createInitializePermanentDelegateInstruction,
+  createInitializeNonTransferableMintInstruction,
   createMint,
   createAccount,
   mintTo,
   getAccount,
+  getAssociatedTokenAddressSync,
+  getMint,
+  getTransferFeeConfig,
+  getTransferFeeAmount,
 } from "@solana/spl-token";
 import { expect } from "chai";
+import { createHash, randomBytes } from "crypto";
+import * as fs from "fs";
+import {
+  x25519,
+  RescueCipher,
+  deserializeLE,
+  getArciumEnv,
+  getMXEAccAddress,
+  getMempoolAccAddress,
+  getExecutingPoolAccAddress,
+  getComputationAccAddress,
+  getCompDefAccAddress,
+  getCompDefAccOffset,
+  getClusterAccAddress,
+  getFeePoolAccAddress,
+  getClockAccAddress,
+  getMXEPublicKey,
+  uploadCircuit,
+  awaitComputationFinalization,
+} from "@arcium-hq/client";
 import { DuskExchange } from "../target/types/dusk_exchange";
+import { AddOrder } from "../build/add_order";
+import { RemoveOrder } from "../build/remove_order";
 
 describe("dusk_exchange", () => {
   // Configure the client to use the local cluster
@@ -43,13 +79,312 @@ describe("dusk_exchange", () => {
   let marketBump: number;
   let baseVaultPda: PublicKey;
   let quoteVaultPda: PublicKey;
+  let exchangeConfigPda: PublicKey;
 
   const MARKET_ID = new anchor.BN(1);
   const FEE_RATE_BPS = 30; // 0.3%
+  const SUB_ACCOUNT_ID = new anchor.BN(0);
+  const PROTOCOL_FEE_SHARE_BPS = 2000; // 20% of trading fees
+
+  const orderBookPda = (market: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("orderbook"), market.toBuffer()],
+      program.programId
+    )[0];
+
+  const registryEntryPda = (base: PublicKey, quote: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("market_registry"), base.toBuffer(), quote.toBuffer()],
+      program.programId
+    )[0];
+
+  // Offset of a market's `index`-th computation, as Market::computation_offset derives it
+  const computationOffset = (market: PublicKey, index: number) =>
+    new anchor.BN(
+      createHash("sha256")
+        .update(market.toBuffer())
+        .update(new anchor.BN(index).toArrayLike(Buffer, "le", 8))
+        .digest()
+        .subarray(0, 8),
+      "le"
+    );
+
+  const compDefAccount = (circuit: string) =>
+    getCompDefAccAddress(
+      program.programId,
+      Buffer.from(getCompDefAccOffset(circuit)).readUInt32LE()
+    );
+
+  // Arcium accounts of a queued computation on the local cluster
+  const arciumAccounts = (offset: anchor.BN, circuit: string) => {
+    const { arciumClusterOffset } = getArciumEnv();
+    return {
+      mxeAccount: getMXEAccAddress(program.programId),
+      mempoolAccount: getMempoolAccAddress(arciumClusterOffset),
+      executingPool: getExecutingPoolAccAddress(arciumClusterOffset),
+      computationAccount: getComputationAccAddress(arciumClusterOffset, offset),
+      compDefAccount: compDefAccount(circuit),
+      clusterAccount: getClusterAccAddress(arciumClusterOffset),
+      poolAccount: getFeePoolAccAddress(),
+      clockAccount: getClockAccAddress(),
+    };
+  };
 
   // Price scale: 10^6 (so $100 = 100_000_000)
   const PRICE_SCALE = 1_000_000;
 
+  const positionPda = (market: PublicKey, owner: PublicKey, subAccountId = 0) =>
+    PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("user_position"),
+        market.toBuffer(),
+        owner.toBuffer(),
+        new anchor.BN(subAccountId).toArrayLike(Buffer, "le", 2),
+      ],
+      program.programId
+    )[0];
+
+  const settlementPda = (market: PublicKey, settlementId: number) =>
+    PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("settlement"),
+        market.toBuffer(),
+        new anchor.BN(settlementId).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    )[0];
+
+  const openOrderPda = (position: PublicKey, orderId: number) =>
+    PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("order"),
+        position.toBuffer(),
+        new anchor.BN(orderId).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    )[0];
+
+  const disclosureRecordPda = (position: PublicKey, offset: anchor.BN) =>
+    PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("disclosure"),
+        position.toBuffer(),
+        offset.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    )[0];
+
+  // Offset of the next computation a market will accept
+  const nextOffset = async (market: PublicKey) => {
+    const marketAccount = await program.account.market.fetch(market);
+    return computationOffset(market, marketAccount.computationCount.toNumber() + 1);
+  };
+
+  // Resolve with the next program event of the given name
+  const awaitEvent = async <E extends keyof anchor.IdlEvents<DuskExchange>>(
+    eventName: E
+  ): Promise<anchor.IdlEvents<DuskExchange>[E]> => {
+    let listenerId: number;
+    const event = await new Promise<anchor.IdlEvents<DuskExchange>[E]>((res) => {
+      listenerId = program.addEventListener(eventName, (event) => {
+        res(event);
+      });
+    });
+    await program.removeEventListener(listenerId);
+    return event;
+  };
+
+  // Computation definitions registered so far in this run
+  const registeredCircuits = new Set<string>();
+
+  // Register a circuit's computation definition and upload the compiled circuit
+  const registerCircuit = async (circuit: string) => {
+    if (registeredCircuits.has(circuit)) return;
+    const pascal = circuit
+      .split("_")
+      .map((word) => word[0].toUpperCase() + word.slice(1))
+      .join("");
+    await (program.methods as any)
+      [`init${pascal}CompDef`]()
+      .accountsPartial({
+        payer: provider.wallet.publicKey,
+        mxeAccount: getMXEAccAddress(program.programId),
+        compDefAccount: compDefAccount(circuit),
+      })
+      .rpc();
+    await uploadCircuit(
+      provider,
+      circuit,
+      program.programId,
+      fs.readFileSync(`build/${circuit}.arcis`),
+      true
+    );
+    registeredCircuits.add(circuit);
+  };
+
+  // Encrypt an OrderInput under a fresh x25519 key shared with the MXE
+  const encryptOrder = async (price: bigint, amount: bigint) => {
+    const mxePublicKey = await getMXEPublicKey(provider, program.programId);
+    const privateKey = x25519.utils.randomPrivateKey();
+    const nonce = randomBytes(16);
+    const cipher = new RescueCipher(x25519.getSharedSecret(privateKey, mxePublicKey));
+    const [encryptedPrice, encryptedAmount] = cipher.encrypt([price, amount], nonce);
+    return {
+      publicKey: x25519.getPublicKey(privateKey),
+      nonce,
+      cipher,
+      encryptedPrice,
+      encryptedAmount,
+    };
+  };
+
+  // A fresh market over a new base mint, where user1 holds quote and user2 base
+  const createTradingMarket = async (marketId: number) => {
+    const id = new anchor.BN(marketId);
+    const tradingBaseMint = await createMint(
+      provider.connection,
+      authority,
+      authority.publicKey,
+      null,
+      9
+    );
+    const [market] = PublicKey.findProgramAddressSync(
+      [Buffer.from("market"), id.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [baseVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("base_vault"), market.toBuffer()],
+      program.programId
+    );
+    const [quoteVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("quote_vault"), market.toBuffer()],
+      program.programId
+    );
+    const user1Base = await createAccount(
+      provider.connection,
+      user1,
+      tradingBaseMint,
+      user1.publicKey
+    );
+    const user2Base = await createAccount(
+      provider.connection,
+      user2,
+      tradingBaseMint,
+      user2.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      authority,
+      tradingBaseMint,
+      user2Base,
+      authority,
+      10 * 10 ** 9
+    );
+    await mintTo(
+      provider.connection,
+      authority,
+      quoteMint,
+      user1QuoteAccount,
+      authority,
+      1_000 * 10 ** 6
+    );
+
+    await program.methods
+      .initializeMarket(computationOffset(market, 1), id, FEE_RATE_BPS)
+      .accountsPartial({
+        authority: authority.publicKey,
+        market,
+        orderBook: orderBookPda(market),
+        exchangeConfig: exchangeConfigPda,
+        registryEntry: registryEntryPda(tradingBaseMint, quoteMint),
+        baseMint: tradingBaseMint,
+        quoteMint,
+        baseVault,
+        quoteVault,
+        baseTokenProgram: TOKEN_PROGRAM_ID,
+        quoteTokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        ...arciumAccounts(computationOffset(market, 1), "init_book"),
+      })
+      .signers([authority])
+      .rpc();
+    await awaitComputationFinalization(
+      provider,
+      computationOffset(market, 1),
+      program.programId,
+      "confirmed"
+    );
+
+    // 1,000 USDC for user1 to bid with and 10 SOL for user2 to offer
+    const deposits: [Keypair, PublicKey, PublicKey, PublicKey, number, boolean][] = [
+      [user1, quoteMint, user1QuoteAccount, quoteVault, 1_000 * 10 ** 6, false],
+      [user2, tradingBaseMint, user2Base, baseVault, 10 * 10 ** 9, true],
+    ];
+    for (const [user, mint, userTokenAccount, vault, amount, isBase] of deposits) {
+      await program.methods
+        .deposit(new anchor.BN(amount), isBase, 0)
+        .accounts({
+          user: user.publicKey,
+          market,
+          userPosition: positionPda(market, user.publicKey),
+          mint,
+          userTokenAccount,
+          vault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+    }
+
+    return { market, baseMint: tradingBaseMint, baseVault, quoteVault, user1Base, user2Base };
+  };
+
+  // Place an order and wait until the cluster has added it to the book
+  const placeOrder = async (
+    market: PublicKey,
+    user: Keypair,
+    orderId: number,
+    isBuy: boolean,
+    price: number,
+    amount: number,
+    lockAmount: number
+  ) => {
+    const position = positionPda(market, user.publicKey);
+    const order = await encryptOrder(BigInt(price), BigInt(amount));
+    const offset = await nextOffset(market);
+
+    await program.methods
+      .placeOrder(
+        offset,
+        new anchor.BN(orderId),
+        isBuy,
+        Buffer.from(order.encryptedPrice),
+        Buffer.from(order.encryptedAmount),
+        Array.from(order.publicKey),
+        new anchor.BN(deserializeLE(order.nonce).toString()),
+        new anchor.BN(lockAmount)
+      )
+      .accountsPartial({
+        user: user.publicKey,
+        market,
+        orderBook: orderBookPda(market),
+        userPosition: position,
+        openOrder: openOrderPda(position, orderId),
+        ...arciumAccounts(offset, "add_order"),
+      })
+      .signers([user])
+      .rpc();
+    await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+
+    // The cipher decrypts whatever the cluster later encrypts to the order's key
+    return { openOrder: openOrderPda(position, orderId), cipher: order.cipher };
+  };
+
+  // Market 5, which the opening auction tests trade on and later tests settle
+  let auctionMarket: Awaited<ReturnType<typeof createTradingMarket>>;
+
   before(async () => {
     // Generate keypairs
     authority = Keypair.generate();
@@ -169,22 +504,86 @@ describe("dusk_exchange", () => {
       [Buffer.from("quote_vault"), marketPda.toBuffer()],
       program.programId
     );
+
+    [exchangeConfigPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("exchange_config")],
+      program.programId
+    );
+
+    // initialize_market queues init_book, so its circuit must be registered
+    await registerCircuit("init_book");
+  });
+
+  describe("Exchange Config", () => {
+    // Tests deploy the program as upgradeable, with the provider wallet as
+    // its upgrade authority
+    const programDataPda = () =>
+      PublicKey.findProgramAddressSync(
+        [program.programId.toBuffer()],
+        new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+      )[0];
+
+    it("rejects initialization by anyone but the upgrade authority", async () => {
+      try {
+        await program.methods
+          .initializeExchange(false, PROTOCOL_FEE_SHARE_BPS)
+          .accounts({
+            admin: authority.publicKey,
+            upgradeAuthority: authority.publicKey,
+            program: program.programId,
+            programData: programDataPda(),
+            exchangeConfig: exchangeConfigPda,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([authority])
+          .rpc();
+        expect.fail("Should have thrown Unauthorized error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("Unauthorized");
+      }
+    });
+
+    it("initializes the exchange with admin-only market creation", async () => {
+      await program.methods
+        .initializeExchange(false, PROTOCOL_FEE_SHARE_BPS)
+        .accounts({
+          admin: authority.publicKey,
+          upgradeAuthority: provider.wallet.publicKey,
+          program: program.programId,
+          programData: programDataPda(),
+          exchangeConfig: exchangeConfigPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([authority])
+        .rpc();
+
+      const config = await program.account.exchangeConfig.fetch(exchangeConfigPda);
+      expect(config.admin.toString()).to.equal(authority.publicKey.toString());
+      expect(config.permissionlessMarkets).to.be.false;
+      expect(config.protocolFeeShareBps).to.equal(PROTOCOL_FEE_SHARE_BPS);
+      expect(config.marketCount.toNumber()).to.equal(0);
+    });
   });
 
   describe("Market Initialization", () => {
     it("initializes a new market", async () => {
       const tx = await program.methods
-        .initializeMarket(MARKET_ID, FEE_RATE_BPS)
-        .accounts({
+        .initializeMarket(computationOffset(marketPda, 1), MARKET_ID, FEE_RATE_BPS)
+        .accountsPartial({
           authority: authority.publicKey,
           market: marketPda,
+          orderBook: orderBookPda(marketPda),
+          exchangeConfig: exchangeConfigPda,
+          registryEntry: registryEntryPda(baseMint, quoteMint),
           baseMint,
           quoteMint,
           baseVault: baseVaultPda,
           quoteVault: quoteVaultPda,
-          tokenProgram: TOKEN_PROGRAM_ID,
+          baseTokenProgram: TOKEN_PROGRAM_ID,
+          quoteTokenProgram: TOKEN_PROGRAM_ID,
           systemProgram: SystemProgram.programId,
           rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          ...arciumAccounts(computationOffset(marketPda, 1), "init_book"),
         })
         .signers([authority])
         .rpc();
@@ -201,6 +600,179 @@ describe("dusk_exchange", () => {
       expect(marketAccount.marketId.toNumber()).to.equal(MARKET_ID.toNumber());
       expect(marketAccount.feeRateBps).to.equal(FEE_RATE_BPS);
       expect(marketAccount.orderCount.toNumber()).to.equal(0);
+      expect(marketAccount.status).to.deep.equal({ auction: {} });
+      expect(marketAccount.orderbookRef.toString()).to.equal(
+        orderBookPda(marketPda).toString()
+      );
+
+      // The empty encrypted state is written by the init_book callback
+      await awaitComputationFinalization(
+        provider,
+        computationOffset(marketPda, 1),
+        program.programId,
+        "confirmed"
+      );
+      const orderBook = await program.account.orderBook.fetch(orderBookPda(marketPda));
+      expect(orderBook.market.toString()).to.equal(marketPda.toString());
+      expect(orderBook.initialized).to.be.true;
+      expect(orderBook.pendingUntil.toNumber()).to.equal(0);
+      expect(marketAccount.computationCount.toNumber()).to.equal(1);
+
+      const entry = await program.account.marketRegistryEntry.fetch(
+        registryEntryPda(baseMint, quoteMint)
+      );
+      expect(entry.market.toString()).to.equal(marketPda.toString());
+
+      const config = await program.account.exchangeConfig.fetch(exchangeConfigPda);
+      expect(config.marketCount.toNumber()).to.equal(1);
+    });
+
+    it("rejects a second market for the same mint pair", async () => {
+      const duplicateId = new anchor.BN(99);
+      const [duplicateMarketPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("market"), duplicateId.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      );
+
+      try {
+        await program.methods
+          .initializeMarket(computationOffset(duplicateMarketPda, 1), duplicateId, FEE_RATE_BPS)
+          .accountsPartial({
+            authority: authority.publicKey,
+            market: duplicateMarketPda,
+            orderBook: orderBookPda(duplicateMarketPda),
+            exchangeConfig: exchangeConfigPda,
+            registryEntry: registryEntryPda(baseMint, quoteMint),
+            baseMint,
+            quoteMint,
+            baseVault: PublicKey.findProgramAddressSync(
+              [Buffer.from("base_vault"), duplicateMarketPda.toBuffer()],
+              program.programId
+            )[0],
+            quoteVault: PublicKey.findProgramAddressSync(
+              [Buffer.from("quote_vault"), duplicateMarketPda.toBuffer()],
+              program.programId
+            )[0],
+            baseTokenProgram: TOKEN_PROGRAM_ID,
+            quoteTokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+            ...arciumAccounts(computationOffset(duplicateMarketPda, 1), "init_book"),
+          })
+          .signers([authority])
+          .rpc();
+
+        expect.fail("Should have rejected the duplicate mint pair");
+      } catch (error: any) {
+        // The registry entry for the pair already exists
+        expect(error.toString()).to.include("already in use");
+      }
+    });
+  });
+
+  describe("Market Status", () => {
+    it("pauses and resumes the market into a new auction", async () => {
+      await program.methods
+        .setMarketPaused(true)
+        .accounts({
+          authority: authority.publicKey,
+          market: marketPda,
+        })
+        .signers([authority])
+        .rpc();
+
+      let marketAccount = await program.account.market.fetch(marketPda);
+      expect(marketAccount.status).to.deep.equal({ paused: {} });
+
+      await program.methods
+        .setMarketPaused(false)
+        .accounts({
+          authority: authority.publicKey,
+          market: marketPda,
+        })
+        .signers([authority])
+        .rpc();
+
+      marketAccount = await program.account.market.fetch(marketPda);
+      expect(marketAccount.status).to.deep.equal({ auction: {} });
+    });
+
+    it("fails to resume a market that is not paused", async () => {
+      try {
+        await program.methods
+          .setMarketPaused(false)
+          .accounts({
+            authority: authority.publicKey,
+            market: marketPda,
+          })
+          .signers([authority])
+          .rpc();
+
+        expect.fail("Should have thrown MarketNotPaused error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("MarketNotPaused");
+      }
+    });
+  });
+
+  describe("Keeper Rewards", () => {
+    it("sets the keeper reward", async () => {
+      const keeperReward = new anchor.BN(10_000); // 0.01 USDC per crank
+
+      await program.methods
+        .setKeeperReward(keeperReward)
+        .accounts({
+          authority: authority.publicKey,
+          market: marketPda,
+        })
+        .signers([authority])
+        .rpc();
+
+      const marketAccount = await program.account.market.fetch(marketPda);
+      expect(marketAccount.keeperReward.toNumber()).to.equal(
+        keeperReward.toNumber()
+      );
+      expect(marketAccount.feesAccrued.toNumber()).to.equal(0);
+    });
+
+    it("fails to set the keeper reward without market authority", async () => {
+      try {
+        await program.methods
+          .setKeeperReward(new anchor.BN(1))
+          .accounts({
+            authority: user1.publicKey,
+            market: marketPda,
+          })
+          .signers([user1])
+          .rpc();
+
+        expect.fail("Should have thrown Unauthorized error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("Unauthorized");
+      }
+    });
+
+    it("fails to set a keeper reward the fee on a fill can't fund", async () => {
+      // 0.3% of the 100 USDC reference fill, split across a fill's four cranks
+      const maxReward = (100_000_000 * FEE_RATE_BPS) / 10_000 / 4;
+
+      try {
+        await program.methods
+          .setKeeperReward(new anchor.BN(maxReward + 1))
+          .accounts({
+            authority: authority.publicKey,
+            market: marketPda,
+          })
+          .signers([authority])
+          .rpc();
+
+        expect.fail("Should have thrown KeeperRewardTooHigh error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("KeeperRewardTooHigh");
+      }
+
+      const marketAccount = await program.account.market.fetch(marketPda);
+      expect(marketAccount.keeperReward.toNumber()).to.equal(10_000);
     });
   });
 
@@ -214,16 +786,18 @@ describe("dusk_exchange", () => {
           Buffer.from("user_position"),
           marketPda.toBuffer(),
           user1.publicKey.toBuffer(),
+          SUB_ACCOUNT_ID.toArrayLike(Buffer, "le", 2),
         ],
         program.programId
       );
 
       const tx = await program.methods
-        .deposit(depositAmount, true) // true = base token
+        .deposit(depositAmount, true, 0) // true = base token
         .accounts({
           user: user1.publicKey,
           market: marketPda,
           userPosition: userPositionPda,
+          mint: baseMint,
           userTokenAccount: user1BaseAccount,
           vault: baseVaultPda,
           tokenProgram: TOKEN_PROGRAM_ID,
@@ -252,16 +826,18 @@ describe("dusk_exchange", () => {
           Buffer.from("user_position"),
           marketPda.toBuffer(),
           user1.publicKey.toBuffer(),
+          SUB_ACCOUNT_ID.toArrayLike(Buffer, "le", 2),
         ],
         program.programId
       );
 
       const tx = await program.methods
-        .deposit(depositAmount, false) // false = quote token
+        .deposit(depositAmount, false, 0) // false = quote token
         .accounts({
           user: user1.publicKey,
           market: marketPda,
           userPosition: userPositionPda,
+          mint: quoteMint,
           userTokenAccount: user1QuoteAccount,
           vault: quoteVaultPda,
           tokenProgram: TOKEN_PROGRAM_ID,
@@ -290,17 +866,19 @@ describe("dusk_exchange", () => {
           Buffer.from("user_position"),
           marketPda.toBuffer(),
           user2.publicKey.toBuffer(),
+          SUB_ACCOUNT_ID.toArrayLike(Buffer, "le", 2),
         ],
         program.programId
       );
 
       // Deposit base
       await program.methods
-        .deposit(baseAmount, true)
+        .deposit(baseAmount, true, 0)
         .accounts({
           user: user2.publicKey,
           market: marketPda,
           userPosition: userPositionPda,
+          mint: baseMint,
           userTokenAccount: user2BaseAccount,
           vault: baseVaultPda,
           tokenProgram: TOKEN_PROGRAM_ID,
@@ -311,11 +889,12 @@ describe("dusk_exchange", () => {
 
       // Deposit quote
       await program.methods
-        .deposit(quoteAmount, false)
+        .deposit(quoteAmount, false, 0)
         .accounts({
           user: user2.publicKey,
           market: marketPda,
           userPosition: userPositionPda,
+          mint: quoteMint,
           userTokenAccount: user2QuoteAccount,
           vault: quoteVaultPda,
           tokenProgram: TOKEN_PROGRAM_ID,
@@ -340,6 +919,7 @@ describe("dusk_exchange", () => {
           Buffer.from("user_position"),
           marketPda.toBuffer(),
           user1.publicKey.toBuffer(),
+          SUB_ACCOUNT_ID.toArrayLike(Buffer, "le", 2),
         ],
         program.programId
       );
@@ -359,6 +939,7 @@ describe("dusk_exchange", () => {
           user: user1.publicKey,
           market: marketPda,
           userPosition: userPositionPda,
+          mint: baseMint,
           userTokenAccount: user1BaseAccount,
           vault: baseVaultPda,
           tokenProgram: TOKEN_PROGRAM_ID,
@@ -392,6 +973,7 @@ describe("dusk_exchange", () => {
           Buffer.from("user_position"),
           marketPda.toBuffer(),
           user1.publicKey.toBuffer(),
+          SUB_ACCOUNT_ID.toArrayLike(Buffer, "le", 2),
         ],
         program.programId
       );
@@ -406,6 +988,7 @@ describe("dusk_exchange", () => {
             user: user1.publicKey,
             market: marketPda,
             userPosition: userPositionPda,
+            mint: baseMint,
             userTokenAccount: user1BaseAccount,
             vault: baseVaultPda,
             tokenProgram: TOKEN_PROGRAM_ID,
@@ -420,42 +1003,3483 @@ describe("dusk_exchange", () => {
     });
   });
 
-  // Note: Order placement and matching tests require Arcium integration
-  // These would be implemented once Arcium testnet is connected
-  describe("Order Placement (requires Arcium)", () => {
-    it.skip("places an encrypted buy order", async () => {
-      // TODO: Implement with Arcium encryption
-      // 1. Encrypt order price and amount using Arcium client SDK
-      // 2. Call placeOrder with encrypted data
-      // 3. Wait for computation callback
-    });
+  describe("Delegates", () => {
+    it("user1 sets and revokes a trading delegate", async () => {
+      const sessionKey = Keypair.generate().publicKey;
+      const expiresAt = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+
+      const [userPositionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_position"),
+          marketPda.toBuffer(),
+          user1.publicKey.toBuffer(),
+          SUB_ACCOUNT_ID.toArrayLike(Buffer, "le", 2),
+        ],
+        program.programId
+      );
+
+      await program.methods
+        .setDelegate(sessionKey, expiresAt)
+        .accounts({
+          user: user1.publicKey,
+          market: marketPda,
+          userPosition: userPositionPda,
+        })
+        .signers([user1])
+        .rpc();
+
+      let position = await program.account.userPosition.fetch(userPositionPda);
+      expect(position.delegate.toString()).to.equal(sessionKey.toString());
+      expect(position.delegateExpiresAt.toNumber()).to.equal(
+        expiresAt.toNumber()
+      );
+
+      await program.methods
+        .revokeDelegate()
+        .accounts({
+          user: user1.publicKey,
+          market: marketPda,
+          userPosition: userPositionPda,
+        })
+        .signers([user1])
+        .rpc();
 
-    it.skip("places an encrypted sell order", async () => {
-      // TODO: Implement with Arcium encryption
+      position = await program.account.userPosition.fetch(userPositionPda);
+      expect(position.delegate.toString()).to.equal(
+        PublicKey.default.toString()
+      );
     });
   });
 
-  describe("Order Matching (requires Arcium)", () => {
-    it.skip("matches crossing orders", async () => {
-      // TODO: Implement with Arcium
-      // 1. Place buy order at $101
-      // 2. Place sell order at $100
-      // 3. Call matchOrders
-      // 4. Verify execution at midpoint (~$100.50)
+  describe("Token-2022 Markets", () => {
+    const FEE_MARKET_ID = new anchor.BN(2);
+    const TRANSFER_FEE_BPS = 100; // 1%
+
+    let feeMint: PublicKey;
+    let feeMarketPda: PublicKey;
+    let feeBaseVaultPda: PublicKey;
+    let feeQuoteVaultPda: PublicKey;
+    let user1FeeAccount: PublicKey;
+
+    before(async () => {
+      // Create a Token-2022 mint with a transfer-fee extension
+      const mintKeypair = Keypair.generate();
+      feeMint = mintKeypair.publicKey;
+      const mintLen = getMintLen([ExtensionType.TransferFeeConfig]);
+      const lamports =
+        await provider.connection.getMinimumBalanceForRentExemption(mintLen);
+
+      const tx = new Transaction().add(
+        SystemProgram.createAccount({
+          fromPubkey: authority.publicKey,
+          newAccountPubkey: feeMint,
+          space: mintLen,
+          lamports,
+          programId: TOKEN_2022_PROGRAM_ID,
+        }),
+        createInitializeTransferFeeConfigInstruction(
+          feeMint,
+          authority.publicKey,
+          authority.publicKey,
+          TRANSFER_FEE_BPS,
+          BigInt(1_000 * 10 ** 9), // max fee
+          TOKEN_2022_PROGRAM_ID
+        ),
+        createInitializeMintInstruction(
+          feeMint,
+          9,
+          authority.publicKey,
+          null,
+          TOKEN_2022_PROGRAM_ID
+        )
+      );
+      await sendAndConfirmTransaction(provider.connection, tx, [
+        authority,
+        mintKeypair,
+      ]);
+
+      user1FeeAccount = await createAccount(
+        provider.connection,
+        user1,
+        feeMint,
+        user1.publicKey,
+        undefined,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+
+      await mintTo(
+        provider.connection,
+        authority,
+        feeMint,
+        user1FeeAccount,
+        authority,
+        100 * 10 ** 9,
+        [],
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+
+      [feeMarketPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("market"), FEE_MARKET_ID.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      );
+      [feeBaseVaultPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("base_vault"), feeMarketPda.toBuffer()],
+        program.programId
+      );
+      [feeQuoteVaultPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("quote_vault"), feeMarketPda.toBuffer()],
+        program.programId
+      );
     });
 
-    it.skip("does not match non-crossing orders", async () => {
-      // TODO: Implement with Arcium
-      // Buy at $99, Sell at $101 - should not match
+    it("initializes a market over a Token-2022 base mint", async () => {
+      await program.methods
+        .initializeMarket(computationOffset(feeMarketPda, 1), FEE_MARKET_ID, FEE_RATE_BPS)
+        .accountsPartial({
+          authority: authority.publicKey,
+          market: feeMarketPda,
+          orderBook: orderBookPda(feeMarketPda),
+          exchangeConfig: exchangeConfigPda,
+          registryEntry: registryEntryPda(feeMint, quoteMint),
+          baseMint: feeMint,
+          quoteMint,
+          baseVault: feeBaseVaultPda,
+          quoteVault: feeQuoteVaultPda,
+          baseTokenProgram: TOKEN_2022_PROGRAM_ID,
+          quoteTokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          ...arciumAccounts(computationOffset(feeMarketPda, 1), "init_book"),
+        })
+        .signers([authority])
+        .rpc();
+
+      const marketAccount = await program.account.market.fetch(feeMarketPda);
+      expect(marketAccount.baseTokenProgram.toString()).to.equal(
+        TOKEN_2022_PROGRAM_ID.toString()
+      );
     });
-  });
 
-  describe("Trade Settlement", () => {
-    it.skip("settles a matched trade", async () => {
-      // TODO: Implement after matching works
-      // 1. Have a matched trade in settlement account
-      // 2. Call settleTrade
-      // 3. Verify token transfers
+    // Create a Token-2022 mint carrying a single extension
+    const createMintWithExtension = async (
+      extension: ExtensionType,
+      initExtension: (mint: PublicKey) => anchor.web3.TransactionInstruction
+    ) => {
+      const mintKeypair = Keypair.generate();
+      const mintLen = getMintLen([extension]);
+      const lamports =
+        await provider.connection.getMinimumBalanceForRentExemption(mintLen);
+
+      const tx = new Transaction().add(
+        SystemProgram.createAccount({
+          fromPubkey: authority.publicKey,
+          newAccountPubkey: mintKeypair.publicKey,
+          space: mintLen,
+          lamports,
+          programId: TOKEN_2022_PROGRAM_ID,
+        }),
+        initExtension(mintKeypair.publicKey),
+        createInitializeMintInstruction(
+          mintKeypair.publicKey,
+          9,
+          authority.publicKey,
+          null,
+          TOKEN_2022_PROGRAM_ID
+        )
+      );
+      await sendAndConfirmTransaction(provider.connection, tx, [
+        authority,
+        mintKeypair,
+      ]);
+
+      return mintKeypair.publicKey;
+    };
+
+    // Try to create a market over `mint` and expect it to be refused
+    const expectUnsupportedMint = async (mint: PublicKey, marketId: anchor.BN) => {
+      const [marketPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("market"), marketId.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      );
+      const [baseVaultPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("base_vault"), marketPda.toBuffer()],
+        program.programId
+      );
+      const [quoteVaultPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("quote_vault"), marketPda.toBuffer()],
+        program.programId
+      );
+
+      try {
+        await program.methods
+          .initializeMarket(computationOffset(marketPda, 1), marketId, FEE_RATE_BPS)
+          .accountsPartial({
+            authority: authority.publicKey,
+            market: marketPda,
+            orderBook: orderBookPda(marketPda),
+            exchangeConfig: exchangeConfigPda,
+            registryEntry: registryEntryPda(mint, quoteMint),
+            baseMint: mint,
+            quoteMint,
+            baseVault: baseVaultPda,
+            quoteVault: quoteVaultPda,
+            baseTokenProgram: TOKEN_2022_PROGRAM_ID,
+            quoteTokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+            ...arciumAccounts(computationOffset(marketPda, 1), "init_book"),
+          })
+          .signers([authority])
+          .rpc();
+
+        expect.fail("Should have thrown UnsupportedMintExtension error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("UnsupportedMintExtension");
+      }
+    };
+
+    it("rejects a base mint with a permanent delegate", async () => {
+      const mint = await createMintWithExtension(
+        ExtensionType.PermanentDelegate,
+        (mint) =>
+          createInitializePermanentDelegateInstruction(
+            mint,
+            authority.publicKey,
+            TOKEN_2022_PROGRAM_ID
+          )
+      );
+
+      await expectUnsupportedMint(mint, new anchor.BN(20));
+    });
+
+    it("rejects a non-transferable base mint", async () => {
+      const mint = await createMintWithExtension(
+        ExtensionType.NonTransferable,
+        (mint) =>
+          createInitializeNonTransferableMintInstruction(mint, TOKEN_2022_PROGRAM_ID)
+      );
+
+      await expectUnsupportedMint(mint, new anchor.BN(21));
+    });
+
+    it("credits the amount received after the transfer fee", async () => {
+      const depositAmount = new anchor.BN(10 * 10 ** 9); // 10 tokens
+      const expectedFee = depositAmount
+        .muln(TRANSFER_FEE_BPS)
+        .divn(10_000);
+
+      const [userPositionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_position"),
+          feeMarketPda.toBuffer(),
+          user1.publicKey.toBuffer(),
+          SUB_ACCOUNT_ID.toArrayLike(Buffer, "le", 2),
+        ],
+        program.programId
+      );
+
+      await program.methods
+        .deposit(depositAmount, true, 0)
+        .accounts({
+          user: user1.publicKey,
+          market: feeMarketPda,
+          userPosition: userPositionPda,
+          mint: feeMint,
+          userTokenAccount: user1FeeAccount,
+          vault: feeBaseVaultPda,
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user1])
+        .rpc();
+
+      const position = await program.account.userPosition.fetch(userPositionPda);
+      expect(position.baseDeposited.toNumber()).to.equal(
+        depositAmount.sub(expectedFee).toNumber()
+      );
+
+      const vaultAccount = await getAccount(
+        provider.connection,
+        feeBaseVaultPda,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      expect(Number(vaultAccount.amount)).to.equal(
+        position.baseDeposited.toNumber()
+      );
+    });
+
+    it("delists the market into cancel-only and rejects new deposits", async () => {
+      const [userPositionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_position"),
+          feeMarketPda.toBuffer(),
+          user1.publicKey.toBuffer(),
+          SUB_ACCOUNT_ID.toArrayLike(Buffer, "le", 2),
+        ],
+        program.programId
+      );
+
+      await program.methods
+        .delistMarket()
+        .accounts({
+          authority: authority.publicKey,
+          market: feeMarketPda,
+        })
+        .signers([authority])
+        .rpc();
+
+      const marketAccount = await program.account.market.fetch(feeMarketPda);
+      expect(marketAccount.status).to.deep.equal({ cancelOnly: {} });
+
+      try {
+        await program.methods
+          .deposit(new anchor.BN(1_000), true, 0)
+          .accounts({
+            user: user1.publicKey,
+            market: feeMarketPda,
+            userPosition: userPositionPda,
+            mint: feeMint,
+            userTokenAccount: user1FeeAccount,
+            vault: feeBaseVaultPda,
+            tokenProgram: TOKEN_2022_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user1])
+          .rpc();
+
+        expect.fail("Should have thrown MarketDelisted error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("MarketDelisted");
+      }
+
+    });
+
+    it("closes the market although the base vault holds withheld transfer fees", async () => {
+      const userPositionPda = positionPda(feeMarketPda, user1.publicKey);
+
+      // The deposit fee is withheld on the vault itself
+      const vaultBefore = await getAccount(
+        provider.connection,
+        feeBaseVaultPda,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      const withheld = getTransferFeeAmount(vaultBefore)!.withheldAmount;
+      expect(withheld > BigInt(0)).to.be.true;
+
+      await registerCircuit("clear_book");
+      const offset = await nextOffset(feeMarketPda);
+      await program.methods
+        .clearBook(offset)
+        .accountsPartial({
+          authority: authority.publicKey,
+          market: feeMarketPda,
+          orderBook: orderBookPda(feeMarketPda),
+          ...arciumAccounts(offset, "clear_book"),
+        })
+        .signers([authority])
+        .rpc();
+      await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+
+      const position = await program.account.userPosition.fetch(userPositionPda);
+      await program.methods
+        .withdraw(position.baseDeposited, true)
+        .accounts({
+          user: user1.publicKey,
+          market: feeMarketPda,
+          userPosition: userPositionPda,
+          mint: feeMint,
+          userTokenAccount: user1FeeAccount,
+          vault: feeBaseVaultPda,
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+      await program.methods
+        .closePosition()
+        .accounts({ user: user1.publicKey, market: feeMarketPda, userPosition: userPositionPda })
+        .signers([user1])
+        .rpc();
+
+      const authorityFeeAccount = await createAccount(
+        provider.connection,
+        authority,
+        feeMint,
+        authority.publicKey,
+        undefined,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      const authorityQuoteAccount = await createAccount(
+        provider.connection,
+        authority,
+        quoteMint,
+        authority.publicKey,
+        Keypair.generate()
+      );
+      await program.methods
+        .closeMarket()
+        .accountsPartial({
+          authority: authority.publicKey,
+          market: feeMarketPda,
+          orderBook: orderBookPda(feeMarketPda),
+          registryEntry: registryEntryPda(feeMint, quoteMint),
+          baseMint: feeMint,
+          quoteMint,
+          baseVault: feeBaseVaultPda,
+          quoteVault: feeQuoteVaultPda,
+          baseDestination: authorityFeeAccount,
+          quoteDestination: authorityQuoteAccount,
+          baseTokenProgram: TOKEN_2022_PROGRAM_ID,
+          quoteTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([authority])
+        .rpc();
+
+      // The withheld fees were harvested to the mint before the vault closed
+      expect(await provider.connection.getAccountInfo(feeBaseVaultPda)).to.be.null;
+      const mint = await getMint(provider.connection, feeMint, undefined, TOKEN_2022_PROGRAM_ID);
+      expect(getTransferFeeConfig(mint)!.withheldAmount >= withheld).to.be.true;
+    });
+  });
+
+  describe("Native SOL", () => {
+    const SOL_MARKET_ID = new anchor.BN(3);
+
+    let solMarketPda: PublicKey;
+    let solBaseVaultPda: PublicKey;
+    let solQuoteVaultPda: PublicKey;
+    let userPositionPda: PublicKey;
+
+    before(async () => {
+      [solMarketPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("market"), SOL_MARKET_ID.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      );
+      [solBaseVaultPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("base_vault"), solMarketPda.toBuffer()],
+        program.programId
+      );
+      [solQuoteVaultPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("quote_vault"), solMarketPda.toBuffer()],
+        program.programId
+      );
+      [userPositionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_position"),
+          solMarketPda.toBuffer(),
+          user1.publicKey.toBuffer(),
+          SUB_ACCOUNT_ID.toArrayLike(Buffer, "le", 2),
+        ],
+        program.programId
+      );
+
+      await program.methods
+        .initializeMarket(computationOffset(solMarketPda, 1), SOL_MARKET_ID, FEE_RATE_BPS)
+        .accountsPartial({
+          authority: authority.publicKey,
+          market: solMarketPda,
+          orderBook: orderBookPda(solMarketPda),
+          exchangeConfig: exchangeConfigPda,
+          registryEntry: registryEntryPda(NATIVE_MINT, quoteMint),
+          baseMint: NATIVE_MINT,
+          quoteMint,
+          baseVault: solBaseVaultPda,
+          quoteVault: solQuoteVaultPda,
+          baseTokenProgram: TOKEN_PROGRAM_ID,
+          quoteTokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          ...arciumAccounts(computationOffset(solMarketPda, 1), "init_book"),
+        })
+        .signers([authority])
+        .rpc();
+    });
+
+    it("wraps native SOL into the base vault", async () => {
+      const depositAmount = new anchor.BN(2 * LAMPORTS_PER_SOL);
+
+      await program.methods
+        .depositNative(depositAmount, 0)
+        .accounts({
+          user: user1.publicKey,
+          market: solMarketPda,
+          userPosition: userPositionPda,
+          vault: solBaseVaultPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user1])
+        .rpc();
+
+      const position = await program.account.userPosition.fetch(userPositionPda);
+      expect(position.baseDeposited.toNumber()).to.equal(
+        depositAmount.toNumber()
+      );
+
+      const vaultAccount = await getAccount(provider.connection, solBaseVaultPda);
+      expect(Number(vaultAccount.amount)).to.equal(depositAmount.toNumber());
+    });
+
+    it("rejects a token program other than the market's", async () => {
+      try {
+        await program.methods
+          .depositNative(new anchor.BN(LAMPORTS_PER_SOL), 0)
+          .accounts({
+            user: user1.publicKey,
+            market: solMarketPda,
+            userPosition: userPositionPda,
+            vault: solBaseVaultPda,
+            tokenProgram: TOKEN_2022_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown InvalidMarketConfig error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("InvalidMarketConfig");
+      }
+    });
+
+    it("unwraps withdrawn wSOL to the user's system account", async () => {
+      const withdrawAmount = new anchor.BN(1 * LAMPORTS_PER_SOL);
+
+      const [unwrapPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("unwrap"),
+          solMarketPda.toBuffer(),
+          user1.publicKey.toBuffer(),
+        ],
+        program.programId
+      );
+
+      const initialLamports = await provider.connection.getBalance(
+        user1.publicKey
+      );
+
+      await program.methods
+        .withdrawNative(withdrawAmount)
+        .accounts({
+          user: user1.publicKey,
+          market: solMarketPda,
+          userPosition: userPositionPda,
+          nativeMint: NATIVE_MINT,
+          vault: solBaseVaultPda,
+          unwrapAccount: unwrapPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user1])
+        .rpc();
+
+      const finalLamports = await provider.connection.getBalance(
+        user1.publicKey
+      );
+      // Withdrawn amount minus the transaction fee
+      expect(finalLamports - initialLamports).to.be.greaterThan(
+        withdrawAmount.toNumber() - 10_000
+      );
+
+      const position = await program.account.userPosition.fetch(userPositionPda);
+      expect(position.baseDeposited.toNumber()).to.equal(
+        withdrawAmount.toNumber()
+      );
+
+      const unwrapAccount = await provider.connection.getAccountInfo(unwrapPda);
+      expect(unwrapAccount).to.be.null;
+    });
+  });
+
+  describe("Sub-accounts", () => {
+    const subAccountPda = (id: number) =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_position"),
+          marketPda.toBuffer(),
+          user1.publicKey.toBuffer(),
+          new anchor.BN(id).toArrayLike(Buffer, "le", 2),
+        ],
+        program.programId
+      )[0];
+
+    it("user1 opens sub-account 1 and moves funds into it", async () => {
+      const depositAmount = new anchor.BN(1 * 10 ** 9); // 1 SOL
+      const transferAmount = new anchor.BN(5 * 10 ** 8); // 0.5 SOL
+
+      await program.methods
+        .deposit(depositAmount, true, 1)
+        .accounts({
+          user: user1.publicKey,
+          market: marketPda,
+          userPosition: subAccountPda(1),
+          mint: baseMint,
+          userTokenAccount: user1BaseAccount,
+          vault: baseVaultPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user1])
+        .rpc();
+
+      const mainBefore = await program.account.userPosition.fetch(subAccountPda(0));
+
+      const transferEvent = awaitEvent("subAccountTransfer");
+      await program.methods
+        .transferBalance(transferAmount, true)
+        .accounts({
+          user: user1.publicKey,
+          market: marketPda,
+          fromPosition: subAccountPda(0),
+          toPosition: subAccountPda(1),
+        })
+        .signers([user1])
+        .rpc();
+
+      const event = await transferEvent;
+      expect(event.fromSubAccount).to.equal(0);
+      expect(event.toSubAccount).to.equal(1);
+
+      const main = await program.account.userPosition.fetch(subAccountPda(0));
+      const sub = await program.account.userPosition.fetch(subAccountPda(1));
+
+      expect(sub.subAccountId).to.equal(1);
+      expect(main.baseDeposited.toString()).to.equal(
+        mainBefore.baseDeposited.sub(transferAmount).toString()
+      );
+      expect(sub.baseDeposited.toString()).to.equal(
+        depositAmount.add(transferAmount).toString()
+      );
+    });
+  });
+
+  describe("Internal Transfers", () => {
+    it("user1 transfers quote to user2 without touching the vault", async () => {
+      const transferAmount = new anchor.BN(10 * 10 ** 6); // 10 USDC
+
+      const [fromPositionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_position"),
+          marketPda.toBuffer(),
+          user1.publicKey.toBuffer(),
+          SUB_ACCOUNT_ID.toArrayLike(Buffer, "le", 2),
+        ],
+        program.programId
+      );
+      const [toPositionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_position"),
+          marketPda.toBuffer(),
+          user2.publicKey.toBuffer(),
+          SUB_ACCOUNT_ID.toArrayLike(Buffer, "le", 2),
+        ],
+        program.programId
+      );
+
+      const fromBefore = await program.account.userPosition.fetch(fromPositionPda);
+      const toBefore = await program.account.userPosition.fetch(toPositionPda);
+      const vaultBefore = await getAccount(provider.connection, quoteVaultPda);
+
+      await program.methods
+        .transferBalance(transferAmount, false)
+        .accounts({
+          user: user1.publicKey,
+          market: marketPda,
+          fromPosition: fromPositionPda,
+          toPosition: toPositionPda,
+        })
+        .signers([user1])
+        .rpc();
+
+      const fromAfter = await program.account.userPosition.fetch(fromPositionPda);
+      const toAfter = await program.account.userPosition.fetch(toPositionPda);
+      const vaultAfter = await getAccount(provider.connection, quoteVaultPda);
+
+      expect(fromAfter.quoteDeposited.toString()).to.equal(
+        fromBefore.quoteDeposited.sub(transferAmount).toString()
+      );
+      expect(toAfter.quoteDeposited.toString()).to.equal(
+        toBefore.quoteDeposited.add(transferAmount).toString()
+      );
+      expect(vaultAfter.amount.toString()).to.equal(vaultBefore.amount.toString());
+    });
+  });
+
+  describe("Withdrawal Recipients", () => {
+    let userPositionPda: PublicKey;
+    const amount = new anchor.BN(1 * 10 ** 6); // 1 USDC
+
+    const withdrawToUser2 = () =>
+      program.methods
+        .withdraw(amount, false)
+        .accounts({
+          user: user1.publicKey,
+          market: marketPda,
+          userPosition: userPositionPda,
+          mint: quoteMint,
+          userTokenAccount: user2QuoteAccount,
+          vault: quoteVaultPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+    before(() => {
+      [userPositionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_position"),
+          marketPda.toBuffer(),
+          user1.publicKey.toBuffer(),
+          SUB_ACCOUNT_ID.toArrayLike(Buffer, "le", 2),
+        ],
+        program.programId
+      );
+    });
+
+    it("withdraws to another wallet's token account", async () => {
+      const before = await getAccount(provider.connection, user2QuoteAccount);
+      await withdrawToUser2();
+      const after = await getAccount(provider.connection, user2QuoteAccount);
+
+      expect((after.amount - before.amount).toString()).to.equal(amount.toString());
+    });
+
+    it("rejects recipients that are not yet active on the allowlist", async () => {
+      await program.methods
+        .setWithdrawAllowlist(true)
+        .accounts({
+          user: user1.publicKey,
+          market: marketPda,
+          userPosition: userPositionPda,
+        })
+        .signers([user1])
+        .rpc();
+
+      await program.methods
+        .addWithdrawRecipient(user2.publicKey)
+        .accounts({
+          user: user1.publicKey,
+          market: marketPda,
+          userPosition: userPositionPda,
+        })
+        .signers([user1])
+        .rpc();
+
+      const position = await program.account.userPosition.fetch(userPositionPda);
+      expect(position.withdrawAllowlistEnabled).to.be.true;
+      expect(position.withdrawAllowlist[0].recipient.toString()).to.equal(
+        user2.publicKey.toString()
+      );
+
+      // The new entry only activates after the 24 hour delay
+      try {
+        await withdrawToUser2();
+        expect.fail("Should have thrown RecipientNotAllowed error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("RecipientNotAllowed");
+      }
+    });
+
+    it("rejects internal transfers to another owner's position", async () => {
+      const [user2PositionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_position"),
+          marketPda.toBuffer(),
+          user2.publicKey.toBuffer(),
+          SUB_ACCOUNT_ID.toArrayLike(Buffer, "le", 2),
+        ],
+        program.programId
+      );
+
+      try {
+        await program.methods
+          .transferBalance(amount, false)
+          .accounts({
+            user: user1.publicKey,
+            market: marketPda,
+            fromPosition: userPositionPda,
+            toPosition: user2PositionPda,
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown RecipientNotAllowed error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("RecipientNotAllowed");
+      }
+    });
+
+    it("keeps funds behind the allowlist when they move to the owner's sub-accounts", async () => {
+      const subAccountPda = (id: number) =>
+        PublicKey.findProgramAddressSync(
+          [
+            Buffer.from("user_position"),
+            marketPda.toBuffer(),
+            user1.publicKey.toBuffer(),
+            new anchor.BN(id).toArrayLike(Buffer, "le", 2),
+          ],
+          program.programId
+        )[0];
+
+      // A fresh sub-account opened by a deposit starts without an allowlist
+      await program.methods
+        .deposit(amount, false, 2)
+        .accounts({
+          user: user1.publicKey,
+          market: marketPda,
+          userPosition: subAccountPda(2),
+          mint: quoteMint,
+          userTokenAccount: user1QuoteAccount,
+          vault: quoteVaultPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user1])
+        .rpc();
+
+      // Neither move may reach a sub-account that could withdraw to user2 at once
+      for (const subAccountId of [1, 2]) {
+        try {
+          await program.methods
+            .transferBalance(amount, false)
+            .accounts({
+              user: user1.publicKey,
+              market: marketPda,
+              fromPosition: userPositionPda,
+              toPosition: subAccountPda(subAccountId),
+            })
+            .signers([user1])
+            .rpc();
+          expect.fail("Should have thrown RecipientNotAllowed error");
+        } catch (error: any) {
+          expect(error.error.errorCode.code).to.equal("RecipientNotAllowed");
+        }
+      }
+
+      // Once sub-account 1 carries the allowlist, funds may move there, but
+      // user2 is no more reachable from it than from the main position
+      for (const manage of [
+        program.methods.setWithdrawAllowlist(true),
+        program.methods.addWithdrawRecipient(user2.publicKey),
+      ]) {
+        await manage
+          .accounts({
+            user: user1.publicKey,
+            market: marketPda,
+            userPosition: subAccountPda(1),
+          })
+          .signers([user1])
+          .rpc();
+      }
+
+      const before = await program.account.userPosition.fetch(subAccountPda(1));
+      await program.methods
+        .transferBalance(amount, false)
+        .accounts({
+          user: user1.publicKey,
+          market: marketPda,
+          fromPosition: userPositionPda,
+          toPosition: subAccountPda(1),
+        })
+        .signers([user1])
+        .rpc();
+      const after = await program.account.userPosition.fetch(subAccountPda(1));
+      expect(after.quoteDeposited.sub(before.quoteDeposited).toString()).to.equal(
+        amount.toString()
+      );
+
+      try {
+        await program.methods
+          .withdraw(amount, false)
+          .accounts({
+            user: user1.publicKey,
+            market: marketPda,
+            userPosition: subAccountPda(1),
+            mint: quoteMint,
+            userTokenAccount: user2QuoteAccount,
+            vault: quoteVaultPda,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown RecipientNotAllowed error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("RecipientNotAllowed");
+      }
+    });
+  });
+
+  describe("Market Audit", () => {
+    it("tracks total deposits that match the vault balances", async () => {
+      await program.methods
+        .auditMarket()
+        .accounts({
+          caller: user2.publicKey,
+          market: marketPda,
+          baseVault: baseVaultPda,
+          quoteVault: quoteVaultPda,
+        })
+        .signers([user2])
+        .rpc();
+
+      const marketAccount = await program.account.market.fetch(marketPda);
+      const baseVault = await getAccount(provider.connection, baseVaultPda);
+      const quoteVault = await getAccount(provider.connection, quoteVaultPda);
+
+      expect(marketAccount.baseDeposited.toString()).to.equal(
+        baseVault.amount.toString()
+      );
+      expect(
+        marketAccount.quoteDeposited
+          .add(marketAccount.feesAccrued)
+          .add(marketAccount.protocolFeesAccrued)
+          .toString()
+      ).to.equal(quoteVault.amount.toString());
+    });
+
+    it("reports tokens sent straight to a vault as a surplus, not a discrepancy", async () => {
+      await mintTo(provider.connection, authority, quoteMint, quoteVaultPda, authority, 5 * 10 ** 6);
+
+      let discrepancy = false;
+      const listenerId = program.addEventListener("marketAuditDiscrepancy", () => {
+        discrepancy = true;
+      });
+      const surplusEvent = awaitEvent("marketAuditSurplus");
+      await program.methods
+        .auditMarket()
+        .accounts({
+          caller: user2.publicKey,
+          market: marketPda,
+          baseVault: baseVaultPda,
+          quoteVault: quoteVaultPda,
+        })
+        .signers([user2])
+        .rpc();
+
+      const event = await surplusEvent;
+      await program.removeEventListener(listenerId);
+      expect(event.baseSurplus.toNumber()).to.equal(0);
+      expect(event.quoteSurplus.toNumber()).to.equal(5 * 10 ** 6);
+      expect(discrepancy).to.be.false;
+    });
+  });
+
+  describe("Position Closing", () => {
+    it("fails to close a position that still holds funds", async () => {
+      const [userPositionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_position"),
+          marketPda.toBuffer(),
+          user1.publicKey.toBuffer(),
+          SUB_ACCOUNT_ID.toArrayLike(Buffer, "le", 2),
+        ],
+        program.programId
+      );
+
+      try {
+        await program.methods
+          .closePosition()
+          .accounts({
+            user: user1.publicKey,
+            market: marketPda,
+            userPosition: userPositionPda,
+          })
+          .signers([user1])
+          .rpc();
+
+        expect.fail("Should have thrown PositionNotEmpty error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("PositionNotEmpty");
+      }
+    });
+
+    it("user2 withdraws everything and closes the position", async () => {
+      const [userPositionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_position"),
+          marketPda.toBuffer(),
+          user2.publicKey.toBuffer(),
+          SUB_ACCOUNT_ID.toArrayLike(Buffer, "le", 2),
+        ],
+        program.programId
+      );
+
+      const position = await program.account.userPosition.fetch(userPositionPda);
+
+      // Withdraw all base
+      await program.methods
+        .withdraw(position.baseDeposited, true)
+        .accounts({
+          user: user2.publicKey,
+          market: marketPda,
+          userPosition: userPositionPda,
+          mint: baseMint,
+          userTokenAccount: user2BaseAccount,
+          vault: baseVaultPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user2])
+        .rpc();
+
+      // Withdraw all quote
+      await program.methods
+        .withdraw(position.quoteDeposited, false)
+        .accounts({
+          user: user2.publicKey,
+          market: marketPda,
+          userPosition: userPositionPda,
+          mint: quoteMint,
+          userTokenAccount: user2QuoteAccount,
+          vault: quoteVaultPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user2])
+        .rpc();
+
+      const initialLamports = await provider.connection.getBalance(
+        user2.publicKey
+      );
+
+      const tx = await program.methods
+        .closePosition()
+        .accounts({
+          user: user2.publicKey,
+          market: marketPda,
+          userPosition: userPositionPda,
+        })
+        .signers([user2])
+        .rpc();
+
+      console.log("Close position tx:", tx);
+
+      // Verify account is gone and rent was refunded
+      const closed = await provider.connection.getAccountInfo(userPositionPda);
+      expect(closed).to.be.null;
+
+      const finalLamports = await provider.connection.getBalance(
+        user2.publicKey
+      );
+      expect(finalLamports).to.be.greaterThan(initialLamports);
+    });
+  });
+
+  describe("Order Encryption", () => {
+    // Ciphertext counts taken from the circuit layouts in build/
+    const ORDER_INPUT_FIELDS: AddOrder["inputs"][0]["content"][1]["content"][0]["content"]["length"] = 2;
+    const REMAINING_FIELDS: RemoveOrder["outputs"][0]["content"][2]["content"][1]["content"][0]["content"]["length"] = 1;
+
+    // Stand-in for the MXE key; on a cluster this comes from getMXEPublicKey
+    const mxePrivateKey = x25519.utils.randomPrivateKey();
+    const mxePublicKey = x25519.getPublicKey(mxePrivateKey);
+
+    it("encrypts an order in the add_order input layout", async () => {
+      const clientPrivateKey = x25519.utils.randomPrivateKey();
+      const clientPublicKey = x25519.getPublicKey(clientPrivateKey);
+      const nonce = randomBytes(16);
+
+      const price = BigInt(100_000_000);
+      const amount = BigInt(2_500_000_000);
+      const clientCipher = new RescueCipher(
+        x25519.getSharedSecret(clientPrivateKey, mxePublicKey)
+      );
+      const ciphertexts = clientCipher.encrypt([price, amount], nonce);
+
+      // place_order takes one 32-byte ciphertext per OrderInput field, the
+      // client public key and the nonce as a u128
+      expect(ciphertexts).to.have.length(ORDER_INPUT_FIELDS);
+      ciphertexts.forEach((c) => expect(c).to.have.length(32));
+      expect(clientPublicKey).to.have.length(32);
+      const nonceArg = new anchor.BN(deserializeLE(nonce).toString());
+      expect(nonceArg.byteLength()).to.be.at.most(16);
+
+      // The MXE derives the same secret from the client public key
+      const mxeCipher = new RescueCipher(
+        x25519.getSharedSecret(mxePrivateKey, clientPublicKey)
+      );
+      expect(mxeCipher.decrypt(ciphertexts, nonce)).to.deep.equal([price, amount]);
+    });
+
+    it("decrypts the remaining amount returned by remove_order", async () => {
+      const clientPrivateKey = x25519.utils.randomPrivateKey();
+      const clientPublicKey = x25519.getPublicKey(clientPrivateKey);
+      const nonce = randomBytes(16);
+
+      const remaining = BigInt(750_000_000);
+      const mxeCipher = new RescueCipher(
+        x25519.getSharedSecret(mxePrivateKey, clientPublicKey)
+      );
+      const encryptedRemaining = mxeCipher.encrypt([remaining], nonce);
+      expect(encryptedRemaining).to.have.length(REMAINING_FIELDS);
+
+      // Only the client holding the private key can read OrderCancelled
+      const clientCipher = new RescueCipher(
+        x25519.getSharedSecret(clientPrivateKey, mxePublicKey)
+      );
+      expect(clientCipher.decrypt(encryptedRemaining, nonce)).to.deep.equal([remaining]);
+
+      const otherCipher = new RescueCipher(
+        x25519.getSharedSecret(x25519.utils.randomPrivateKey(), mxePublicKey)
+      );
+      expect(otherCipher.decrypt(encryptedRemaining, nonce)).to.not.deep.equal([remaining]);
+    });
+  });
+
+  describe("Order Placement", () => {
+    before(async () => {
+      await registerCircuit("add_order");
+    });
+
+    it("places a buy order encrypted to the MXE key", async () => {
+      const [userPositionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_position"),
+          marketPda.toBuffer(),
+          user1.publicKey.toBuffer(),
+          SUB_ACCOUNT_ID.toArrayLike(Buffer, "le", 2),
+        ],
+        program.programId
+      );
+      const orderId = new anchor.BN(1);
+      const [openOrderPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("order"),
+          userPositionPda.toBuffer(),
+          orderId.toArrayLike(Buffer, "le", 8),
+        ],
+        program.programId
+      );
+
+      // OrderInput is encrypted under the secret shared with the cluster's MXE key
+      const mxePublicKey = await getMXEPublicKey(provider, program.programId);
+      const clientPrivateKey = x25519.utils.randomPrivateKey();
+      const clientPublicKey = x25519.getPublicKey(clientPrivateKey);
+      const nonce = randomBytes(16);
+      const cipher = new RescueCipher(
+        x25519.getSharedSecret(clientPrivateKey, mxePublicKey)
+      );
+      const price = BigInt(100 * PRICE_SCALE); // $100
+      const amount = BigInt(10 ** 9); // 1 SOL
+      const [encryptedPrice, encryptedAmount] = cipher.encrypt([price, amount], nonce);
+      const lockAmount = new anchor.BN(100 * 10 ** 6); // 100 USDC
+
+      const before = await program.account.userPosition.fetch(userPositionPda);
+      const market = await program.account.market.fetch(marketPda);
+      const offset = computationOffset(
+        marketPda,
+        market.computationCount.toNumber() + 1
+      );
+
+      await program.methods
+        .placeOrder(
+          offset,
+          orderId,
+          true,
+          Buffer.from(encryptedPrice),
+          Buffer.from(encryptedAmount),
+          Array.from(clientPublicKey),
+          new anchor.BN(deserializeLE(nonce).toString()),
+          lockAmount
+        )
+        .accountsPartial({
+          user: user1.publicKey,
+          market: marketPda,
+          orderBook: orderBookPda(marketPda),
+          userPosition: userPositionPda,
+          openOrder: openOrderPda,
+          ...arciumAccounts(offset, "add_order"),
+        })
+        .signers([user1])
+        .rpc();
+
+      // The lock is taken when the order is queued
+      const order = await program.account.openOrder.fetch(openOrderPda);
+      expect(order.lockedAmount.toString()).to.equal(lockAmount.toString());
+      expect(order.clientPubkey).to.deep.equal(Array.from(clientPublicKey));
+      const position = await program.account.userPosition.fetch(userPositionPda);
+      expect(position.quoteLocked.sub(before.quoteLocked).toString()).to.equal(
+        lockAmount.toString()
+      );
+
+      // The order rests once the cluster has added it to the book
+      await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+      const placed = await program.account.openOrder.fetch(openOrderPda);
+      expect(placed.status).to.deep.equal({ resting: {} });
+      const orderBook = await program.account.orderBook.fetch(orderBookPda(marketPda));
+      expect(orderBook.pendingUntil.toNumber()).to.equal(0);
+      expect(orderBook.computationOffset.toString()).to.equal(offset.toString());
+    });
+
+    it("rejects a computation offset other than the market's next one", async () => {
+      const [userPositionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_position"),
+          marketPda.toBuffer(),
+          user1.publicKey.toBuffer(),
+          SUB_ACCOUNT_ID.toArrayLike(Buffer, "le", 2),
+        ],
+        program.programId
+      );
+      const orderId = new anchor.BN(2);
+      const [openOrderPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("order"),
+          userPositionPda.toBuffer(),
+          orderId.toArrayLike(Buffer, "le", 8),
+        ],
+        program.programId
+      );
+
+      // Skipping ahead would let a caller pick the computation account
+      const market = await program.account.market.fetch(marketPda);
+      const offset = computationOffset(
+        marketPda,
+        market.computationCount.toNumber() + 2
+      );
+
+      try {
+        await program.methods
+          .placeOrder(
+            offset,
+            orderId,
+            true,
+            Buffer.alloc(32),
+            Buffer.alloc(32),
+            Array.from(x25519.getPublicKey(x25519.utils.randomPrivateKey())),
+            new anchor.BN(0),
+            new anchor.BN(100 * 10 ** 6)
+          )
+          .accountsPartial({
+            user: user1.publicKey,
+            market: marketPda,
+            orderBook: orderBookPda(marketPda),
+            userPosition: userPositionPda,
+            openOrder: openOrderPda,
+            ...arciumAccounts(offset, "add_order"),
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown InvalidComputationOffset error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("InvalidComputationOffset");
+      }
+    });
+
+    it("claims one offset per job and records it on the order and the book", async () => {
+      const before = await program.account.market.fetch(marketPda);
+      const expected = computationOffset(marketPda, before.computationCount.toNumber() + 1);
+
+      // A bid below user1's resting $100 bid leaves the best bid unchanged
+      const { openOrder } = await placeOrder(marketPda, user1, 2, true, 99 * PRICE_SCALE, 10 ** 6, 10 ** 6);
+
+      const after = await program.account.market.fetch(marketPda);
+      expect(after.computationCount.toNumber()).to.equal(
+        before.computationCount.toNumber() + 1
+      );
+      const order = await program.account.openOrder.fetch(openOrder);
+      expect(order.computationOffset.toString()).to.equal(expected.toString());
+      const orderBook = await program.account.orderBook.fetch(orderBookPda(marketPda));
+      expect(orderBook.computationOffset.toString()).to.equal(expected.toString());
+
+      // The same job index on another market lands on another computation account
+      const { arciumClusterOffset } = getArciumEnv();
+      const [otherMarket] = PublicKey.findProgramAddressSync(
+        [Buffer.from("market"), new anchor.BN(2).toArrayLike(Buffer, "le", 8)],
+        program.programId
+      );
+      const other = computationOffset(otherMarket, before.computationCount.toNumber() + 1);
+      expect(other.toString()).to.not.equal(expected.toString());
+      expect(
+        getComputationAccAddress(arciumClusterOffset, other).toString()
+      ).to.not.equal(getComputationAccAddress(arciumClusterOffset, expected).toString());
+    });
+  });
+
+  describe("Order Queries", () => {
+    let userPositionPda: PublicKey;
+
+    before(async () => {
+      [userPositionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_position"),
+          marketPda.toBuffer(),
+          user1.publicKey.toBuffer(),
+          SUB_ACCOUNT_ID.toArrayLike(Buffer, "le", 2),
+        ],
+        program.programId
+      );
+
+      await registerCircuit("query_my_orders");
+    });
+
+    it("rejects a query from a wallet that cannot trade the position", async () => {
+      const market = await program.account.market.fetch(marketPda);
+      const offset = computationOffset(
+        marketPda,
+        market.computationCount.toNumber() + 1
+      );
+
+      try {
+        await program.methods
+          .queryMyOrders(
+            offset,
+            Array.from(x25519.getPublicKey(x25519.utils.randomPrivateKey())),
+            new anchor.BN(0)
+          )
+          .accountsPartial({
+            user: user2.publicKey,
+            market: marketPda,
+            orderBook: orderBookPda(marketPda),
+            userPosition: userPositionPda,
+            ...arciumAccounts(offset, "query_my_orders"),
+          })
+          .signers([user2])
+          .rpc();
+        expect.fail("Should have thrown Unauthorized error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("Unauthorized");
+      }
+    });
+
+    it("user1 reads its resting order back", async () => {
+      const clientPrivateKey = x25519.utils.randomPrivateKey();
+      const clientPublicKey = x25519.getPublicKey(clientPrivateKey);
+      const nonce = randomBytes(16);
+      const mxePublicKey = await getMXEPublicKey(provider, program.programId);
+      const market = await program.account.market.fetch(marketPda);
+      const offset = computationOffset(
+        marketPda,
+        market.computationCount.toNumber() + 1
+      );
+      const queriedEvent = awaitEvent("myOrdersQueried");
+
+      await program.methods
+        .queryMyOrders(
+          offset,
+          Array.from(clientPublicKey),
+          new anchor.BN(deserializeLE(nonce).toString())
+        )
+        .accountsPartial({
+          user: user1.publicKey,
+          market: marketPda,
+          orderBook: orderBookPda(marketPda),
+          userPosition: userPositionPda,
+          ...arciumAccounts(offset, "query_my_orders"),
+        })
+        .signers([user1])
+        .rpc();
+
+      // Only the latest query may answer for the position
+      const position = await program.account.userPosition.fetch(userPositionPda);
+      expect(position.queryComputationOffset.toString()).to.equal(offset.toString());
+
+      // The query does not take the book, so orders can still be placed meanwhile
+      const orderBook = await program.account.orderBook.fetch(orderBookPda(marketPda));
+      expect(orderBook.pendingUntil.toNumber()).to.equal(0);
+
+      await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+
+      // The answer is encrypted to the key the query was made with
+      const event = await queriedEvent;
+      expect(event.position.toString()).to.equal(userPositionPda.toString());
+      const cipher = new RescueCipher(
+        x25519.getSharedSecret(clientPrivateKey, mxePublicKey)
+      );
+      const [bidOrderId, bidPrice, bidAmount, askOrderId, askPrice, askAmount] =
+        cipher.decrypt(
+          event.orders,
+          new Uint8Array(event.nonce.toArrayLike(Buffer, "le", 16))
+        );
+
+      // Order 1 is user1's best bid; the worse bid of order 2 never took the slot
+      expect(bidOrderId).to.equal(BigInt(1));
+      expect(bidPrice).to.equal(BigInt(100 * PRICE_SCALE));
+      expect(bidAmount).to.equal(BigInt(10 ** 9));
+      expect(askOrderId).to.equal(BigInt(0));
+      expect(askPrice).to.equal(BigInt(0));
+      expect(askAmount).to.equal(BigInt(0));
+    });
+  });
+
+  describe("Book Probes", () => {
+    before(async () => {
+      await registerCircuit("probe_cross");
+    });
+
+    it("rejects a probe while the market is in its auction", async () => {
+      const market = await program.account.market.fetch(marketPda);
+      expect(market.status).to.deep.equal({ auction: {} });
+      const offset = computationOffset(
+        marketPda,
+        market.computationCount.toNumber() + 1
+      );
+
+      try {
+        await program.methods
+          .probeCross(offset)
+          .accountsPartial({
+            caller: user1.publicKey,
+            market: marketPda,
+            orderBook: orderBookPda(marketPda),
+            ...arciumAccounts(offset, "probe_cross"),
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown MarketInAuction error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("MarketInAuction");
+      }
+    });
+
+    it("rejects a probe callback for a computation other than the latest probe", async () => {
+      const market = await program.account.market.fetch(marketPda);
+      const { arciumClusterOffset } = getArciumEnv();
+
+      try {
+        await program.methods
+          .probeCrossCallback({ failure: {} })
+          .accountsPartial({
+            compDefAccount: compDefAccount("probe_cross"),
+            mxeAccount: getMXEAccAddress(program.programId),
+            computationAccount: getComputationAccAddress(
+              arciumClusterOffset,
+              market.probeComputationOffset.addn(1)
+            ),
+            clusterAccount: getClusterAccAddress(arciumClusterOffset),
+            market: marketPda,
+          })
+          .rpc();
+        expect.fail("Should have thrown ComputationMismatch error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("ComputationMismatch");
+      }
+    });
+
+    it("rejects a probe callback without an output signed by the cluster", async () => {
+      const market = await program.account.market.fetch(marketPda);
+      const { arciumClusterOffset } = getArciumEnv();
+
+      try {
+        await program.methods
+          .probeCrossCallback({ failure: {} })
+          .accountsPartial({
+            compDefAccount: compDefAccount("probe_cross"),
+            mxeAccount: getMXEAccAddress(program.programId),
+            computationAccount: getComputationAccAddress(
+              arciumClusterOffset,
+              market.probeComputationOffset
+            ),
+            clusterAccount: getClusterAccAddress(arciumClusterOffset),
+            market: marketPda,
+          })
+          .rpc();
+        expect.fail("Should have thrown AbortedComputation error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("AbortedComputation");
+      }
+
+      const after = await program.account.market.fetch(marketPda);
+      expect(after.bookCrossed).to.be.false;
+    });
+  });
+
+  describe("Callback Authentication", () => {
+    before(async () => {
+      await registerCircuit("match_book");
+    });
+
+    it("rejects a match callback for a computation other than the book's latest job", async () => {
+      const orderBook = await program.account.orderBook.fetch(orderBookPda(marketPda));
+      const { arciumClusterOffset } = getArciumEnv();
+
+      try {
+        await program.methods
+          .matchBookCallback({ failure: {} })
+          .accountsPartial({
+            compDefAccount: compDefAccount("match_book"),
+            mxeAccount: getMXEAccAddress(program.programId),
+            computationAccount: getComputationAccAddress(
+              arciumClusterOffset,
+              orderBook.computationOffset.addn(1)
+            ),
+            clusterAccount: getClusterAccAddress(arciumClusterOffset),
+            market: marketPda,
+            orderBook: orderBookPda(marketPda),
+            keeperPosition: null,
+          })
+          .rpc();
+        expect.fail("Should have thrown ComputationMismatch error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("ComputationMismatch");
+      }
+    });
+
+    it("rejects a match callback without an output signed by the cluster", async () => {
+      const orderBook = await program.account.orderBook.fetch(orderBookPda(marketPda));
+      const { arciumClusterOffset } = getArciumEnv();
+
+      try {
+        await program.methods
+          .matchBookCallback({ failure: {} })
+          .accountsPartial({
+            compDefAccount: compDefAccount("match_book"),
+            mxeAccount: getMXEAccAddress(program.programId),
+            computationAccount: getComputationAccAddress(
+              arciumClusterOffset,
+              orderBook.computationOffset
+            ),
+            clusterAccount: getClusterAccAddress(arciumClusterOffset),
+            market: marketPda,
+            orderBook: orderBookPda(marketPda),
+            keeperPosition: null,
+          })
+          .rpc();
+        expect.fail("Should have thrown AbortedComputation error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("AbortedComputation");
+      }
+
+      // Nothing from the spoofed call reached the market or the book
+      const market = await program.account.market.fetch(marketPda);
+      expect(market.hasPendingMatch).to.be.false;
+      const after = await program.account.orderBook.fetch(orderBookPda(marketPda));
+      expect(after.stateNonce.toString()).to.equal(orderBook.stateNonce.toString());
+    });
+
+    it("rejects a replayed add_order callback once a later job has been queued on the book", async () => {
+      const position = positionPda(marketPda, user1.publicKey);
+      const openOrder = openOrderPda(position, 1);
+      const order = await program.account.openOrder.fetch(openOrder);
+      const { arciumClusterOffset } = getArciumEnv();
+
+      try {
+        await program.methods
+          .addOrderCallback({ failure: {} })
+          .accountsPartial({
+            compDefAccount: compDefAccount("add_order"),
+            mxeAccount: getMXEAccAddress(program.programId),
+            computationAccount: getComputationAccAddress(
+              arciumClusterOffset,
+              order.computationOffset
+            ),
+            clusterAccount: getClusterAccAddress(arciumClusterOffset),
+            market: marketPda,
+            orderBook: orderBookPda(marketPda),
+            user: user1.publicKey,
+            userPosition: position,
+            openOrder,
+          })
+          .rpc();
+        expect.fail("Should have thrown ComputationMismatch error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("ComputationMismatch");
+      }
+    });
+  });
+
+  describe("Auditor Keys", () => {
+    let userPositionPda: PublicKey;
+
+    before(async () => {
+      [userPositionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_position"),
+          marketPda.toBuffer(),
+          user1.publicKey.toBuffer(),
+          SUB_ACCOUNT_ID.toArrayLike(Buffer, "le", 2),
+        ],
+        program.programId
+      );
+
+      await registerCircuit("disclose_orders");
+    });
+
+    it("fails to disclose orders without an auditor key", async () => {
+      const market = await program.account.market.fetch(marketPda);
+      const offset = computationOffset(
+        marketPda,
+        market.computationCount.toNumber() + 1
+      );
+
+      try {
+        await program.methods
+          .discloseOrders(offset, new anchor.BN(0))
+          .accountsPartial({
+            user: user1.publicKey,
+            market: marketPda,
+            orderBook: orderBookPda(marketPda),
+            userPosition: userPositionPda,
+            disclosureRecord: disclosureRecordPda(userPositionPda, offset),
+            ...arciumAccounts(offset, "disclose_orders"),
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown NoAuditorKey error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("NoAuditorKey");
+      }
+    });
+
+    it("user1 registers and removes an auditor key", async () => {
+      const auditorPublicKey = x25519.getPublicKey(x25519.utils.randomPrivateKey());
+
+      await program.methods
+        .setAuditorKey(Array.from(auditorPublicKey))
+        .accounts({
+          user: user1.publicKey,
+          market: marketPda,
+          userPosition: userPositionPda,
+        })
+        .signers([user1])
+        .rpc();
+
+      let position = await program.account.userPosition.fetch(userPositionPda);
+      expect(position.auditorPubkey).to.deep.equal(Array.from(auditorPublicKey));
+
+      // An all-zero key removes the auditor
+      await program.methods
+        .setAuditorKey(new Array(32).fill(0))
+        .accounts({
+          user: user1.publicKey,
+          market: marketPda,
+          userPosition: userPositionPda,
+        })
+        .signers([user1])
+        .rpc();
+
+      position = await program.account.userPosition.fetch(userPositionPda);
+      expect(position.auditorPubkey).to.deep.equal(new Array(32).fill(0));
+    });
+  });
+
+  describe("Confidential Balances", () => {
+    const CONF_MARKET_ID = new anchor.BN(4);
+
+    let confBaseMint: PublicKey;
+    let confMarketPda: PublicKey;
+    let confBaseVaultPda: PublicKey;
+    let confQuoteVaultPda: PublicKey;
+    let user1ConfBaseAccount: PublicKey;
+    let userPositionPda: PublicKey;
+    let confidentialBalancePda: PublicKey;
+
+    before(async () => {
+      // A fresh mint pair, since the registry allows one market per pair
+      confBaseMint = await createMint(
+        provider.connection,
+        authority,
+        authority.publicKey,
+        null,
+        9
+      );
+      user1ConfBaseAccount = await createAccount(
+        provider.connection,
+        user1,
+        confBaseMint,
+        user1.publicKey
+      );
+      await mintTo(
+        provider.connection,
+        authority,
+        confBaseMint,
+        user1ConfBaseAccount,
+        authority,
+        100 * 10 ** 9
+      );
+
+      [confMarketPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("market"), CONF_MARKET_ID.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      );
+      [confBaseVaultPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("base_vault"), confMarketPda.toBuffer()],
+        program.programId
+      );
+      [confQuoteVaultPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("quote_vault"), confMarketPda.toBuffer()],
+        program.programId
+      );
+      [userPositionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_position"),
+          confMarketPda.toBuffer(),
+          user1.publicKey.toBuffer(),
+          SUB_ACCOUNT_ID.toArrayLike(Buffer, "le", 2),
+        ],
+        program.programId
+      );
+      [confidentialBalancePda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("confidential_balance"), userPositionPda.toBuffer()],
+        program.programId
+      );
+
+      await registerCircuit("init_balance");
+      await registerCircuit("credit_balance");
+
+      await program.methods
+        .initializeMarket(computationOffset(confMarketPda, 1), CONF_MARKET_ID, FEE_RATE_BPS)
+        .accountsPartial({
+          authority: authority.publicKey,
+          market: confMarketPda,
+          orderBook: orderBookPda(confMarketPda),
+          exchangeConfig: exchangeConfigPda,
+          registryEntry: registryEntryPda(confBaseMint, quoteMint),
+          baseMint: confBaseMint,
+          quoteMint,
+          baseVault: confBaseVaultPda,
+          quoteVault: confQuoteVaultPda,
+          baseTokenProgram: TOKEN_PROGRAM_ID,
+          quoteTokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          ...arciumAccounts(computationOffset(confMarketPda, 1), "init_book"),
+        })
+        .signers([authority])
+        .rpc();
+    });
+
+    it("fails to switch a market that already has open positions", async () => {
+      const market = await program.account.market.fetch(marketPda);
+      expect(market.openPositionCount.toNumber()).to.be.greaterThan(0);
+
+      try {
+        await program.methods
+          .setConfidentialBalances(true)
+          .accounts({
+            authority: authority.publicKey,
+            market: marketPda,
+          })
+          .signers([authority])
+          .rpc();
+        expect.fail("Should have thrown MarketNotEmpty error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("MarketNotEmpty");
+      }
+    });
+
+    it("switches an empty market to confidential balances", async () => {
+      await program.methods
+        .setConfidentialBalances(true)
+        .accounts({
+          authority: authority.publicKey,
+          market: confMarketPda,
+        })
+        .signers([authority])
+        .rpc();
+
+      const market = await program.account.market.fetch(confMarketPda);
+      expect(market.confidentialBalances).to.be.true;
+    });
+
+    it("rejects public deposits on a confidential market", async () => {
+      try {
+        await program.methods
+          .deposit(new anchor.BN(10 ** 9), true, 0)
+          .accounts({
+            user: user1.publicKey,
+            market: confMarketPda,
+            userPosition: userPositionPda,
+            mint: confBaseMint,
+            userTokenAccount: user1ConfBaseAccount,
+            vault: confBaseVaultPda,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown ConfidentialMarket error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("ConfidentialMarket");
+      }
+    });
+
+    it("rejects confidential balances on a public market", async () => {
+      const [publicPositionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("user_position"),
+          marketPda.toBuffer(),
+          user1.publicKey.toBuffer(),
+          SUB_ACCOUNT_ID.toArrayLike(Buffer, "le", 2),
+        ],
+        program.programId
+      );
+      const [publicBalancePda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("confidential_balance"), publicPositionPda.toBuffer()],
+        program.programId
+      );
+      const offset = await nextOffset(marketPda);
+
+      try {
+        await program.methods
+          .initConfidentialBalance(offset, 0)
+          .accountsPartial({
+            user: user1.publicKey,
+            market: marketPda,
+            userPosition: publicPositionPda,
+            confidentialBalance: publicBalancePda,
+            ...arciumAccounts(offset, "init_balance"),
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown NotConfidentialMarket error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("NotConfidentialMarket");
+      }
+    });
+
+    it("user1 opens a confidential balance, which locks the market's mode", async () => {
+      const offset = await nextOffset(confMarketPda);
+
+      await program.methods
+        .initConfidentialBalance(offset, 0)
+        .accountsPartial({
+          user: user1.publicKey,
+          market: confMarketPda,
+          userPosition: userPositionPda,
+          confidentialBalance: confidentialBalancePda,
+          ...arciumAccounts(offset, "init_balance"),
+        })
+        .signers([user1])
+        .rpc();
+
+      const market = await program.account.market.fetch(confMarketPda);
+      expect(market.openPositionCount.toNumber()).to.equal(1);
+
+      await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+      const balance = await program.account.confidentialBalance.fetch(
+        confidentialBalancePda
+      );
+      expect(balance.initialized).to.be.true;
+      expect(balance.pendingUntil.toNumber()).to.equal(0);
+
+      try {
+        await program.methods
+          .setConfidentialBalances(false)
+          .accounts({
+            authority: authority.publicKey,
+            market: confMarketPda,
+          })
+          .signers([authority])
+          .rpc();
+        expect.fail("Should have thrown MarketNotEmpty error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("MarketNotEmpty");
+      }
+    });
+
+    it("rejects a second deposit while the first credit is in flight", async () => {
+      const market = await program.account.market.fetch(confMarketPda);
+      const first = computationOffset(
+        confMarketPda,
+        market.computationCount.toNumber() + 1
+      );
+      const second = computationOffset(
+        confMarketPda,
+        market.computationCount.toNumber() + 2
+      );
+      const depositAccounts = (offset: anchor.BN) => ({
+        user: user1.publicKey,
+        market: confMarketPda,
+        userPosition: userPositionPda,
+        confidentialBalance: confidentialBalancePda,
+        mint: confBaseMint,
+        userTokenAccount: user1ConfBaseAccount,
+        vault: confBaseVaultPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        ...arciumAccounts(offset, "credit_balance"),
+      });
+
+      // Both deposits in one transaction, so the first credit cannot have
+      // called back before the second one runs
+      const secondDeposit = await program.methods
+        .depositConfidential(second, new anchor.BN(10 ** 9), true)
+        .accountsPartial(depositAccounts(second))
+        .instruction();
+
+      try {
+        await program.methods
+          .depositConfidential(first, new anchor.BN(10 ** 9), true)
+          .accountsPartial(depositAccounts(first))
+          .postInstructions([secondDeposit])
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown ConfidentialBalanceBusy error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("ConfidentialBalanceBusy");
+      }
+
+      // Nothing of the failed transaction was kept
+      const balance = await program.account.confidentialBalance.fetch(
+        confidentialBalancePda
+      );
+      expect(balance.pendingBaseCredit.toNumber()).to.equal(0);
+      expect(balance.pendingUntil.toNumber()).to.equal(0);
+    });
+  });
+
+  describe("Confidential Trading", () => {
+    const CONF_MARKET_ID = new anchor.BN(4);
+
+    let confMarketPda: PublicKey;
+    let confBaseMint: PublicKey;
+    let confBaseVaultPda: PublicKey;
+    let confQuoteVaultPda: PublicKey;
+    let user1ConfBaseAccount: PublicKey;
+    let userPositionPda: PublicKey;
+    let confidentialBalancePda: PublicKey;
+    let user2PositionPda: PublicKey;
+    let user2BalancePda: PublicKey;
+    let user2ConfBaseAccount: PublicKey;
+
+    const withdrawConfidential = async (
+      user: Keypair,
+      position: PublicKey,
+      balance: PublicKey,
+      amount: number,
+      isBase: boolean,
+      userTokenAccount: PublicKey
+    ) => {
+      const offset = await nextOffset(confMarketPda);
+      await program.methods
+        .withdrawConfidential(offset, new anchor.BN(amount), isBase)
+        .accountsPartial({
+          user: user.publicKey,
+          market: confMarketPda,
+          userPosition: position,
+          confidentialBalance: balance,
+          mint: isBase ? confBaseMint : quoteMint,
+          userTokenAccount,
+          vault: isBase ? confBaseVaultPda : confQuoteVaultPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          ...arciumAccounts(offset, "debit_balance"),
+        })
+        .signers([user])
+        .rpc();
+      await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+    };
+
+    const placeConfidentialOrder = async (
+      user: Keypair,
+      position: PublicKey,
+      balance: PublicKey,
+      orderId: number,
+      isBuy: boolean,
+      price: number,
+      amount: number
+    ) => {
+      const order = await encryptOrder(BigInt(price), BigInt(amount));
+      const offset = await nextOffset(confMarketPda);
+      await program.methods
+        .placeConfidentialOrder(
+          offset,
+          new anchor.BN(orderId),
+          isBuy,
+          Buffer.from(order.encryptedPrice),
+          Buffer.from(order.encryptedAmount),
+          Array.from(order.publicKey),
+          new anchor.BN(deserializeLE(order.nonce).toString())
+        )
+        .accountsPartial({
+          user: user.publicKey,
+          market: confMarketPda,
+          orderBook: orderBookPda(confMarketPda),
+          userPosition: position,
+          confidentialBalance: balance,
+          openOrder: openOrderPda(position, orderId),
+          ...arciumAccounts(offset, "add_order_locked"),
+        })
+        .signers([user])
+        .rpc();
+      await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+      return openOrderPda(position, orderId);
+    };
+
+    before(async () => {
+      // Market 4, where "Confidential Balances" opened user1's balance
+      [confMarketPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("market"), CONF_MARKET_ID.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      );
+      const market = await program.account.market.fetch(confMarketPda);
+      confBaseMint = market.baseMint;
+      confBaseVaultPda = market.baseVault;
+      confQuoteVaultPda = market.quoteVault;
+      user1ConfBaseAccount = getAssociatedTokenAddressSync(confBaseMint, user1.publicKey);
+      userPositionPda = positionPda(confMarketPda, user1.publicKey);
+      [confidentialBalancePda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("confidential_balance"), userPositionPda.toBuffer()],
+        program.programId
+      );
+
+      await registerCircuit("debit_balance");
+      await registerCircuit("add_order_locked");
+      await registerCircuit("remove_order_unlocked");
+      await registerCircuit("uncross_book");
+      await registerCircuit("settle_fill");
+      await registerCircuit("balance_is_empty");
+
+      user2PositionPda = positionPda(confMarketPda, user2.publicKey);
+      [user2BalancePda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("confidential_balance"), user2PositionPda.toBuffer()],
+        program.programId
+      );
+      user2ConfBaseAccount = await createAccount(
+        provider.connection,
+        user2,
+        confBaseMint,
+        user2.publicKey
+      );
+
+      const initOffset = await nextOffset(confMarketPda);
+      await program.methods
+        .initConfidentialBalance(initOffset, 0)
+        .accountsPartial({
+          user: user2.publicKey,
+          market: confMarketPda,
+          userPosition: user2PositionPda,
+          confidentialBalance: user2BalancePda,
+          ...arciumAccounts(initOffset, "init_balance"),
+        })
+        .signers([user2])
+        .rpc();
+      await awaitComputationFinalization(provider, initOffset, program.programId, "confirmed");
+
+      // user1 trades from 10 base tokens and user2 from 200 USDC
+      const deposits: [Keypair, PublicKey, PublicKey, number, boolean, PublicKey][] = [
+        [user1, userPositionPda, confidentialBalancePda, 10 * 10 ** 9, true, user1ConfBaseAccount],
+        [user2, user2PositionPda, user2BalancePda, 200 * 10 ** 6, false, user2QuoteAccount],
+      ];
+      for (const [user, position, balance, amount, isBase, userTokenAccount] of deposits) {
+        const offset = await nextOffset(confMarketPda);
+        await program.methods
+          .depositConfidential(offset, new anchor.BN(amount), isBase)
+          .accountsPartial({
+            user: user.publicKey,
+            market: confMarketPda,
+            userPosition: position,
+            confidentialBalance: balance,
+            mint: isBase ? confBaseMint : quoteMint,
+            userTokenAccount,
+            vault: isBase ? confBaseVaultPda : confQuoteVaultPda,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            ...arciumAccounts(offset, "credit_balance"),
+          })
+          .signers([user])
+          .rpc();
+        await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+
+        // The deposit is in the encrypted balance once credit_balance calls back
+        const credited = await program.account.confidentialBalance.fetch(balance);
+        expect(credited.pendingBaseCredit.toNumber()).to.equal(0);
+        expect(credited.pendingQuoteCredit.toNumber()).to.equal(0);
+      }
+    });
+
+    it("withdraws from a confidential balance once the MXE finds it covers the amount", async () => {
+      const walletBefore = await getAccount(provider.connection, user1ConfBaseAccount);
+      const vaultBefore = await getAccount(provider.connection, confBaseVaultPda);
+
+      await withdrawConfidential(
+        user1,
+        userPositionPda,
+        confidentialBalancePda,
+        10 ** 9,
+        true,
+        user1ConfBaseAccount
+      );
+
+      const walletAfter = await getAccount(provider.connection, user1ConfBaseAccount);
+      const vaultAfter = await getAccount(provider.connection, confBaseVaultPda);
+      expect((walletAfter.amount - walletBefore.amount).toString()).to.equal(
+        (10 ** 9).toString()
+      );
+      expect((vaultBefore.amount - vaultAfter.amount).toString()).to.equal(
+        (10 ** 9).toString()
+      );
+
+      // The callback paid the token account recorded when the withdrawal was queued
+      const balance = await program.account.confidentialBalance.fetch(confidentialBalancePda);
+      expect(balance.pendingWithdrawal.toNumber()).to.equal(0);
+      expect(balance.pendingWithdrawalRecipient.toString()).to.equal(
+        user1ConfBaseAccount.toString()
+      );
+      expect(balance.pendingUntil.toNumber()).to.equal(0);
+    });
+
+    it("rejects a confidential withdrawal larger than the encrypted balance on callback", async () => {
+      const walletBefore = await getAccount(provider.connection, user1ConfBaseAccount);
+      const rejectedEvent = awaitEvent("confidentialWithdrawalRejected");
+
+      // 9 base tokens are left, so 50 can't be paid out
+      await withdrawConfidential(
+        user1,
+        userPositionPda,
+        confidentialBalancePda,
+        50 * 10 ** 9,
+        true,
+        user1ConfBaseAccount
+      );
+
+      const event = await rejectedEvent;
+      expect(event.user.toString()).to.equal(user1.publicKey.toString());
+      expect(event.amount.toNumber()).to.equal(50 * 10 ** 9);
+      expect(event.isBase).to.be.true;
+
+      // Nothing left the vault, and the balance is free for the next computation
+      const walletAfter = await getAccount(provider.connection, user1ConfBaseAccount);
+      expect(walletAfter.amount.toString()).to.equal(walletBefore.amount.toString());
+      const balance = await program.account.confidentialBalance.fetch(confidentialBalancePda);
+      expect(balance.pendingWithdrawal.toNumber()).to.equal(0);
+      expect(balance.pendingUntil.toNumber()).to.equal(0);
+    });
+
+    it("places a confidential order under an encrypted lock and cancels it", async () => {
+      const openOrder = await placeConfidentialOrder(
+        user1,
+        userPositionPda,
+        confidentialBalancePda,
+        1,
+        false,
+        100 * PRICE_SCALE,
+        10 ** 6
+      );
+
+      // The lock was taken inside the MXE; only its ciphertext is on chain
+      const order = await program.account.openOrder.fetch(openOrder);
+      expect(order.status).to.deep.equal({ resting: {} });
+      expect(order.lockedAmount.toNumber()).to.equal(0);
+      expect(order.lockNonce.toString()).to.not.equal("0");
+      expect(order.encryptedLock.some((byte: number) => byte !== 0)).to.be.true;
+      const position = await program.account.userPosition.fetch(userPositionPda);
+      expect(position.baseLocked.toNumber()).to.equal(0);
+      let market = await program.account.market.fetch(confMarketPda);
+      expect(market.activeAsks).to.equal(1);
+
+      const clientPrivateKey = x25519.utils.randomPrivateKey();
+      const nonce = randomBytes(16);
+      const offset = await nextOffset(confMarketPda);
+      await program.methods
+        .cancelConfidentialOrder(
+          offset,
+          new anchor.BN(1),
+          Array.from(x25519.getPublicKey(clientPrivateKey)),
+          new anchor.BN(deserializeLE(nonce).toString())
+        )
+        .accountsPartial({
+          user: user1.publicKey,
+          market: confMarketPda,
+          orderBook: orderBookPda(confMarketPda),
+          userPosition: userPositionPda,
+          confidentialBalance: confidentialBalancePda,
+          openOrder,
+          ...arciumAccounts(offset, "remove_order_unlocked"),
+        })
+        .signers([user1])
+        .rpc();
+      await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+
+      // The lock went back to the encrypted balance with the order
+      expect(await provider.connection.getAccountInfo(openOrder)).to.be.null;
+      market = await program.account.market.fetch(confMarketPda);
+      expect(market.activeAsks).to.equal(0);
+      const balance = await program.account.confidentialBalance.fetch(confidentialBalancePda);
+      expect(balance.pendingUntil.toNumber()).to.equal(0);
+    });
+
+    it("rejects a confidential order the encrypted balance can't cover on callback", async () => {
+      const rejectedEvent = awaitEvent("confidentialOrderRejected");
+
+      // Offering 50 base tokens from a balance of 9
+      const openOrder = await placeConfidentialOrder(
+        user1,
+        userPositionPda,
+        confidentialBalancePda,
+        2,
+        false,
+        100 * PRICE_SCALE,
+        50 * 10 ** 9
+      );
+
+      const event = await rejectedEvent;
+      expect(event.orderId.toNumber()).to.equal(2);
+
+      // The rejected order is closed and its rent goes back to the owner
+      expect(await provider.connection.getAccountInfo(openOrder)).to.be.null;
+      const market = await program.account.market.fetch(confMarketPda);
+      expect(market.activeAsks).to.equal(0);
+
+      // Neither the book nor the balance is held by the rejected order
+      const orderBook = await program.account.orderBook.fetch(orderBookPda(confMarketPda));
+      expect(orderBook.pendingUntil.toNumber()).to.equal(0);
+      const balance = await program.account.confidentialBalance.fetch(confidentialBalancePda);
+      expect(balance.pendingUntil.toNumber()).to.equal(0);
+    });
+
+    it("settles a fill between two confidential balances", async () => {
+      // user1 offers 10^6 base units at $100 and user2 bids $101 for them
+      const makerOrder = await placeConfidentialOrder(
+        user1,
+        userPositionPda,
+        confidentialBalancePda,
+        3,
+        false,
+        100 * PRICE_SCALE,
+        10 ** 6
+      );
+      const takerOrder = await placeConfidentialOrder(
+        user2,
+        user2PositionPda,
+        user2BalancePda,
+        1,
+        true,
+        101 * PRICE_SCALE,
+        10 ** 6
+      );
+
+      let offset = await nextOffset(confMarketPda);
+      await program.methods
+        .uncross(offset)
+        .accountsPartial({
+          caller: user1.publicKey,
+          market: confMarketPda,
+          orderBook: orderBookPda(confMarketPda),
+          ...arciumAccounts(offset, "uncross_book"),
+        })
+        .signers([user1])
+        .rpc();
+      await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+
+      await program.methods
+        .createSettlement()
+        .accountsPartial({
+          payer: user1.publicKey,
+          market: confMarketPda,
+          settlement: settlementPda(confMarketPda, 1),
+          makerOrder,
+          takerOrder,
+          keeperPosition: null,
+        })
+        .signers([user1])
+        .rpc();
+
+      // The public settlement path leaves confidential balances alone
+      try {
+        await program.methods
+          .settleTrade()
+          .accountsPartial({
+            caller: user2.publicKey,
+            market: confMarketPda,
+            exchangeConfig: exchangeConfigPda,
+            settlement: settlementPda(confMarketPda, 1),
+            makerPosition: userPositionPda,
+            takerPosition: user2PositionPda,
+            makerOrder,
+            takerOrder,
+            makerOwner: user1.publicKey,
+            takerOwner: user2.publicKey,
+            keeperPosition: null,
+            baseVault: confBaseVaultPda,
+            quoteVault: confQuoteVaultPda,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user2])
+          .rpc();
+        expect.fail("Should have thrown ConfidentialMarket error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("ConfidentialMarket");
+      }
+
+      const settledEvent = awaitEvent("tradeSettled");
+      offset = await nextOffset(confMarketPda);
+      await program.methods
+        .settleConfidentialTrade(offset)
+        .accountsPartial({
+          caller: user2.publicKey,
+          market: confMarketPda,
+          exchangeConfig: exchangeConfigPda,
+          settlement: settlementPda(confMarketPda, 1),
+          makerPosition: userPositionPda,
+          takerPosition: user2PositionPda,
+          makerBalance: confidentialBalancePda,
+          takerBalance: user2BalancePda,
+          makerOrder,
+          takerOrder,
+          ...arciumAccounts(offset, "settle_fill"),
+        })
+        .signers([user2])
+        .rpc();
+      await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+
+      // Equal sizes cleared at the $100.5 midpoint
+      const quoteAmount = 100_500_000;
+      const fee = (quoteAmount * FEE_RATE_BPS) / 10_000;
+      const protocolFee = (fee * PROTOCOL_FEE_SHARE_BPS) / 10_000;
+      const event = await settledEvent;
+      expect(event.baseTransferred.toNumber()).to.equal(10 ** 6);
+      expect(event.quoteTransferred.toNumber()).to.equal(quoteAmount);
+
+      const settlement = await program.account.tradeSettlement.fetch(
+        settlementPda(confMarketPda, 1)
+      );
+      expect(settlement.settled).to.be.true;
+      const market = await program.account.market.fetch(confMarketPda);
+      expect(market.openSettlements).to.equal(0);
+      expect(market.protocolFeesAccrued.toNumber()).to.equal(protocolFee);
+      expect(market.feesAccrued.toNumber()).to.equal(fee - protocolFee);
+
+      // Both orders were filled by their only fill, and their locks released
+      expect(await provider.connection.getAccountInfo(makerOrder)).to.be.null;
+      expect(await provider.connection.getAccountInfo(takerOrder)).to.be.null;
+      for (const balance of [confidentialBalancePda, user2BalancePda]) {
+        const account = await program.account.confidentialBalance.fetch(balance);
+        expect(account.pendingUntil.toNumber()).to.equal(0);
+      }
+    });
+
+    it("closes a confidential position only once the MXE finds its balance empty", async () => {
+      const closePosition = async () => {
+        const offset = await nextOffset(confMarketPda);
+        await program.methods
+          .closeConfidentialPosition(offset)
+          .accountsPartial({
+            user: user2.publicKey,
+            market: confMarketPda,
+            userPosition: user2PositionPda,
+            confidentialBalance: user2BalancePda,
+            ...arciumAccounts(offset, "balance_is_empty"),
+          })
+          .signers([user2])
+          .rpc();
+        await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+      };
+
+      // user2 still holds the bought base and the quote it didn't spend
+      await closePosition();
+      const balance = await program.account.confidentialBalance.fetch(user2BalancePda);
+      expect(balance.pendingUntil.toNumber()).to.equal(0);
+      const before = await program.account.market.fetch(confMarketPda);
+
+      await withdrawConfidential(
+        user2,
+        user2PositionPda,
+        user2BalancePda,
+        10 ** 6,
+        true,
+        user2ConfBaseAccount
+      );
+      await withdrawConfidential(
+        user2,
+        user2PositionPda,
+        user2BalancePda,
+        200 * 10 ** 6 - 100_500_000,
+        false,
+        user2QuoteAccount
+      );
+      const base = await getAccount(provider.connection, user2ConfBaseAccount);
+      expect(base.amount.toString()).to.equal((10 ** 6).toString());
+
+      await closePosition();
+      expect(await provider.connection.getAccountInfo(user2PositionPda)).to.be.null;
+      expect(await provider.connection.getAccountInfo(user2BalancePda)).to.be.null;
+      const after = await program.account.market.fetch(confMarketPda);
+      expect(after.openPositionCount.toNumber()).to.equal(
+        before.openPositionCount.toNumber() - 1
+      );
+    });
+  });
+
+  describe("Opening Auction", () => {
+    const AUCTION_MARKET_ID = 5;
+
+    before(async () => {
+      await registerCircuit("add_order");
+      await registerCircuit("uncross_book");
+      auctionMarket = await createTradingMarket(AUCTION_MARKET_ID);
+    });
+
+    it("uncrosses a book that crossed before the market was paused and resumed", async () => {
+      const { market } = auctionMarket;
+
+      // user1 bids $101 and user2 offers at $100, both for 10^6 base units,
+      // which settle for about 100 USDC (quote = base * price / 10^6)
+      await placeOrder(market, user1, 1, true, 101 * PRICE_SCALE, 10 ** 6, 101 * 10 ** 6);
+      await placeOrder(market, user2, 1, false, 100 * PRICE_SCALE, 10 ** 6, 10 ** 6);
+      // A $99 bid doesn't improve on the best and never enters the book
+      await placeOrder(market, user1, 2, true, 99 * PRICE_SCALE, 10 ** 6, 99 * 10 ** 6);
+
+      for (const paused of [true, false]) {
+        await program.methods
+          .setMarketPaused(paused)
+          .accounts({ authority: authority.publicKey, market })
+          .signers([authority])
+          .rpc();
+      }
+      let marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.status).to.deep.equal({ auction: {} });
+      expect(marketAccount.hasPendingMatch).to.be.false;
+
+      const offset = await nextOffset(market);
+      await program.methods
+        .uncross(offset)
+        .accountsPartial({
+          caller: user1.publicKey,
+          market,
+          orderBook: orderBookPda(market),
+          ...arciumAccounts(offset, "uncross_book"),
+        })
+        .signers([user1])
+        .rpc();
+      await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+
+      // Equal sizes clear at the midpoint, with the ask as maker
+      marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.status).to.deep.equal({ continuous: {} });
+      expect(marketAccount.hasPendingMatch).to.be.true;
+      expect(marketAccount.pendingExecutionPrice.toNumber()).to.equal(100.5 * PRICE_SCALE);
+      expect(marketAccount.pendingExecutionAmount.toNumber()).to.equal(10 ** 6);
+      expect(marketAccount.pendingMaker.toString()).to.equal(
+        positionPda(market, user2.publicKey).toString()
+      );
+      expect(marketAccount.pendingTaker.toString()).to.equal(
+        positionPda(market, user1.publicKey).toString()
+      );
+      expect(marketAccount.pendingMakerFilled).to.be.true;
+      expect(marketAccount.pendingTakerFilled).to.be.true;
+
+      // Both crossing orders filled, so the uncross left the book empty
+      expect(marketAccount.uncrossOrderSeq.toNumber()).to.equal(3);
+      expect(marketAccount.uncrossBidOrderId.toNumber()).to.equal(0);
+      expect(marketAccount.uncrossAskOrderId.toNumber()).to.equal(0);
+    });
+
+    it("releases the lock of an auction order the uncross left out of the book", async () => {
+      const { market } = auctionMarket;
+      const userPosition = positionPda(market, user1.publicKey);
+      const releaseLocks = (orderId: number) =>
+        program.methods
+          .releaseLocks()
+          .accountsPartial({
+            caller: user2.publicKey,
+            market,
+            userPosition,
+            openOrder: openOrderPda(userPosition, orderId),
+            owner: user1.publicKey,
+          })
+          .signers([user2])
+          .rpc();
+
+      // The clearing match still names the first bid
+      try {
+        await releaseLocks(1);
+        expect.fail("Should have thrown OrderStillInBook error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("OrderStillInBook");
+      }
+
+      const before = await program.account.userPosition.fetch(userPosition);
+      const releasedEvent = awaitEvent("locksReleased");
+      await releaseLocks(2);
+
+      const released = await releasedEvent;
+      expect(released.orderId.toNumber()).to.equal(2);
+      expect(released.quoteReleased.toNumber()).to.equal(99 * 10 ** 6);
+
+      const after = await program.account.userPosition.fetch(userPosition);
+      expect(before.quoteLocked.sub(after.quoteLocked).toNumber()).to.equal(99 * 10 ** 6);
+      expect(after.activeOrderCount).to.equal(before.activeOrderCount - 1);
+      expect(await provider.connection.getAccountInfo(openOrderPda(userPosition, 2))).to.be.null;
+
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.activeBids).to.equal(1);
+    });
+  });
+
+  describe("Order Matching (requires Arcium)", () => {
+    it.skip("matches crossing orders", async () => {
+      // TODO: Implement with Arcium
+      // 1. Place buy order at $101
+      // 2. Place sell order at $100
+      // 3. Call matchOrders
+      // 4. Verify execution at midpoint (~$100.50)
+    });
+
+    it.skip("does not match non-crossing orders", async () => {
+      // TODO: Implement with Arcium
+      // Buy at $99, Sell at $101 - should not match
+    });
+  });
+
+  describe("Trade Settlement", () => {
+    let authorityQuoteAccount: PublicKey;
+
+    before(async () => {
+      authorityQuoteAccount = await createAccount(
+        provider.connection,
+        authority,
+        quoteMint,
+        authority.publicKey
+      );
+    });
+
+    it("creates a settlement from the auction's clearing match", async () => {
+      const { market } = auctionMarket;
+      const maker = positionPda(market, user2.publicKey);
+      const taker = positionPda(market, user1.publicKey);
+
+      await program.methods
+        .createSettlement()
+        .accountsPartial({
+          payer: user1.publicKey,
+          market,
+          settlement: settlementPda(market, 1),
+          makerOrder: openOrderPda(maker, 1),
+          takerOrder: openOrderPda(taker, 1),
+          keeperPosition: null,
+        })
+        .signers([user1])
+        .rpc();
+
+      const settlement = await program.account.tradeSettlement.fetch(settlementPda(market, 1));
+      expect(settlement.maker.toString()).to.equal(maker.toString());
+      expect(settlement.taker.toString()).to.equal(taker.toString());
+      expect(settlement.executionPrice.toNumber()).to.equal(100.5 * PRICE_SCALE);
+      expect(settlement.settled).to.be.false;
+
+      // Both orders left the book with this fill
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.hasPendingMatch).to.be.false;
+      expect(marketAccount.openSettlements).to.equal(1);
+      expect(marketAccount.activeBids).to.equal(0);
+      expect(marketAccount.activeAsks).to.equal(0);
+    });
+
+    it("settles the trade and books the protocol share of the fee apart from the keeper pool", async () => {
+      const { market, baseVault, quoteVault } = auctionMarket;
+      const maker = positionPda(market, user2.publicKey);
+      const taker = positionPda(market, user1.publicKey);
+      const sellerBefore = await program.account.userPosition.fetch(maker);
+
+      // With a reward set but no keeper position passed, the reward stays in the fees
+      await program.methods
+        .setKeeperReward(new anchor.BN(1_000))
+        .accounts({ authority: authority.publicKey, market })
+        .signers([authority])
+        .rpc();
+      const skippedEvent = awaitEvent("keeperRewardSkipped");
+
+      await program.methods
+        .settleTrade()
+        .accountsPartial({
+          caller: user2.publicKey,
+          market,
+          exchangeConfig: exchangeConfigPda,
+          settlement: settlementPda(market, 1),
+          makerPosition: maker,
+          takerPosition: taker,
+          makerOrder: openOrderPda(maker, 1),
+          takerOrder: openOrderPda(taker, 1),
+          makerOwner: user2.publicKey,
+          takerOwner: user1.publicKey,
+          keeperPosition: null,
+          baseVault,
+          quoteVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user2])
+        .rpc();
+
+      const skipped = await skippedEvent;
+      expect(skipped.market.toString()).to.equal(market.toString());
+      expect(skipped.crank).to.deep.equal({ settleTrade: {} });
+
+      // 100.5 USDC at 0.3% is a fee of 0.3015 USDC, 20% of it for the protocol
+      const quoteAmount = 100_500_000;
+      const fee = (quoteAmount * FEE_RATE_BPS) / 10_000;
+      const protocolFee = (fee * PROTOCOL_FEE_SHARE_BPS) / 10_000;
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.protocolFeesAccrued.toNumber()).to.equal(protocolFee);
+      expect(marketAccount.feesAccrued.toNumber()).to.equal(fee - protocolFee);
+      expect(marketAccount.openSettlements).to.equal(0);
+
+      // The seller is paid the quote less the whole fee
+      const seller = await program.account.userPosition.fetch(maker);
+      expect(seller.quoteDeposited.sub(sellerBefore.quoteDeposited).toNumber()).to.equal(
+        quoteAmount - fee
+      );
+
+      // The fee never left the vault
+      const vault = await getAccount(provider.connection, quoteVault);
+      expect(
+        marketAccount.quoteDeposited
+          .add(marketAccount.feesAccrued)
+          .add(marketAccount.protocolFeesAccrued)
+          .toString()
+      ).to.equal(vault.amount.toString());
+
+      // Filled orders are closed once their only fill is settled
+      expect(await provider.connection.getAccountInfo(openOrderPda(maker, 1))).to.be.null;
+      expect(await provider.connection.getAccountInfo(openOrderPda(taker, 1))).to.be.null;
+    });
+
+    it("fails to collect protocol fees without the exchange admin", async () => {
+      const { market, quoteVault } = auctionMarket;
+
+      try {
+        await program.methods
+          .collectProtocolFees()
+          .accountsPartial({
+            admin: user1.publicKey,
+            exchangeConfig: exchangeConfigPda,
+            market,
+            quoteMint,
+            quoteVault,
+            destination: user1QuoteAccount,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown Unauthorized error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("Unauthorized");
+      }
+    });
+
+    it("collects the protocol share and leaves the keeper pool in the vault", async () => {
+      const { market, quoteVault } = auctionMarket;
+      const before = await program.account.market.fetch(market);
+
+      await program.methods
+        .collectProtocolFees()
+        .accountsPartial({
+          admin: authority.publicKey,
+          exchangeConfig: exchangeConfigPda,
+          market,
+          quoteMint,
+          quoteVault,
+          destination: authorityQuoteAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([authority])
+        .rpc();
+
+      const destination = await getAccount(provider.connection, authorityQuoteAccount);
+      expect(destination.amount.toString()).to.equal(before.protocolFeesAccrued.toString());
+
+      const after = await program.account.market.fetch(market);
+      expect(after.protocolFeesAccrued.toNumber()).to.equal(0);
+      expect(after.feesAccrued.toString()).to.equal(before.feesAccrued.toString());
+
+      const vault = await getAccount(provider.connection, quoteVault);
+      expect(after.quoteDeposited.add(after.feesAccrued).toString()).to.equal(
+        vault.amount.toString()
+      );
+    });
+  });
+
+  describe("Crossed Book Probes", () => {
+    before(async () => {
+      await registerCircuit("probe_cross");
+    });
+
+    const probe = async (market: PublicKey) => {
+      const offset = await nextOffset(market);
+      await program.methods
+        .probeCross(offset)
+        .accountsPartial({
+          caller: user1.publicKey,
+          market,
+          orderBook: orderBookPda(market),
+          ...arciumAccounts(offset, "probe_cross"),
+        })
+        .signers([user1])
+        .rpc();
+      return offset;
+    };
+
+    it("rejects a probe of a book with an empty side", async () => {
+      const { market } = auctionMarket;
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.status).to.deep.equal({ continuous: {} });
+      expect(marketAccount.activeBids).to.equal(0);
+
+      try {
+        await probe(market);
+        expect.fail("Should have thrown NoMatchingOrders error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("NoMatchingOrders");
+      }
+    });
+
+    it("reports whether the best bid and ask cross", async () => {
+      const { market } = auctionMarket;
+
+      // $99 against $100 leaves a spread
+      await placeOrder(market, user1, 2, true, 99 * PRICE_SCALE, 10 ** 6, 99 * 10 ** 6);
+      await placeOrder(market, user2, 2, false, 100 * PRICE_SCALE, 10 ** 6, 10 ** 6);
+
+      let offset = await probe(market);
+      await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+      let marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.probeComputationOffset.toString()).to.equal(offset.toString());
+      expect(marketAccount.bookCrossed).to.be.false;
+
+      // A $101 bid takes the best bid slot and crosses the ask
+      await placeOrder(market, user1, 3, true, 101 * PRICE_SCALE, 10 ** 6, 101 * 10 ** 6);
+      const probedEvent = awaitEvent("bookProbed");
+      offset = await probe(market);
+      await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+
+      // The verdict is tied to the book state it read, which no job has replaced since
+      marketAccount = await program.account.market.fetch(market);
+      const orderBook = await program.account.orderBook.fetch(orderBookPda(market));
+      expect(marketAccount.bookCrossed).to.be.true;
+      expect(marketAccount.probeStateNonce.toString()).to.equal(
+        orderBook.stateNonce.toString()
+      );
+      const event = await probedEvent;
+      expect(event.crossed).to.be.true;
+      expect(event.stateNonce.toString()).to.equal(orderBook.stateNonce.toString());
+    });
+  });
+
+  describe("Side-hidden Orders", () => {
+    before(async () => {
+      await registerCircuit("add_hidden_order");
+      await registerCircuit("remove_order");
+    });
+
+    it("locks both sides for a side-hidden order and never publishes its side", async () => {
+      const { market } = auctionMarket;
+      const position = positionPda(market, user2.publicKey);
+      const openOrder = openOrderPda(position, 3);
+      const positionBefore = await program.account.userPosition.fetch(position);
+      const marketBefore = await program.account.market.fetch(market);
+
+      // A $150 offer, with the side encrypted alongside the price and amount
+      const mxePublicKey = await getMXEPublicKey(provider, program.programId);
+      const clientPrivateKey = x25519.utils.randomPrivateKey();
+      const nonce = randomBytes(16);
+      const cipher = new RescueCipher(
+        x25519.getSharedSecret(clientPrivateKey, mxePublicKey)
+      );
+      const [encryptedPrice, encryptedAmount, encryptedSide] = cipher.encrypt(
+        [BigInt(150 * PRICE_SCALE), BigInt(10 ** 6), BigInt(0)],
+        nonce
+      );
+      const baseLock = 10 ** 6;
+      const quoteLock = 50 * 10 ** 6;
+
+      const placedEvent = awaitEvent("hiddenOrderPlaced");
+      const offset = await nextOffset(market);
+      await program.methods
+        .placeHiddenOrder(
+          offset,
+          new anchor.BN(3),
+          Buffer.from(encryptedPrice),
+          Buffer.from(encryptedAmount),
+          Buffer.from(encryptedSide),
+          Array.from(x25519.getPublicKey(clientPrivateKey)),
+          new anchor.BN(deserializeLE(nonce).toString()),
+          new anchor.BN(baseLock),
+          new anchor.BN(quoteLock)
+        )
+        .accountsPartial({
+          user: user2.publicKey,
+          market,
+          orderBook: orderBookPda(market),
+          userPosition: position,
+          openOrder,
+          ...arciumAccounts(offset, "add_hidden_order"),
+        })
+        .signers([user2])
+        .rpc();
+
+      // Both locks are taken, so the lock pattern doesn't give the side away
+      const positionAfter = await program.account.userPosition.fetch(position);
+      expect(positionAfter.baseLocked.sub(positionBefore.baseLocked).toNumber()).to.equal(
+        baseLock
+      );
+      expect(positionAfter.quoteLocked.sub(positionBefore.quoteLocked).toNumber()).to.equal(
+        quoteLock
+      );
+      const order = await program.account.openOrder.fetch(openOrder);
+      expect(order.sideHidden).to.be.true;
+      expect(order.lockedBase.toNumber()).to.equal(baseLock);
+      expect(order.lockedAmount.toNumber()).to.equal(quoteLock);
+
+      const event = await placedEvent;
+      expect(Object.keys(event).sort()).to.deep.equal(
+        ["computationOffset", "market", "orderId", "timestamp", "user"].sort()
+      );
+      expect(event.orderId.toNumber()).to.equal(3);
+
+      // Counted apart from both sides until a fill reveals its side
+      await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+      const placed = await program.account.openOrder.fetch(openOrder);
+      expect(placed.status).to.deep.equal({ resting: {} });
+      const marketAfter = await program.account.market.fetch(market);
+      expect(marketAfter.activeHidden).to.equal(marketBefore.activeHidden + 1);
+      expect(marketAfter.activeBids).to.equal(marketBefore.activeBids);
+      expect(marketAfter.activeAsks).to.equal(marketBefore.activeAsks);
+    });
+
+    it("stops counting a cancelled order that was displaced from the book", async () => {
+      const { market } = auctionMarket;
+      const position = positionPda(market, user2.publicKey);
+      const openOrder = openOrderPda(position, 3);
+      const positionBefore = await program.account.userPosition.fetch(position);
+      const marketBefore = await program.account.market.fetch(market);
+
+      // The $150 offer never took the ask slot from the $100 one, so the
+      // circuit can't find it
+      const cancelledEvent = awaitEvent("orderCancelled");
+      const offset = await nextOffset(market);
+      await program.methods
+        .cancelOrder(
+          offset,
+          new anchor.BN(3),
+          Array.from(x25519.getPublicKey(x25519.utils.randomPrivateKey())),
+          new anchor.BN(deserializeLE(randomBytes(16)).toString())
+        )
+        .accountsPartial({
+          user: user2.publicKey,
+          market,
+          orderBook: orderBookPda(market),
+          userPosition: position,
+          openOrder,
+          ...arciumAccounts(offset, "remove_order"),
+        })
+        .signers([user2])
+        .rpc();
+      await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+      expect((await cancelledEvent).orderId.toNumber()).to.equal(3);
+
+      // Both locks are released and the order no longer counts as active
+      expect(await provider.connection.getAccountInfo(openOrder)).to.be.null;
+      const positionAfter = await program.account.userPosition.fetch(position);
+      expect(positionBefore.baseLocked.sub(positionAfter.baseLocked).toNumber()).to.equal(10 ** 6);
+      expect(positionBefore.quoteLocked.sub(positionAfter.quoteLocked).toNumber()).to.equal(
+        50 * 10 ** 6
+      );
+      const marketAfter = await program.account.market.fetch(market);
+      expect(marketAfter.activeHidden).to.equal(marketBefore.activeHidden - 1);
+      expect(marketAfter.activeAsks).to.equal(marketBefore.activeAsks);
+    });
+  });
+
+  describe("Sealed Fills", () => {
+    const SEALED_MARKET_ID = 7;
+
+    let sealedMarket: Awaited<ReturnType<typeof createTradingMarket>>;
+    let makerCipher: RescueCipher;
+    let takerCipher: RescueCipher;
+
+    before(async () => {
+      await registerCircuit("add_order");
+      await registerCircuit("uncross_book");
+      await registerCircuit("match_book_sealed");
+      await registerCircuit("open_sealed_match");
+      await registerCircuit("reveal_fill");
+      await registerCircuit("disclose_fill");
+      sealedMarket = await createTradingMarket(SEALED_MARKET_ID);
+
+      await program.methods
+        .setSealedFills(true)
+        .accounts({ authority: authority.publicKey, market: sealedMarket.market })
+        .signers([authority])
+        .rpc();
+
+      // Uncrossing the empty book ends the opening auction without a match
+      const offset = await nextOffset(sealedMarket.market);
+      await program.methods
+        .uncross(offset)
+        .accountsPartial({
+          caller: user1.publicKey,
+          market: sealedMarket.market,
+          orderBook: orderBookPda(sealedMarket.market),
+          ...arciumAccounts(offset, "uncross_book"),
+        })
+        .signers([user1])
+        .rpc();
+      await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+    });
+
+    it("fails to match with sealed terms on a market that reveals its fills", async () => {
+      const { market } = auctionMarket;
+      const offset = await nextOffset(market);
+
+      try {
+        await program.methods
+          .matchSealedOrders(offset)
+          .accountsPartial({
+            caller: user1.publicKey,
+            market,
+            orderBook: orderBookPda(market),
+            keeperPosition: null,
+            ...arciumAccounts(offset, "match_book_sealed"),
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown NotSealedFillMarket error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("NotSealedFillMarket");
+      }
+    });
+
+    it("matches crossing orders without publishing the terms or the parties", async () => {
+      const { market } = sealedMarket;
+
+      // Each side keeps the cipher of its order to read its copy of the terms
+      takerCipher = (await placeOrder(market, user1, 1, true, 101 * PRICE_SCALE, 10 ** 6, 101 * 10 ** 6)).cipher;
+      makerCipher = (await placeOrder(market, user2, 1, false, 100 * PRICE_SCALE, 10 ** 6, 10 ** 6)).cipher;
+
+      const offset = await nextOffset(market);
+      const matchedEvent = awaitEvent("sealedOrdersMatched");
+      await program.methods
+        .matchSealedOrders(offset)
+        .accountsPartial({
+          caller: user1.publicKey,
+          market,
+          orderBook: orderBookPda(market),
+          keeperPosition: null,
+          ...arciumAccounts(offset, "match_book_sealed"),
+        })
+        .signers([user1])
+        .rpc();
+      await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+
+      // Price and amount stay 0 in the clear; the encrypted terms wait for
+      // settlement, and the parties for one of them to open the match
+      let marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.hasPendingMatch).to.be.true;
+      expect(marketAccount.pendingMatchSealed).to.be.true;
+      expect(marketAccount.pendingMaker.toString()).to.equal(PublicKey.default.toString());
+      expect(marketAccount.pendingTaker.toString()).to.equal(PublicKey.default.toString());
+      expect(marketAccount.pendingExecutionPrice.toNumber()).to.equal(0);
+      expect(marketAccount.pendingExecutionAmount.toNumber()).to.equal(0);
+
+      const event = await matchedEvent;
+      expect(event).to.not.have.property("maker");
+      expect(event).to.not.have.property("taker");
+
+      // Nobody can settle a match whose parties are still sealed
+      try {
+        await program.methods
+          .createSettlement()
+          .accountsPartial({
+            payer: user1.publicKey,
+            market,
+            settlement: settlementPda(market, 1),
+            makerOrder: openOrderPda(positionPda(market, user2.publicKey), 1),
+            takerOrder: openOrderPda(positionPda(market, user1.publicKey), 1),
+            keeperPosition: null,
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown SealedMatchNotOpened error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("SealedMatchNotOpened");
+      }
+
+      // During the head start only a position can claim the match
+      const refusedOffset = await nextOffset(market);
+      try {
+        await program.methods
+          .openSealedMatch(refusedOffset)
+          .accountsPartial({
+            caller: authority.publicKey,
+            market,
+            claimerPosition: null,
+            ...arciumAccounts(refusedOffset, "open_sealed_match"),
+          })
+          .signers([authority])
+          .rpc();
+        expect.fail("Should have thrown Unauthorized error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("Unauthorized");
+      }
+
+      // The taker opens it with its position
+      const openOffset = await nextOffset(market);
+      const openedEvent = awaitEvent("sealedMatchOpened");
+      await program.methods
+        .openSealedMatch(openOffset)
+        .accountsPartial({
+          caller: user1.publicKey,
+          market,
+          claimerPosition: positionPda(market, user1.publicKey),
+          ...arciumAccounts(openOffset, "open_sealed_match"),
+        })
+        .signers([user1])
+        .rpc();
+      await awaitComputationFinalization(provider, openOffset, program.programId, "confirmed");
+
+      const opened = await openedEvent;
+      expect(opened.maker.toString()).to.equal(positionPda(market, user2.publicKey).toString());
+      expect(opened.taker.toString()).to.equal(positionPda(market, user1.publicKey).toString());
+      expect(opened.makerOrderId.toNumber()).to.equal(1);
+      expect(opened.takerOrderId.toNumber()).to.equal(1);
+      marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.pendingMatchSealed).to.be.false;
+
+      await program.methods
+        .createSettlement()
+        .accountsPartial({
+          payer: user1.publicKey,
+          market,
+          settlement: settlementPda(market, 1),
+          makerOrder: openOrderPda(positionPda(market, user2.publicKey), 1),
+          takerOrder: openOrderPda(positionPda(market, user1.publicKey), 1),
+          keeperPosition: null,
+        })
+        .signers([user1])
+        .rpc();
+
+      const settlement = await program.account.tradeSettlement.fetch(settlementPda(market, 1));
+      expect(settlement.sealedFill).to.be.true;
+      expect(settlement.executionPrice.toNumber()).to.equal(0);
+      expect(settlement.fillCommitment).to.deep.equal(event.fillCommitment);
+    });
+
+    it("settles the sealed trade and hands each counterparty the terms", async () => {
+      const { market, quoteVault } = sealedMarket;
+      const maker = positionPda(market, user2.publicKey);
+      const taker = positionPda(market, user1.publicKey);
+      const sellerBefore = await program.account.userPosition.fetch(maker);
+      const buyerBefore = await program.account.userPosition.fetch(taker);
+
+      const offset = await nextOffset(market);
+      const settledEvent = awaitEvent("sealedTradeSettled");
+      await program.methods
+        .settleSealedTrade(offset)
+        .accountsPartial({
+          caller: user2.publicKey,
+          market,
+          exchangeConfig: exchangeConfigPda,
+          settlement: settlementPda(market, 1),
+          makerPosition: maker,
+          takerPosition: taker,
+          makerOrder: openOrderPda(maker, 1),
+          takerOrder: openOrderPda(taker, 1),
+          makerOwner: user2.publicKey,
+          takerOwner: user1.publicKey,
+          ...arciumAccounts(offset, "reveal_fill"),
+        })
+        .signers([user2])
+        .rpc();
+      await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+
+      // Equal sizes fill at the midpoint, as on a revealed market
+      const event = await settledEvent;
+      for (const [cipher, fill, nonce] of [
+        [makerCipher, event.makerFill, event.makerFillNonce],
+        [takerCipher, event.takerFill, event.takerFillNonce],
+      ] as const) {
+        const [price, amount, salt] = cipher.decrypt(
+          fill,
+          new Uint8Array(nonce.toArrayLike(Buffer, "le", 16))
+        );
+        expect(price).to.equal(BigInt(100.5 * PRICE_SCALE));
+        expect(amount).to.equal(BigInt(10 ** 6));
+
+        // The salt opens the commitment published at match time
+        const preimage = Buffer.alloc(32);
+        preimage.writeBigUInt64LE(price, 0);
+        preimage.writeBigUInt64LE(amount, 8);
+        preimage.writeBigUInt64LE(salt & BigInt("0xffffffffffffffff"), 16);
+        preimage.writeBigUInt64LE(salt >> BigInt(64), 24);
+        expect(Array.from(createHash("sha3-256").update(preimage).digest())).to.deep.equal(
+          event.fillCommitment
+        );
+      }
+
+      // The amounts moved are public and give the price away
+      const quoteAmount = 100_500_000;
+      expect(event.baseTransferred.toNumber()).to.equal(10 ** 6);
+      expect(event.quoteTransferred.toNumber()).to.equal(quoteAmount);
+
+      const fee = (quoteAmount * FEE_RATE_BPS) / 10_000;
+      const protocolFee = (fee * PROTOCOL_FEE_SHARE_BPS) / 10_000;
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.protocolFeesAccrued.toNumber()).to.equal(protocolFee);
+      expect(marketAccount.feesAccrued.toNumber()).to.equal(fee - protocolFee);
+      expect(marketAccount.openSettlements).to.equal(0);
+
+      const seller = await program.account.userPosition.fetch(maker);
+      expect(seller.quoteDeposited.sub(sellerBefore.quoteDeposited).toNumber()).to.equal(
+        quoteAmount - fee
+      );
+      const buyer = await program.account.userPosition.fetch(taker);
+      expect(buyer.baseDeposited.sub(buyerBefore.baseDeposited).toNumber()).to.equal(10 ** 6);
+
+      const vault = await getAccount(provider.connection, quoteVault);
+      expect(
+        marketAccount.quoteDeposited
+          .add(marketAccount.feesAccrued)
+          .add(marketAccount.protocolFeesAccrued)
+          .toString()
+      ).to.equal(vault.amount.toString());
+
+      expect(await provider.connection.getAccountInfo(openOrderPda(maker, 1))).to.be.null;
+      expect(await provider.connection.getAccountInfo(openOrderPda(taker, 1))).to.be.null;
+    });
+
+    it("user1 discloses its sealed fill to its auditor", async () => {
+      const { market } = sealedMarket;
+      const userPosition = positionPda(market, user1.publicKey);
+      const auditorPrivateKey = x25519.utils.randomPrivateKey();
+      const mxePublicKey = await getMXEPublicKey(provider, program.programId);
+
+      await program.methods
+        .setAuditorKey(Array.from(x25519.getPublicKey(auditorPrivateKey)))
+        .accounts({ user: user1.publicKey, market, userPosition })
+        .signers([user1])
+        .rpc();
+
+      const offset = await nextOffset(market);
+      const nonce = randomBytes(16);
+      await program.methods
+        .discloseFill(offset, new anchor.BN(deserializeLE(nonce).toString()))
+        .accountsPartial({
+          user: user1.publicKey,
+          market,
+          userPosition,
+          settlement: settlementPda(market, 1),
+          disclosureRecord: disclosureRecordPda(userPosition, offset),
+          ...arciumAccounts(offset, "disclose_fill"),
+        })
+        .signers([user1])
+        .rpc();
+      await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+
+      const record = await program.account.disclosureRecord.fetch(
+        disclosureRecordPda(userPosition, offset)
+      );
+      expect(record.kind).to.deep.equal({ fill: {} });
+      expect(record.settlement.toString()).to.equal(settlementPda(market, 1).toString());
+      expect(record.completed).to.be.true;
+
+      // Only the auditor key reads the terms back
+      const cipher = new RescueCipher(
+        x25519.getSharedSecret(auditorPrivateKey, mxePublicKey)
+      );
+      const [price, amount] = cipher.decrypt(
+        record.result.slice(0, 2),
+        new Uint8Array(record.resultNonce.toArrayLike(Buffer, "le", 16))
+      );
+      expect(price).to.equal(BigInt(100.5 * PRICE_SCALE));
+      expect(amount).to.equal(BigInt(10 ** 6));
+    });
+  });
+
+  describe("Market Closing", () => {
+    const CLOSING_MARKET_ID = 6;
+
+    let closingMarket: Awaited<ReturnType<typeof createTradingMarket>>;
+
+    const clearBook = (offset: anchor.BN) =>
+      program.methods
+        .clearBook(offset)
+        .accountsPartial({
+          authority: authority.publicKey,
+          market: closingMarket.market,
+          orderBook: orderBookPda(closingMarket.market),
+          ...arciumAccounts(offset, "clear_book"),
+        })
+        .signers([authority]);
+
+    before(async () => {
+      await registerCircuit("clear_book");
+      closingMarket = await createTradingMarket(CLOSING_MARKET_ID);
+      const { market } = closingMarket;
+
+      // A crossed auction leaves a match to settle when the market is delisted
+      await placeOrder(market, user1, 1, true, 101 * PRICE_SCALE, 10 ** 6, 101 * 10 ** 6);
+      await placeOrder(market, user2, 1, false, 100 * PRICE_SCALE, 10 ** 6, 10 ** 6);
+      const offset = await nextOffset(market);
+      await program.methods
+        .uncross(offset)
+        .accountsPartial({
+          caller: user1.publicKey,
+          market,
+          orderBook: orderBookPda(market),
+          ...arciumAccounts(offset, "uncross_book"),
+        })
+        .signers([user1])
+        .rpc();
+      await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+
+      // A bid that is still resting when the book is cleared
+      await placeOrder(market, user1, 2, true, 90 * PRICE_SCALE, 10 ** 6, 90 * 10 ** 6);
+
+      await program.methods
+        .delistMarket()
+        .accounts({ authority: authority.publicKey, market })
+        .signers([authority])
+        .rpc();
+    });
+
+    const releaseLocks = (user: Keypair, orderId: number) => {
+      const userPosition = positionPda(closingMarket.market, user.publicKey);
+      return program.methods
+        .releaseLocks()
+        .accountsPartial({
+          caller: user2.publicKey,
+          market: closingMarket.market,
+          userPosition,
+          openOrder: openOrderPda(userPosition, orderId),
+          owner: user.publicKey,
+        })
+        .signers([user2])
+        .rpc();
+    };
+
+    it("refuses to release an order's lock before the book is cleared", async () => {
+      try {
+        await releaseLocks(user1, 2);
+        expect.fail("Should have thrown InvalidDelistingPhase error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("InvalidDelistingPhase");
+      }
+    });
+
+    it("refuses to clear the book while a match is not yet settled", async () => {
+      const { market, baseVault, quoteVault } = closingMarket;
+      const maker = positionPda(market, user2.publicKey);
+      const taker = positionPda(market, user1.publicKey);
+
+      // Clearing releases the locks that back the match, first while it is
+      // pending and then while its settlement is open
+      for (const step of ["pending", "open"]) {
+        try {
+          await clearBook(await nextOffset(market)).rpc();
+          expect.fail("Should have thrown ComputationNotReady error");
+        } catch (error: any) {
+          expect(error.error.errorCode.code).to.equal("ComputationNotReady");
+        }
+
+        if (step === "pending") {
+          await program.methods
+            .createSettlement()
+            .accountsPartial({
+              payer: user1.publicKey,
+              market,
+              settlement: settlementPda(market, 1),
+              makerOrder: openOrderPda(maker, 1),
+              takerOrder: openOrderPda(taker, 1),
+              keeperPosition: null,
+            })
+            .signers([user1])
+            .rpc();
+        }
+      }
+
+      await program.methods
+        .settleTrade()
+        .accountsPartial({
+          caller: user1.publicKey,
+          market,
+          exchangeConfig: exchangeConfigPda,
+          settlement: settlementPda(market, 1),
+          makerPosition: maker,
+          takerPosition: taker,
+          makerOrder: openOrderPda(maker, 1),
+          takerOrder: openOrderPda(taker, 1),
+          makerOwner: user2.publicKey,
+          takerOwner: user1.publicKey,
+          keeperPosition: null,
+          baseVault,
+          quoteVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+    });
+
+    it("refuses to clear the book while another book job is running", async () => {
+      const { market } = closingMarket;
+      const marketAccount = await program.account.market.fetch(market);
+      const first = computationOffset(market, marketAccount.computationCount.toNumber() + 1);
+      const second = computationOffset(market, marketAccount.computationCount.toNumber() + 2);
+
+      // Both in one transaction, so the first cannot have called back yet
+      const secondClear = await clearBook(second).instruction();
+      try {
+        await clearBook(first).postInstructions([secondClear]).rpc();
+        expect.fail("Should have thrown OrderBookBusy error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("OrderBookBusy");
+      }
+
+      const orderBook = await program.account.orderBook.fetch(orderBookPda(market));
+      expect(orderBook.pendingUntil.toNumber()).to.equal(0);
+    });
+
+    it("clears the book and makes the market withdraw-only", async () => {
+      const { market } = closingMarket;
+      const offset = await nextOffset(market);
+
+      await clearBook(offset).rpc();
+      await awaitComputationFinalization(provider, offset, program.programId, "confirmed");
+
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.status).to.deep.equal({ withdrawOnly: {} });
+      expect(marketAccount.activeBids).to.equal(0);
+      expect(marketAccount.activeAsks).to.equal(0);
+    });
+
+    it("releases the lock of an order left in the cleared book and closes it", async () => {
+      const { market } = closingMarket;
+      const userPosition = positionPda(market, user1.publicKey);
+      const openOrder = openOrderPda(userPosition, 2);
+      const rent = await provider.connection.getBalance(openOrder);
+      const ownerBefore = await provider.connection.getBalance(user1.publicKey);
+      const marketBefore = await program.account.market.fetch(market);
+
+      await releaseLocks(user1, 2);
+
+      const position = await program.account.userPosition.fetch(userPosition);
+      expect(position.quoteLocked.toNumber()).to.equal(0);
+      expect(position.activeOrderCount).to.equal(0);
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.quoteLocked.toNumber()).to.equal(
+        marketBefore.quoteLocked.toNumber() - 90 * 10 ** 6
+      );
+
+      // The order's rent goes to its owner, not to the caller
+      expect(await provider.connection.getAccountInfo(openOrder)).to.be.null;
+      expect(await provider.connection.getBalance(user1.publicKey)).to.equal(
+        ownerBefore + rent
+      );
+    });
+
+    const closeMarket = (baseDestination: PublicKey, quoteDestination: PublicKey) =>
+      program.methods
+        .closeMarket()
+        .accountsPartial({
+          authority: authority.publicKey,
+          market: closingMarket.market,
+          orderBook: orderBookPda(closingMarket.market),
+          registryEntry: registryEntryPda(closingMarket.baseMint, quoteMint),
+          baseMint: closingMarket.baseMint,
+          quoteMint,
+          baseVault: closingMarket.baseVault,
+          quoteVault: closingMarket.quoteVault,
+          baseDestination,
+          quoteDestination,
+          baseTokenProgram: TOKEN_PROGRAM_ID,
+          quoteTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([authority])
+        .rpc();
+
+    it("refuses to close the market while positions are open", async () => {
+      const authorityBase = await createAccount(
+        provider.connection,
+        authority,
+        closingMarket.baseMint,
+        authority.publicKey
+      );
+
+      try {
+        await closeMarket(
+          authorityBase,
+          getAssociatedTokenAddressSync(quoteMint, authority.publicKey)
+        );
+        expect.fail("Should have thrown MarketNotEmpty error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("MarketNotEmpty");
+      }
+    });
+
+    it("closes the market and returns every account's rent to the authority", async () => {
+      const { market, baseMint: closingBaseMint, baseVault, quoteVault } = closingMarket;
+      const authorityBase = getAssociatedTokenAddressSync(closingBaseMint, authority.publicKey);
+      const authorityQuote = getAssociatedTokenAddressSync(quoteMint, authority.publicKey);
+
+      // Both traders leave, and the protocol share of the fee is collected
+      const traders: [Keypair, PublicKey, PublicKey][] = [
+        [user1, closingMarket.user1Base, user1QuoteAccount],
+        [user2, closingMarket.user2Base, user2QuoteAccount],
+      ];
+      for (const [user, baseAccount, quoteAccount] of traders) {
+        const userPosition = positionPda(market, user.publicKey);
+        const position = await program.account.userPosition.fetch(userPosition);
+        for (const [amount, isBase, mint, userTokenAccount, vault] of [
+          [position.baseDeposited, true, closingBaseMint, baseAccount, baseVault],
+          [position.quoteDeposited, false, quoteMint, quoteAccount, quoteVault],
+        ] as const) {
+          if (amount.isZero()) continue;
+          await program.methods
+            .withdraw(amount, isBase)
+            .accounts({
+              user: user.publicKey,
+              market,
+              userPosition,
+              mint,
+              userTokenAccount,
+              vault,
+              tokenProgram: TOKEN_PROGRAM_ID,
+            })
+            .signers([user])
+            .rpc();
+        }
+        await program.methods
+          .closePosition()
+          .accounts({ user: user.publicKey, market, userPosition })
+          .signers([user])
+          .rpc();
+      }
+      await program.methods
+        .collectProtocolFees()
+        .accountsPartial({
+          admin: authority.publicKey,
+          exchangeConfig: exchangeConfigPda,
+          market,
+          quoteMint,
+          quoteVault,
+          destination: authorityQuote,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([authority])
+        .rpc();
+
+      const closedAccounts = [
+        market,
+        orderBookPda(market),
+        registryEntryPda(closingBaseMint, quoteMint),
+        baseVault,
+        quoteVault,
+      ];
+      let rent = 0;
+      for (const address of closedAccounts) {
+        rent += await provider.connection.getBalance(address);
+      }
+      const keeperPool = (await program.account.market.fetch(market)).feesAccrued;
+      const quoteBefore = await getAccount(provider.connection, authorityQuote);
+      const lamportsBefore = await provider.connection.getBalance(authority.publicKey);
+      const configBefore = await program.account.exchangeConfig.fetch(exchangeConfigPda);
+
+      // The provider wallet pays the transaction fee, so the authority's
+      // balance only moves by the rent it gets back
+      await closeMarket(authorityBase, authorityQuote);
+
+      for (const address of closedAccounts) {
+        expect(await provider.connection.getAccountInfo(address)).to.be.null;
+      }
+      expect(await provider.connection.getBalance(authority.publicKey)).to.equal(
+        lamportsBefore + rent
+      );
+
+      // The closed market no longer counts as listed
+      const config = await program.account.exchangeConfig.fetch(exchangeConfigPda);
+      expect(config.marketCount.toNumber()).to.equal(configBefore.marketCount.toNumber() - 1);
+
+      // Unpaid keeper rewards are swept out with the vault
+      const quoteAfter = await getAccount(provider.connection, authorityQuote);
+      expect((quoteAfter.amount - quoteBefore.amount).toString()).to.equal(
+        keeperPool.toString()
+      );
     });
   });
 });